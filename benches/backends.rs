@@ -0,0 +1,56 @@
+//!
+//! Measures computing the same compiled expressions repeatedly with each
+//! backend: walking the tree, and running the bytecode compiled from it on a
+//! stack machine. Each expression is lexed and parsed once, as a program
+//! filtering the rows of a table would, so only computing it is measured.
+//!
+//! Run with `cargo bench --bench backends`.
+//!
+
+use std::{hint::black_box, time::{Duration, Instant}};
+
+use pxpr::{compile, compiled::CompiledExpression, context::Context, expression::Value, vm::Backend};
+
+
+const EXPRESSIONS: [&str; 5] = [
+    "a * b + c",
+    "(a + b) * (a - b) / (c + 1) % 7",
+    "a * b > 100 && c != 0 || !(a < b)",
+    "a when a > b; b when b > c; c otherwise",
+    "max(a, b, c) - min(a, b, c) + gcd(a, c)",
+];
+
+
+///
+/// Compute an expression repeatedly for at least half a second, binding
+/// new values to its variables each time, and get the average time taken.
+///
+fn measure(expression: &CompiledExpression, backend: Backend) -> Duration {
+    let mut context = Context::new();
+    context.backend = backend;
+    let mut runs: i64 = 0;
+    let start = Instant::now();
+
+    while runs == 0 || start.elapsed() < Duration::from_millis(500) {
+        context.set("a", Value::Integer(runs % 97 + 1));
+        context.set("b", Value::Integer(runs % 89 + 2));
+        context.set("c", Value::Integer(runs % 83 + 3));
+        let result = expression.evaluate(black_box(&mut context));
+        assert!(result.is_ok());
+        runs += 1;
+    }
+
+    start.elapsed() / runs as u32
+}
+
+
+fn main() {
+    println!("{:<44}  {:>12}  {:>12}", "expression", "tree", "vm");
+
+    for source in EXPRESSIONS {
+        let expression = compile(source).expect("the benchmarked expressions are valid");
+        let tree = measure(&expression, Backend::Tree);
+        let vm = measure(&expression, Backend::Vm);
+        println!("{:<44}  {:>12?}  {:>12?}", source, tree, vm);
+    }
+}
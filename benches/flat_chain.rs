@@ -0,0 +1,82 @@
+//!
+//! Measures computing enormous flat chains of additions, such as the sums
+//! produced by data pipelines, which the evaluator folds in a loop. A balanced
+//! tree of the same number of additions, which is computed recursively, is
+//! measured alongside for comparison. Each tree is copied into an arena
+//! once, as the parser would have read it, before being computed.
+//!
+//! Run with `cargo bench --bench flat_chain`.
+//!
+
+use std::{hint::black_box, time::{Duration, Instant}};
+
+use pxpr::{
+    arena::Arena, context::Context, expression::execute_in, parser::{AstNode, AstNodeKind, BinaryOperationType}, span::Span
+};
+
+
+const SIZES: [i64; 4] = [1_000, 10_000, 100_000, 1_000_000];
+
+
+fn integer(n: i64) -> Box<AstNode> {
+    Box::new(AstNode::new(AstNodeKind::Integer(n), Span::new(0, 1)))
+}
+
+
+fn addition(left: Box<AstNode>, right: Box<AstNode>) -> Box<AstNode> {
+    Box::new(AstNode::new(AstNodeKind::BinaryOperation(BinaryOperationType::Add, left, right), Span::new(0, 1)))
+}
+
+
+///
+/// Build `1 + 2 + ... + n`, which parses as a chain leaning to the left.
+///
+fn flat_chain(n: i64) -> Box<AstNode> {
+    (2..=n).fold(integer(1), |chain, term| addition(chain, integer(term)))
+}
+
+
+///
+/// Build a sum of the integers from `low` to `high` as a balanced tree.
+///
+fn balanced_tree(low: i64, high: i64) -> Box<AstNode> {
+    match low == high {
+        true => integer(low),
+        false => {
+            let middle = low + (high - low) / 2;
+            addition(balanced_tree(low, middle), balanced_tree(middle + 1, high))
+        }
+    }
+}
+
+
+///
+/// Compute an expression repeatedly for at least half a second and get
+/// the average time taken.
+///
+fn measure(ast: &AstNode, expected: i64) -> Duration {
+    let (arena, root) = Arena::from_tree(ast);
+    let mut context = Context::new();
+    let mut runs = 0;
+    let start = Instant::now();
+
+    while runs == 0 || start.elapsed() < Duration::from_millis(500) {
+        let result = execute_in(black_box(&arena), root, &mut context).ok().and_then(|value| value.as_integer());
+        assert_eq!(result, Some(expected));
+        runs += 1;
+    }
+
+    start.elapsed() / runs
+}
+
+
+fn main() {
+    println!("{:>10}  {:>14}  {:>14}", "terms", "flat chain", "balanced tree");
+
+    for n in SIZES {
+        let expected = n * (n + 1) / 2;
+        let chain = measure(&flat_chain(n), expected);
+        let tree = measure(&balanced_tree(1, n), expected);
+        println!("{:>10}  {:>14?}  {:>14?}", n, chain, tree);
+    }
+}
@@ -0,0 +1,69 @@
+//!
+//! Measures lexing expressions of increasing size, up to a megabyte, such as
+//! the generated formulas of a spreadsheet export. The lexer reads its source
+//! through a byte cursor, so the time per byte stays the same as the source
+//! grows, whatever characters it holds, and tokens borrow their lexemes
+//! from it rather than copying each one.
+//!
+//! Run with `cargo bench --bench lexer`.
+//!
+
+use std::{hint::black_box, time::{Duration, Instant}};
+
+use pxpr::lexer::Lexer;
+
+
+const SIZES: [usize; 4] = [1_000, 10_000, 100_000, 1_000_000];
+
+
+///
+/// The terms the expressions are made of, which mix numbers, names, strings
+/// and operators outside of ASCII, such as `×`.
+///
+const TERMS: [&str; 6] = ["12345", "3.25 × Δt", "max(x, 7)", "\"naïve\" == s", "1.5e3", "~flags & 255"];
+
+
+///
+/// Build an expression of at least `size` bytes by joining terms with `+`.
+///
+fn expression(size: usize) -> String {
+    let mut source = String::from("0");
+    for term in TERMS.iter().cycle() {
+        if source.len() >= size {
+            break;
+        }
+        source.push_str(" + ");
+        source.push_str(term);
+    }
+    source
+}
+
+
+///
+/// Lex a source repeatedly for at least half a second and get the average
+/// time taken and the number of tokens.
+///
+fn measure(source: &str) -> (Duration, usize) {
+    let mut runs = 0;
+    let mut tokens = 0;
+    let start = Instant::now();
+
+    while runs == 0 || start.elapsed() < Duration::from_millis(500) {
+        let mut lexer = Lexer::new(black_box(source));
+        tokens = lexer.tokenize().expect("the benchmarked expressions are valid").len();
+        runs += 1;
+    }
+
+    (start.elapsed() / runs, tokens)
+}
+
+
+fn main() {
+    println!("{:>10}  {:>10}  {:>12}  {:>12}", "bytes", "tokens", "time", "per byte");
+
+    for size in SIZES {
+        let source = expression(size);
+        let (time, tokens) = measure(&source);
+        println!("{:>10}  {:>10}  {:>12?}  {:>12?}", source.len(), tokens, time, time / source.len() as u32);
+    }
+}
@@ -0,0 +1,77 @@
+use std::collections::BTreeSet;
+
+use crate::{builtins, optimize::IMPURE_FUNCTIONS, parser::{AstNode, AstNodeKind}, visit::{walk, AstVisitor}};
+
+
+///
+/// What can be told about an expression from its tree alone, without
+/// computing it, such as which variables must be bound to compute it.
+///
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Analysis {
+    /// The names the expression refers to, in alphabetical order. Builtin
+    /// constants such as `pi` are left out, since they have a value without
+    /// being bound, as are the names of functions called.
+    pub free_variables: BTreeSet<String>,
+
+    /// How many nodes the tree has, counting leaves.
+    pub node_count: usize,
+
+    /// How many nodes are on the longest path from the root to a leaf, which
+    /// is 1 for a lone literal.
+    pub max_depth: usize,
+
+    /// Whether the expression computes the same result whatever the context,
+    /// having no free variables, `?` parameters or dice rolls, and calling no
+    /// function other than builtins whose result depends only on their
+    /// arguments.
+    pub is_constant: bool
+}
+
+
+impl Analysis {
+    ///
+    /// Analyze a tree, such as to cache compiled expressions keyed on the
+    /// variables they read.
+    ///
+    pub fn of(tree: &AstNode) -> Analysis {
+        let mut analyzer = Analyzer { analysis: Analysis { is_constant: true, ..Analysis::default() }, depth: 0 };
+        walk(tree, &mut analyzer);
+        analyzer.analysis
+    }
+}
+
+
+struct Analyzer {
+    analysis: Analysis,
+
+    /// How deep the node being visited is.
+    depth: usize
+}
+
+
+impl AstVisitor for Analyzer {
+    fn enter(&mut self, node: &AstNode) -> bool {
+        self.depth += 1;
+        self.analysis.node_count += 1;
+        self.analysis.max_depth = self.analysis.max_depth.max(self.depth);
+
+        match &node.kind {
+            AstNodeKind::Identifier(name) if builtins::constant(name).is_none() => {
+                self.analysis.free_variables.insert(name.clone());
+                self.analysis.is_constant = false;
+            },
+            AstNodeKind::Parameter(_) | AstNodeKind::Dice(..) => self.analysis.is_constant = false,
+            AstNodeKind::FunctionCall(name, _) if IMPURE_FUNCTIONS.contains(&name.as_str()) || builtins::lookup(name).is_none() => {
+                self.analysis.is_constant = false;
+            },
+            _ => {},
+        }
+        true
+    }
+
+
+    fn leave(&mut self, _node: &AstNode) {
+        self.depth -= 1;
+    }
+}
@@ -0,0 +1,274 @@
+use std::ops::{Index, IndexMut};
+
+use crate::{
+    expression::Value, parser::{AstNode, AstNodeKind, BinaryOperationType, UnaryOperationType}, span::Span
+};
+
+
+///
+/// The index of a node in an `Arena`.
+///
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct NodeId(u32);
+
+
+///
+/// The children of a call or of a conditional chain, which an `Arena`
+/// stores one after another.
+///
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct NodeList {
+    start: u32,
+    length: u32
+}
+
+
+impl NodeList {
+    pub fn len(&self) -> usize {
+        self.length as usize
+    }
+
+
+    pub fn is_empty(&self) -> bool {
+        self.length == 0
+    }
+}
+
+
+///
+/// The kind of a node stored in an `Arena`, which refers to its children by
+/// their ids. The variants are those of `AstNodeKind`.
+///
+#[derive(Debug, Clone)]
+pub enum NodeKind {
+    BinaryOperation(BinaryOperationType, NodeId, NodeId),
+    UnaryOperation(UnaryOperationType, NodeId),
+    FunctionCall(String, NodeList),
+    Integer(i64),
+    Float(f64),
+    Boolean(bool),
+    String(String),
+    Dice(i64, i64),
+    Identifier(String),
+    Parameter(usize),
+    Conditional(NodeList)
+}
+
+
+impl NodeKind {
+    ///
+    /// Get the literal node which computes to a value.
+    ///
+    pub fn literal(value: &Value) -> Self {
+        match value {
+            Value::Float(x) => NodeKind::Float(*x),
+            Value::Integer(n) => NodeKind::Integer(*n),
+            Value::Boolean(b) => NodeKind::Boolean(*b),
+            Value::String(s) => NodeKind::String(s.clone()),
+        }
+    }
+}
+
+
+///
+/// A node stored in an `Arena`, along with the span of the source it was
+/// parsed from.
+///
+#[derive(Debug, Clone)]
+pub struct Node {
+    pub kind: NodeKind,
+    pub span: Span
+}
+
+
+///
+/// The nodes of syntax trees, stored in one vector rather than allocated
+/// one by one, where each node refers to its children by id. The parser
+/// reads expressions into an arena, which the evaluator, the optimizer and
+/// the bytecode compiler work on, while `AstNode` is the tree handed to and
+/// taken from library users.
+///
+/// Children are always added before the nodes holding them. An arena can
+/// hold nodes which no tree refers to any more, such as the operands the
+/// optimizer folded into a literal.
+///
+#[derive(Debug, Clone, Default)]
+pub struct Arena {
+    nodes: Vec<Node>,
+    lists: Vec<NodeId>
+}
+
+
+impl Arena {
+    pub fn new() -> Self {
+        Arena::default()
+    }
+
+
+    ///
+    /// Copy a tree into a new arena.
+    ///
+    /// # Returns
+    /// The arena, and the id of the root of the tree in it.
+    ///
+    pub fn from_tree(tree: &AstNode) -> (Arena, NodeId) {
+        let mut arena = Arena::new();
+        let root = arena.add_tree(tree);
+        (arena, root)
+    }
+
+
+    pub fn len(&self) -> usize {
+        self.nodes.len()
+    }
+
+
+    pub fn is_empty(&self) -> bool {
+        self.nodes.is_empty()
+    }
+
+
+    ///
+    /// Add a node, whose children must already be in the arena.
+    ///
+    pub fn add(&mut self, kind: NodeKind, span: Span) -> NodeId {
+        self.nodes.push(Node { kind, span });
+        NodeId(self.nodes.len() as u32 - 1)
+    }
+
+
+    ///
+    /// Store the children of a call or of a conditional chain.
+    ///
+    pub fn add_list(&mut self, ids: &[NodeId]) -> NodeList {
+        let start = self.lists.len() as u32;
+        self.lists.extend_from_slice(ids);
+        NodeList { start, length: ids.len() as u32 }
+    }
+
+
+    pub fn list(&self, list: NodeList) -> &[NodeId] {
+        &self.lists[list.start as usize..(list.start + list.length) as usize]
+    }
+
+
+    ///
+    /// Get the direct children of a node, from left to right.
+    ///
+    pub fn children(&self, id: NodeId) -> Vec<NodeId> {
+        match &self[id].kind {
+            NodeKind::BinaryOperation(_, left, right) => vec![*left, *right],
+            NodeKind::UnaryOperation(_, operand) => vec![*operand],
+            NodeKind::FunctionCall(_, list) | NodeKind::Conditional(list) => self.list(*list).to_vec(),
+            _ => vec![],
+        }
+    }
+
+
+    ///
+    /// Get every node of the arena, in the order they were added, such as
+    /// to fill in the parameters of a parsed expression.
+    ///
+    pub fn nodes_mut(&mut self) -> impl Iterator<Item = &mut Node> {
+        self.nodes.iter_mut()
+    }
+
+
+    ///
+    /// Copy a tree into the arena, children first, with a loop rather than
+    /// recursion, as in `AstNode::clone`.
+    ///
+    /// # Returns
+    /// The id of the root of the tree.
+    ///
+    pub fn add_tree(&mut self, tree: &AstNode) -> NodeId {
+        let mut pending = vec![(tree, false)];
+        let mut added: Vec<NodeId> = vec![];
+
+        while let Some((node, children_added)) = pending.pop() {
+            let children = node.children();
+            if !children_added && !children.is_empty() {
+                pending.push((node, true));
+                pending.extend(children.into_iter().rev().map(|child| (child, false)));
+                continue;
+            }
+
+            let start = added.len() - children.len();
+            let kind = match &node.kind {
+                AstNodeKind::BinaryOperation(operation_type, _, _) => NodeKind::BinaryOperation(*operation_type, added[start], added[start + 1]),
+                AstNodeKind::UnaryOperation(operation_type, _) => NodeKind::UnaryOperation(*operation_type, added[start]),
+                AstNodeKind::FunctionCall(name, _) => NodeKind::FunctionCall(name.clone(), self.add_list(&added[start..])),
+                AstNodeKind::Conditional(_) => NodeKind::Conditional(self.add_list(&added[start..])),
+                AstNodeKind::Integer(x) => NodeKind::Integer(*x),
+                AstNodeKind::Float(x) => NodeKind::Float(*x),
+                AstNodeKind::Boolean(x) => NodeKind::Boolean(*x),
+                AstNodeKind::String(x) => NodeKind::String(x.clone()),
+                AstNodeKind::Dice(count, sides) => NodeKind::Dice(*count, *sides),
+                AstNodeKind::Identifier(name) => NodeKind::Identifier(name.clone()),
+                AstNodeKind::Parameter(index) => NodeKind::Parameter(*index),
+            };
+            added.truncate(start);
+            added.push(self.add(kind, node.span));
+        }
+
+        added.pop().expect("the root is added last")
+    }
+
+
+    ///
+    /// Build the tree of a node of the arena, children first, with a loop
+    /// rather than recursion.
+    ///
+    pub fn to_tree(&self, root: NodeId) -> AstNode {
+        let mut pending = vec![(root, false)];
+        let mut built: Vec<AstNode> = vec![];
+
+        while let Some((id, children_built)) = pending.pop() {
+            let children = self.children(id);
+            if !children_built && !children.is_empty() {
+                pending.push((id, true));
+                pending.extend(children.into_iter().rev().map(|child| (child, false)));
+                continue;
+            }
+
+            let mut children = built.split_off(built.len() - children.len()).into_iter();
+            let mut child = || Box::new(children.next().expect("the children of a node are built before it"));
+            let node = &self[id];
+            let kind = match &node.kind {
+                NodeKind::BinaryOperation(operation_type, _, _) => {
+                    let left = child();
+                    AstNodeKind::BinaryOperation(*operation_type, left, child())
+                },
+                NodeKind::UnaryOperation(operation_type, _) => AstNodeKind::UnaryOperation(*operation_type, child()),
+                NodeKind::FunctionCall(name, _) => AstNodeKind::FunctionCall(name.clone(), children.collect()),
+                NodeKind::Conditional(_) => AstNodeKind::Conditional(children.collect()),
+                NodeKind::Integer(x) => AstNodeKind::Integer(*x),
+                NodeKind::Float(x) => AstNodeKind::Float(*x),
+                NodeKind::Boolean(x) => AstNodeKind::Boolean(*x),
+                NodeKind::String(x) => AstNodeKind::String(x.clone()),
+                NodeKind::Dice(count, sides) => AstNodeKind::Dice(*count, *sides),
+                NodeKind::Identifier(name) => AstNodeKind::Identifier(name.clone()),
+                NodeKind::Parameter(index) => AstNodeKind::Parameter(*index),
+            };
+            built.push(AstNode::new(kind, node.span));
+        }
+
+        built.pop().expect("the root is built last")
+    }
+}
+
+
+impl Index<NodeId> for Arena {
+    type Output = Node;
+
+    fn index(&self, id: NodeId) -> &Node {
+        &self.nodes[id.0 as usize]
+    }
+}
+
+
+impl IndexMut<NodeId> for Arena {
+    fn index_mut(&mut self, id: NodeId) -> &mut Node {
+        &mut self.nodes[id.0 as usize]
+    }
+}
@@ -0,0 +1,283 @@
+use std::{fmt, ops};
+
+use crate::{
+    self as pxpr, context::Context, expression::{execute, Value},
+    parser::{AstNode, AstNodeKind, BinaryOperationType, UnaryOperationType}, span::Span
+};
+
+
+///
+/// An expression built in code rather than parsed from a string, such as
+/// `Expr::int(3) + Expr::var("x") * 2`. Building an expression this way
+/// never goes through source, so values such as strings cannot change its
+/// structure the way they could when pasted into a string.
+///
+/// Built nodes have no source, so they all have an empty span at column 0.
+///
+#[derive(Debug, Clone)]
+pub struct Expr {
+    node: Box<AstNode>
+}
+
+
+impl Expr {
+    fn leaf(kind: AstNodeKind) -> Self {
+        Expr { node: Box::new(AstNode::new(kind, Span::new(0, 0))) }
+    }
+
+
+    fn binary(self, operation_type: BinaryOperationType, right: impl Into<Expr>) -> Self {
+        Expr::leaf(AstNodeKind::BinaryOperation(operation_type, self.node, right.into().node))
+    }
+
+
+    fn unary(self, operation_type: UnaryOperationType) -> Self {
+        Expr::leaf(AstNodeKind::UnaryOperation(operation_type, self.node))
+    }
+
+
+    pub fn int(value: i64) -> Self {
+        Expr::leaf(AstNodeKind::Integer(value))
+    }
+
+
+    pub fn float(value: f64) -> Self {
+        Expr::leaf(AstNodeKind::Float(value))
+    }
+
+
+    pub fn boolean(value: bool) -> Self {
+        Expr::leaf(AstNodeKind::Boolean(value))
+    }
+
+
+    pub fn string(value: impl Into<String>) -> Self {
+        Expr::leaf(AstNodeKind::String(value.into()))
+    }
+
+
+    ///
+    /// Create a roll of `count` dice with `sides` sides each, such as `3d6`.
+    ///
+    pub fn dice(count: i64, sides: i64) -> Self {
+        Expr::leaf(AstNodeKind::Dice(count, sides))
+    }
+
+
+    ///
+    /// Create a reference to a named value, such as the constant `pi`.
+    ///
+    pub fn var(name: impl Into<String>) -> Self {
+        Expr::leaf(AstNodeKind::Identifier(name.into()))
+    }
+
+
+    ///
+    /// Create a call to the built-in function `name`, such as `max(1, 2)`.
+    ///
+    pub fn call(name: impl Into<String>, arguments: impl IntoIterator<Item = Expr>) -> Self {
+        let arguments = arguments.into_iter().map(|argument| *argument.node).collect();
+        Expr::leaf(AstNodeKind::FunctionCall(name.into(), arguments))
+    }
+
+
+    pub fn and(self, right: impl Into<Expr>) -> Self {
+        self.binary(BinaryOperationType::And, right)
+    }
+
+
+    pub fn or(self, right: impl Into<Expr>) -> Self {
+        self.binary(BinaryOperationType::Or, right)
+    }
+
+
+    ///
+    /// Create the implication `self => right`.
+    ///
+    pub fn implies(self, right: impl Into<Expr>) -> Self {
+        self.binary(BinaryOperationType::If, right)
+    }
+
+
+    pub fn equal(self, right: impl Into<Expr>) -> Self {
+        self.binary(BinaryOperationType::Equal, right)
+    }
+
+
+    pub fn not_equal(self, right: impl Into<Expr>) -> Self {
+        self.binary(BinaryOperationType::NotEqual, right)
+    }
+
+
+    pub fn less(self, right: impl Into<Expr>) -> Self {
+        self.binary(BinaryOperationType::Less, right)
+    }
+
+
+    pub fn less_equal(self, right: impl Into<Expr>) -> Self {
+        self.binary(BinaryOperationType::LessEqual, right)
+    }
+
+
+    pub fn greater(self, right: impl Into<Expr>) -> Self {
+        self.binary(BinaryOperationType::Greater, right)
+    }
+
+
+    pub fn greater_equal(self, right: impl Into<Expr>) -> Self {
+        self.binary(BinaryOperationType::GreaterEqual, right)
+    }
+
+
+    ///
+    /// Create the smaller of `self` and `right`, written `self min right`.
+    ///
+    pub fn min(self, right: impl Into<Expr>) -> Self {
+        self.binary(BinaryOperationType::Minimum, right)
+    }
+
+
+    ///
+    /// Create the larger of `self` and `right`, written `self max right`.
+    ///
+    pub fn max(self, right: impl Into<Expr>) -> Self {
+        self.binary(BinaryOperationType::Maximum, right)
+    }
+
+
+    ///
+    /// Create the bitwise complement `~self`. The `!` operator creates the
+    /// logical negation instead.
+    ///
+    pub fn bit_not(self) -> Self {
+        self.unary(UnaryOperationType::BitwiseNot)
+    }
+
+
+    pub fn factorial(self) -> Self {
+        self.unary(UnaryOperationType::Factorial)
+    }
+
+
+    ///
+    /// Compute the value of the expression.
+    ///
+    pub fn evaluate(&self, context: &mut Context) -> Result<Value, pxpr::Error> {
+        execute(&self.node, context)
+    }
+
+
+    ///
+    /// Get the syntax tree of the expression.
+    ///
+    pub fn node(&self) -> &AstNode {
+        &self.node
+    }
+
+
+    pub fn into_node(self) -> Box<AstNode> {
+        self.node
+    }
+}
+
+
+impl From<i64> for Expr {
+    fn from(value: i64) -> Self {
+        Expr::int(value)
+    }
+}
+
+
+impl From<f64> for Expr {
+    fn from(value: f64) -> Self {
+        Expr::float(value)
+    }
+}
+
+
+impl From<bool> for Expr {
+    fn from(value: bool) -> Self {
+        Expr::boolean(value)
+    }
+}
+
+
+impl From<&str> for Expr {
+    ///
+    /// Create a string, not a reference to a name, which is created by `Expr::var`.
+    ///
+    fn from(value: &str) -> Self {
+        Expr::string(value)
+    }
+}
+
+
+impl From<Value> for Expr {
+    fn from(value: Value) -> Self {
+        Expr::leaf(value.to_literal())
+    }
+}
+
+
+impl From<Box<AstNode>> for Expr {
+    fn from(node: Box<AstNode>) -> Self {
+        Expr { node }
+    }
+}
+
+
+impl fmt::Display for Expr {
+    ///
+    /// Write the expression as source which `parse_expression` reads back
+    /// as the same expression.
+    ///
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.node)
+    }
+}
+
+
+///
+/// Implement an operator trait creating the binary operation of the same name.
+///
+macro_rules! binary_operator {
+    ($trait:ident, $method:ident, $operation_type:ident) => {
+        impl<T: Into<Expr>> ops::$trait<T> for Expr {
+            type Output = Expr;
+
+            fn $method(self, right: T) -> Expr {
+                self.binary(BinaryOperationType::$operation_type, right)
+            }
+        }
+    };
+}
+
+
+binary_operator!(Add, add, Add);
+binary_operator!(Sub, sub, Subtract);
+binary_operator!(Mul, mul, Multiply);
+binary_operator!(Div, div, Divide);
+binary_operator!(Rem, rem, Modulus);
+binary_operator!(BitAnd, bitand, BitwiseAnd);
+binary_operator!(BitOr, bitor, BitwiseOr);
+binary_operator!(BitXor, bitxor, BitwiseXor);
+binary_operator!(Shl, shl, BitwiseLeftShift);
+binary_operator!(Shr, shr, BitwiseRightShift);
+
+
+impl ops::Neg for Expr {
+    type Output = Expr;
+
+    fn neg(self) -> Expr {
+        self.unary(UnaryOperationType::ArithmeticNegate)
+    }
+}
+
+
+impl ops::Not for Expr {
+    type Output = Expr;
+
+    fn not(self) -> Expr {
+        self.unary(UnaryOperationType::LogicalNot)
+    }
+}
@@ -0,0 +1,872 @@
+use std::{cmp::Ordering, fmt, num::IntErrorKind};
+
+use crate::{
+    self as pxpr, arena::{Arena, NodeId, NodeKind}, context::{bit_mask, Context}, expression::{compute_tree, Value}, format::format_in_base, math, span::Span
+};
+
+
+///
+/// The number of arguments a built-in function accepts.
+///
+#[derive(Debug, Clone, Copy)]
+pub enum Arity {
+    Exact(usize),
+
+    /// Between the two numbers of arguments, both included.
+    Between(usize, usize),
+
+    /// At least the number of arguments, such as for `max(...)`.
+    AtLeast(usize)
+}
+
+
+impl Arity {
+    pub fn accepts(self, count: usize) -> bool {
+        match self {
+            Arity::Exact(n) => count == n,
+            Arity::Between(low, high) => (low..=high).contains(&count),
+            Arity::AtLeast(n) => count >= n,
+        }
+    }
+}
+
+
+impl fmt::Display for Arity {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Arity::Exact(n) => write!(f, "{}", n),
+            Arity::Between(low, high) => write!(f, "{} to {}", low, high),
+            Arity::AtLeast(n) => write!(f, "at least {}", n),
+        }
+    }
+}
+
+
+///
+/// How a built-in function gets the values of its arguments.
+///
+enum Function {
+    /// The arguments are computed before the function is called.
+    Eager(fn(&[Value], &mut Context) -> Result<Value, pxpr::Error>),
+
+    /// The function computes its arguments itself, so that it can leave some
+    /// of them uncomputed, such as `any` after the first true argument.
+    Lazy(fn(&Arena, &[NodeId], &mut Context) -> Result<Value, pxpr::Error>)
+}
+
+
+///
+/// A function which can be called by name from within an expression.
+///
+pub struct Builtin {
+    pub name: &'static str,
+    pub arity: Arity,
+    function: Function
+}
+
+
+impl Builtin {
+    ///
+    /// Check whether the function computes its own arguments, so that it
+    /// must be given their trees.
+    ///
+    pub(crate) fn is_lazy(&self) -> bool {
+        matches!(self.function, Function::Lazy(_))
+    }
+
+
+    fn check_arity(&self, count: usize) -> Result<(), pxpr::Error> {
+        match self.arity.accepts(count) {
+            true => Ok(()),
+            false => Err(pxpr::Error::arity(0, format!(
+                "'{}' expects {} argument(s), found {}", self.name, self.arity, count))),
+        }
+    }
+
+
+    ///
+    /// Call the function with a list of already computed arguments.
+    ///
+    pub fn call(&self, arguments: &[Value], context: &mut Context) -> Result<Value, pxpr::Error> {
+        self.check_arity(arguments.len())?;
+
+        match self.function {
+            Function::Eager(function) => function(arguments, context),
+            Function::Lazy(function) => {
+                let mut arena = Arena::new();
+                let arguments: Vec<NodeId> = arguments.iter()
+                    .map(|argument| arena.add(NodeKind::literal(argument), Span::new(0, 0)))
+                    .collect();
+                function(&arena, &arguments, context)
+            },
+        }
+    }
+
+
+    ///
+    /// Call the function with the trees of its arguments, computing them as
+    /// it needs. Errors raised by the function itself are moved to `column`.
+    ///
+    pub(crate) fn call_with_trees(&self, arena: &Arena, arguments: &[NodeId], column: u32, context: &mut Context) -> Result<Value, pxpr::Error> {
+        match self.function {
+            Function::Eager(_) => {
+                let values = arguments
+                    .iter()
+                    .map(|argument| compute_tree(arena, *argument, context))
+                    .collect::<Result<Vec<Value>, pxpr::Error>>()?;
+
+                self.call(&values, context).map_err(|error| error.at(column))
+            },
+            Function::Lazy(function) => {
+                self.check_arity(arguments.len()).map_err(|error| error.at(column))?;
+                function(arena, arguments, context)
+            },
+        }
+    }
+}
+
+
+const BUILTINS: &[Builtin] = &[
+    Builtin { name: "hex", arity: Arity::Exact(1), function: Function::Eager(builtin_hex) },
+    Builtin { name: "bin", arity: Arity::Exact(1), function: Function::Eager(builtin_bin) },
+    Builtin { name: "oct", arity: Arity::Exact(1), function: Function::Eager(builtin_oct) },
+    Builtin { name: "to_base", arity: Arity::Exact(2), function: Function::Eager(builtin_to_base) },
+    Builtin { name: "from_base", arity: Arity::Exact(2), function: Function::Eager(builtin_from_base) },
+    Builtin { name: "rotl", arity: Arity::Exact(2), function: Function::Eager(builtin_rotl) },
+    Builtin { name: "rotr", arity: Arity::Exact(2), function: Function::Eager(builtin_rotr) },
+    Builtin { name: "popcount", arity: Arity::Exact(1), function: Function::Eager(builtin_popcount) },
+    Builtin { name: "clz", arity: Arity::Exact(1), function: Function::Eager(builtin_clz) },
+    Builtin { name: "ctz", arity: Arity::Exact(1), function: Function::Eager(builtin_ctz) },
+    Builtin { name: "bit", arity: Arity::Exact(2), function: Function::Eager(builtin_bit) },
+    Builtin { name: "ncr", arity: Arity::Exact(2), function: Function::Eager(builtin_ncr) },
+    Builtin { name: "npr", arity: Arity::Exact(2), function: Function::Eager(builtin_npr) },
+    Builtin { name: "gcd", arity: Arity::Exact(2), function: Function::Eager(builtin_gcd) },
+    Builtin { name: "lcm", arity: Arity::Exact(2), function: Function::Eager(builtin_lcm) },
+    Builtin { name: "modpow", arity: Arity::Exact(3), function: Function::Eager(builtin_modpow) },
+    Builtin { name: "is_prime", arity: Arity::Exact(1), function: Function::Eager(builtin_is_prime) },
+    Builtin { name: "rand", arity: Arity::Exact(0), function: Function::Eager(builtin_rand) },
+    Builtin { name: "rand_int", arity: Arity::Exact(2), function: Function::Eager(builtin_rand_int) },
+    Builtin { name: "min", arity: Arity::AtLeast(1), function: Function::Eager(builtin_min) },
+    Builtin { name: "max", arity: Arity::AtLeast(1), function: Function::Eager(builtin_max) },
+    Builtin { name: "all", arity: Arity::AtLeast(1), function: Function::Lazy(builtin_all) },
+    Builtin { name: "any", arity: Arity::AtLeast(1), function: Function::Lazy(builtin_any) },
+    Builtin { name: "clamp", arity: Arity::Exact(3), function: Function::Eager(builtin_clamp) },
+    Builtin { name: "sign", arity: Arity::Exact(1), function: Function::Eager(builtin_sign) },
+    Builtin { name: "round", arity: Arity::Between(1, 2), function: Function::Eager(builtin_round) },
+    Builtin { name: "trunc", arity: Arity::Exact(1), function: Function::Eager(builtin_trunc) },
+    Builtin { name: "floor", arity: Arity::Exact(1), function: Function::Eager(builtin_floor) },
+    Builtin { name: "ceil", arity: Arity::Exact(1), function: Function::Eager(builtin_ceil) },
+    Builtin { name: "int", arity: Arity::Exact(1), function: Function::Eager(builtin_int) },
+    Builtin { name: "float", arity: Arity::Exact(1), function: Function::Eager(builtin_float) },
+    Builtin { name: "bool", arity: Arity::Exact(1), function: Function::Eager(builtin_bool) },
+    Builtin { name: "vars", arity: Arity::Exact(0), function: Function::Eager(builtin_vars) },
+    Builtin { name: "functions", arity: Arity::Exact(0), function: Function::Eager(builtin_functions) },
+];
+
+
+///
+/// The constants which can be used by name from within an expression.
+///
+const CONSTANTS: &[(&str, f64)] = &[
+    ("pi", std::f64::consts::PI),
+    ("tau", std::f64::consts::TAU),
+    ("e", std::f64::consts::E),
+    ("π", std::f64::consts::PI),
+    ("τ", std::f64::consts::TAU),
+];
+
+
+///
+/// Get the value of the built-in constant with the given name.
+///
+pub fn constant(name: &str) -> Option<Value> {
+    CONSTANTS.iter()
+        .find(|(constant, _)| *constant == name)
+        .map(|(_, value)| Value::Float(*value))
+}
+
+
+///
+/// Get the names bound to values, in the order they are defined.
+///
+pub fn constant_names() -> impl Iterator<Item = &'static str> {
+    CONSTANTS.iter().map(|(name, _)| *name)
+}
+
+
+///
+/// Get the names of the functions which can be called, in the order they
+/// are defined.
+///
+pub fn function_names() -> impl Iterator<Item = &'static str> {
+    BUILTINS.iter().map(|builtin| builtin.name)
+}
+
+
+///
+/// Find the built-in function with the given name.
+///
+pub fn lookup(name: &str) -> Option<&'static Builtin> {
+    BUILTINS.iter().find(|builtin| builtin.name == name)
+}
+
+
+///
+/// Get an argument as an integer. Floats without a fractional part
+/// are accepted, so that the result of arithmetic can be passed directly.
+///
+fn integer_argument(function: &str, argument: &Value) -> Result<i64, pxpr::Error> {
+    match argument.as_integral() {
+        Some(n) => Ok(n),
+        None => Err(pxpr::Error::type_mismatch(0, format!("Invalid argument for '{}': {}", function, argument))),
+    }
+}
+
+
+///
+/// Compute an argument of a lazy function which must be a boolean. An
+/// argument of the wrong type is pointed out where it starts.
+///
+fn boolean_argument(function: &str, arena: &Arena, argument: NodeId, context: &mut Context) -> Result<bool, pxpr::Error> {
+    match compute_tree(arena, argument, context)? {
+        Value::Boolean(b) => Ok(b),
+        other => Err(pxpr::Error::type_mismatch(arena[argument].span.start, format!("Invalid argument for '{}': {}", function, other))),
+    }
+}
+
+
+///
+/// Get an argument which must be an integer. Unlike `integer_argument`,
+/// floats are rejected even if they have no fractional part.
+///
+fn exact_integer_argument(function: &str, argument: &Value) -> Result<i64, pxpr::Error> {
+    match argument {
+        Value::Integer(n) => Ok(*n),
+        _ => Err(pxpr::Error::type_mismatch(0, format!(
+            "'{}' expects integer arguments, found {} {}", function, argument.type_name(), argument))),
+    }
+}
+
+
+///
+/// Get an argument which must be a number, either an integer or a float.
+///
+fn number_argument<'a>(function: &str, argument: &'a Value) -> Result<&'a Value, pxpr::Error> {
+    match argument {
+        Value::Integer(_) | Value::Float(_) => Ok(argument),
+        _ => Err(pxpr::Error::type_mismatch(0, format!("Invalid argument for '{}': {}", function, argument))),
+    }
+}
+
+
+fn string_argument<'a>(function: &str, argument: &'a Value) -> Result<&'a str, pxpr::Error> {
+    match argument.as_string() {
+        Some(s) => Ok(s),
+        None => Err(pxpr::Error::type_mismatch(0, format!("Invalid argument for '{}': {}", function, argument))),
+    }
+}
+
+
+fn base_argument(function: &str, argument: &Value) -> Result<u32, pxpr::Error> {
+    match integer_argument(function, argument)? {
+        base @ 2..=36 => Ok(base as u32),
+        base => Err(pxpr::Error::domain(0, format!("Invalid base for '{}': {} (expected 2 to 36)", function, base))),
+    }
+}
+
+
+fn builtin_hex(arguments: &[Value], _context: &mut Context) -> Result<Value, pxpr::Error> {
+    let value = integer_argument("hex", &arguments[0])?;
+    Ok(Value::String(format_in_base(value, 16, "0x")))
+}
+
+
+fn builtin_bin(arguments: &[Value], _context: &mut Context) -> Result<Value, pxpr::Error> {
+    let value = integer_argument("bin", &arguments[0])?;
+    Ok(Value::String(format_in_base(value, 2, "0b")))
+}
+
+
+fn builtin_oct(arguments: &[Value], _context: &mut Context) -> Result<Value, pxpr::Error> {
+    let value = integer_argument("oct", &arguments[0])?;
+    Ok(Value::String(format_in_base(value, 8, "0o")))
+}
+
+
+fn builtin_to_base(arguments: &[Value], _context: &mut Context) -> Result<Value, pxpr::Error> {
+    let value = integer_argument("to_base", &arguments[0])?;
+    let base = base_argument("to_base", &arguments[1])?;
+    Ok(Value::String(format_in_base(value, base, "")))
+}
+
+
+fn builtin_from_base(arguments: &[Value], _context: &mut Context) -> Result<Value, pxpr::Error> {
+    let digits = string_argument("from_base", &arguments[0])?;
+    let base = base_argument("from_base", &arguments[1])?;
+
+    match i64::from_str_radix(digits.trim(), base) {
+        Ok(value) => Ok(Value::Integer(value)),
+        Err(_) => Err(pxpr::Error::domain(0, format!("Invalid base {} number: \"{}\"", base, digits))),
+    }
+}
+
+
+///
+/// Get an argument as a bit index, which must be at least 0 and less
+/// than the number of bits integers have in the context.
+///
+fn bit_index_argument(function: &str, argument: &Value, context: &Context) -> Result<u32, pxpr::Error> {
+    let bits = context.integer_bits();
+    match integer_argument(function, argument)? {
+        index if (0..bits as i64).contains(&index) => Ok(index as u32),
+        index => Err(pxpr::Error::domain(0, format!(
+            "Invalid bit index for '{}': {} (expected 0 to {})", function, index, bits - 1))),
+    }
+}
+
+
+///
+/// Rotate the bit pattern of an integer left by `amount` bits within the
+/// bit width of the context.
+///
+fn rotate_left(value: i64, amount: i64, context: &Context) -> i64 {
+    let bits = context.integer_bits();
+    let pattern = context.bit_pattern(value);
+    let amount = amount.rem_euclid(bits as i64) as u32;
+
+    if amount == 0 {
+        return context.wrap(value);
+    }
+
+    let rotated = (pattern << amount | pattern >> (bits - amount)) & bit_mask(bits);
+    context.wrap(rotated as i64)
+}
+
+
+fn builtin_rotl(arguments: &[Value], context: &mut Context) -> Result<Value, pxpr::Error> {
+    let value = integer_argument("rotl", &arguments[0])?;
+    let amount = integer_argument("rotl", &arguments[1])?;
+    Ok(Value::Integer(rotate_left(value, amount, context)))
+}
+
+
+fn builtin_rotr(arguments: &[Value], context: &mut Context) -> Result<Value, pxpr::Error> {
+    let value = integer_argument("rotr", &arguments[0])?;
+    let amount = integer_argument("rotr", &arguments[1])?;
+    Ok(Value::Integer(rotate_left(value, -amount.rem_euclid(context.integer_bits() as i64), context)))
+}
+
+
+fn builtin_popcount(arguments: &[Value], context: &mut Context) -> Result<Value, pxpr::Error> {
+    let value = integer_argument("popcount", &arguments[0])?;
+    Ok(Value::Integer(context.bit_pattern(value).count_ones() as i64))
+}
+
+
+fn builtin_clz(arguments: &[Value], context: &mut Context) -> Result<Value, pxpr::Error> {
+    let value = integer_argument("clz", &arguments[0])?;
+    let unused_bits = u64::BITS - context.integer_bits();
+    Ok(Value::Integer((context.bit_pattern(value).leading_zeros() - unused_bits) as i64))
+}
+
+
+fn builtin_ctz(arguments: &[Value], context: &mut Context) -> Result<Value, pxpr::Error> {
+    let value = integer_argument("ctz", &arguments[0])?;
+    let trailing_zeros = context.bit_pattern(value).trailing_zeros();
+    Ok(Value::Integer(trailing_zeros.min(context.integer_bits()) as i64))
+}
+
+
+///
+/// Get the value (0 or 1) of a single bit of an integer. Example: bit(5, 2)
+///
+fn builtin_bit(arguments: &[Value], context: &mut Context) -> Result<Value, pxpr::Error> {
+    let value = integer_argument("bit", &arguments[0])?;
+    let index = bit_index_argument("bit", &arguments[1], context)?;
+    Ok(Value::Integer((context.bit_pattern(value) >> index & 1) as i64))
+}
+
+
+///
+/// Get the arguments `n` and `k` of a combinatorics function, which must
+/// both be non-negative integers.
+///
+fn combinatorics_arguments(function: &str, arguments: &[Value]) -> Result<(i64, i64), pxpr::Error> {
+    let n = integer_argument(function, &arguments[0])?;
+    let k = integer_argument(function, &arguments[1])?;
+
+    match n < 0 || k < 0 {
+        true => Err(pxpr::Error::domain(0, format!("Invalid arguments for '{}': {}, {} (expected non-negative integers)", function, n, k))),
+        false => Ok((n, k)),
+    }
+}
+
+
+///
+/// Count the ways to choose `k` of `n` items, ignoring order. Example: ncr(5, 2)
+///
+fn builtin_ncr(arguments: &[Value], _context: &mut Context) -> Result<Value, pxpr::Error> {
+    let (n, k) = combinatorics_arguments("ncr", arguments)?;
+    if k > n {
+        return Ok(Value::Integer(0));
+    }
+
+    // Each partial product is itself a binomial coefficient, so the division
+    // is always exact, and the partial products never decrease, so the
+    // computation can stop as soon as one no longer fits in an integer.
+    let smaller_k = k.min(n - k) as u128;
+    let mut result: u128 = 1;
+    for i in 1..=smaller_k {
+        result = result * (n as u128 - smaller_k + i) / i;
+        if result > i64::MAX as u128 {
+            return Err(pxpr::Error::overflow(0, format!("Integer overflow computing ncr({}, {})", n, k)));
+        }
+    }
+
+    Ok(Value::Integer(result as i64))
+}
+
+
+///
+/// Count the ways to choose `k` of `n` items in order. Example: npr(5, 2)
+///
+fn builtin_npr(arguments: &[Value], _context: &mut Context) -> Result<Value, pxpr::Error> {
+    let (n, k) = combinatorics_arguments("npr", arguments)?;
+    if k > n {
+        return Ok(Value::Integer(0));
+    }
+
+    (n - k + 1..=n)
+        .try_fold(1i64, |product, factor| product.checked_mul(factor))
+        .map(Value::Integer)
+        .ok_or_else(|| pxpr::Error::overflow(0, format!("Integer overflow computing npr({}, {})", n, k)))
+}
+
+
+fn greatest_common_divisor(mut a: u64, mut b: u64) -> u64 {
+    while b != 0 {
+        (a, b) = (b, a % b);
+    }
+    a
+}
+
+
+fn builtin_gcd(arguments: &[Value], _context: &mut Context) -> Result<Value, pxpr::Error> {
+    let a = exact_integer_argument("gcd", &arguments[0])?;
+    let b = exact_integer_argument("gcd", &arguments[1])?;
+
+    match i64::try_from(greatest_common_divisor(a.unsigned_abs(), b.unsigned_abs())) {
+        Ok(divisor) => Ok(Value::Integer(divisor)),
+        Err(_) => Err(pxpr::Error::overflow(0, format!("Integer overflow computing gcd({}, {})", a, b))),
+    }
+}
+
+
+fn builtin_lcm(arguments: &[Value], _context: &mut Context) -> Result<Value, pxpr::Error> {
+    let a = exact_integer_argument("lcm", &arguments[0])?;
+    let b = exact_integer_argument("lcm", &arguments[1])?;
+
+    if a == 0 || b == 0 {
+        return Ok(Value::Integer(0));
+    }
+
+    let divisor = greatest_common_divisor(a.unsigned_abs(), b.unsigned_abs());
+    (a.unsigned_abs() / divisor)
+        .checked_mul(b.unsigned_abs())
+        .and_then(|multiple| i64::try_from(multiple).ok())
+        .map(Value::Integer)
+        .ok_or_else(|| pxpr::Error::overflow(0, format!("Integer overflow computing lcm({}, {})", a, b)))
+}
+
+
+///
+/// Compute `base` to the power of `exponent`, modulo `modulus`, without
+/// overflowing. Example: modpow(4, 13, 497)
+///
+fn modular_power(base: u64, mut exponent: u64, modulus: u64) -> u64 {
+    let modulus = modulus as u128;
+    let mut base = base as u128 % modulus;
+    let mut result = 1 % modulus;
+
+    while exponent > 0 {
+        if exponent & 1 == 1 {
+            result = result * base % modulus;
+        }
+        base = base * base % modulus;
+        exponent >>= 1;
+    }
+
+    result as u64
+}
+
+
+fn builtin_modpow(arguments: &[Value], _context: &mut Context) -> Result<Value, pxpr::Error> {
+    let base = exact_integer_argument("modpow", &arguments[0])?;
+    let exponent = exact_integer_argument("modpow", &arguments[1])?;
+    let modulus = exact_integer_argument("modpow", &arguments[2])?;
+
+    if exponent < 0 {
+        return Err(pxpr::Error::domain(0, format!("Invalid exponent for 'modpow': {} (expected a non-negative integer)", exponent)));
+    }
+
+    if modulus <= 0 {
+        return Err(pxpr::Error::domain(0, format!("Invalid modulus for 'modpow': {} (expected a positive integer)", modulus)));
+    }
+
+    let base = base.rem_euclid(modulus) as u64;
+    Ok(Value::Integer(modular_power(base, exponent as u64, modulus as u64) as i64))
+}
+
+
+///
+/// Check whether a number is prime with the Miller-Rabin test. Testing
+/// the first twelve primes as witnesses is enough for every 64-bit number.
+///
+fn is_prime(n: u64) -> bool {
+    const WITNESSES: [u64; 12] = [2, 3, 5, 7, 11, 13, 17, 19, 23, 29, 31, 37];
+
+    if n < 2 {
+        return false;
+    }
+
+    for witness in WITNESSES {
+        if n.is_multiple_of(witness) {
+            return n == witness;
+        }
+    }
+
+    // Write n - 1 as d * 2^s, with d odd.
+    let s = (n - 1).trailing_zeros();
+    let d = (n - 1) >> s;
+
+    WITNESSES.iter().all(|&witness| {
+        let mut x = modular_power(witness, d, n);
+        if x == 1 || x == n - 1 {
+            return true;
+        }
+
+        for _ in 1..s {
+            x = (x as u128 * x as u128 % n as u128) as u64;
+            if x == n - 1 {
+                return true;
+            }
+        }
+
+        false
+    })
+}
+
+
+fn builtin_is_prime(arguments: &[Value], _context: &mut Context) -> Result<Value, pxpr::Error> {
+    let n = exact_integer_argument("is_prime", &arguments[0])?;
+    Ok(Value::Boolean(n >= 0 && is_prime(n as u64)))
+}
+
+
+///
+/// Get a random float in the range [0, 1).
+///
+fn builtin_rand(_arguments: &[Value], context: &mut Context) -> Result<Value, pxpr::Error> {
+    Ok(Value::Float(context.rng.next_f64()))
+}
+
+
+///
+/// Check whether every argument is true, computing them from left to right
+/// only until one is false. Example: all(x > 0, y > 0)
+///
+fn builtin_all(arena: &Arena, arguments: &[NodeId], context: &mut Context) -> Result<Value, pxpr::Error> {
+    for argument in arguments {
+        if !boolean_argument("all", arena, *argument, context)? {
+            return Ok(Value::Boolean(false));
+        }
+    }
+
+    Ok(Value::Boolean(true))
+}
+
+
+///
+/// Check whether any argument is true, computing them from left to right
+/// only until one is true. Example: any(x < 0, y < 0)
+///
+fn builtin_any(arena: &Arena, arguments: &[NodeId], context: &mut Context) -> Result<Value, pxpr::Error> {
+    for argument in arguments {
+        if boolean_argument("any", arena, *argument, context)? {
+            return Ok(Value::Boolean(true));
+        }
+    }
+
+    Ok(Value::Boolean(false))
+}
+
+
+///
+/// List the names bound to values, separated by commas, such as `pi, tau, e`:
+/// the constants, followed by the variables of the context.
+///
+fn builtin_vars(_arguments: &[Value], context: &mut Context) -> Result<Value, pxpr::Error> {
+    let mut names: Vec<&str> = constant_names().collect();
+    names.extend(context.variable_names());
+    Ok(Value::String(names.join(", ")))
+}
+
+
+///
+/// List the names of the functions which can be called, separated by commas:
+/// the built-in functions, followed by the functions registered in the context.
+///
+fn builtin_functions(_arguments: &[Value], context: &mut Context) -> Result<Value, pxpr::Error> {
+    let mut names: Vec<&str> = function_names().collect();
+    names.extend(context.function_names());
+    Ok(Value::String(names.join(", ")))
+}
+
+
+///
+/// Get a random integer between `low` and `high`, both included.
+///
+fn builtin_rand_int(arguments: &[Value], context: &mut Context) -> Result<Value, pxpr::Error> {
+    let low = integer_argument("rand_int", &arguments[0])?;
+    let high = integer_argument("rand_int", &arguments[1])?;
+
+    if low > high {
+        return Err(pxpr::Error::domain(0, format!("Invalid range for 'rand_int': {} is greater than {}", low, high)));
+    }
+
+    Ok(Value::Integer(context.rng.range(low, high)))
+}
+
+
+///
+/// Compare two numbers. Integers are compared exactly, while any
+/// comparison involving a float is done with floats.
+///
+fn compare_numbers(a: &Value, b: &Value) -> Ordering {
+    match (a, b) {
+        (Value::Integer(a), Value::Integer(b)) => a.cmp(b),
+        _ => a.as_float().unwrap().total_cmp(&b.as_float().unwrap()),
+    }
+}
+
+
+///
+/// Find the argument which compares as `wanted` against every other argument,
+/// keeping its type, so that `min(1, 2.5)` is the integer `1`.
+///
+fn extreme_argument(function: &str, arguments: &[Value], wanted: Ordering) -> Result<Value, pxpr::Error> {
+    let mut extreme = number_argument(function, &arguments[0])?;
+    for argument in &arguments[1..] {
+        let argument = number_argument(function, argument)?;
+        if compare_numbers(argument, extreme) == wanted {
+            extreme = argument;
+        }
+    }
+
+    Ok(extreme.clone())
+}
+
+
+fn builtin_min(arguments: &[Value], _context: &mut Context) -> Result<Value, pxpr::Error> {
+    extreme_argument("min", arguments, Ordering::Less)
+}
+
+
+fn builtin_max(arguments: &[Value], _context: &mut Context) -> Result<Value, pxpr::Error> {
+    extreme_argument("max", arguments, Ordering::Greater)
+}
+
+
+///
+/// Get the smaller of two numbers, for the operator in `a min b`.
+///
+pub fn minimum(a: &Value, b: &Value) -> Result<Value, pxpr::Error> {
+    extreme_argument("min", &[a.clone(), b.clone()], Ordering::Less)
+}
+
+
+///
+/// Get the larger of two numbers, for the operator in `a max b`.
+///
+pub fn maximum(a: &Value, b: &Value) -> Result<Value, pxpr::Error> {
+    extreme_argument("max", &[a.clone(), b.clone()], Ordering::Greater)
+}
+
+
+///
+/// Limit `x` to the range from `low` to `high`, both included.
+///
+fn builtin_clamp(arguments: &[Value], _context: &mut Context) -> Result<Value, pxpr::Error> {
+    let x = number_argument("clamp", &arguments[0])?;
+    let low = number_argument("clamp", &arguments[1])?;
+    let high = number_argument("clamp", &arguments[2])?;
+
+    if compare_numbers(low, high) == Ordering::Greater {
+        return Err(pxpr::Error::domain(0, format!("Invalid range for 'clamp': {} is greater than {}", low, high)));
+    }
+
+    match (compare_numbers(x, low), compare_numbers(x, high)) {
+        (Ordering::Less, _) => Ok(low.clone()),
+        (_, Ordering::Greater) => Ok(high.clone()),
+        _ => Ok(x.clone()),
+    }
+}
+
+
+///
+/// Get -1, 0 or 1 depending on the sign of a number.
+///
+fn builtin_sign(arguments: &[Value], _context: &mut Context) -> Result<Value, pxpr::Error> {
+    match number_argument("sign", &arguments[0])? {
+        Value::Integer(n) => Ok(Value::Integer(n.signum())),
+        Value::Float(x) if *x == 0.0 => Ok(Value::Float(0.0)),
+        Value::Float(x) => Ok(Value::Float(x.signum())),
+        _ => unreachable!(),
+    }
+}
+
+
+///
+/// Round a number to a number of decimal digits, 0 by default, with halves
+/// rounded away from zero. A negative number of digits rounds to tens,
+/// hundreds and so on, so that `round(1250, -2)` is `1300`.
+///
+fn builtin_round(arguments: &[Value], _context: &mut Context) -> Result<Value, pxpr::Error> {
+    let x = number_argument("round", &arguments[0])?;
+    let digits = match arguments.get(1) {
+        Some(digits) => exact_integer_argument("round", digits)?,
+        None => 0,
+    };
+
+    match x {
+        Value::Integer(n) if digits >= 0 => Ok(Value::Integer(*n)),
+        Value::Integer(n) => {
+            let factor = u32::try_from(-digits).ok()
+                .and_then(|exponent| 10i128.checked_pow(exponent))
+                .unwrap_or(i128::MAX);
+            let n = *n as i128;
+            let rounded = (n + n.signum() * (factor / 2)) / factor * factor;
+            i64::try_from(rounded).map(Value::Integer)
+                .map_err(|_| pxpr::Error::overflow(0, format!("Integer overflow rounding {} to {} digits", n, digits)))
+        },
+        Value::Float(x) => {
+            let factor = math::powi(10.0, digits.clamp(-308, 308) as i32);
+            match factor.is_finite() && (x * factor).is_finite() {
+                true => Ok(Value::Float((x * factor).round() / factor)),
+                false => Ok(Value::Float(*x)),
+            }
+        },
+        _ => unreachable!(),
+    }
+}
+
+
+///
+/// Drop the fractional part of a number, rounding towards zero, so that
+/// `trunc(-2.7)` is `-2.0`. Like `round`, this keeps the type of the number.
+///
+fn builtin_trunc(arguments: &[Value], _context: &mut Context) -> Result<Value, pxpr::Error> {
+    whole_number("trunc", &arguments[0], f64::trunc)
+}
+
+
+///
+/// Round a number down, so that `floor(-2.5)` is `-3.0`.
+///
+fn builtin_floor(arguments: &[Value], _context: &mut Context) -> Result<Value, pxpr::Error> {
+    whole_number("floor", &arguments[0], f64::floor)
+}
+
+
+///
+/// Round a number up, so that `ceil(2.1)` is `3.0`.
+///
+fn builtin_ceil(arguments: &[Value], _context: &mut Context) -> Result<Value, pxpr::Error> {
+    whole_number("ceil", &arguments[0], f64::ceil)
+}
+
+
+///
+/// Round a float to a whole number with `rounding`, leaving an integer as
+/// it is.
+///
+fn whole_number(function: &str, argument: &Value, rounding: fn(f64) -> f64) -> Result<Value, pxpr::Error> {
+    match number_argument(function, argument)? {
+        Value::Float(x) => Ok(Value::Float(rounding(*x))),
+        integer => Ok(integer.clone()),
+    }
+}
+
+
+///
+/// Convert a value to an integer:
+/// - A float has its fractional part dropped, so that `int(-2.7)` is `-2`;
+///   `int(round(x))`, `int(floor(x))` and `int(ceil(x))` round it otherwise.
+///   A float which is infinite or NaN has no integer, and one beyond the
+///   range of integers overflows.
+/// - `true` is 1 and `false` is 0.
+/// - A string is read as a decimal integer, such as `"-42"`, ignoring
+///   surrounding whitespace.
+///
+fn builtin_int(arguments: &[Value], _context: &mut Context) -> Result<Value, pxpr::Error> {
+    match &arguments[0] {
+        Value::Integer(n) => Ok(Value::Integer(*n)),
+        Value::Float(x) if !x.is_finite() => Err(pxpr::Error::domain(0, format!("Cannot convert {} to an integer", x))),
+        // Every float of this range, once truncated, is an integer.
+        Value::Float(x) if (i64::MIN as f64..-(i64::MIN as f64)).contains(&x.trunc()) => Ok(Value::Integer(x.trunc() as i64)),
+        Value::Float(x) => Err(pxpr::Error::overflow(0, format!("Integer overflow converting {} to an integer", x))),
+        Value::Boolean(x) => Ok(Value::Integer(i64::from(*x))),
+        Value::String(s) => match s.trim().parse::<i64>() {
+            Ok(n) => Ok(Value::Integer(n)),
+            Err(error) if matches!(error.kind(), IntErrorKind::PosOverflow | IntErrorKind::NegOverflow) => {
+                Err(pxpr::Error::overflow(0, format!("Integer overflow converting \"{}\" to an integer", s)))
+            },
+            Err(_) => Err(pxpr::Error::domain(0, format!("Cannot convert \"{}\" to an integer", s))),
+        },
+    }
+}
+
+
+///
+/// Convert a value to a float:
+/// - An integer becomes the closest float, which above 2^53 may differ.
+/// - `true` is 1.0 and `false` is 0.0.
+/// - A string is read as a finite decimal number, such as `"2.5"` or
+///   `"1e-3"`, ignoring surrounding whitespace.
+///
+fn builtin_float(arguments: &[Value], _context: &mut Context) -> Result<Value, pxpr::Error> {
+    match &arguments[0] {
+        Value::Integer(n) => Ok(Value::Float(*n as f64)),
+        Value::Float(x) => Ok(Value::Float(*x)),
+        Value::Boolean(x) => Ok(Value::Float(match x {
+            true => 1.0,
+            false => 0.0,
+        })),
+        Value::String(s) => match s.trim().parse::<f64>() {
+            Ok(x) if x.is_finite() => Ok(Value::Float(x)),
+            _ => Err(pxpr::Error::domain(0, format!("Cannot convert \"{}\" to a float", s))),
+        },
+    }
+}
+
+
+///
+/// Convert a value to a boolean:
+/// - A number is `false` when it is zero, including `-0.0`, and `true`
+///   otherwise. NaN is neither, and cannot be converted.
+/// - A string must be `"true"` or `"false"`, ignoring surrounding
+///   whitespace, so that `bool("false")` is `false`.
+///
+fn builtin_bool(arguments: &[Value], _context: &mut Context) -> Result<Value, pxpr::Error> {
+    match &arguments[0] {
+        Value::Boolean(x) => Ok(Value::Boolean(*x)),
+        Value::Integer(n) => Ok(Value::Boolean(*n != 0)),
+        Value::Float(x) if x.is_nan() => Err(pxpr::Error::domain(0, String::from("Cannot convert NaN to a boolean"))),
+        Value::Float(x) => Ok(Value::Boolean(*x != 0.0)),
+        Value::String(s) => match s.trim() {
+            "true" => Ok(Value::Boolean(true)),
+            "false" => Ok(Value::Boolean(false)),
+            _ => Err(pxpr::Error::domain(0, format!("Cannot convert \"{}\" to a boolean", s))),
+        },
+    }
+}
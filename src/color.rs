@@ -0,0 +1,141 @@
+use std::str::FromStr;
+
+use crate::lexer::{Lexer, NumberFormat, TokenType};
+
+
+///
+/// The environment variable which, when set to anything but an empty
+/// string, turns off color unless it is asked for explicitly. See
+/// https://no-color.org.
+///
+pub const NO_COLOR_VARIABLE: &str = "NO_COLOR";
+
+
+///
+/// When output is highlighted with ANSI escape codes.
+///
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum ColorChoice {
+    /// Only when writing to a terminal and `NO_COLOR` is not set.
+    #[default]
+    Auto,
+
+    Always,
+
+    Never
+}
+
+
+impl ColorChoice {
+    ///
+    /// Whether to highlight output written to a stream, given whether the
+    /// stream is a terminal.
+    ///
+    pub fn enabled(self, is_terminal: bool) -> bool {
+        match self {
+            ColorChoice::Always => true,
+            ColorChoice::Never => false,
+            ColorChoice::Auto => is_terminal && std::env::var_os(NO_COLOR_VARIABLE).is_none_or(|value| value.is_empty()),
+        }
+    }
+}
+
+
+impl FromStr for ColorChoice {
+    type Err = String;
+
+    fn from_str(choice: &str) -> Result<Self, Self::Err> {
+        match choice {
+            "auto" => Ok(ColorChoice::Auto),
+            "always" => Ok(ColorChoice::Always),
+            "never" => Ok(ColorChoice::Never),
+            _ => Err(format!("Unknown color choice: '{}' (expected 'auto', 'always' or 'never')", choice)),
+        }
+    }
+}
+
+
+///
+/// Highlight text in red, as errors are, if `colored`.
+///
+pub fn red(text: &str, colored: bool) -> String {
+    match colored {
+        true => format!("\x1b[31m{}\x1b[39m", text),
+        false => text.to_string(),
+    }
+}
+
+
+///
+/// The colors of parentheses nested ever deeper, cycling back to the first.
+///
+const RAINBOW: &[&str] = &["94", "95", "93"];
+
+
+///
+/// The ANSI color code of a token, if it is highlighted.
+///
+fn token_color(token_type: &TokenType) -> Option<&'static str> {
+    match token_type {
+        TokenType::Integer | TokenType::Float | TokenType::Dice => Some("36"),
+        TokenType::String => Some("32"),
+        TokenType::Boolean | TokenType::When | TokenType::Otherwise | TokenType::Min | TokenType::Max => Some("35"),
+        TokenType::Invalid => Some("31"),
+        TokenType::Identifier | TokenType::Eof | TokenType::LeftParen | TokenType::RightParen => None,
+        _ => Some("33"),
+    }
+}
+
+
+///
+/// Highlight an expression as it is typed: numbers, strings, keywords and
+/// operators each in their own color, matching parentheses in the same color
+/// by how deeply they are nested, and unmatched parentheses and characters
+/// which do not form a token in red.
+///
+pub fn highlight(source: &str, number_format: NumberFormat) -> String {
+    let mut lexer = Lexer::new(source).with_number_format(number_format);
+    let (tokens, _) = lexer.tokenize_recovering();
+
+    let mut colors: Vec<Option<&str>> = tokens.iter().map(|token| token_color(&token.type_)).collect();
+    let mut open = vec![];
+    for (index, token) in tokens.iter().enumerate() {
+        match token.type_ {
+            TokenType::LeftParen => {
+                colors[index] = Some(RAINBOW[open.len() % RAINBOW.len()]);
+                open.push(index);
+            },
+            TokenType::RightParen => colors[index] = match open.pop() {
+                Some(_) => Some(RAINBOW[open.len() % RAINBOW.len()]),
+                None => Some("31"),
+            },
+            _ => {},
+        }
+    }
+    for index in open {
+        colors[index] = Some("31");
+    }
+
+    // Spans count characters, and the text between tokens, such as
+    // whitespace and comments, is kept as it is.
+    let characters: Vec<char> = source.chars().collect();
+    let mut highlighted = String::new();
+    let mut position = 0;
+    for (token, color) in tokens.iter().zip(colors) {
+        let (start, end) = (token.span.start as usize, token.span.end as usize);
+        if start < position || end > characters.len() {
+            continue;
+        }
+
+        highlighted.extend(&characters[position..start]);
+        let text: String = characters[start..end].iter().collect();
+        match color {
+            Some(code) => highlighted.push_str(&format!("\x1b[{}m{}\x1b[39m", code, text)),
+            None => highlighted.push_str(&text),
+        }
+        position = end;
+    }
+    highlighted.extend(&characters[position..]);
+
+    highlighted
+}
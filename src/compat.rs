@@ -0,0 +1,83 @@
+use std::{fmt, str::FromStr};
+
+use crate::parser::Precedence;
+
+
+///
+/// The environment variable read for the default compatibility version
+/// when none is given on the command line.
+///
+pub const COMPAT_VARIABLE: &str = "PXPR_COMPAT";
+
+
+///
+/// The numeric semantics expressions are evaluated with, so that scripts
+/// written against an older version of pxpr keep working.
+///
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum Compat {
+    /// Arithmetic on integers produces integers, such as `7 - 2 = 5`.
+    #[default]
+    Current,
+
+    /// Arithmetic always produces floats, and binary operators other than
+    /// `*`, `/` and `%` bind equally from left to right, as in pxpr 0.x.
+    Version0
+}
+
+
+impl Compat {
+    ///
+    /// Whether `+`, `-`, `*`, `/` and `%` keep integers as integers.
+    ///
+    pub fn integer_arithmetic(self) -> bool {
+        self == Compat::Current
+    }
+
+
+    ///
+    /// Get the rules expressions are parsed with for which operators bind
+    /// tighter than others.
+    ///
+    pub fn precedence(self) -> Precedence {
+        match self {
+            Compat::Current => Precedence::Table,
+            Compat::Version0 => Precedence::Flat,
+        }
+    }
+
+
+    ///
+    /// Read the compatibility version from the `PXPR_COMPAT` environment
+    /// variable, if it is set.
+    ///
+    pub fn from_env() -> Result<Option<Self>, String> {
+        match std::env::var(COMPAT_VARIABLE) {
+            Ok(version) => version.parse().map(Some).map_err(|message| format!("{}: {}", COMPAT_VARIABLE, message)),
+            Err(_) => Ok(None),
+        }
+    }
+}
+
+
+impl fmt::Display for Compat {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Compat::Current => write!(f, "current"),
+            Compat::Version0 => write!(f, "0.x"),
+        }
+    }
+}
+
+
+impl FromStr for Compat {
+    type Err = String;
+
+    fn from_str(version: &str) -> Result<Self, Self::Err> {
+        match version.trim() {
+            "current" => Ok(Compat::Current),
+            "0.x" | "0" => Ok(Compat::Version0),
+            _ => Err(format!("Unknown compatibility version: '{}' (expected '0.x' or 'current')", version)),
+        }
+    }
+}
@@ -0,0 +1,144 @@
+use std::{cell::{OnceCell, Ref, RefCell}, fmt};
+
+use crate::{
+    arena::{Arena, NodeId, NodeKind}, check_compat, context::Context, expression::{execute_in, Value}, optimize::{optimize_in, optimizes}, parse_arena_for,
+    parser::AstNode, sanitize,
+    stats::{self, Stage}, suggest_on_type_error, typecheck::Type, vm::{self, Backend, Program}, Error
+};
+
+
+///
+/// An expression which is lexed and parsed once by `pxpr::compile`, and can
+/// then be computed any number of times, such as once for each row of a
+/// table with the values of the row bound to its variables.
+///
+/// Names are looked up when the expression is computed, so a variable or
+/// function only has to exist in the context it is computed with. The
+/// first time the expression is computed with the bytecode backend, its tree
+/// is optimized for that context and compiled into a program, which is kept
+/// for as long as the variables it reads hold the same types of values. The
+/// optimizer removes identities such as `x * 1` only for a number `x`, so
+/// the program is compiled again once a variable holds another type, or is
+/// bound or unbound, and once the context starts or stops tracing the
+/// bytecode, which is traced as written. The other settings of the contexts
+/// the expression is computed with are assumed to stay the same.
+///
+#[derive(Debug, Clone)]
+pub struct CompiledExpression {
+    source: String,
+    arena: Arena,
+    root: NodeId,
+    /// The names the expression reads, which may be variables.
+    names: Vec<String>,
+    tree: OnceCell<AstNode>,
+    program: RefCell<Option<CompiledProgram>>
+}
+
+
+///
+/// A program compiled for the bytecode backend, with the types of the
+/// variables it was optimized for, in the order of `CompiledExpression::names`,
+/// and whether it was optimized at all.
+///
+#[derive(Debug, Clone)]
+struct CompiledProgram {
+    types: Vec<Option<Type>>,
+    optimized: bool,
+    program: Program
+}
+
+
+impl CompiledExpression {
+    ///
+    /// Read an expression with the number format, operator precedence and
+    /// sanitizing of a context, recording any notes about its source there.
+    ///
+    pub(crate) fn new(raw_expression: &str, context: &mut Context) -> Result<Self, Error> {
+        let source = sanitize::sanitize(raw_expression, context);
+        let (arena, root) = parse_arena_for(&source, context)?;
+        if context.pedantic_compat {
+            check_compat(&source, &arena, &[root], context);
+        }
+        let names = names(&arena, root);
+        Ok(CompiledExpression { source, arena, root, names, tree: OnceCell::new(), program: RefCell::new(None) })
+    }
+
+
+    ///
+    /// Compute the expression with the variables, functions, settings and
+    /// backend of a context, which records the warnings and dice rolls like
+    /// `compute_expression` does.
+    ///
+    pub fn evaluate(&self, context: &mut Context) -> Result<Value, Error> {
+        let result = stats::measure(Stage::Evaluator, || match context.backend {
+            Backend::Tree => execute_in(&self.arena, self.root, context),
+            Backend::Vm => {
+                let types = || self.names.iter().map(|name| context.variable(name).map(Type::of));
+                let optimized = optimizes(context);
+                let stale = self.program.borrow().as_ref()
+                    .is_none_or(|compiled| compiled.optimized != optimized || !compiled.types.iter().copied().eq(types()));
+                if stale {
+                    let (arena, root) = optimize_in(&self.arena, self.root, context);
+                    let program = vm::compile_in(arena, root);
+                    *self.program.borrow_mut() = Some(CompiledProgram { types: types().collect(), optimized, program });
+                }
+
+                let compiled = self.program.borrow();
+                compiled.as_ref().expect("the program was compiled").program.run(context)
+            },
+        });
+        result.map_err(|error| suggest_on_type_error(error, &self.source, context))
+    }
+
+
+    ///
+    /// Get the source of the expression, after sanitizing.
+    ///
+    pub fn source(&self) -> &str {
+        &self.source
+    }
+
+
+    ///
+    /// Get the syntax tree the expression was parsed into, which is built
+    /// from the arena of its nodes the first time it is asked for.
+    ///
+    pub fn tree(&self) -> &AstNode {
+        self.tree.get_or_init(|| self.arena.to_tree(self.root))
+    }
+
+
+    ///
+    /// Get the program the expression was last compiled into for the
+    /// bytecode backend, once it has been computed with it.
+    ///
+    pub fn program(&self) -> Option<Ref<'_, Program>> {
+        Ref::filter_map(self.program.borrow(), |compiled| compiled.as_ref().map(|compiled| &compiled.program)).ok()
+    }
+}
+
+
+///
+/// Get the names a tree reads, in alphabetical order, without repeats.
+///
+fn names(arena: &Arena, root: NodeId) -> Vec<String> {
+    let mut names = vec![];
+    let mut pending = vec![root];
+    while let Some(id) = pending.pop() {
+        if let NodeKind::Identifier(name) = &arena[id].kind {
+            names.push(name.clone());
+        }
+        pending.extend(arena.children(id));
+    }
+
+    names.sort();
+    names.dedup();
+    names
+}
+
+
+impl fmt::Display for CompiledExpression {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.source)
+    }
+}
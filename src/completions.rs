@@ -0,0 +1,183 @@
+use std::{fmt::Write, str::FromStr};
+
+
+///
+/// A shell which `pxpr completions` writes a completion script for.
+///
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Shell {
+    Bash,
+    Zsh,
+    Fish
+}
+
+
+impl FromStr for Shell {
+    type Err = String;
+
+    fn from_str(shell: &str) -> Result<Self, Self::Err> {
+        match shell {
+            "bash" => Ok(Shell::Bash),
+            "zsh" => Ok(Shell::Zsh),
+            "fish" => Ok(Shell::Fish),
+            _ => Err(format!("Unknown shell: '{}' (expected 'bash', 'zsh' or 'fish')", shell)),
+        }
+    }
+}
+
+
+///
+/// What follows an option on the command line.
+///
+#[derive(Debug, Clone, Copy)]
+enum Argument {
+    /// Nothing; the option is a switch.
+    None,
+
+    /// A path to a file.
+    File,
+
+    /// One of a fixed set of words.
+    Choice(&'static [&'static str]),
+
+    /// Anything, such as a number, which cannot be completed.
+    Value(&'static str)
+}
+
+
+///
+/// An option of the command line, as the completion scripts offer it.
+///
+struct CompletedOption {
+    flag: &'static str,
+    argument: Argument,
+    description: &'static str
+}
+
+
+const OPTIONS: &[CompletedOption] = &[
+    CompletedOption { flag: "--file", argument: Argument::File, description: "compute a file of expressions" },
+    CompletedOption { flag: "--keep-going", argument: Argument::None, description: "keep computing a file after an expression fails" },
+    CompletedOption { flag: "--template", argument: Argument::None, description: "fill in the ${...} placeholders of standard input" },
+    CompletedOption { flag: "--each", argument: Argument::None, description: "compute each argument as a separate expression" },
+    CompletedOption { flag: "--no-cache", argument: Argument::None, description: "compute repeated expressions again" },
+    CompletedOption { flag: "--batch", argument: Argument::None, description: "compute piped lines across all processor cores" },
+    CompletedOption { flag: "--check", argument: Argument::None, description: "check the types of expressions without computing them" },
+    CompletedOption { flag: "--eval-range", argument: Argument::Value("range"), description: "compute only the subexpression covering a range of columns" },
+    CompletedOption { flag: "--no-sanitize", argument: Argument::None, description: "do not replace pasted symbols" },
+    CompletedOption { flag: "-D", argument: Argument::Value("name=value"), description: "define a variable" },
+    CompletedOption { flag: "--env", argument: Argument::Value("names"), description: "define variables from environment variables" },
+    CompletedOption { flag: "--bits", argument: Argument::Choice(&["8", "16", "32", "64"]), description: "wrap integers to a fixed bit width" },
+    CompletedOption { flag: "--overflow", argument: Argument::Value("policy"), description: "what integer overflow does" },
+    CompletedOption { flag: "--strict", argument: Argument::None, description: "turn warnings into errors" },
+    CompletedOption { flag: "--strict-types", argument: Argument::None, description: "fail on arithmetic mixing integers and floats" },
+    CompletedOption { flag: "--compat", argument: Argument::Choice(&["current", "0.x"]), description: "use the numeric semantics of an earlier version" },
+    CompletedOption { flag: "--pedantic-compat", argument: Argument::None, description: "warn where the compatibility version changes the meaning" },
+    CompletedOption { flag: "--decimal-comma", argument: Argument::None, description: "read and write ',' as the decimal separator" },
+    CompletedOption { flag: "--locale", argument: Argument::Value("locale"), description: "read and write numbers with the separators of a locale" },
+    CompletedOption { flag: "--seed", argument: Argument::Value("seed"), description: "seed the random number generator" },
+    CompletedOption { flag: "--backend", argument: Argument::Choice(&["tree", "vm"]), description: "how expressions are computed" },
+    CompletedOption { flag: "--trace-vm", argument: Argument::None, description: "print each bytecode instruction run and the stack" },
+    CompletedOption { flag: "--max-length", argument: Argument::Value("characters"), description: "fail on longer expressions" },
+    CompletedOption { flag: "--max-depth", argument: Argument::Value("levels"), description: "fail on operands nested deeper" },
+    CompletedOption { flag: "--max-steps", argument: Argument::Value("steps"), description: "fail on computations taking more steps" },
+    CompletedOption { flag: "--timeout", argument: Argument::Value("milliseconds"), description: "fail on computations taking longer" },
+    CompletedOption { flag: "--output", argument: Argument::Choice(&["plain", "color", "json", "csv"]), description: "the format of results" },
+    CompletedOption { flag: "--color", argument: Argument::Choice(&["auto", "always", "never"]), description: "when to highlight errors and warnings" },
+    CompletedOption { flag: "--prefix", argument: Argument::Choice(&["equals", "expression", "none"]), description: "what comes before each result" },
+    CompletedOption { flag: "--prog", argument: Argument::None, description: "show integers in several bases" },
+    CompletedOption { flag: "--group", argument: Argument::None, description: "group digits in thousands" },
+    CompletedOption { flag: "--show-types", argument: Argument::None, description: "show the type of each result" },
+    CompletedOption { flag: "--precision", argument: Argument::Value("digits"), description: "round floats to at most this many digits" },
+    CompletedOption { flag: "--scientific", argument: Argument::None, description: "write floats in scientific notation" },
+    CompletedOption { flag: "--engineering", argument: Argument::None, description: "write floats in engineering notation" },
+    CompletedOption { flag: "--show-rolls", argument: Argument::None, description: "show the individual dice rolled" },
+    CompletedOption { flag: "--show-bits", argument: Argument::None, description: "show the bits of integer results" },
+    CompletedOption { flag: "--profile-expr", argument: Argument::None, description: "count the operators applied and functions called" },
+    CompletedOption { flag: "--summary", argument: Argument::None, description: "show what the REPL session computed when it ends" },
+    CompletedOption { flag: "--history-size", argument: Argument::Value("lines"), description: "keep at most this many lines of REPL history" },
+    CompletedOption { flag: "--history-dups", argument: Argument::Choice(&["keep", "ignore"]), description: "whether a repeated line is kept in the REPL history" },
+    CompletedOption { flag: "--alloc-stats", argument: Argument::None, description: "show the allocations made by each stage" },
+    CompletedOption { flag: "--time", argument: Argument::None, description: "show the time each stage took" },
+    CompletedOption { flag: "--help", argument: Argument::None, description: "list the options" },
+];
+
+
+///
+/// The subcommands, which can only come first.
+///
+const SUBCOMMANDS: &[&str] = &["selftest", "import", "optimize", "latex", "to-rpn", "to-prefix", "ast", "completions"];
+
+
+///
+/// Get the script which completes the options of pxpr in a shell.
+///
+pub fn script(shell: Shell) -> String {
+    match shell {
+        Shell::Bash => bash_script(),
+        Shell::Zsh => zsh_script(),
+        Shell::Fish => fish_script(),
+    }
+}
+
+
+fn bash_script() -> String {
+    let mut script = String::from("_pxpr() {\n");
+    script.push_str("    local current=\"${COMP_WORDS[COMP_CWORD]}\" previous=\"${COMP_WORDS[COMP_CWORD-1]}\"\n");
+    script.push_str("    case \"$previous\" in\n");
+    for option in OPTIONS {
+        match option.argument {
+            Argument::None => {},
+            Argument::File => writeln!(script, "        {}) COMPREPLY=($(compgen -f -- \"$current\")); return;;", option.flag).unwrap(),
+            Argument::Choice(choices) => writeln!(script, "        {}) COMPREPLY=($(compgen -W \"{}\" -- \"$current\")); return;;",
+                option.flag, choices.join(" ")).unwrap(),
+            Argument::Value(_) => writeln!(script, "        {}) return;;", option.flag).unwrap(),
+        }
+    }
+    script.push_str("    esac\n");
+
+    let flags: Vec<&str> = OPTIONS.iter().map(|option| option.flag).collect();
+    writeln!(script, "    local words=\"{}\"", flags.join(" ")).unwrap();
+    writeln!(script, "    [[ $COMP_CWORD -eq 1 ]] && words=\"$words {}\"", SUBCOMMANDS.join(" ")).unwrap();
+    script.push_str("    COMPREPLY=($(compgen -W \"$words\" -- \"$current\"))\n");
+    script.push_str("}\n");
+    script.push_str("complete -F _pxpr pxpr\n");
+    script
+}
+
+
+fn zsh_script() -> String {
+    let mut script = String::from("#compdef pxpr\n\n_arguments \\\n");
+    for option in OPTIONS {
+        let argument = match option.argument {
+            Argument::None => String::new(),
+            Argument::File => String::from(":file:_files"),
+            Argument::Choice(choices) => format!(":value:({})", choices.join(" ")),
+            Argument::Value(name) => format!(":{}: ", name),
+        };
+        writeln!(script, "    '{}[{}]{}' \\", option.flag, option.description.replace('\'', "'\\''"), argument).unwrap();
+    }
+    writeln!(script, "    '1::command:({})' \\", SUBCOMMANDS.join(" ")).unwrap();
+    script.push_str("    '*::expression: '\n");
+    script
+}
+
+
+fn fish_script() -> String {
+    let mut script = String::new();
+    writeln!(script, "complete -c pxpr -n __fish_use_subcommand -x -a '{}'", SUBCOMMANDS.join(" ")).unwrap();
+    for option in OPTIONS {
+        let name = match option.flag.strip_prefix("--") {
+            Some(long) => format!("-l {}", long),
+            None => format!("-s {}", &option.flag[1..]),
+        };
+        let argument = match option.argument {
+            Argument::None => String::new(),
+            Argument::File => String::from(" -r -F"),
+            Argument::Choice(choices) => format!(" -x -a '{}'", choices.join(" ")),
+            Argument::Value(_) => String::from(" -x"),
+        };
+        writeln!(script, "complete -c pxpr {} -d '{}'{}", name, option.description.replace('\'', "\\'"), argument).unwrap();
+    }
+    script
+}
@@ -0,0 +1,417 @@
+use std::{collections::BTreeMap, fmt, rc::Rc};
+
+use crate::{
+    self as pxpr, builtins::Arity, compat::Compat, expression::Value, lexer::NumberFormat, limits::{Budget, Limits}, overflow::{OperationClass, Overflow, OverflowPolicy}, random::Rng, stats::Profile, vm::Backend,
+    Warning
+};
+
+
+///
+/// The settings and state an expression is evaluated with.
+///
+#[derive(Debug, Default)]
+pub struct Context {
+    /// The number of bits integers are wrapped to by bitwise operations,
+    /// or `None` to use signed 64-bit integers.
+    pub bit_width: Option<u32>,
+
+    /// What each class of integer operations does when it overflows.
+    pub overflow: OverflowPolicy,
+
+    /// Whether warnings are treated as errors.
+    pub strict: bool,
+
+    /// Whether arithmetic mixing an integer and a float is an error, rather
+    /// than converting the integer to a float, so that every conversion is
+    /// written with `float()` or `int()`.
+    pub strict_types: bool,
+
+    /// The numeric semantics and operator precedence to evaluate with.
+    pub compat: Compat,
+
+    /// Whether to warn about expressions whose meaning differs under the
+    /// other compatibility version.
+    pub pedantic_compat: bool,
+
+    /// How numbers are written in expressions.
+    pub number_format: NumberFormat,
+
+    /// Whether typographic characters, such as the en dash of formulas pasted
+    /// from documents, are replaced with the ASCII operators they stand for.
+    /// Enabled by `Context::new`.
+    pub sanitize: bool,
+
+    /// The source of random numbers, seeded from the current time
+    /// unless a seed is given.
+    pub rng: Rng,
+
+    /// The operators and functions used, counted only when a profile is given.
+    pub profile: Option<Profile>,
+
+    /// How the trees of expressions are computed.
+    pub backend: Backend,
+
+    /// The instructions run by the bytecode backend, each with the stack it
+    /// leaves, recorded only when a trace is given.
+    pub vm_trace: Option<Vec<String>>,
+
+    /// The limits on the length, nesting, steps and time of expressions,
+    /// all unset unless given.
+    pub limits: Limits,
+
+    variables: BTreeMap<String, Value>,
+    functions: BTreeMap<String, RegisteredFunction>,
+    warnings: Vec<Warning>,
+    rolls: Vec<Roll>,
+    budget: Budget
+}
+
+
+///
+/// The signature of a function registered by `Context::register_fn`.
+///
+pub type NativeFunction = dyn Fn(&[Value]) -> Result<Value, pxpr::Error>;
+
+
+///
+/// A function written in Rust by a program embedding pxpr, which expressions
+/// call by name like a built-in function.
+///
+#[derive(Clone)]
+pub struct RegisteredFunction {
+    pub arity: Arity,
+    function: Rc<NativeFunction>
+}
+
+
+impl RegisteredFunction {
+    ///
+    /// Call the function with a list of computed arguments, once their
+    /// number is checked against its arity.
+    ///
+    pub fn call(&self, name: &str, arguments: &[Value]) -> Result<Value, pxpr::Error> {
+        match self.arity.accepts(arguments.len()) {
+            true => (self.function)(arguments),
+            false => Err(pxpr::Error::arity(0, format!(
+                "'{}' expects {} argument(s), found {}", name, self.arity, arguments.len()))),
+        }
+    }
+}
+
+
+impl fmt::Debug for RegisteredFunction {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("RegisteredFunction").field("arity", &self.arity).finish_non_exhaustive()
+    }
+}
+
+
+///
+/// The variables and functions of a context at one moment, taken by
+/// `Context::snapshot` so that `Context::rollback` can undo the changes made
+/// after it.
+///
+#[derive(Debug, Clone)]
+pub struct Snapshot {
+    variables: BTreeMap<String, Value>,
+    functions: BTreeMap<String, RegisteredFunction>
+}
+
+
+///
+/// The individual results of rolling dice, such as the three dice of `3d6`.
+///
+#[derive(Debug, Clone)]
+pub struct Roll {
+    pub count: i64,
+    pub sides: i64,
+    pub results: Vec<i64>
+}
+
+
+impl Context {
+    pub fn new() -> Self {
+        Context { sanitize: true, ..Context::default() }
+    }
+
+
+    ///
+    /// Create a context with the same settings, variables, functions and random
+    /// number generator state, but without the warnings, rolls and profile recorded in this
+    /// one, for computing an expression without affecting this context.
+    ///
+    pub fn fork(&self) -> Self {
+        Context {
+            bit_width: self.bit_width,
+            overflow: self.overflow,
+            strict: self.strict,
+            strict_types: self.strict_types,
+            compat: self.compat,
+            pedantic_compat: self.pedantic_compat,
+            number_format: self.number_format,
+            sanitize: self.sanitize,
+            backend: self.backend,
+            limits: self.limits,
+            rng: self.rng.clone(),
+            variables: self.variables.clone(),
+            functions: self.functions.clone(),
+            ..Context::default()
+        }
+    }
+
+
+    ///
+    /// Wrap an integer to the bit width of the context, so that for example
+    /// `~0` is `255` when using 8 bits.
+    ///
+    pub fn wrap(&self, value: i64) -> i64 {
+        match self.bit_width {
+            Some(bits) => (value as u64 & bit_mask(bits)) as i64,
+            None => value,
+        }
+    }
+
+
+    ///
+    /// Get what a class of integer operations does when it overflows.
+    ///
+    pub fn overflow_policy(&self, class: OperationClass) -> Overflow {
+        self.overflow.get(class, self.bit_width)
+    }
+
+
+    ///
+    /// Get the number of bits integers have in this context.
+    ///
+    pub fn integer_bits(&self) -> u32 {
+        self.bit_width.unwrap_or(i64::BITS)
+    }
+
+
+    ///
+    /// Get the bit pattern of an integer in this context, with every bit
+    /// above the bit width cleared.
+    ///
+    pub fn bit_pattern(&self, value: i64) -> u64 {
+        value as u64 & bit_mask(self.integer_bits())
+    }
+
+
+    ///
+    /// Bind a value to a name, which expressions can then use in place of
+    /// the value. A variable hides a constant of the same name.
+    ///
+    pub fn define(&mut self, name: impl Into<String>, value: Value) {
+        self.variables.insert(name.into(), value);
+    }
+
+
+    ///
+    /// Get the value bound to a name by `define`.
+    ///
+    pub fn variable(&self, name: &str) -> Option<&Value> {
+        self.variables.get(name)
+    }
+
+
+    ///
+    /// Bind a value to a name like `define`, and get the value it was bound
+    /// to before, if any.
+    ///
+    pub fn set(&mut self, name: impl Into<String>, value: Value) -> Option<Value> {
+        self.variables.insert(name.into(), value)
+    }
+
+
+    ///
+    /// Remove the value bound to a name, so that a constant of the same name
+    /// is no longer hidden, and get the value.
+    ///
+    pub fn unset(&mut self, name: &str) -> Option<Value> {
+        self.variables.remove(name)
+    }
+
+
+    ///
+    /// Get the value bound to a name if it is an integer.
+    ///
+    pub fn get_integer(&self, name: &str) -> Option<i64> {
+        self.variable(name).and_then(Value::as_integer)
+    }
+
+
+    ///
+    /// Get the value bound to a name as a float, if it is a number.
+    ///
+    pub fn get_float(&self, name: &str) -> Option<f64> {
+        self.variable(name).and_then(Value::as_float)
+    }
+
+
+    ///
+    /// Get the value bound to a name if it is a boolean.
+    ///
+    pub fn get_boolean(&self, name: &str) -> Option<bool> {
+        self.variable(name).and_then(Value::as_boolean)
+    }
+
+
+    ///
+    /// Get the value bound to a name if it is a string.
+    ///
+    pub fn get_string(&self, name: &str) -> Option<&str> {
+        self.variable(name).and_then(Value::as_string)
+    }
+
+
+    ///
+    /// Register a function written in Rust, which expressions can then call
+    /// by name, such as `volume(2, 3, 4)`. The number of arguments is checked
+    /// against `arity` before the function is called, and any error it
+    /// returns is reported at the call. A registered function hides a
+    /// built-in function of the same name.
+    ///
+    pub fn register_fn<F>(&mut self, name: impl Into<String>, arity: Arity, function: F)
+    where
+        F: Fn(&[Value]) -> Result<Value, pxpr::Error> + 'static
+    {
+        self.functions.insert(name.into(), RegisteredFunction { arity, function: Rc::new(function) });
+    }
+
+
+    ///
+    /// Remove a function registered by `register_fn`, and check whether there was one.
+    ///
+    pub fn unregister_fn(&mut self, name: &str) -> bool {
+        self.functions.remove(name).is_some()
+    }
+
+
+    ///
+    /// Get the function registered under a name by `register_fn`.
+    ///
+    pub fn function(&self, name: &str) -> Option<&RegisteredFunction> {
+        self.functions.get(name)
+    }
+
+
+    ///
+    /// Get the names registered by `register_fn`, in alphabetical order.
+    ///
+    pub fn function_names(&self) -> impl Iterator<Item = &str> {
+        self.functions.keys().map(String::as_str)
+    }
+
+
+    ///
+    /// Take a snapshot of the variables and functions, such as before trying
+    /// out a change which may have to be undone.
+    ///
+    pub fn snapshot(&self) -> Snapshot {
+        Snapshot { variables: self.variables.clone(), functions: self.functions.clone() }
+    }
+
+
+    ///
+    /// Restore the variables and functions to a snapshot, undoing every
+    /// binding made or removed since it was taken. The settings are left as
+    /// they are.
+    ///
+    pub fn rollback(&mut self, snapshot: Snapshot) {
+        self.variables = snapshot.variables;
+        self.functions = snapshot.functions;
+    }
+
+
+    ///
+    /// Get the names bound by `define`, in alphabetical order.
+    ///
+    pub fn variable_names(&self) -> impl Iterator<Item = &str> {
+        self.variables.keys().map(String::as_str)
+    }
+
+
+    ///
+    /// Record a warning, or in strict mode, fail with it as an error.
+    ///
+    pub fn warn(&mut self, warning: Warning) -> Result<(), pxpr::Error> {
+        match self.strict {
+            true => Err(warning.into()),
+            false => {
+                self.warnings.push(warning);
+                Ok(())
+            }
+        }
+    }
+
+
+    ///
+    /// Record an informational warning, which unlike other warnings is
+    /// never treated as an error.
+    ///
+    pub fn note(&mut self, warning: Warning) {
+        self.warnings.push(warning);
+    }
+
+
+    ///
+    /// Get the warnings recorded since they were last taken.
+    ///
+    pub fn take_warnings(&mut self) -> Vec<Warning> {
+        std::mem::take(&mut self.warnings)
+    }
+
+
+    ///
+    /// Start counting the steps and time of computing a tree against the
+    /// limits of the context.
+    ///
+    pub(crate) fn start_budget(&mut self) {
+        self.budget = Budget::start(&self.limits);
+    }
+
+
+    ///
+    /// Count a step of computing a tree, failing once the computation takes
+    /// more steps or time than the limits allow.
+    ///
+    pub(crate) fn spend(&mut self) -> Result<(), pxpr::Error> {
+        self.budget.spend(&self.limits)
+    }
+
+
+    ///
+    /// Record the results of rolling dice.
+    ///
+    pub fn record_roll(&mut self, roll: Roll) {
+        self.rolls.push(roll);
+    }
+
+
+    ///
+    /// Get the dice rolled since the rolls were last taken.
+    ///
+    pub fn take_rolls(&mut self) -> Vec<Roll> {
+        std::mem::take(&mut self.rolls)
+    }
+
+
+    ///
+    /// Get the instructions traced since the trace was last taken, leaving
+    /// the trace kept if it is.
+    ///
+    pub fn take_vm_trace(&mut self) -> Vec<String> {
+        self.vm_trace.as_mut().map(std::mem::take).unwrap_or_default()
+    }
+}
+
+
+///
+/// Get a mask of the lowest `bits` bits.
+///
+pub fn bit_mask(bits: u32) -> u64 {
+    match bits >= u64::BITS {
+        true => u64::MAX,
+        false => (1 << bits) - 1,
+    }
+}
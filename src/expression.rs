@@ -1,36 +1,63 @@
-use core::fmt;
+use core::{cmp::Ordering, fmt};
 
-use crate::{parser::{AstNode, BinaryOperationType, UnaryOperationType}, pxpr};
+use crate::{self as pxpr, arena::{Arena, NodeId, NodeKind}, builtins, context::{Context, Roll}, lexer, format::{DefaultFormatter, ValueFormatter}, math, overflow::{OperationClass, Overflow}, Warning, parser::{AstNode, AstNodeKind, BinaryOperationType, UnaryOperationType}};
 
 
-#[derive(Debug)]
+///
+/// The most dice which can be rolled at once, such as by `1000d6`.
+///
+const MAX_DICE: i64 = 10_000;
+
+
+#[derive(Debug, Clone)]
 pub enum Value {
     Float(f64),
     Integer(i64),
-    Boolean(bool)
+    Boolean(bool),
+    String(String)
 }
 
 
 impl fmt::Display for Value {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        match self {
-            Value::Integer(n) => write!(f, "{}", n),
-            Value::Float(n) => write!(f, "{}", n),
-            Value::Boolean(b) => write!(f, "{}", b),
-        }
+        write!(f, "{}", DefaultFormatter::default().format(self))
     }
 }
 
 
 impl Value {
-    fn as_integer(&self) -> Option<i64> {
+    ///
+    /// Get the name of the type of the value, as shown to users.
+    /// 
+    pub fn type_name(&self) -> &'static str {
+        match self {
+            Value::Float(_) => "float",
+            Value::Integer(_) => "integer",
+            Value::Boolean(_) => "boolean",
+            Value::String(_) => "string",
+        }
+    }
+
+    ///
+    /// Get the literal node which computes to the value.
+    /// 
+    pub fn to_literal(&self) -> AstNodeKind {
+        match self {
+            Value::Float(x) => AstNodeKind::Float(*x),
+            Value::Integer(n) => AstNodeKind::Integer(*n),
+            Value::Boolean(b) => AstNodeKind::Boolean(*b),
+            Value::String(s) => AstNodeKind::String(s.clone()),
+        }
+    }
+
+    pub fn as_integer(&self) -> Option<i64> {
         match self {
             Value::Integer(n) => Some(*n),
             _ => None,
         }
     }
 
-    fn as_float(&self) -> Option<f64> {
+    pub fn as_float(&self) -> Option<f64> {
         match self {
             Value::Float(n) => Some(*n),
             Value::Integer(n) => Some(*n as f64),
@@ -38,67 +65,459 @@ impl Value {
         }
     }
 
-    fn as_boolean(&self) -> Option<bool> {
+    ///
+    /// Get the value as an integer if it is an integer, or a float without
+    /// a fractional part, so that the result of arithmetic can be used
+    /// where an integer is expected.
+    /// 
+    pub fn as_integral(&self) -> Option<i64> {
+        match self {
+            Value::Integer(n) => Some(*n),
+            Value::Float(x) if x.fract() == 0.0 && x.abs() < i64::MAX as f64 => Some(*x as i64),
+            _ => None,
+        }
+    }
+
+    pub fn as_boolean(&self) -> Option<bool> {
         match self {
             Value::Boolean(b) => Some(*b),
             _ => None,
         }
     }
+
+    pub fn as_string(&self) -> Option<&str> {
+        match self {
+            Value::String(s) => Some(s),
+            _ => None,
+        }
+    }
 }
 
 
-pub fn execute(expression: &Box<AstNode>) -> Result<Value, pxpr::Error> {
-    let root_node = expression;
-    let current_node = root_node.as_ref();
+///
+/// A step of computing a tree, kept on a stack by `execute`.
+/// 
+enum Step<'a> {
+    /// Compute a node and push its value.
+    Compute(NodeId),
+
+    /// Apply a unary operation to the value on top of the stack, locating
+    /// its errors at the column.
+    Unary(UnaryOperationType, u32),
 
-    match current_node {
-        AstNode::BinaryOperation(
-                        operation_type, 
-                        left, 
-                        right
-            ) => compute_binary(operation_type, left, right),
-        AstNode::UnaryOperation(
-                operation_type, 
-                operand
-            ) => compute_unary(operation_type, operand),
+    /// Apply a binary operation to the two values on top of the stack, given
+    /// the columns of its left and right operands.
+    Binary(BinaryOperationType, u32, u32),
 
-        AstNode::Integer(x) => Ok(Value::Integer(*x)),
-        AstNode::Boolean(x) => Ok(Value::Boolean(*x)),
-        AstNode::Float(x) => Ok(Value::Float(*x)),
+    /// Call a function with the given number of arguments on top of the stack.
+    Call(&'a str, usize, u32),
+
+    /// Pop the guard of the case of a conditional chain starting at this
+    /// index of its parts, and compute the value of the case if it holds,
+    /// or else go on to the next case.
+    Choose(&'a [NodeId], usize)
+}
+
+
+///
+/// Computes the value of a tree, after copying it into an arena.
+/// 
+pub fn execute(expression: &AstNode, context: &mut Context) -> Result<Value, pxpr::Error> {
+    let (arena, root) = Arena::from_tree(expression);
+    execute_in(&arena, root, context)
+}
+
+
+///
+/// Computes the value of the tree of a node of an arena. The steps left to
+/// take are kept on a stack of their own, with the values computed so far on
+/// another, rather than recursing once per node, so that trees nested a
+/// hundred thousand levels deep, such as `((((1))))` or `----1`, cannot
+/// overflow the stack. Only a function computing its own arguments, such as
+/// `any`, computes each of them by calling `compute_tree`.
+/// 
+/// Each step counts towards the step limit and timeout of the context.
+/// 
+pub fn execute_in(arena: &Arena, root: NodeId, context: &mut Context) -> Result<Value, pxpr::Error> {
+    context.start_budget();
+    compute_tree(arena, root, context)
+}
+
+
+///
+/// Computes a tree like `execute_in`, as part of a computation which has
+/// already started, such as an argument of `any`, so that its steps count
+/// towards the limits of that computation.
+/// 
+pub(crate) fn compute_tree(arena: &Arena, root: NodeId, context: &mut Context) -> Result<Value, pxpr::Error> {
+    let mut steps = vec![Step::Compute(root)];
+    let mut values: Vec<Value> = vec![];
+
+    while let Some(step) = steps.pop() {
+        context.spend()?;
+        match step {
+            Step::Compute(id) => match compute_leaf(arena, id, context)? {
+                Some(value) => values.push(value),
+                None => schedule(arena, id, &mut steps, context)?,
+            },
+            Step::Unary(operation_type, column) => {
+                let operand = pop(&mut values);
+                values.push(apply_unary(&operation_type, operand, column, context)?);
+            },
+            Step::Binary(operation_type, left_column, right_column) => {
+                let right_side = pop(&mut values);
+                let left_side = pop(&mut values);
+                values.push(apply_binary(&operation_type, left_side, left_column, right_side, right_column, context)?);
+            },
+            Step::Call(name, count, column) => {
+                let arguments = values.split_off(values.len() - count);
+                values.push(call_function(name, &arguments, column, context)?);
+            },
+            Step::Choose(parts, index) => {
+                let guard = &arena[parts[index + 1]];
+                match pop(&mut values) {
+                    Value::Boolean(true) => steps.push(Step::Compute(parts[index])),
+                    Value::Boolean(false) => match parts.get(index + 3) {
+                        Some(next_guard) => steps.extend([Step::Choose(parts, index + 2), Step::Compute(*next_guard)]),
+                        None => steps.push(Step::Compute(parts[index + 2])),
+                    },
+                    other => return Err(pxpr::Error::type_mismatch(guard.span.start, 
+                        format!("Expected a boolean guard after 'when', found {}", other))),
+                }
+            },
+        }
     }
+
+    Ok(pop(&mut values))
 }
 
 
 ///
-/// Computes the result of a unary operation.
+/// Computes a node without children, or a call to a function which computes
+/// its own arguments.
 /// 
-fn compute_unary(operation_type: &UnaryOperationType, operand: &Box<AstNode>) -> Result<Value, pxpr::Error> {
-    let operand_value = execute(operand)?;
-    match operation_type {
-        UnaryOperationType::ArithmeticNegate => compute_arithmetic_negation(operand_value),
-        UnaryOperationType::LogicalNot => compute_logical_not(operand_value),
-        UnaryOperationType::BitwiseNot => compute_bitwise_not(operand_value)
+/// # Returns
+/// The value of the node, or `None` if it has children to compute first.
+/// 
+fn compute_leaf(arena: &Arena, id: NodeId, context: &mut Context) -> Result<Option<Value>, pxpr::Error> {
+    let node = &arena[id];
+    let column = node.span.start;
+    let value = match &node.kind {
+        NodeKind::Integer(x) => Value::Integer(*x),
+        NodeKind::Boolean(x) => Value::Boolean(*x),
+        NodeKind::Float(x) => Value::Float(*x),
+        NodeKind::String(x) => Value::String(x.clone()),
+        NodeKind::Dice(count, sides) => compute_dice(*count, *sides, context)
+            .map_err(|error| error.at(column))?,
+        NodeKind::Parameter(index) => return Err(pxpr::Error::usage(column, 
+            format!("No value given for parameter {}", index + 1))),
+        NodeKind::Identifier(name) => compute_identifier(name, context)
+            .map_err(|error| error.at(column))?,
+
+        // A function registered in the context hides a built-in function of
+        // the same name.
+        NodeKind::FunctionCall(name, arguments) => match builtins::lookup(name) {
+            Some(builtin) if builtin.is_lazy() && context.function(name).is_none() => {
+                check_function(name, column, context)?;
+                builtin.call_with_trees(arena, arena.list(*arguments), column, context)?
+            },
+            _ => return Ok(None),
+        },
+        _ => return Ok(None),
+    };
+
+    Ok(Some(value))
+}
+
+
+///
+/// Pushes the steps computing a node with children, in the reverse of the
+/// order they are taken.
+/// 
+/// Flat chains such as `1 + 2 + 3 + 4` parse as `((1 + 2) + 3) + 4`, so the
+/// left side of a binary operation is walked to its first operand, and the
+/// chain folded from left to right. The guards of a conditional chain are
+/// computed one case at a time, so that only the guards up to the case whose
+/// guard holds and its value are computed.
+/// 
+fn schedule<'a>(arena: &'a Arena, id: NodeId, steps: &mut Vec<Step<'a>>, context: &mut Context) -> Result<(), pxpr::Error> {
+    let node = &arena[id];
+    match &node.kind {
+        NodeKind::BinaryOperation(..) => {
+            let mut chain = vec![];
+            let mut first = id;
+            while let NodeKind::BinaryOperation(operation_type, left, right) = arena[first].kind {
+                chain.push((operation_type, right));
+                first = left;
+            }
+
+            let first_column = arena[first].span.start;
+            for (operation_type, right) in chain {
+                steps.extend([Step::Binary(operation_type, first_column, arena[right].span.start), Step::Compute(right)]);
+            }
+            steps.push(Step::Compute(first));
+        },
+        NodeKind::UnaryOperation(operation_type, operand) => {
+            steps.extend([Step::Unary(*operation_type, node.span.start), Step::Compute(*operand)]);
+        },
+        NodeKind::FunctionCall(name, arguments) => {
+            check_function(name, node.span.start, context)?;
+            steps.push(Step::Call(name, arguments.len(), node.span.start));
+            steps.extend(arena.list(*arguments).iter().rev().copied().map(Step::Compute));
+        },
+        NodeKind::Conditional(parts) => {
+            let parts = arena.list(*parts);
+            steps.extend([Step::Choose(parts, 0), Step::Compute(parts[1])]);
+        },
+        _ => unreachable!("leaves are computed by compute_leaf"),
+    }
+
+    Ok(())
+}
+
+
+///
+/// Pops a value computed by an earlier step, which is always pushed before
+/// the step needing it.
+/// 
+fn pop(values: &mut Vec<Value>) -> Value {
+    values.pop().expect("the values a step needs are computed before it")
+}
+
+
+///
+/// Computes the value of a name, which is a variable of the context or
+/// a constant such as `pi`. Names are case sensitive, so a keyword or
+/// constant written in another case, such as `True` or `PI`, is pointed
+/// out in the error.
+/// 
+pub(crate) fn compute_identifier(name: &str, context: &Context) -> Result<Value, pxpr::Error> {
+    if let Some(value) = context.variable(name) {
+        return Ok(value.clone());
+    }
+
+    if let Some(value) = builtins::constant(name) {
+        return Ok(value);
+    }
+
+    let lowercase = name.to_lowercase();
+    let miscased = lowercase != name && (lexer::keyword(&lowercase).is_some() || builtins::constant(&lowercase).is_some());
+    match miscased {
+        true => Err(pxpr::Error::unknown_name(0, format!("Unknown name: '{}' (names are case sensitive; did you mean '{}'?)", name, lowercase))),
+        false => Err(pxpr::Error::unknown_name(0, format!("Unknown name: '{}'", name))),
+    }
+}
+
+
+///
+/// Check that a function exists before its arguments are computed, and
+/// count the call in the profile.
+/// 
+pub(crate) fn check_function(name: &str, column: u32, context: &mut Context) -> Result<(), pxpr::Error> {
+    if context.function(name).is_none() && builtins::lookup(name).is_none() {
+        return Err(pxpr::Error::unknown_name(column, format!("Unknown function: '{}'", name)));
+    }
+
+    if let Some(profile) = &mut context.profile {
+        profile.count_call(name);
     }
+
+    Ok(())
 }
 
 
-fn compute_bitwise_not(operand: Value) -> Result<Value, pxpr::Error> {
+///
+/// Call a function registered in the context, or else a built-in function,
+/// with its computed arguments.
+/// 
+pub(crate) fn call_function(name: &str, arguments: &[Value], column: u32, context: &mut Context) -> Result<Value, pxpr::Error> {
+    let result = match (context.function(name).cloned(), builtins::lookup(name)) {
+        (Some(function), _) => function.call(name, arguments),
+        (None, Some(builtin)) => builtin.call(arguments, context),
+        (None, None) => Err(pxpr::Error::unknown_name(0, format!("Unknown function: '{}'", name))),
+    };
+
+    result.map_err(|error| error.at(column))
+}
+
+
+///
+/// Applies a unary operation to its computed operand. Errors raised by the
+/// operation are located at `column`, its start.
+/// 
+pub(crate) fn apply_unary(
+    operation_type: &UnaryOperationType,
+    operand_value: Value,
+    column: u32,
+    context: &mut Context
+) -> Result<Value, pxpr::Error> {
+    if let Some(profile) = &mut context.profile {
+        profile.count_operator(operation_type.symbol());
+    }
+
+    let result = match operation_type {
+        UnaryOperationType::ArithmeticNegate => compute_arithmetic_negation(operand_value, context),
+        UnaryOperationType::LogicalNot => compute_logical_not(operand_value),
+        UnaryOperationType::BitwiseNot => compute_bitwise_not(operand_value, context),
+        UnaryOperationType::Factorial => compute_factorial(operand_value)
+    };
+
+    result.map_err(|error| error.at(column))
+}
+
+
+///
+/// Computes the bitwise complement of an integer, wrapped to the bit
+/// width of the context. Example: ~0
+/// 
+fn compute_bitwise_not(operand: Value, context: &Context) -> Result<Value, pxpr::Error> {
     match operand.as_integer() {
-        Some(x) => Ok(Value::Integer(!x)),
-        None => Err(pxpr::Error::new(0, format!("Invalid operand for '~': {}", operand))),
+        Some(x) => Ok(Value::Integer(context.wrap(!x))),
+        None => Err(pxpr::Error::type_mismatch(0, format!("Invalid operand for '~': {}", operand))),
     }
 }
 
 
-fn compute_binary(
+///
+/// Computes the factorial of a non-negative integer. Example: 5!
+/// 
+fn compute_factorial(operand: Value) -> Result<Value, pxpr::Error> {
+    match operand.as_integral() {
+        Some(n) if n >= 0 => (1..=n)
+            .try_fold(1i64, |product, factor| product.checked_mul(factor))
+            .map(Value::Integer)
+            .ok_or_else(|| pxpr::Error::overflow(0, format!("Integer overflow computing {}!", n))),
+        _ => Err(pxpr::Error::type_mismatch(0, format!("Invalid operand for '!': {}", operand))),
+    }
+}
+
+
+///
+/// With strict types, fail on an arithmetic operation mixing an integer and
+/// a float, which would otherwise convert the integer to a float.
+/// 
+fn check_mixed_types(
+    left_side: &Value,
+    right_side: &Value,
     operation_type: &BinaryOperationType,
-    left: &Box<AstNode>, 
-    right: &Box<AstNode>
-) -> Result<Value, pxpr::Error> {
-    let left_side = execute(left)?;
-    let right_side = execute(right)?;
+    context: &Context
+) -> Result<(), pxpr::Error> {
+    match (left_side, right_side) {
+        (Value::Integer(_), Value::Float(_)) | (Value::Float(_), Value::Integer(_)) if context.strict_types => {
+            Err(pxpr::Error::type_mismatch(0, format!(
+                "Cannot mix {} {} and {} {} in '{}' with strict types (convert one with float() or int())",
+                left_side.type_name(), left_side, right_side.type_name(), right_side, operation_type.symbol())))
+        },
+        _ => Ok(()),
+    }
+}
+
 
+///
+/// Warn when an integer operand of an arithmetic operation cannot be
+/// converted to a float exactly, which happens to integers above 2^53.
+/// 
+fn check_float_precision(
+    operand: &Value, 
+    column: u32, 
+    operation_type: &BinaryOperationType, 
+    context: &mut Context
+) -> Result<(), pxpr::Error> {
+    match operand {
+        Value::Integer(n) if (*n as f64) as i128 != *n as i128 => context.warn(Warning::new(
+            column,
+            format!("{} loses precision when converted to a float for '{}' (it becomes {})", 
+                n, operation_type.symbol(), *n as f64)
+        )),
+        _ => Ok(()),
+    }
+}
+
+
+///
+/// Get the operands of an arithmetic operation when it should be computed
+/// with integers: both operands are integers, the compatibility version
+/// keeps integers as integers, and for `/` the division is exact.
+/// Otherwise the operation is computed with floats.
+///
+fn integer_operands(
+    operation_type: &BinaryOperationType,
+    left_side: &Value,
+    right_side: &Value,
+    context: &Context
+) -> Option<(i64, i64)> {
+    if !context.compat.integer_arithmetic() {
+        return None;
+    }
+
+    let (a, b) = (left_side.as_integer()?, right_side.as_integer()?);
     match operation_type {
+        BinaryOperationType::Divide if b == 0 || a.wrapping_rem(b) != 0 => None,
+        BinaryOperationType::Modulus if b == 0 => None,
+        _ => Some((a, b)),
+    }
+}
+
+
+///
+/// Computes an arithmetic operation on two integers. What happens when the
+/// result overflows 64 bits depends on the overflow policy of the context,
+/// and with a bit width the result is then wrapped to it.
+///
+fn compute_integer_arithmetic(
+    operation_type: &BinaryOperationType,
+    a: i64,
+    b: i64,
+    context: &Context
+) -> Result<Value, pxpr::Error> {
+    // Every result of two 64-bit integers fits in 128 bits.
+    let (a, b) = (a as i128, b as i128);
+    let (exact, class) = match operation_type {
+        BinaryOperationType::Add => (a + b, OperationClass::Add),
+        BinaryOperationType::Subtract => (a - b, OperationClass::Add),
+        BinaryOperationType::Multiply => (a * b, OperationClass::Multiply),
+        BinaryOperationType::Divide => (a / b, OperationClass::Multiply),
+        BinaryOperationType::Modulus => (a % b, OperationClass::Multiply),
+        _ => unreachable!("'{}' is not an arithmetic operation", operation_type.symbol()),
+    };
+
+    match context.overflow_policy(class).apply(exact) {
+        Some(Value::Integer(result)) => Ok(Value::Integer(context.wrap(result))),
+        Some(result) => Ok(result),
+        None => Err(pxpr::Error::overflow(0, 
+            format!("Integer overflow computing {} {} {}", a, operation_type.symbol(), b))),
+    }
+}
+
+
+///
+/// Applies a binary operation to its computed operands. The columns of the
+/// operands are used to locate warnings, while errors are located at the
+/// start of the operation, which is the start of its left operand.
+/// 
+pub(crate) fn apply_binary(
+    operation_type: &BinaryOperationType,
+    left_side: Value,
+    left_column: u32,
+    right_side: Value,
+    right_column: u32,
+    context: &mut Context
+) -> Result<Value, pxpr::Error> {
+    if let Some(profile) = &mut context.profile {
+        profile.count_operator(operation_type.symbol());
+    }
+
+    if operation_type.is_arithmetic() {
+        if let Some((a, b)) = integer_operands(operation_type, &left_side, &right_side, context) {
+            return compute_integer_arithmetic(operation_type, a, b, context).map_err(|error| error.at(left_column));
+        }
+
+        check_mixed_types(&left_side, &right_side, operation_type, context).map_err(|error| error.at(left_column))?;
+        check_float_precision(&left_side, left_column, operation_type, context)?;
+        check_float_precision(&right_side, right_column, operation_type, context)?;
+    }
+
+    let result = match operation_type {
         BinaryOperationType::Add => compute_addition(&left_side, &right_side),
         BinaryOperationType::Subtract => compute_subtraction(&left_side, &right_side),
         BinaryOperationType::Multiply => compute_multiplication(&left_side, &right_side),
@@ -107,24 +526,64 @@ fn compute_binary(
         BinaryOperationType::And => compute_conjunction(&left_side, &right_side),
         BinaryOperationType::Or => compute_disjunction(&left_side, &right_side),
         BinaryOperationType::If => compute_implication(&left_side, &right_side),
-        BinaryOperationType::Equal => todo!(),
-        BinaryOperationType::NotEqual => todo!(),
-        BinaryOperationType::BitwiseAnd => todo!(),
-        BinaryOperationType::BitwiseOr => todo!(),
-        BinaryOperationType::BitwiseXor => todo!(),
-        BinaryOperationType::BitwiseLeftShift => todo!(),
-        BinaryOperationType::BitwiseRightShift => todo!(),
+        BinaryOperationType::Equal | BinaryOperationType::NotEqual |
+        BinaryOperationType::Less | BinaryOperationType::LessEqual |
+        BinaryOperationType::Greater | BinaryOperationType::GreaterEqual 
+            => compute_comparison(operation_type, &left_side, &right_side),
+        BinaryOperationType::BitwiseAnd => compute_bitwise_and(&left_side, &right_side, context),
+        BinaryOperationType::BitwiseOr => compute_bitwise_or(&left_side, &right_side, context),
+        BinaryOperationType::BitwiseXor => compute_bitwise_xor(&left_side, &right_side, context),
+        BinaryOperationType::BitwiseLeftShift => compute_left_shift(&left_side, &right_side, context),
+        BinaryOperationType::BitwiseRightShift => compute_right_shift(&left_side, &right_side, context),
+        BinaryOperationType::Minimum => builtins::minimum(&left_side, &right_side),
+        BinaryOperationType::Maximum => builtins::maximum(&left_side, &right_side),
+    };
+
+    result.map_err(|error| error.at(left_column))
+}
+
+
+///
+/// Rolls `count` dice with `sides` sides each using the context's random
+/// number generator, and computes their total. Example: 3d6
+/// 
+pub(crate) fn compute_dice(count: i64, sides: i64, context: &mut Context) -> Result<Value, pxpr::Error> {
+    if !(1..=MAX_DICE).contains(&count) || sides < 1 {
+        return Err(pxpr::Error::domain(0, 
+            format!("Invalid dice '{}d{}': expected 1 to {} dice with at least 1 side", count, sides, MAX_DICE)));
     }
+
+    if let Some(profile) = &mut context.profile {
+        profile.count_operator("dice");
+    }
+
+    let results: Vec<i64> = (0..count).map(|_| context.rng.range(1, sides)).collect();
+    let total = results.iter().try_fold(0i64, |total, result| total.checked_add(*result))
+        .ok_or_else(|| pxpr::Error::overflow(0, format!("Integer overflow computing {}d{}", count, sides)))?;
+
+    context.record_roll(Roll { count, sides, results });
+    Ok(Value::Integer(total))
 }
 
 
 ///
 /// Computes negation of a number. Example: -2
 /// 
-fn compute_arithmetic_negation(operand: Value) -> Result<Value, pxpr::Error> {
+/// Negating the smallest integer overflows, which is handled by the
+/// overflow policy for `+` and `-` as for subtracting it from 0.
+/// 
+fn compute_arithmetic_negation(operand: Value, context: &Context) -> Result<Value, pxpr::Error> {
+    if let Some(x) = operand.as_integer() {
+        return match context.overflow_policy(OperationClass::Add).apply(-(x as i128)) {
+            Some(Value::Integer(result)) => Ok(Value::Integer(context.wrap(result))),
+            Some(result) => Ok(result),
+            None => Err(pxpr::Error::overflow(0, format!("Integer overflow computing -({})", x))),
+        };
+    }
+
     match operand.as_float() {
         Some(x) => Ok(Value::Float(-x)),
-        None => Err(pxpr::Error::new(0, format!("Invalid operand for '-': {}", operand))),
+        None => Err(pxpr::Error::type_mismatch(0, format!("Invalid operand for '-': {}", operand))),
     }
 }
 
@@ -135,7 +594,7 @@ fn compute_arithmetic_negation(operand: Value) -> Result<Value, pxpr::Error> {
 fn compute_logical_not(operand: Value) -> Result<Value, pxpr::Error> {
     match operand.as_boolean() {
         Some(b) => Ok(Value::Boolean(!b)),
-        None => Err(pxpr::Error::new(0, format!("Invalid operand for '!': {}", operand))),
+        None => Err(pxpr::Error::type_mismatch(0, format!("Invalid operand for '!': {}", operand))),
     }
 }
 
@@ -146,9 +605,9 @@ fn compute_addition(left_side: &Value, right_side: &Value) -> Result<Value, pxpr
             => Ok(Value::Float(left + right)),
 
         (None, Some(_)) 
-            => Err(pxpr::Error::new(0, format!("Invalid left operand for '+': {}", left_side))),
+            => Err(pxpr::Error::type_mismatch(0, format!("Invalid left operand for '+': {}", left_side))),
             
-        _ => Err(pxpr::Error::new(0, format!("Invalid right operand for '+': {}", left_side))),
+        _ => Err(pxpr::Error::type_mismatch(0, format!("Invalid right operand for '+': {}", right_side))),
     }
 }
 
@@ -159,9 +618,9 @@ fn compute_subtraction(left_side: &Value, right_side: &Value) -> Result<Value, p
             => Ok(Value::Float(left - right)),
 
         (None, Some(_))
-             => Err(pxpr::Error::new(0, format!("Invalid left operand for '-': {}", left_side))),
+             => Err(pxpr::Error::type_mismatch(0, format!("Invalid left operand for '-': {}", left_side))),
 
-        _ => Err(pxpr::Error::new(0, format!("Invalid right operand for '-': {}", left_side))),
+        _ => Err(pxpr::Error::type_mismatch(0, format!("Invalid right operand for '-': {}", right_side))),
     }
 }
 
@@ -172,9 +631,9 @@ fn compute_multiplication(left_side: &Value, right_side: &Value) -> Result<Value
              => Ok(Value::Float(left * right)),
 
         (None, Some(_)) 
-            => Err(pxpr::Error::new(0, format!("Invalid left operand for '*': {}", left_side))),
+            => Err(pxpr::Error::type_mismatch(0, format!("Invalid left operand for '*': {}", left_side))),
 
-        _ => Err(pxpr::Error::new(0, format!("Invalid right operand for '*': {}", left_side))),
+        _ => Err(pxpr::Error::type_mismatch(0, format!("Invalid right operand for '*': {}", right_side))),
     }
 }
 
@@ -182,15 +641,15 @@ fn compute_multiplication(left_side: &Value, right_side: &Value) -> Result<Value
 fn compute_division(left_side: &Value, right_side: &Value) -> Result<Value, pxpr::Error> {
     match (left_side.as_float(), right_side.as_float()) {
         (Some(_), Some(0.0)) 
-            => Err(pxpr::Error::new(0, String::from("Division by 0"))),
+            => Err(pxpr::Error::division_by_zero(0)),
 
         (Some(left), Some(right)) 
             => Ok(Value::Float(left / right)),
 
         (None, Some(_)) => 
-            Err(pxpr::Error::new(0, format!("Invalid left operand for '/': {}", left_side))),
+            Err(pxpr::Error::type_mismatch(0, format!("Invalid left operand for '/': {}", left_side))),
 
-        _ => Err(pxpr::Error::new(0, format!("Invalid right operand for '/': {}", left_side))),
+        _ => Err(pxpr::Error::type_mismatch(0, format!("Invalid right operand for '/': {}", right_side))),
     }
 }
 
@@ -198,28 +657,64 @@ fn compute_division(left_side: &Value, right_side: &Value) -> Result<Value, pxpr
 fn compute_modulus(left_side: &Value, right_side: &Value) -> Result<Value, pxpr::Error> {
     match (left_side.as_float(), right_side.as_float()) {
         (Some(_), Some(0.0)) 
-            => Err(pxpr::Error::new(0, String::from("Division by 0"))),
+            => Err(pxpr::Error::division_by_zero(0)),
 
         (Some(left), Some(right)) 
             => Ok(Value::Float(left % right)),
 
         (None, Some(_)) 
-            => Err(pxpr::Error::new(0, format!("Invalid left operand for '%': {}", left_side))),
+            => Err(pxpr::Error::type_mismatch(0, format!("Invalid left operand for '%': {}", left_side))),
         
-        _ => Err(pxpr::Error::new(0, format!("Invalid right operand for '%': {}", left_side))),
+        _ => Err(pxpr::Error::type_mismatch(0, format!("Invalid right operand for '%': {}", right_side))),
     }
 }
 
 
+///
+/// Computes a comparison. Numbers are compared by value, so that `1 == 1.0`,
+/// while booleans and strings can only be tested for equality with values
+/// of the same type. Example: 1 < 2
+/// 
+fn compute_comparison(
+    operation_type: &BinaryOperationType, 
+    left_side: &Value, 
+    right_side: &Value
+) -> Result<Value, pxpr::Error> {
+    let is_equality = matches!(operation_type, BinaryOperationType::Equal | BinaryOperationType::NotEqual);
+
+    let ordering = match (left_side, right_side) {
+        (Value::Integer(a), Value::Integer(b)) => Some(a.cmp(b)),
+        (Value::Integer(_) | Value::Float(_), Value::Integer(_) | Value::Float(_)) 
+            => left_side.as_float().partial_cmp(&right_side.as_float()),
+        (Value::Boolean(a), Value::Boolean(b)) if is_equality => Some(a.cmp(b)),
+        (Value::String(a), Value::String(b)) if is_equality => Some(a.cmp(b)),
+        _ => return Err(pxpr::Error::type_mismatch(0, format!("Cannot compare {} {} with {} {} using '{}'", 
+                left_side.type_name(), left_side, right_side.type_name(), right_side, operation_type.symbol()))),
+    };
+
+    let result = match operation_type {
+        BinaryOperationType::Equal => ordering == Some(Ordering::Equal),
+        BinaryOperationType::NotEqual => ordering != Some(Ordering::Equal),
+        BinaryOperationType::Less => ordering == Some(Ordering::Less),
+        BinaryOperationType::LessEqual => matches!(ordering, Some(Ordering::Less | Ordering::Equal)),
+        BinaryOperationType::Greater => ordering == Some(Ordering::Greater),
+        BinaryOperationType::GreaterEqual => matches!(ordering, Some(Ordering::Greater | Ordering::Equal)),
+        _ => unreachable!("'{}' is not a comparison", operation_type.symbol()),
+    };
+
+    Ok(Value::Boolean(result))
+}
+
+
 fn compute_conjunction(left_side: &Value, right_side: &Value) -> Result<Value, pxpr::Error> {
     match (left_side.as_boolean(), right_side.as_boolean()) {
         (Some(left), Some(right)) 
             => Ok(Value::Boolean(left && right)),
 
         (None, Some(_))
-             => Err(pxpr::Error::new(0, format!("Invalid left operand for '&&': {}", left_side))),
+             => Err(pxpr::Error::type_mismatch(0, format!("Invalid left operand for '&&': {}", left_side))),
 
-        _ => Err(pxpr::Error::new(0, format!("Invalid right operand for '&&': {}", left_side))),
+        _ => Err(pxpr::Error::type_mismatch(0, format!("Invalid right operand for '&&': {}", right_side))),
     }
 }
 
@@ -230,9 +725,9 @@ fn compute_disjunction(left_side: &Value, right_side: &Value) -> Result<Value, p
             => Ok(Value::Boolean(left || right)),
 
         (None, Some(_))
-             => Err(pxpr::Error::new(0, format!("Invalid left operand for '||': {}", left_side))),
+             => Err(pxpr::Error::type_mismatch(0, format!("Invalid left operand for '||': {}", left_side))),
 
-        _ => Err(pxpr::Error::new(0, format!("Invalid right operand for '||': {}", left_side))),
+        _ => Err(pxpr::Error::type_mismatch(0, format!("Invalid right operand for '||': {}", right_side))),
     }
 }
 
@@ -243,10 +738,117 @@ fn compute_implication(left_side: &Value, right_side: &Value) -> Result<Value, p
             => Ok(Value::Boolean(!left || right)),
 
         (None, Some(_))
-             => Err(pxpr::Error::new(0, format!("Invalid left operand for '=>': {}", left_side))),
+             => Err(pxpr::Error::type_mismatch(0, format!("Invalid left operand for '=>': {}", left_side))),
+
+        _ => Err(pxpr::Error::type_mismatch(0, format!("Invalid right operand for '=>': {}", right_side))),
+    }
+}
+
+
+fn compute_bitwise_and(left_side: &Value, right_side: &Value, context: &Context) -> Result<Value, pxpr::Error> {
+    match (left_side.as_integer(), right_side.as_integer()) {
+        (Some(left), Some(right)) 
+            => Ok(Value::Integer(context.wrap(left & right))),
+
+        (None, Some(_))
+             => Err(pxpr::Error::type_mismatch(0, format!("Invalid left operand for '&': {}", left_side))),
+
+        _ => Err(pxpr::Error::type_mismatch(0, format!("Invalid right operand for '&': {}", right_side))),
+    }
+}
+
+
+fn compute_bitwise_or(left_side: &Value, right_side: &Value, context: &Context) -> Result<Value, pxpr::Error> {
+    match (left_side.as_integer(), right_side.as_integer()) {
+        (Some(left), Some(right)) 
+            => Ok(Value::Integer(context.wrap(left | right))),
+
+        (None, Some(_))
+             => Err(pxpr::Error::type_mismatch(0, format!("Invalid left operand for '|': {}", left_side))),
 
-        _ => Err(pxpr::Error::new(0, format!("Invalid right operand for '=>': {}", left_side))),
+        _ => Err(pxpr::Error::type_mismatch(0, format!("Invalid right operand for '|': {}", right_side))),
     }
 }
 
 
+fn compute_bitwise_xor(left_side: &Value, right_side: &Value, context: &Context) -> Result<Value, pxpr::Error> {
+    match (left_side.as_integer(), right_side.as_integer()) {
+        (Some(left), Some(right)) 
+            => Ok(Value::Integer(context.wrap(left ^ right))),
+
+        (None, Some(_))
+             => Err(pxpr::Error::type_mismatch(0, format!("Invalid left operand for '^': {}", left_side))),
+
+        _ => Err(pxpr::Error::type_mismatch(0, format!("Invalid right operand for '^': {}", right_side))),
+    }
+}
+
+
+///
+/// Computes a left shift. By default bits shifted past the bit width of the
+/// context are discarded, while other overflow policies apply to results
+/// which do not fit in 64 bits. Example: 1 << 4
+/// 
+fn compute_left_shift(left_side: &Value, right_side: &Value, context: &Context) -> Result<Value, pxpr::Error> {
+    let overflow = context.overflow_policy(OperationClass::Shift);
+    match (left_side.as_integer(), right_side.as_integer()) {
+        (Some(_), Some(amount)) if amount < 0
+            => Err(pxpr::Error::domain(0, format!("Invalid shift amount for '<<': {}", amount))),
+
+        (Some(_), Some(amount)) if amount >= i64::BITS as i64 && overflow == Overflow::Wrap
+            => Ok(Value::Integer(0)),
+
+        (Some(left), Some(amount)) if overflow == Overflow::Wrap
+            => Ok(Value::Integer(context.wrap(((left as u64) << amount) as i64))),
+
+        (Some(left), Some(amount)) if amount >= i64::BITS as i64 && overflow == Overflow::Promote
+            => Ok(Value::Float(left as f64 * math::pow(2.0, amount as f64))),
+
+        (Some(left), Some(amount)) => {
+            // Shifting by 64 or more overflows unless there is nothing to shift.
+            let exact = match amount < i64::BITS as i64 {
+                true => (left as i128) << amount,
+                false => left.signum() as i128 * i128::MAX,
+            };
+
+            match overflow.apply(exact) {
+                Some(Value::Integer(result)) => Ok(Value::Integer(context.wrap(result))),
+                Some(result) => Ok(result),
+                None => Err(pxpr::Error::overflow(0, format!("Integer overflow computing {} << {}", left, amount))),
+            }
+        },
+
+        (None, Some(_))
+             => Err(pxpr::Error::type_mismatch(0, format!("Invalid left operand for '<<': {}", left_side))),
+
+        _ => Err(pxpr::Error::type_mismatch(0, format!("Invalid right operand for '<<': {}", right_side))),
+    }
+}
+
+
+///
+/// Computes a right shift. The shift is arithmetic for signed 64-bit
+/// integers, and logical when the context has a bit width. Example: 16 >> 2
+/// 
+fn compute_right_shift(left_side: &Value, right_side: &Value, context: &Context) -> Result<Value, pxpr::Error> {
+    match (left_side.as_integer(), right_side.as_integer()) {
+        (Some(_), Some(amount)) if amount < 0
+            => Err(pxpr::Error::domain(0, format!("Invalid shift amount for '>>': {}", amount))),
+
+        (Some(_), Some(amount)) if amount >= i64::BITS as i64 && context.bit_width.is_some()
+            => Ok(Value::Integer(0)),
+
+        (Some(left), Some(amount)) => {
+            let amount = amount.min(i64::BITS as i64 - 1);
+            match context.bit_width {
+                Some(_) => Ok(Value::Integer(((context.wrap(left) as u64) >> amount) as i64)),
+                None => Ok(Value::Integer(left >> amount)),
+            }
+        },
+
+        (None, Some(_))
+             => Err(pxpr::Error::type_mismatch(0, format!("Invalid left operand for '>>': {}", left_side))),
+
+        _ => Err(pxpr::Error::type_mismatch(0, format!("Invalid right operand for '>>': {}", right_side))),
+    }
+}
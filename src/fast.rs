@@ -0,0 +1,214 @@
+use crate::{compat::Compat, context::Context, expression::Value, lexer::NumberFormat, limits::Limits, overflow::OperationClass, vm::Backend};
+
+
+///
+/// The most tokens an expression may have to be computed by the fast path.
+///
+const MAX_TOKENS: usize = 16;
+
+
+///
+/// Compute a tiny expression of number literals, `+`, `-`, `*`, `/`, `%`,
+/// negation and parentheses while reading it, without lexing it into tokens
+/// or building a tree, which is most of the cost of the expressions typed on
+/// the command line, such as `2+2`.
+///
+/// The fast path only computes what the general path would compute the same
+/// way, with no warning or error: it gives up on anything else, such as an
+/// overflow, a division by zero, a name, or an expression longer than
+/// `MAX_TOKENS` tokens, so that the general path reports it. The self-test
+/// checks that both paths agree.
+///
+/// # Returns
+/// The value of the expression, or `None` if it must be computed by the
+/// general path.
+///
+pub(crate) fn compute(source: &str, context: &Context) -> Option<Value> {
+    // Settings which change how expressions are read or computed, or which
+    // record more than the value, are left to the general path.
+    let plain = context.compat == Compat::Current && context.bit_width.is_none() && !context.pedantic_compat && !context.strict_types
+        && context.profile.is_none() && context.number_format == NumberFormat::default() && context.backend == Backend::Tree
+        && context.limits == Limits::default();
+    if !plain || !source.is_ascii() {
+        return None;
+    }
+
+    let mut reader = Reader { source: source.as_bytes(), position: 0, tokens: 0, context };
+    let value = reader.sum()?;
+    match reader.peek() {
+        None => Some(value),
+        Some(_) => None,
+    }
+}
+
+
+///
+/// Reads an expression by recursive descent, computing each operation as
+/// soon as both of its operands are read.
+///
+struct Reader<'a> {
+    source: &'a [u8],
+    position: usize,
+    tokens: usize,
+    context: &'a Context
+}
+
+
+impl Reader<'_> {
+    ///
+    /// Get the next character which is not whitespace, without consuming it.
+    ///
+    fn peek(&mut self) -> Option<u8> {
+        while self.source.get(self.position).is_some_and(|ch| *ch == b' ' || *ch == b'\t') {
+            self.position += 1;
+        }
+        self.source.get(self.position).copied()
+    }
+
+
+    ///
+    /// Consume the next character if it is one of `characters`, counting it
+    /// as a token, unless the expression then has too many tokens.
+    ///
+    fn take(&mut self, characters: &[u8]) -> Option<u8> {
+        let ch = self.peek().filter(|ch| characters.contains(ch))?;
+        self.position += 1;
+        self.tokens += 1;
+        (self.tokens <= MAX_TOKENS).then_some(ch)
+    }
+
+
+    ///
+    /// Read `+` and `-` operations, which bind loosest.
+    ///
+    fn sum(&mut self) -> Option<Value> {
+        let mut left = self.product()?;
+        while let Some(operator) = self.take(b"+-") {
+            let right = self.product()?;
+            left = self.apply(operator, left, right)?;
+        }
+        Some(left)
+    }
+
+
+    ///
+    /// Read `*`, `/` and `%` operations.
+    ///
+    fn product(&mut self) -> Option<Value> {
+        let mut left = self.negation()?;
+        while let Some(operator) = self.take(b"*/%") {
+            let right = self.negation()?;
+            left = self.apply(operator, left, right)?;
+        }
+        Some(left)
+    }
+
+
+    ///
+    /// Read an operand, negated by any number of `-`.
+    ///
+    fn negation(&mut self) -> Option<Value> {
+        match self.take(b"-") {
+            Some(_) => match self.negation()? {
+                // Negating the smallest integer overflows, which is left
+                // to the evaluator and its overflow policy.
+                Value::Integer(x) => x.checked_neg().map(Value::Integer),
+                Value::Float(x) => Some(Value::Float(-x)),
+                _ => None,
+            },
+            None => self.primary(),
+        }
+    }
+
+
+    ///
+    /// Read a number or an expression in parentheses.
+    ///
+    fn primary(&mut self) -> Option<Value> {
+        if self.take(b"(").is_some() {
+            let value = self.sum()?;
+            self.take(b")")?;
+            return Some(value);
+        }
+
+        self.peek()?;
+        let start = self.position;
+        self.digits()?;
+        let is_integer = self.source.get(self.position) != Some(&b'.');
+        if !is_integer {
+            self.position += 1;
+            self.digits()?;
+        }
+
+        self.tokens += 1;
+        if self.tokens > MAX_TOKENS {
+            return None;
+        }
+
+        // The text is ASCII, so any slice of it is a string.
+        let text = std::str::from_utf8(&self.source[start..self.position]).ok()?;
+        match is_integer {
+            true => text.parse().ok().map(Value::Integer),
+            false => text.parse().ok().map(Value::Float),
+        }
+    }
+
+
+    ///
+    /// Consume a run of at least one digit.
+    ///
+    fn digits(&mut self) -> Option<()> {
+        let start = self.position;
+        while self.source.get(self.position).is_some_and(u8::is_ascii_digit) {
+            self.position += 1;
+        }
+        (self.position > start).then_some(())
+    }
+
+
+    ///
+    /// Apply an arithmetic operator as `expression::apply_binary` does, on
+    /// integers while both operands are integers and a division is exact,
+    /// and otherwise on floats.
+    ///
+    fn apply(&self, operator: u8, left: Value, right: Value) -> Option<Value> {
+        if let (Value::Integer(a), Value::Integer(b)) = (&left, &right) {
+            let (a, b) = (*a as i128, *b as i128);
+            let (exact, class) = match operator {
+                b'+' => (Some(a + b), OperationClass::Add),
+                b'-' => (Some(a - b), OperationClass::Add),
+                b'*' => (Some(a * b), OperationClass::Multiply),
+                b'/' if b != 0 && a % b == 0 => (Some(a / b), OperationClass::Multiply),
+                b'%' if b != 0 => (Some(a % b), OperationClass::Multiply),
+                _ => (None, OperationClass::Multiply),
+            };
+
+            if let Some(exact) = exact {
+                return self.context.overflow_policy(class).apply(exact);
+            }
+        }
+
+        let (a, b) = (exact_float(&left)?, exact_float(&right)?);
+        match operator {
+            b'+' => Some(Value::Float(a + b)),
+            b'-' => Some(Value::Float(a - b)),
+            b'*' => Some(Value::Float(a * b)),
+            b'/' if b != 0.0 => Some(Value::Float(a / b)),
+            b'%' if b != 0.0 => Some(Value::Float(a % b)),
+            _ => None,
+        }
+    }
+}
+
+
+///
+/// Get a number as a float, or `None` for an integer which a float cannot
+/// hold exactly, which the general path warns about.
+///
+fn exact_float(value: &Value) -> Option<f64> {
+    match value {
+        Value::Integer(n) if (*n as f64) as i128 == *n as i128 => Some(*n as f64),
+        Value::Float(x) => Some(*x),
+        _ => None,
+    }
+}
@@ -0,0 +1,409 @@
+use crate::{context::bit_mask, expression::Value, lexer::NumberFormat, math};
+
+
+///
+/// How results are displayed to the user.
+///
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum DisplayMode {
+    #[default]
+    Normal,
+
+    /// Integers are displayed in decimal, hexadecimal, binary and octal at once.
+    Programmer
+}
+
+
+///
+/// Controls how values are rendered as text. The REPL, the command line
+/// and `Value`'s `Display` implementation all format values through this
+/// trait, so embedders can change how results look by implementing it,
+/// overriding only the methods for the types they care about.
+///
+pub trait ValueFormatter {
+    fn format_integer(&self, value: i64) -> String {
+        value.to_string()
+    }
+
+    fn format_float(&self, value: f64) -> String {
+        value.to_string()
+    }
+
+    fn format_boolean(&self, value: bool) -> String {
+        value.to_string()
+    }
+
+    fn format_string(&self, value: &str) -> String {
+        value.to_string()
+    }
+
+    ///
+    /// Format any value by dispatching on its type.
+    ///
+    fn format(&self, value: &Value) -> String {
+        match value {
+            Value::Integer(n) => self.format_integer(*n),
+            Value::Float(x) => self.format_float(*x),
+            Value::Boolean(b) => self.format_boolean(*b),
+            Value::String(s) => self.format_string(s),
+        }
+    }
+}
+
+
+///
+/// How floats are written.
+///
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum FloatNotation {
+    /// Such as `1500.25`.
+    #[default]
+    Decimal,
+
+    /// With one digit before the decimal point, such as `1.50025e3`.
+    Scientific,
+
+    /// With an exponent which is a multiple of 3, such as `15.2e-6`.
+    Engineering
+}
+
+
+///
+/// The formatter used by pxpr itself.
+///
+#[derive(Debug, Default, Clone)]
+pub struct DefaultFormatter {
+    /// When given, integers are displayed as unsigned integers of this
+    /// many bits, so that for example `-1` is displayed as `255` with 8 bits.
+    pub bit_width: Option<u32>,
+
+    /// When given, floats are rounded to at most this many digits after the
+    /// decimal point, with trailing zeros removed.
+    pub precision: Option<usize>,
+
+    pub notation: FloatNotation,
+
+    /// The decimal separator to write floats with, and the thousands
+    /// separator used when digits are grouped.
+    pub number_format: NumberFormat,
+
+    /// Whether the digits of numbers are grouped in thousands, such as
+    /// `1,099,511,627,776`.
+    pub group_digits: bool,
+
+    /// Whether floats with no fractional part keep a `.0`, so that the float
+    /// `2.0` can be told apart from the integer `2`.
+    pub show_types: bool
+}
+
+
+impl DefaultFormatter {
+    ///
+    /// Get the separator digits are grouped with: the thousands separator of
+    /// the number format, or otherwise whichever of `,` and `.` is not the
+    /// decimal separator.
+    ///
+    fn group_separator(&self) -> char {
+        match (self.number_format.thousands_separator, self.number_format.decimal_separator) {
+            (Some(separator), _) => separator,
+            (None, ',') => '.',
+            (None, _) => ',',
+        }
+    }
+
+
+    ///
+    /// Write a number formatted by Rust with the separators of the formatter.
+    ///
+    fn localize(&self, number: String) -> String {
+        if !self.group_digits && self.number_format.decimal_separator == '.' {
+            return number;
+        }
+
+        let (mantissa, exponent) = match number.split_once('e') {
+            Some((mantissa, exponent)) => (mantissa, format!("e{}", exponent)),
+            None => (number.as_str(), String::new()),
+        };
+        let (sign, mantissa) = match mantissa.strip_prefix('-') {
+            Some(mantissa) => ("-", mantissa),
+            None => ("", mantissa),
+        };
+        let (whole, fraction) = match mantissa.split_once('.') {
+            Some((whole, fraction)) => (whole, format!("{}{}", self.number_format.decimal_separator, fraction)),
+            None => (mantissa, String::new()),
+        };
+
+        let whole = match self.group_digits && whole.bytes().all(|b| b.is_ascii_digit()) {
+            true => group_thousands(whole, self.group_separator()),
+            false => whole.to_string(),
+        };
+
+        format!("{}{}{}{}", sign, whole, fraction, exponent)
+    }
+
+
+    ///
+    /// Format a float with the precision of the formatter, rounding halves
+    /// away from zero, or with as many digits as needed to represent it exactly.
+    ///
+    fn format_decimal(&self, value: f64) -> String {
+        match self.precision {
+            Some(precision) => {
+                let factor = math::powi(10.0, precision.min(308) as i32);
+                let rounded = match (value * factor).is_finite() {
+                    true => (value * factor).round() / factor,
+                    false => value,
+                };
+                trim_zeros(format!("{:.*}", precision, rounded))
+            },
+            None => shortest(value),
+        }
+    }
+
+
+    ///
+    /// Format a float as a mantissa and an exponent, with the exponent
+    /// rounded down to a multiple of `step`. The digits are taken from Rust's
+    /// own scientific notation, which avoids the rounding errors of dividing
+    /// by a power of ten.
+    ///
+    fn format_exponential(&self, value: f64, step: i32) -> String {
+        let exponent_of = |formatted: &str| formatted.split_once('e').unwrap().1.parse::<i32>().unwrap();
+
+        // Moving the decimal point right to reach a multiple of `step` needs
+        // that many more digits to keep the same precision.
+        let formatted = match self.precision {
+            Some(precision) => {
+                let shift = exponent_of(&format!("{:e}", value)).rem_euclid(step) as usize;
+                format!("{:.*e}", precision + shift, value)
+            },
+            None => format!("{:e}", value),
+        };
+
+        let (mantissa, exponent) = formatted.split_once('e').unwrap();
+        let exponent: i32 = exponent.parse().unwrap();
+        let (sign, mantissa) = match mantissa.strip_prefix('-') {
+            Some(mantissa) => ("-", mantissa),
+            None => ("", mantissa),
+        };
+
+        let shift = exponent.rem_euclid(step) as usize;
+        let mut digits = mantissa.replace('.', "");
+        while digits.len() <= shift {
+            digits.push('0');
+        }
+
+        let (whole, fraction) = digits.split_at(shift + 1);
+        format!("{}{}e{}", sign, trim_zeros(format!("{}.{}", whole, fraction)), exponent - shift as i32)
+    }
+}
+
+
+impl ValueFormatter for DefaultFormatter {
+    fn format_integer(&self, value: i64) -> String {
+        let number = match self.bit_width {
+            Some(bits) => (value as u64 & bit_mask(bits)).to_string(),
+            None => value.to_string(),
+        };
+
+        self.localize(number)
+    }
+
+    fn format_float(&self, value: f64) -> String {
+        if !value.is_finite() {
+            return value.to_string();
+        }
+
+        let number = match self.notation {
+            FloatNotation::Decimal => self.format_decimal(value),
+            FloatNotation::Scientific => self.format_exponential(value, 1),
+            FloatNotation::Engineering => self.format_exponential(value, 3),
+        };
+
+        match self.show_types && !number.contains(['.', 'e']) {
+            true => self.localize(number + ".0"),
+            false => self.localize(number),
+        }
+    }
+}
+
+
+///
+/// Format a float with the fewest digits which read back as the same float,
+/// like ryu does. Rust already picks the fewest digits, but writes every
+/// digit of very large and very small floats, such as `1e300`, in full, so
+/// those are written in scientific notation instead.
+///
+pub(crate) fn shortest(value: f64) -> String {
+    let scientific = format!("{:e}", value);
+    let exponent: i32 = scientific.split_once('e').unwrap().1.parse().unwrap();
+
+    match (-5..16).contains(&exponent) {
+        true => value.to_string(),
+        false => scientific,
+    }
+}
+
+
+///
+/// Remove the zeros at the end of the fractional part of a number, and the
+/// decimal point if nothing is left after it.
+///
+fn trim_zeros(number: String) -> String {
+    match number.contains('.') {
+        true => number.trim_end_matches('0').trim_end_matches('.').to_string(),
+        false => number,
+    }
+}
+
+
+///
+/// Convert the magnitude of an integer to its digits in the given base,
+/// using lowercase letters for digits above 9.
+///
+pub fn format_digits(mut magnitude: u64, base: u32) -> String {
+    let mut digits = vec![];
+
+    loop {
+        let digit = (magnitude % base as u64) as u32;
+        digits.push(char::from_digit(digit, base).unwrap());
+        magnitude /= base as u64;
+
+        if magnitude == 0 {
+            break;
+        }
+    }
+
+    digits.iter().rev().collect()
+}
+
+
+///
+/// Format an integer in the given base behind a prefix such as `0x`,
+/// keeping the sign in front of the prefix. Example: -0xff
+///
+pub fn format_in_base(value: i64, base: u32, prefix: &str) -> String {
+    let digits = format_digits(value.unsigned_abs(), base);
+    match value < 0 {
+        true => format!("-{}{}", prefix, digits),
+        false => format!("{}{}", prefix, digits),
+    }
+}
+
+
+///
+/// Separate a string of digits into groups of three, counted from the right.
+/// Example: 1234567 -> 1,234,567
+///
+fn group_thousands(digits: &str, separator: char) -> String {
+    let mut grouped = String::new();
+    for (index, digit) in digits.chars().enumerate() {
+        if index > 0 && (digits.len() - index).is_multiple_of(3) {
+            grouped.push(separator);
+        }
+        grouped.push(digit);
+    }
+    grouped
+}
+
+
+///
+/// Split a string of digits into groups of `size` digits, counted from the
+/// right, left-padding the first group with zeros. Example: 101 -> 0101
+///
+fn group_digits(digits: &str, size: usize) -> String {
+    let padding = (size - digits.len() % size) % size;
+    let padded = "0".repeat(padding) + digits;
+
+    padded.as_bytes()
+        .chunks(size)
+        .map(|chunk| String::from_utf8_lossy(chunk).into_owned())
+        .collect::<Vec<String>>()
+        .join(" ")
+}
+
+
+///
+/// Get the number of bits needed to represent an integer. Negative
+/// integers are represented in two's complement and so use every bit.
+///
+fn minimum_bit_width(value: i64) -> u32 {
+    match value < 0 {
+        true => i64::BITS,
+        false => (i64::BITS - value.leading_zeros()).max(1),
+    }
+}
+
+
+///
+/// Get the rows of the programmer display of an integer: its decimal,
+/// hexadecimal, binary (grouped in nibbles) and octal representations, and
+/// its bit width. Negative integers are shown in two's complement, using
+/// `bit_width` bits if given.
+///
+pub fn programmer_rows(value: i64, bit_width: Option<u32>) -> Vec<(&'static str, String)> {
+    let bits = value as u64 & bit_mask(bit_width.unwrap_or(u64::BITS));
+    let decimal = match bit_width {
+        Some(_) => bits.to_string(),
+        None => value.to_string(),
+    };
+
+    vec![
+        ("dec", decimal),
+        ("hex", format!("0x{}", format_digits(bits, 16))),
+        ("bin", format!("0b{}", group_digits(&format_digits(bits, 2), 4))),
+        ("oct", format!("0o{}", format_digits(bits, 8))),
+        ("width", format!("{} bits", bit_width.unwrap_or_else(|| minimum_bit_width(value)))),
+    ]
+}
+
+
+///
+/// Get the rows of a ruler showing the bits of an integer, grouped in
+/// nibbles with the index of the highest bit of each nibble above it:
+///
+/// ```text
+/// 7    3
+/// 1010 0101
+/// ```
+///
+/// The ruler is `bit_width` bits wide if given, and otherwise as wide as
+/// the whole nibbles needed to represent the integer.
+///
+pub fn bit_ruler(value: i64, bit_width: Option<u32>) -> Vec<String> {
+    let width = bit_width.unwrap_or_else(|| minimum_bit_width(value).div_ceil(4) * 4);
+    let bits = value as u64 & bit_mask(width);
+
+    let nibbles: Vec<u32> = (0..width.div_ceil(4)).rev().collect();
+    let indices: Vec<String> = nibbles.iter()
+        .map(|nibble| format!("{:<4}", (nibble * 4 + 3).min(width - 1)))
+        .collect();
+    let digits: Vec<String> = nibbles.iter()
+        .map(|nibble| (nibble * 4..(nibble * 4 + 4).min(width))
+            .rev()
+            .map(|bit| match bits >> bit & 1 {
+                1 => '1',
+                _ => '0',
+            })
+            .collect())
+        .collect();
+
+    vec![indices.join(" ").trim_end().to_string(), digits.join(" ")]
+}
+
+
+///
+/// Format a value for display according to the display mode. Every
+/// line of the result is a separate row of output.
+///
+pub fn format_value(value: &Value, mode: DisplayMode, formatter: &dyn ValueFormatter, bit_width: Option<u32>) -> Vec<String> {
+    match (mode, value) {
+        (DisplayMode::Programmer, Value::Integer(n)) => {
+            programmer_rows(*n, bit_width)
+                .into_iter()
+                .map(|(label, text)| format!("{:<5} = {}", label, text))
+                .collect()
+        },
+        _ => vec![format!("= {}", formatter.format(value))],
+    }
+}
@@ -0,0 +1,90 @@
+use crate::{
+    arena::{Arena, NodeId}, check_compat, context::Context, parse_tuple_arena_for, parser::AstNode, suggest_on_type_error, Error
+};
+
+
+///
+/// A notation expressions can be written in, which reads source into the
+/// trees the evaluator computes. The evaluator only sees the trees, so a new
+/// notation, such as reverse Polish notation or a spreadsheet dialect, only
+/// needs a front-end to be computed with `compute_with`.
+///
+pub trait Frontend {
+    ///
+    /// Get the name of the notation, such as `infix`.
+    ///
+    fn name(&self) -> &'static str;
+
+
+    ///
+    /// Read source into a tree for each of the values it holds, such as the
+    /// two of `17 / 5, 17 % 5`. The spans of the trees locate any error
+    /// computing them in the source. Notes about the source, such as
+    /// deprecated syntax, can be recorded in the context.
+    ///
+    /// # Returns
+    /// The trees, or every error found in the source ordered by column.
+    ///
+    fn parse(&self, source: &str, context: &mut Context) -> Result<Vec<Box<AstNode>>, Vec<Error>>;
+
+
+    ///
+    /// Read source like `parse`, into the nodes of an arena, which is what
+    /// `compute_with` computes. By default the trees `parse` reads are added
+    /// to a new arena, so a front-end which can read into an arena directly
+    /// saves building them by overriding this.
+    ///
+    /// # Returns
+    /// The arena and the id of the root of each tree in it, or every error
+    /// found in the source ordered by column.
+    ///
+    fn parse_to_arena(&self, source: &str, context: &mut Context) -> Result<(Arena, Vec<NodeId>), Vec<Error>> {
+        let trees = self.parse(source, context)?;
+        let mut arena = Arena::new();
+        let roots = trees.iter().map(|tree| arena.add_tree(tree)).collect();
+        Ok((arena, roots))
+    }
+
+
+    ///
+    /// Add advice in the notation to an error computing the source of one
+    /// of the trees, such as where parentheses would avoid it. By default
+    /// the error is left as it is.
+    ///
+    fn explain(&self, error: Error, _source: &str, _context: &Context) -> Error {
+        error
+    }
+}
+
+
+///
+/// The usual notation of PXPR, with operators between their operands, read
+/// with the number format and operator precedence of the context.
+///
+#[derive(Debug, Default, Clone, Copy)]
+pub struct Infix;
+
+
+impl Frontend for Infix {
+    fn name(&self) -> &'static str {
+        "infix"
+    }
+
+
+    fn parse(&self, source: &str, context: &mut Context) -> Result<Vec<Box<AstNode>>, Vec<Error>> {
+        let (arena, roots) = self.parse_to_arena(source, context)?;
+        Ok(roots.into_iter().map(|root| Box::new(arena.to_tree(root))).collect())
+    }
+
+
+    fn parse_to_arena(&self, source: &str, context: &mut Context) -> Result<(Arena, Vec<NodeId>), Vec<Error>> {
+        let (arena, roots) = parse_tuple_arena_for(source, context).map_err(|error| vec![error])?;
+        check_compat(source, &arena, &roots, context);
+        Ok((arena, roots))
+    }
+
+
+    fn explain(&self, error: Error, source: &str, context: &Context) -> Error {
+        suggest_on_type_error(error, source, context)
+    }
+}
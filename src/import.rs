@@ -0,0 +1,263 @@
+use std::{collections::BTreeSet, str::FromStr};
+
+use crate::{
+    analysis::Analysis, compute_tuple, context::Context, is_blank, parse_expression,
+    parser::{AstNode, AstNodeKind, BinaryOperationType}, visit::{self, AstTransformer}, Error, ErrorKind
+};
+
+
+///
+/// A calculator whose saved sessions `pxpr import` converts.
+///
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Calculator {
+    /// A file of `bc` statements, as passed to `bc` or saved from its history.
+    Bc,
+
+    /// The history file of `qalc`, the command line of Qalculate!.
+    Qalc,
+
+    /// A SpeedCrunch session exported as plain text, where each result follows
+    /// its expression on a line starting with `=`.
+    SpeedCrunch
+}
+
+
+impl Calculator {
+    ///
+    /// Get the name of the calculator, as `--from` takes it.
+    ///
+    pub fn name(self) -> &'static str {
+        match self {
+            Calculator::Bc => "bc",
+            Calculator::Qalc => "qalc",
+            Calculator::SpeedCrunch => "speedcrunch",
+        }
+    }
+}
+
+
+impl FromStr for Calculator {
+    type Err = String;
+
+    fn from_str(calculator: &str) -> Result<Self, Self::Err> {
+        match calculator {
+            "bc" => Ok(Calculator::Bc),
+            "qalc" => Ok(Calculator::Qalc),
+            "speedcrunch" => Ok(Calculator::SpeedCrunch),
+            _ => Err(format!("Unknown calculator: '{}' (expected 'bc', 'qalc' or 'speedcrunch')", calculator)),
+        }
+    }
+}
+
+
+///
+/// A session converted into PXPR syntax.
+///
+#[derive(Debug, Default)]
+pub struct Conversion {
+    /// The expressions, in the order they were entered, ready for `--file`.
+    pub expressions: Vec<String>,
+
+    /// The options which give the settings and variables of the session,
+    /// such as `--precision 20` and `-D 'x=3'`.
+    pub options: Vec<String>,
+
+    /// The number of each line which could not be converted, counted from 1,
+    /// and why.
+    pub skipped: Vec<(usize, String)>
+}
+
+
+///
+/// The words which start a `bc` statement PXPR has no equivalent of.
+///
+const BC_STATEMENTS: &[&str] = &["define", "if", "while", "for", "print", "auto", "return", "break", "continue", "quit", "halt"];
+
+
+///
+/// What converting a session has found so far, with which the statements
+/// after it are converted.
+///
+struct Session {
+    calculator: Calculator,
+
+    /// The variables defined so far, with their values.
+    context: Context,
+
+    /// The number of fractional digits `bc` divides to, which is none until
+    /// `scale` is set.
+    scale: u32,
+
+    /// The variables assigned again, whose new values PXPR cannot follow.
+    reassigned: BTreeSet<String>
+}
+
+
+///
+/// Convert the saved session of another calculator into PXPR syntax.
+///
+/// An assignment becomes a `-D` option, so a variable can only be assigned
+/// once, and the lines using it after it is assigned again are left out
+/// rather than computed with its first value. Every other line is kept as
+/// it is if PXPR can compute it, which leaves out lines using a name PXPR
+/// does not know, such as a function of the other calculator, and lines
+/// using `^`, which is a power elsewhere but exclusive or in PXPR. While the
+/// `scale` of `bc` is 0, each division is written as `trunc(a / b)`, since
+/// `bc` drops the fractional digits of the quotient.
+///
+pub fn convert(calculator: Calculator, history: &str) -> Conversion {
+    let mut conversion = Conversion::default();
+    let mut session = Session { calculator, context: Context::default(), scale: 0, reassigned: BTreeSet::new() };
+
+    for (index, line) in history.lines().enumerate() {
+        let statements = match calculator {
+            Calculator::Bc => line.split(';').collect(),
+            Calculator::Qalc | Calculator::SpeedCrunch => vec![line],
+        };
+
+        // The statements after one which cannot be converted, such as the
+        // body of a `bc` function, are skipped along with it.
+        for statement in statements {
+            if let Err(reason) = convert_statement(statement.trim(), &mut session, &mut conversion) {
+                conversion.skipped.push((index + 1, reason));
+                break;
+            }
+        }
+    }
+
+    conversion
+}
+
+
+fn convert_statement(statement: &str, session: &mut Session, conversion: &mut Conversion) -> Result<(), String> {
+    let calculator = session.calculator;
+    let first_word = statement.split(|ch: char| !ch.is_alphanumeric() && ch != '_').next().unwrap_or("");
+    let ignored = match calculator {
+        Calculator::Bc => false,
+        // Readline writes the time of each entry as a comment of digits.
+        Calculator::Qalc => statement.strip_prefix('#').is_some_and(|time| !time.is_empty() && time.chars().all(|ch| ch.is_ascii_digit())),
+        Calculator::SpeedCrunch => statement.starts_with('='),
+    };
+
+    match (calculator, first_word) {
+        _ if ignored || is_blank(statement) => return Ok(()),
+        (Calculator::Bc, _) if BC_STATEMENTS.contains(&first_word) || statement.starts_with(['{', '}']) =>
+            return Err(format!("'{}' is a bc statement which PXPR has no equivalent of", statement)),
+        (Calculator::Qalc, "set") => return Err(format!("'{}' is a qalc setting which PXPR has no equivalent of", statement)),
+        _ => {},
+    }
+
+    if statement.contains('^') || statement.contains("**") {
+        return Err(format!("'{}' raises to a power, which PXPR has no operator for", statement));
+    }
+
+    match assignment(statement) {
+        Some(("scale", digits)) if calculator == Calculator::Bc => {
+            let digits: u32 = digits.parse().map_err(|_| format!("'{}' does not set scale to a number", statement))?;
+            session.scale = digits;
+            conversion.options.push(format!("--precision {}", digits));
+            Ok(())
+        },
+        Some((name @ ("ibase" | "obase"), _)) if calculator == Calculator::Bc =>
+            Err(format!("'{}' is not supported; PXPR reads and writes decimal, so use hex(), bin() or to_base() instead", name)),
+        Some((name, _)) if session.context.variable(name).is_some() => {
+            session.reassigned.insert(name.to_string());
+            Err(format!("'{}' is assigned again, but PXPR variables can only be defined once, so the lines using it after this are left out", name))
+        },
+        Some((name, source)) => {
+            let source = session.translate(source)?;
+            let value = computed(statement, compute_tuple(&source, &mut session.context).map(|mut values| values.remove(0).1))?
+                .ok_or(format!("The value of '{}' cannot be computed", name))?;
+            session.context.define(name, value);
+            conversion.options.push(format!("-D '{}={}'", name, source.replace('\'', "'\\''")));
+            Ok(())
+        },
+        None => {
+            let statement = session.translate(statement)?;
+            computed(&statement, compute_tuple(&statement, &mut session.context))?;
+            conversion.expressions.push(statement);
+            Ok(())
+        },
+    }
+}
+
+
+impl Session {
+    ///
+    /// Get the PXPR source computing what an expression of the session
+    /// computes: the expression, or its divisions as `trunc(a / b)` while
+    /// the `scale` of `bc` is 0. An expression which cannot be parsed is
+    /// left as it is, for `computed` to report.
+    ///
+    /// # Returns
+    /// The source, or why the expression cannot be converted, such as a
+    /// variable it uses having been assigned again.
+    ///
+    fn translate(&self, expression: &str) -> Result<String, String> {
+        let Ok(tree) = parse_expression(expression) else {
+            return Ok(expression.to_string());
+        };
+
+        if let Some(name) = Analysis::of(&tree).free_variables.intersection(&self.reassigned).next() {
+            return Err(format!("'{}' uses '{}' after it is assigned again, which PXPR would compute with its first value", expression, name));
+        }
+
+        let divides = self.calculator == Calculator::Bc && self.scale == 0 && expression.contains('/');
+        match divides {
+            true => Ok(visit::transform(*tree, &mut TruncatedDivision).to_source()),
+            false => Ok(expression.to_string()),
+        }
+    }
+}
+
+
+///
+/// Wraps each division in a call to `trunc`, as `bc` divides with a `scale`
+/// of 0.
+///
+struct TruncatedDivision;
+
+
+impl AstTransformer for TruncatedDivision {
+    fn transform_binary_operation(&mut self, node: AstNode) -> AstNode {
+        match node.kind {
+            AstNodeKind::BinaryOperation(BinaryOperationType::Divide, ..) => {
+                let span = node.span;
+                AstNode::new(AstNodeKind::FunctionCall(String::from("trunc"), vec![node]), span)
+            },
+            _ => node,
+        }
+    }
+}
+
+
+///
+/// Split `name = value` (or `name := value`) into its name and value.
+///
+fn assignment(statement: &str) -> Option<(&str, &str)> {
+    let (name, value) = statement.split_once('=')?;
+    let name = name.trim_end();
+    let name = name.strip_suffix(':').unwrap_or(name).trim();
+    let is_name = name.starts_with(|ch: char| ch.is_alphabetic() || ch == '_')
+        && name.chars().all(|ch| ch.is_alphanumeric() || ch == '_');
+
+    match is_name && !value.starts_with('=') {
+        true => Some((name, value.trim())),
+        false => None,
+    }
+}
+
+
+///
+/// Check that a statement is valid PXPR. One which fails to compute for
+/// another reason, such as a division by zero, is still converted.
+///
+fn computed<T>(statement: &str, result: Result<T, Error>) -> Result<Option<T>, String> {
+    match result {
+        Ok(value) => Ok(Some(value)),
+        Err(error) if matches!(error.kind(), ErrorKind::Lex | ErrorKind::Parse | ErrorKind::UnknownName | ErrorKind::Arity) =>
+            Err(format!("'{}' is not valid PXPR: {}", statement, error.message())),
+        Err(_) => Ok(None),
+    }
+}
@@ -0,0 +1,274 @@
+use crate::parser::{AstNode, AstNodeKind, BinaryOperationType, UnaryOperationType, PRIMARY};
+
+
+///
+/// The Greek letters names may hold, with the commands LaTeX writes them with.
+///
+const GREEK: &[(char, &str)] = &[
+    ('α', "\\alpha"), ('β', "\\beta"), ('γ', "\\gamma"), ('δ', "\\delta"), ('ε', "\\varepsilon"),
+    ('ζ', "\\zeta"), ('η', "\\eta"), ('θ', "\\theta"), ('λ', "\\lambda"), ('μ', "\\mu"),
+    ('ν', "\\nu"), ('ξ', "\\xi"), ('π', "\\pi"), ('ρ', "\\rho"), ('σ', "\\sigma"),
+    ('τ', "\\tau"), ('φ', "\\varphi"), ('χ', "\\chi"), ('ψ', "\\psi"), ('ω', "\\omega"),
+    ('Γ', "\\Gamma"), ('Δ', "\\Delta"), ('Θ', "\\Theta"), ('Λ', "\\Lambda"), ('Π', "\\Pi"),
+    ('Σ', "\\Sigma"), ('Φ', "\\Phi"), ('Ψ', "\\Psi"), ('Ω', "\\Omega"),
+];
+
+
+///
+/// The constants written as a symbol of their own rather than as a name.
+///
+const CONSTANTS: &[(&str, &str)] = &[("pi", "\\pi"), ("tau", "\\tau")];
+
+
+///
+/// The functions LaTeX has a command for, which are written upright and
+/// spaced as operators. Other functions use `\operatorname`.
+///
+const FUNCTIONS: &[(&str, &str)] = &[("min", "\\min"), ("max", "\\max"), ("gcd", "\\gcd")];
+
+
+///
+/// How far from 1 a float may be before it is written in scientific
+/// notation, such as `1.5 \times 10^{20}`.
+///
+const SCIENTIFIC_ABOVE: f64 = 1e16;
+const SCIENTIFIC_BELOW: f64 = 1e-5;
+
+
+///
+/// A piece of LaTeX left to write.
+///
+enum Piece<'a> {
+    Text(String),
+
+    /// A node, wrapped in parentheses if it binds looser than the precedence.
+    Operand(&'a AstNode, u8),
+
+    Node(&'a AstNode)
+}
+
+
+///
+/// Write a tree as LaTeX math, for pasting into papers and notes: a
+/// division is a `\frac`, multiplication a `\cdot`, logical operators are
+/// `\land`, `\lor` and `\lnot`, a conditional chain is a `cases`
+/// environment, and very large or small floats are written with a power
+/// of ten. Parentheses are only placed where the tree needs them, and are
+/// sized with `\left` and `\right`.
+///
+/// The tree is written with a loop over a stack of the pieces left to
+/// write, rather than recursion, as in `AstNode::to_source`.
+///
+pub fn to_latex(tree: &AstNode) -> String {
+    let mut latex = String::new();
+    let mut pieces = vec![Piece::Node(tree)];
+
+    while let Some(piece) = pieces.pop() {
+        match piece {
+            Piece::Text(text) => latex.push_str(&text),
+            Piece::Operand(node, precedence) => match precedence_of(node) < precedence {
+                true => pieces.extend([text("\\right)"), Piece::Node(node), text("\\left(")]),
+                false => pieces.push(Piece::Node(node)),
+            },
+            Piece::Node(node) => {
+                let sequence = write_node(node, &mut latex);
+                pieces.extend(sequence.into_iter().rev());
+            },
+        }
+    }
+
+    latex
+}
+
+
+fn text(text: &str) -> Piece<'_> {
+    Piece::Text(text.to_string())
+}
+
+
+///
+/// Get how tightly a node binds when written as LaTeX. A fraction, a
+/// function and a `cases` environment are delimited on their own, so they
+/// never need parentheses.
+///
+fn precedence_of(node: &AstNode) -> u8 {
+    match &node.kind {
+        AstNodeKind::BinaryOperation(BinaryOperationType::Divide | BinaryOperationType::Minimum | BinaryOperationType::Maximum, _, _) => PRIMARY,
+        AstNodeKind::Conditional(_) => PRIMARY,
+        _ => node.precedence(),
+    }
+}
+
+
+///
+/// Write a leaf, or get the pieces of a node with children to be written
+/// in order.
+///
+fn write_node<'a>(node: &'a AstNode, latex: &mut String) -> Vec<Piece<'a>> {
+    match &node.kind {
+        AstNodeKind::BinaryOperation(BinaryOperationType::Divide, left, right) => {
+            vec![text("\\frac{"), Piece::Node(left), text("}{"), Piece::Node(right), text("}")]
+        },
+        AstNodeKind::BinaryOperation(operation_type @ (BinaryOperationType::Minimum | BinaryOperationType::Maximum), left, right) => {
+            let name = match operation_type {
+                BinaryOperationType::Minimum => "\\min",
+                _ => "\\max",
+            };
+            vec![text(name), text("\\left("), Piece::Node(left), text(", "), Piece::Node(right), text("\\right)")]
+        },
+        AstNodeKind::BinaryOperation(operation_type, left, right) => {
+            // Operations are left associative, so a right operand of equal
+            // precedence needs parentheses.
+            let precedence = precedence_of(node);
+            vec![
+                Piece::Operand(left, precedence),
+                Piece::Text(format!(" {} ", binary_symbol(*operation_type))),
+                Piece::Operand(right, precedence + 1)
+            ]
+        },
+        AstNodeKind::UnaryOperation(UnaryOperationType::Factorial, operand) => vec![Piece::Operand(operand, PRIMARY), text("!")],
+        AstNodeKind::UnaryOperation(operation_type, operand) => {
+            let symbol = match operation_type {
+                UnaryOperationType::LogicalNot => "\\lnot ",
+                UnaryOperationType::BitwiseNot => "\\sim ",
+                _ => "-",
+            };
+            vec![text(symbol), Piece::Operand(operand, PRIMARY)]
+        },
+        AstNodeKind::FunctionCall(name, arguments) => {
+            let name = match FUNCTIONS.iter().find(|(function, _)| function == name) {
+                Some((_, command)) => command.to_string(),
+                None => format!("\\operatorname{{{}}}", escape_text(name)),
+            };
+
+            let mut sequence = vec![Piece::Text(name), text("\\left(")];
+            for (index, argument) in arguments.iter().enumerate() {
+                if index > 0 {
+                    sequence.push(text(", "));
+                }
+                sequence.push(Piece::Node(argument));
+            }
+            sequence.push(text("\\right)"));
+            sequence
+        },
+        AstNodeKind::Conditional(parts) => {
+            let cases = parts.chunks_exact(2);
+            let otherwise = cases.remainder();
+            let mut sequence = vec![text("\\begin{cases} ")];
+            for case in cases {
+                sequence.extend([Piece::Node(&case[0]), text(" & \\text{if } "), Piece::Node(&case[1]), text(" \\\\ ")]);
+            }
+            sequence.extend([Piece::Node(&otherwise[0]), text(" & \\text{otherwise} \\end{cases}")]);
+            sequence
+        },
+        AstNodeKind::Float(x) if x.is_finite() && *x != 0.0 && (x.abs() >= SCIENTIFIC_ABOVE || x.abs() < SCIENTIFIC_BELOW) => {
+            let scientific = format!("{:e}", x);
+            let (mantissa, exponent) = scientific.split_once('e').expect("scientific notation has an exponent");
+            latex.push_str(&format!("{} \\times 10^{{{}}}", mantissa, exponent));
+            vec![]
+        },
+        AstNodeKind::Boolean(x) => {
+            latex.push_str(&format!("\\mathrm{{{}}}", x));
+            vec![]
+        },
+        AstNodeKind::String(s) => {
+            latex.push_str(&format!("\\text{{``{}''}}", escape_text(s)));
+            vec![]
+        },
+        AstNodeKind::Dice(count, sides) => {
+            latex.push_str(&format!("{}\\mathrm{{d}}{}", count, sides));
+            vec![]
+        },
+        AstNodeKind::Identifier(name) => {
+            latex.push_str(&identifier(name));
+            vec![]
+        },
+        _ => {
+            latex.push_str(&node.to_string());
+            vec![]
+        },
+    }
+}
+
+
+///
+/// Get the LaTeX symbol of a binary operation written between its operands.
+///
+fn binary_symbol(operation_type: BinaryOperationType) -> &'static str {
+    match operation_type {
+        BinaryOperationType::Multiply => "\\cdot",
+        BinaryOperationType::Modulus => "\\bmod",
+        BinaryOperationType::And => "\\land",
+        BinaryOperationType::Or => "\\lor",
+        BinaryOperationType::If => "\\Rightarrow",
+        BinaryOperationType::Equal => "=",
+        BinaryOperationType::NotEqual => "\\neq",
+        BinaryOperationType::LessEqual => "\\leq",
+        BinaryOperationType::GreaterEqual => "\\geq",
+        BinaryOperationType::BitwiseAnd => "\\mathbin{\\&}",
+        BinaryOperationType::BitwiseOr => "\\mathbin{|}",
+        BinaryOperationType::BitwiseXor => "\\oplus",
+        BinaryOperationType::BitwiseLeftShift => "\\ll",
+        BinaryOperationType::BitwiseRightShift => "\\gg",
+        operation_type => operation_type.symbol(),
+    }
+}
+
+
+///
+/// Write a name as LaTeX. A name of one letter, possibly Greek, is written
+/// in italics as usual for a variable, as is one followed by a subscript
+/// after `_`, such as `x_1`. Longer names are written upright, so that
+/// `rate` does not read as the product of four variables.
+///
+fn identifier(name: &str) -> String {
+    if let Some((_, symbol)) = CONSTANTS.iter().find(|(constant, _)| *constant == name) {
+        return symbol.to_string();
+    }
+
+    let letter = |ch: char| match GREEK.iter().find(|(greek, _)| *greek == ch) {
+        Some((_, command)) => format!("{} ", command),
+        None => ch.to_string(),
+    };
+
+    let (base, subscript) = match name.split_once('_') {
+        Some((base, subscript)) if base.chars().count() == 1 && !subscript.is_empty() => (base, Some(subscript)),
+        _ => (name, None),
+    };
+
+    let mut chars = base.chars();
+    let base = match (chars.next(), chars.next()) {
+        (Some(ch), None) => letter(ch).trim_end().to_string(),
+        _ => format!("\\mathrm{{{}}}", base.chars().map(|ch| match ch {
+            '_' => String::from("\\_"),
+            ch => letter(ch),
+        }).collect::<String>().trim_end()),
+    };
+
+    match subscript {
+        Some(subscript) => format!("{}_{{{}}}", base, escape_text(subscript)),
+        None => base,
+    }
+}
+
+
+///
+/// Escape the characters LaTeX gives a meaning to, for text such as the
+/// contents of a string or the name of a function.
+///
+fn escape_text(text: &str) -> String {
+    let mut escaped = String::new();
+    for ch in text.chars() {
+        match ch {
+            '\\' => escaped.push_str("\\textbackslash{}"),
+            '{' | '}' | '$' | '&' | '#' | '_' | '%' => {
+                escaped.push('\\');
+                escaped.push(ch);
+            },
+            '^' => escaped.push_str("\\textasciicircum{}"),
+            '~' => escaped.push_str("\\textasciitilde{}"),
+            ch => escaped.push(ch),
+        }
+    }
+    escaped
+}
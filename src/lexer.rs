@@ -1,25 +1,119 @@
-use std::fmt;
+use std::{borrow::Cow, fmt};
 
-use crate::pxpr;
+use unicode_normalization::{char::is_combining_mark, is_nfc, UnicodeNormalization};
+
+use crate::{self as pxpr, span::Span};
+
+
+const BYTE_ORDER_MARK: char = '\u{feff}';
+
+
+///
+/// How numbers are written, which differs between locales.
+/// 
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct NumberFormat {
+    /// Either `.` or `,`. With `,`, function arguments are separated by `;`
+    /// instead, as in `max(1,5; 2)`.
+    pub decimal_separator: char,
+
+    /// Separates groups of three digits, such as the `.` of `1.000,5`.
+    pub thousands_separator: Option<char>
+}
+
+
+impl Default for NumberFormat {
+    fn default() -> Self {
+        NumberFormat { decimal_separator: '.', thousands_separator: None }
+    }
+}
+
+
+impl NumberFormat {
+    ///
+    /// The format with a decimal comma and no thousands separator.
+    /// 
+    pub fn decimal_comma() -> Self {
+        NumberFormat { decimal_separator: ',', thousands_separator: None }
+    }
+
+
+    ///
+    /// Get the format of a locale such as `de`, `fr_FR` or `pt-BR`, of which
+    /// only the language is used.
+    /// 
+    pub fn from_locale(locale: &str) -> Result<Self, String> {
+        let language = locale.split(['-', '_', '.']).next().unwrap_or_default().to_lowercase();
+        match language.as_str() {
+            "c" | "en" | "ja" | "ko" | "zh" => Ok(NumberFormat::default()),
+            "de" | "es" | "it" | "nl" | "pt" | "da" | "id" | "tr" => 
+                Ok(NumberFormat { decimal_separator: ',', thousands_separator: Some('.') }),
+            "fr" | "ru" | "pl" | "sv" | "fi" | "cs" | "nb" | "uk" => 
+                Ok(NumberFormat { decimal_separator: ',', thousands_separator: Some(' ') }),
+            _ => Err(format!("Unknown locale: '{}'", locale)),
+        }
+    }
+}
+
+
+///
+/// The words scanned as keywords rather than identifiers, along with the
+/// token each one stands for. Keywords are matched exactly, so `True` is
+/// an identifier.
+/// 
+const KEYWORDS: &[(&str, TokenType, Option<TokenValue>)] = &[
+    ("true", TokenType::Boolean, Some(TokenValue::Boolean(true))),
+    ("false", TokenType::Boolean, Some(TokenValue::Boolean(false))),
+    ("min", TokenType::Min, None),
+    ("max", TokenType::Max, None),
+    ("when", TokenType::When, None),
+    ("otherwise", TokenType::Otherwise, None),
+];
+
+
+///
+/// Get the token type and value of a keyword, or `None` if `word` is not one.
+/// 
+pub fn keyword(word: &str) -> Option<(TokenType, Option<TokenValue>)> {
+    KEYWORDS.iter()
+        .find(|(keyword, _, _)| *keyword == word)
+        .map(|(_, token_type, value)| (token_type.clone(), value.clone()))
+}
 
 
 #[derive(Debug, Clone)]
 pub enum TokenType {
     // Miscellaneaous
-    EOF,
+    Eof, Comma, Semicolon,
 
     // Operations
     Plus, Minus, Asterisk, Slash,
     Modulus, Not, And, Or, If,
-    Equal, NotEqual,
+    Equal, NotEqual, Less, LessEqual, Greater, GreaterEqual,
     BitwiseNot, BitwiseAnd, BitwiseOr,
     BitwiseXor, BitwiseLeftShift, BitwiseRightShift,
+    Min, Max,
+
+    // Conditional chains, such as `x when x >= 0; -x otherwise`
+    When, Otherwise,
 
     // Parentheses
     LeftParen, RightParen,
 
     // Literals
-    Float, Integer, Boolean
+    Float, Integer, Boolean, String,
+
+    // Names
+    Identifier,
+
+    // Dice, such as 3d6
+    Dice,
+
+    // A `?` standing for a value given separately from the source
+    Parameter,
+
+    // Characters which do not form a token, kept when carrying on past errors
+    Invalid
 }
 
 
@@ -34,7 +128,9 @@ impl fmt::Display for TokenType {
 pub enum TokenValue {
     Float(f64),
     Integer(i64),
-    Boolean(bool)
+    Boolean(bool),
+    String(String),
+    Dice(i64, i64)
 }
 
 
@@ -59,6 +155,20 @@ impl TokenValue {
             _ => None
         }
     }
+
+    pub fn as_string(&self) -> Option<&str> {
+        match self {
+            TokenValue::String(s) => Some(s),
+            _ => None
+        }
+    }
+
+    pub fn as_dice(&self) -> Option<(i64, i64)> {
+        match self {
+            TokenValue::Dice(count, sides) => Some((*count, *sides)),
+            _ => None
+        }
+    }
 }
 
 impl fmt::Display for TokenValue {
@@ -69,23 +179,31 @@ impl fmt::Display for TokenValue {
 
 
 
+///
+/// A token scanned from a source, whose lexeme is borrowed from the source
+/// rather than copied, unless it had to be normalized, as an identifier
+/// typed with a combining accent is.
+/// 
 #[derive(Debug, Clone)]
-pub struct Token {
+pub struct Token<'a> {
     pub type_: TokenType,
-    pub lexeme: String,
+    pub lexeme: Cow<'a, str>,
     pub value: Option<TokenValue>,
-    pub column: u32
+    /// The column of the first character of the token, the same as `span.start`.
+    pub column: u32,
+    pub span: Span
 }
 
 
-impl Token {
-    fn new(type_: TokenType, lexeme: String, value: Option<TokenValue>, column: u32) -> Self {
-        Token { type_, lexeme, value, column }
+impl<'a> Token<'a> {
+    fn new(type_: TokenType, lexeme: impl Into<Cow<'a, str>>, value: Option<TokenValue>, span: Span) -> Self {
+        let lexeme = lexeme.into();
+        Token { type_, lexeme, value, column: span.start, span }
     }
 }
 
 
-impl fmt::Display for Token {
+impl fmt::Display for Token<'_> {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         match &self.value {
             Some(value) => write!(f, "[ {}, \"{}\", {} ]", &self.type_, &self.lexeme, &value),
@@ -95,11 +213,28 @@ impl fmt::Display for Token {
 }
 
 
+///
+/// Converts a string to tokens. The source is scanned character by character
+/// rather than byte by byte, so columns count characters and operators outside
+/// of ASCII, such as `×`, are single characters. The characters are read
+/// through a cursor holding the byte offset of the next one, which lexemes are
+/// sliced from, so lexing takes time in proportion to the length of the source.
+/// 
+/// A lexer is also an iterator over the tokens of its source, scanning each
+/// one as it is asked for, so that a `Parser` can read them as they come.
+/// 
 pub struct Lexer<'a> {
     source: &'a str,
+    number_format: NumberFormat,
+    /// The byte offset of the next character.
+    offset: usize,
+    /// The column of the next character.
     current_position: u32,
+    token_offset: usize,
     token_start: u32,
-    token_list: Vec<Box<Token>>
+    token_list: Vec<Token<'a>>,
+    /// Whether the `Eof` token or an error has been scanned.
+    finished: bool
 }
 
 
@@ -107,20 +242,36 @@ impl<'a> Lexer<'a> {
     pub fn new(source: &'a str) -> Self {
         Self { 
             source,
+            number_format: NumberFormat::default(),
+            offset: 0,
             current_position: 0,
+            token_offset: 0,
             token_start: 0,
-            token_list: vec![]
+            token_list: vec![],
+            finished: false
         }
     }
 
 
+    ///
+    /// Scan numbers written in the given format instead of the default.
+    /// 
+    pub fn with_number_format(mut self, number_format: NumberFormat) -> Self {
+        self.number_format = number_format;
+        self
+    }
+
+
     fn has_next(&self) -> bool {
-        (self.current_position as usize) < self.source.len()
+        self.offset < self.source.len()
     }
 
 
+    ///
+    /// Create an error located at the start of the token being scanned.
+    /// 
     fn error(&self, message: String) -> pxpr::Error {
-        pxpr::Error::new(self.current_position, message)
+        pxpr::Error::lex(self.token_start, message)
     }
 
 
@@ -132,6 +283,7 @@ impl<'a> Lexer<'a> {
     /// 
     fn advance(&mut self) -> char {
         let next = self.peek().unwrap();
+        self.offset += next.len_utf8();
         self.current_position += 1;
         next
     }
@@ -145,9 +297,16 @@ impl<'a> Lexer<'a> {
     /// The next character in the input string.
     /// 
     fn peek(&self) -> Option<char> {
-        self.source
-            .chars()
-            .nth(self.current_position as usize)
+        self.source[self.offset..].chars().next()
+    }
+
+
+    ///
+    /// Get the character after the next character in the input string
+    /// without consuming either.
+    /// 
+    fn peek_next(&self) -> Option<char> {
+        self.source[self.offset..].chars().nth(1)
     }
 
 
@@ -156,109 +315,273 @@ impl<'a> Lexer<'a> {
     }
 
 
+    ///
+    /// Get the span of the token currently being scanned.
+    /// 
+    fn token_span(&self) -> Span {
+        Span::new(self.token_start, self.current_position)
+    }
+
+
+    ///
+    /// Get the text of the token currently being scanned.
+    /// 
+    fn lexeme(&self) -> &'a str {
+        &self.source[self.token_offset..self.offset]
+    }
+
+
     fn add_token(&mut self, token_type: TokenType) {
-        let (start, end) = (self.token_start as usize, self.current_position as usize);
-        let lexeme = self.source[start..end].to_string();
-        self.token_list.push(Box::new(Token::new(token_type, lexeme, None, self.current_position)));
+        let lexeme = self.lexeme();
+        self.token_list.push(Token::new(token_type, lexeme, None, self.token_span()));
     }
 
 
     ///
-    /// Scans a number literal.
+    /// Scans a number literal. An integer literal beyond the largest integer
+    /// is an overflow error rather than being rounded to a float.
     /// 
-    fn scan_number(&mut self) {
-        while let Some(ch) = self.peek() {
-            if !ch.is_digit(10) {
-                break;
-            }
+    fn scan_number(&mut self) -> Result<(), pxpr::Error> {
+        self.scan_digits(self.number_format.thousands_separator);
+
+        if self.match_character('d') && self.peek_next().is_some_and(|ch| ch.is_ascii_digit()) {
+            let count = self.lexeme().parse().unwrap_or(i64::MAX);
             self.advance();
+            self.scan_dice(count);
+            return Ok(());
         }
 
         let mut is_integer = true;
 
-        if let Some('.') = self.peek() {
+        // A decimal comma must be followed by a digit, so that a comma
+        // after a number can still separate arguments.
+        let decimal_separator = self.number_format.decimal_separator;
+        if self.match_character(decimal_separator) 
+            && (decimal_separator == '.' || self.peek_next().is_some_and(|ch| ch.is_ascii_digit())) {
             is_integer = false;
             self.advance();
-            while let Some(ch) = self.peek() {
-                if !ch.is_digit(10) {
-                    break;
-                }
-                self.advance();
-            }
+            self.scan_digits(None);
         }
 
-        let (start, end) = (self.token_start as usize, self.current_position as usize);
-        let lexeme = self.source[start..end].to_string();
+        // The digits are only copied when there are separators to drop or replace.
+        let lexeme = self.lexeme();
+        let digits: Cow<str> = match lexeme.chars().all(|ch| ch.is_ascii_digit() || (ch == '.' && decimal_separator == '.')) {
+            true => Cow::Borrowed(lexeme),
+            false => lexeme.chars()
+                .filter(|ch| Some(*ch) != self.number_format.thousands_separator)
+                .map(|ch| match ch == decimal_separator {
+                    true => '.',
+                    false => ch,
+                })
+                .collect(),
+        };
         
         match is_integer {
             true => {
-                let value: i64 = lexeme.parse().unwrap();
-                self.token_list.push(Box::new(
+                let value: i64 = digits.parse().map_err(|_| pxpr::Error::overflow(self.token_start, format!(
+                    "Integer literal {} is larger than the largest integer, {} (write it as a float, such as {}{}0)",
+                    lexeme, i64::MAX, lexeme, decimal_separator)))?;
+                self.token_list.push(
                     Token::new(
                         TokenType::Integer, 
                         lexeme, 
                         Some(TokenValue::Integer(value)),
-                        self.current_position
+                        self.token_span()
                     )
-                ));
+                );
             },
             false => {
-                let value: f64 = lexeme.parse().unwrap();
-                self.token_list.push(Box::new(
+                let value: f64 = digits.parse().map_err(|_| self.error(format!("Invalid number literal: {}", lexeme)))?;
+                self.token_list.push(
                     Token::new(
                         TokenType::Float, 
                         lexeme, 
                         Some(TokenValue::Float(value)),
-                        self.current_position
+                        self.token_span()
                     )
-                ));
+                );
             },
         }
+
+        Ok(())
     }
 
 
     ///
-    /// Scans a boolean literal from the input string.
+    /// Scans a run of digits. A thousands separator is part of the run when
+    /// it is followed by a group of exactly three digits.
+    /// 
+    fn scan_digits(&mut self, thousands_separator: Option<char>) {
+        while let Some(ch) = self.peek() {
+            let is_separator = Some(ch) == thousands_separator && self.is_digit_group(self.offset + ch.len_utf8());
+            if !ch.is_ascii_digit() && !is_separator {
+                break;
+            }
+            self.advance();
+        }
+    }
+
+
+    ///
+    /// Check whether exactly three digits start at a byte offset of the source.
+    /// 
+    fn is_digit_group(&self, offset: usize) -> bool {
+        self.source[offset..].chars().take(4).take_while(char::is_ascii_digit).count() == 3
+    }
+
+
+    ///
+    /// Scans the number of sides of a die after the `d` of dice notation
+    /// such as `3d6`, given the number of dice.
+    /// 
+    fn scan_dice(&mut self, count: i64) {
+        let sides_start = self.offset;
+        while let Some(ch) = self.peek() {
+            if !ch.is_ascii_digit() {
+                break;
+            }
+            self.advance();
+        }
+
+        let lexeme = self.lexeme();
+        let sides = self.source[sides_start..self.offset].parse().unwrap_or(i64::MAX);
+
+        self.token_list.push(
+            Token::new(
+                TokenType::Dice,
+                lexeme,
+                Some(TokenValue::Dice(count, sides)),
+                self.token_span()
+            )
+        );
+    }
+
+
+    ///
+    /// Scans an identifier, such as the name of a function, from the input
+    /// string. Identifiers may contain any Unicode letter, as in `Δt`, and
+    /// are normalized to NFC, so that `é` is the same name whether it was
+    /// typed as one character or as `e` and a combining accent. Words in
+    /// `KEYWORDS`, such as `true`, are scanned as the token they stand for,
+    /// and a `d` followed by digits, such as `d20`, as a single die.
     ///  
-    fn scan_boolean(&mut self) -> Result<(), pxpr::Error> {
+    fn scan_identifier(&mut self) {
         while let Some(ch) = self.peek() {
-            if !ch.is_alphabetic() {
+            if !ch.is_alphanumeric() && ch != '_' && !is_combining_mark(ch) {
                 break;
             }
             self.advance();
         }
 
-        let (start, end) = (self.token_start as usize, self.current_position as usize);
-        let lexeme = self.source[start..end].to_string();
+        let lexeme: Cow<'a, str> = match is_nfc(self.lexeme()) {
+            true => Cow::Borrowed(self.lexeme()),
+            false => Cow::Owned(self.lexeme().nfc().collect()),
+        };
+
+        if let Some((token_type, value)) = keyword(&lexeme) {
+            let span = self.token_span();
+            self.token_list.push(Token::new(token_type, lexeme, value, span));
+            return;
+        }
 
-        match lexeme.as_str() {
-            "true" => {
-                self.token_list.push(Box::new(
+        match lexeme.as_ref() {
+            die if die.len() > 1 && die.starts_with('d') && die[1..].bytes().all(|b| b.is_ascii_digit()) => {
+                let sides = die[1..].parse().unwrap_or(i64::MAX);
+                self.token_list.push(
                     Token::new(
-                        TokenType::Boolean, 
+                        TokenType::Dice, 
                         lexeme, 
-                        Some(TokenValue::Boolean(true)),
-                        self.current_position
+                        Some(TokenValue::Dice(1, sides)),
+                        self.token_span()
                     )
-                ));
+                );
+            }
 
-                return Ok(());
-            },
+            _ => {
+                let span = self.token_span();
+                self.token_list.push(Token::new(TokenType::Identifier, lexeme, None, span));
+            }
+        }
+    }
 
-            "false" => {
-                self.token_list.push(Box::new(
-                    Token::new(
-                        TokenType::Boolean, 
-                        lexeme, 
-                        Some(TokenValue::Boolean(false)),
-                        self.current_position
-                    )
-                ));
-                
-                return Ok(());
+
+    ///
+    /// Scans a string literal enclosed in double quotes. The escape
+    /// sequences `\"` and `\\` are supported.
+    /// 
+    fn scan_string(&mut self) -> Result<(), pxpr::Error> {
+        let mut value = String::new();
+
+        loop {
+            match self.peek() {
+                None => return Err(pxpr::Error::lex(self.token_start, String::from("Unterminated string literal"))),
+                Some('"') => {
+                    self.advance();
+                    break;
+                }
+                Some('\\') => {
+                    let escape_start = self.current_position;
+                    self.advance();
+                    match self.peek() {
+                        Some(ch @ ('"' | '\\')) => {
+                            self.advance();
+                            value.push(ch);
+                        }
+                        _ => {
+                            // Skip the rest of the string, so that lexing can
+                            // carry on after it when recovering from errors.
+                            let error = pxpr::Error::lex(escape_start, String::from("Invalid escape sequence in string literal"));
+                            while self.has_next() && self.advance() != '"' {}
+                            return Err(error);
+                        }
+                    }
+                }
+                Some(_) => {
+                    let ch = self.advance();
+                    value.push(ch);
+                }
+            }
+        }
+
+        let lexeme = self.lexeme();
+
+        self.token_list.push(
+            Token::new(
+                TokenType::String,
+                lexeme,
+                Some(TokenValue::String(value)),
+                self.token_span()
+            )
+        );
+
+        Ok(())
+    }
+
+
+    ///
+    /// Skip a comment from `#` to the end of its line.
+    /// 
+    fn skip_line_comment(&mut self) {
+        while self.has_next() && self.advance() != '\n' {}
+    }
+
+
+    ///
+    /// Skip a comment from `/*` to the next `*/`, which may span lines. An
+    /// unterminated comment is reported where it starts.
+    /// 
+    fn skip_block_comment(&mut self) -> Result<(), pxpr::Error> {
+        self.advance();
+
+        loop {
+            if !self.has_next() {
+                return Err(self.error(String::from("Unterminated block comment")));
             }
 
-            _ => Err(self.error(format!("Unrecognized token: {}", lexeme)))
+            if self.advance() == '*' && self.match_character('/') {
+                self.advance();
+                return Ok(());
+            }
         }
     }
 
@@ -266,7 +589,21 @@ impl<'a> Lexer<'a> {
     fn scan_next(&mut self) -> Result<(), pxpr::Error> {
         let next = self.advance();
         match next {
-            ' ' => {}
+            // Any Unicode whitespace, such as a no-break space or the `\r` of
+            // a Windows line ending, separates tokens. So does the byte order
+            // mark which editors on Windows save at the start of a file.
+            c if c.is_whitespace() || c == BYTE_ORDER_MARK => {}
+
+            // ======================== //
+            // = Comments             = //
+            // ======================== //
+
+            '#' => {
+                self.skip_line_comment();
+            }
+            '/' if self.match_character('*') => {
+                self.skip_block_comment()?;
+            }
             
             // ======================== //
             // = Arithmetic Operators = //
@@ -293,6 +630,18 @@ impl<'a> Lexer<'a> {
             ')' => {
                 self.add_token(TokenType::RightParen);
             }
+            ',' => {
+                self.add_token(TokenType::Comma);
+            }
+            ';' if self.number_format.decimal_separator == ',' => {
+                self.add_token(TokenType::Comma);
+            }
+            ';' => {
+                self.add_token(TokenType::Semicolon);
+            }
+            '?' => {
+                self.add_token(TokenType::Parameter);
+            }
 
             // ======================== //
             // = Boolean Operators    = //
@@ -321,9 +670,6 @@ impl<'a> Lexer<'a> {
                 self.advance();
                 self.add_token(TokenType::If);
             }
-            't' | 'f' => {
-                self.scan_boolean()?;
-            }
 
             // ======================== //
             // = Bitwise Operators    = //
@@ -355,12 +701,62 @@ impl<'a> Lexer<'a> {
                 self.add_token(TokenType::BitwiseLeftShift);
             }
 
+            // ======================== //
+            // = Comparison Operators = //
+            // ======================== //
+
+            '<' if self.match_character('=') => {
+                self.advance();
+                self.add_token(TokenType::LessEqual);
+            }
+
+            '<' => {
+                self.add_token(TokenType::Less);
+            }
+
+            '>' if self.match_character('=') => {
+                self.advance();
+                self.add_token(TokenType::GreaterEqual);
+            }
+
+            '>' => {
+                self.add_token(TokenType::Greater);
+            }
+
+            // ======================== //
+            // = Unicode Operators    = //
+            // ======================== //
+
+            '×' => self.add_token(TokenType::Asterisk),
+            '÷' => self.add_token(TokenType::Slash),
+            '−' => self.add_token(TokenType::Minus),
+            '≤' => self.add_token(TokenType::LessEqual),
+            '≥' => self.add_token(TokenType::GreaterEqual),
+            '≠' => self.add_token(TokenType::NotEqual),
+            '¬' => self.add_token(TokenType::Not),
+            '∧' => self.add_token(TokenType::And),
+            '∨' => self.add_token(TokenType::Or),
+            '⊕' => self.add_token(TokenType::BitwiseXor),
+            '→' => self.add_token(TokenType::If),
+
             // ======================== //
             // = Number Literals      = //
             // ======================== //
 
-            c if c.is_digit(10) => {
-                self.scan_number()
+            c if c.is_ascii_digit() => {
+                self.scan_number()?;
+            }
+
+            // ======================== //
+            // = Identifiers/Strings  = //
+            // ======================== //
+
+            c if c.is_alphabetic() || c == '_' => {
+                self.scan_identifier()
+            }
+
+            '"' => {
+                self.scan_string()?;
             }
 
 
@@ -382,25 +778,81 @@ impl<'a> Lexer<'a> {
     /// Convert an input string to a list of tokens.
     /// 
     /// # Returns
-    /// A `&Vec<Box<Token>>` or rather a reference to a vector of heap-allocated
-    /// tokens constructed from the input string.
+    /// A `&Vec<Token>` or rather a reference to a vector of the tokens
+    /// constructed from the input string.
     /// 
-    pub fn tokenize(&mut self) -> Result<&Vec<Box<Token>>, pxpr::Error> {
+    pub fn tokenize(&mut self) -> Result<&Vec<Token<'a>>, pxpr::Error> {
+        let tokens = self.by_ref().collect::<Result<Vec<Token>, pxpr::Error>>()?;
+        self.token_list = tokens;
+        Ok(&self.token_list)
+    }
+
+
+    ///
+    /// Convert the input string to tokens like `tokenize`, but carry on
+    /// past errors to find every one of them. The characters which caused
+    /// each error become an `Invalid` token, so that the parser can carry
+    /// on past them as well.
+    /// 
+    pub fn tokenize_recovering(&mut self) -> (&Vec<Token<'a>>, Vec<pxpr::Error>) {
+        let mut errors = vec![];
+
         while self.has_next() {
-            // If scanning the next token produces an error,
-            // return that error.
-            self.scan_next()?;
+            if let Err(error) = self.scan_next() {
+                errors.push(error);
+                self.add_token(TokenType::Invalid);
+            }
 
-            // Set the start of the current token to the current position.
+            self.token_offset = self.offset;
             self.token_start = self.current_position;
         }
 
-        // Add the EOF token.
-        self.add_token(TokenType::EOF);
+        self.add_token(TokenType::Eof);
+        (&self.token_list, errors)
+    }
 
-        // No errors occurred so return a success result and
-        // the list of tokens.
-        Ok(&self.token_list)
+
+    ///
+    /// Take the tokens read so far, such as to hand them to a parser
+    /// without copying them.
+    /// 
+    pub fn into_tokens(self) -> Vec<Token<'a>> {
+        self.token_list
+    }
+}
+
+
+impl<'a> Iterator for Lexer<'a> {
+    type Item = Result<Token<'a>, pxpr::Error>;
+
+    ///
+    /// Scan the next token, skipping whitespace and comments. The tokens end
+    /// with the `Eof` token, or with the first error, as `tokenize` does.
+    /// 
+    fn next(&mut self) -> Option<Self::Item> {
+        let scanned = self.token_list.len();
+
+        while self.token_list.len() == scanned {
+            if self.finished {
+                return None;
+            }
+
+            if !self.has_next() {
+                self.add_token(TokenType::Eof);
+                self.finished = true;
+                break;
+            }
+
+            let result = self.scan_next();
+            self.token_offset = self.offset;
+            self.token_start = self.current_position;
+            if let Err(error) = result {
+                self.finished = true;
+                return Some(Err(error));
+            }
+        }
+
+        self.token_list.pop().map(Ok)
     }
 }
 
@@ -0,0 +1,843 @@
+pub mod lexer;
+pub mod parser;
+pub mod expression;
+pub mod builtins;
+pub mod format;
+pub mod frontend;
+pub mod math;
+pub mod context;
+pub mod compat;
+pub mod import;
+pub mod completions;
+pub mod color;
+pub mod overflow;
+pub mod span;
+pub mod random;
+pub mod selftest;
+pub mod stats;
+pub mod render;
+pub mod sanitize;
+pub mod builder;
+pub mod compiled;
+pub mod vm;
+pub mod optimize;
+pub mod limits;
+pub mod arena;
+pub mod latex;
+pub mod notation;
+pub mod visit;
+pub mod analysis;
+pub mod typecheck;
+#[cfg(feature = "arbitrary")]
+pub mod testing;
+mod fast;
+
+use std::{fmt, io::{self, IsTerminal}};
+
+use arena::{Arena, NodeId, NodeKind};
+use context::Context;
+use expression::{execute, execute_in, Value};
+use lexer::{Lexer, NumberFormat, Token};
+use optimize::optimize_in;
+use color::ColorChoice;
+use compat::Compat;
+use compiled::CompiledExpression;
+use frontend::{Frontend, Infix};
+use parser::{AstNode, Parser, Precedence};
+use span::Span;
+use stats::Stage;
+use vm::Backend;
+
+
+///
+/// The kind of an `Error`, for tools which need to tell errors apart. Each
+/// kind has a code which does not change between versions of pxpr.
+/// 
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ErrorKind {
+    /// A character which does not start a token, or a malformed string.
+    Lex,
+
+    /// Tokens which do not form an expression, or no tokens at all.
+    Parse,
+
+    /// An operand or argument of the wrong type, such as `1 + true`.
+    Type,
+
+    DivisionByZero,
+
+    /// An integer result which does not fit in 64 bits.
+    Overflow,
+
+    /// An argument outside of the values an operation accepts, such as a base above 36.
+    Domain,
+
+    /// A name which is neither a constant nor a function.
+    UnknownName,
+
+    /// A function called with the wrong number of arguments.
+    Arity,
+
+    /// A warning treated as an error because of `--strict`.
+    Strict,
+
+    /// An invalid option or REPL command, or parameters which do not match
+    /// the `?` in an expression.
+    Usage,
+
+    /// A source longer than `Limits::max_length` allows.
+    TooLong,
+
+    /// Operands nested deeper than `Limits::max_depth` allows.
+    TooDeep,
+
+    /// A computation taking more steps than `Limits::max_steps` allows.
+    TooManySteps,
+
+    /// A computation taking longer than `Limits::timeout` allows.
+    Timeout
+}
+
+
+impl ErrorKind {
+    ///
+    /// Get the stable code of the kind, such as `E0102`.
+    /// 
+    pub fn code(&self) -> &'static str {
+        match self {
+            ErrorKind::Lex => "E0101",
+            ErrorKind::Parse => "E0102",
+            ErrorKind::Type => "E0201",
+            ErrorKind::DivisionByZero => "E0202",
+            ErrorKind::Overflow => "E0203",
+            ErrorKind::Domain => "E0204",
+            ErrorKind::UnknownName => "E0205",
+            ErrorKind::Arity => "E0206",
+            ErrorKind::Strict => "E0301",
+            ErrorKind::Usage => "E0401",
+            ErrorKind::TooLong => "E0501",
+            ErrorKind::TooDeep => "E0502",
+            ErrorKind::TooManySteps => "E0503",
+            ErrorKind::Timeout => "E0504",
+        }
+    }
+
+
+    ///
+    /// Get the name of the kind, as written in JSON output.
+    /// 
+    pub fn name(&self) -> &'static str {
+        match self {
+            ErrorKind::Lex => "lex",
+            ErrorKind::Parse => "parse",
+            ErrorKind::Type => "type",
+            ErrorKind::DivisionByZero => "division_by_zero",
+            ErrorKind::Overflow => "overflow",
+            ErrorKind::Domain => "domain",
+            ErrorKind::UnknownName => "unknown_name",
+            ErrorKind::Arity => "arity",
+            ErrorKind::Strict => "strict",
+            ErrorKind::Usage => "usage",
+            ErrorKind::TooLong => "too_long",
+            ErrorKind::TooDeep => "too_deep",
+            ErrorKind::TooManySteps => "too_many_steps",
+            ErrorKind::Timeout => "timeout",
+        }
+    }
+}
+
+
+///
+/// An error which stopped an expression from being computed, at a column
+/// of its source. Columns count characters from the start of the source,
+/// across lines, until the error is located in its source by `located_in`.
+/// 
+#[derive(Debug, Clone)]
+pub struct Error {
+    kind: ErrorKind,
+    column: u32,
+    line: Option<u32>,
+    located: bool,
+    message: String
+}
+
+impl Error {
+    pub fn new(kind: ErrorKind, column: u32, message: String) -> Self {
+        Error { kind, column, line: None, located: false, message }
+    }
+
+    pub fn lex(column: u32, message: String) -> Self {
+        Error::new(ErrorKind::Lex, column, message)
+    }
+
+    pub fn parse(column: u32, message: String) -> Self {
+        Error::new(ErrorKind::Parse, column, message)
+    }
+
+    pub fn type_mismatch(column: u32, message: String) -> Self {
+        Error::new(ErrorKind::Type, column, message)
+    }
+
+    pub fn division_by_zero(column: u32) -> Self {
+        Error::new(ErrorKind::DivisionByZero, column, String::from("Division by 0"))
+    }
+
+    pub fn overflow(column: u32, message: String) -> Self {
+        Error::new(ErrorKind::Overflow, column, message)
+    }
+
+    pub fn domain(column: u32, message: String) -> Self {
+        Error::new(ErrorKind::Domain, column, message)
+    }
+
+    pub fn unknown_name(column: u32, message: String) -> Self {
+        Error::new(ErrorKind::UnknownName, column, message)
+    }
+
+    pub fn arity(column: u32, message: String) -> Self {
+        Error::new(ErrorKind::Arity, column, message)
+    }
+
+    pub fn usage(column: u32, message: String) -> Self {
+        Error::new(ErrorKind::Usage, column, message)
+    }
+
+    pub fn too_long(column: u32, message: String) -> Self {
+        Error::new(ErrorKind::TooLong, column, message)
+    }
+
+    pub fn too_deep(column: u32, message: String) -> Self {
+        Error::new(ErrorKind::TooDeep, column, message)
+    }
+
+    pub fn too_many_steps(column: u32, message: String) -> Self {
+        Error::new(ErrorKind::TooManySteps, column, message)
+    }
+
+    pub fn timeout(column: u32, message: String) -> Self {
+        Error::new(ErrorKind::Timeout, column, message)
+    }
+
+    pub fn kind(&self) -> ErrorKind {
+        self.kind
+    }
+
+    pub fn column(&self) -> u32 {
+        self.column
+    }
+
+    pub fn message(&self) -> &str {
+        &self.message
+    }
+
+    ///
+    /// Get the line the error is on, counted from 1, once it has been
+    /// located in a source with several lines.
+    /// 
+    pub fn line(&self) -> Option<u32> {
+        self.line
+    }
+
+    ///
+    /// Locate the error in the source it was found in, as it is shown to
+    /// users: its column becomes the display column, and in a source with
+    /// several lines, the error gets its line and the column is within that
+    /// line. See `span::locate`. An error is only located once.
+    /// 
+    pub fn located_in(mut self, source: &str) -> Self {
+        if !self.located {
+            (self.line, self.column) = span::locate(source, self.column);
+            self.located = true;
+        }
+        self
+    }
+
+    ///
+    /// Move the error to the given column, such as the start of the
+    /// operation which raised it.
+    /// 
+    pub(crate) fn at(mut self, column: u32) -> Self {
+        self.column = column;
+        self
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.line {
+            Some(line) => write!(f, "error[{}] at line {}, column {}: {}", self.kind.code(), line, self.column, self.message),
+            None => write!(f, "error[{}] at column {}: {}", self.kind.code(), self.column, self.message),
+        }
+    }
+}
+
+impl std::error::Error for Error {}
+
+///
+/// Write an error to standard error, highlighted unless `NO_COLOR` is set or
+/// standard error is not a terminal.
+/// 
+pub fn report_error(error: &Error) {
+    eprintln!("{}", render::error_line(error, ColorChoice::Auto.enabled(io::stderr().is_terminal())));
+}
+
+
+///
+/// How serious a `Warning` is.
+/// 
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Severity {
+    /// Something pxpr did for the user, such as replacing a character.
+    Info,
+
+    /// Something which may make the result differ from what the user expects.
+    Warning,
+
+    /// An expression whose meaning differs between versions of pxpr. Like
+    /// information, it is never treated as an error.
+    Deprecation
+}
+
+impl Severity {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Severity::Info => "info",
+            Severity::Warning => "warning",
+            Severity::Deprecation => "deprecation",
+        }
+    }
+}
+
+
+///
+/// A problem which does not prevent an expression from being computed,
+/// but which the user should know about.
+/// 
+#[derive(Debug, Clone)]
+pub struct Warning {
+    column: u32,
+    line: Option<u32>,
+    located: bool,
+    message: String,
+    severity: Severity
+}
+
+impl Warning {
+    pub fn new(column: u32, message: String) -> Self {
+        Warning { column, line: None, located: false, message, severity: Severity::Warning }
+    }
+
+    pub fn info(column: u32, message: String) -> Self {
+        Warning { column, line: None, located: false, message, severity: Severity::Info }
+    }
+
+    pub fn deprecation(column: u32, message: String) -> Self {
+        Warning { column, line: None, located: false, message, severity: Severity::Deprecation }
+    }
+
+    pub fn column(&self) -> u32 {
+        self.column
+    }
+
+    pub fn severity(&self) -> Severity {
+        self.severity
+    }
+
+    pub fn message(&self) -> &str {
+        &self.message
+    }
+
+    ///
+    /// Get the line the warning is on, as for `Error::line`.
+    /// 
+    pub fn line(&self) -> Option<u32> {
+        self.line
+    }
+
+    ///
+    /// Locate the warning in the source it was found in, as for `Error::located_in`.
+    /// 
+    pub fn located_in(mut self, source: &str) -> Self {
+        if !self.located {
+            (self.line, self.column) = span::locate(source, self.column);
+            self.located = true;
+        }
+        self
+    }
+}
+
+impl From<Warning> for Error {
+    fn from(warning: Warning) -> Self {
+        Error { kind: ErrorKind::Strict, column: warning.column, line: warning.line, located: warning.located, message: warning.message }
+    }
+}
+
+///
+/// Write a warning to standard error, highlighted the same as by `report_error`.
+/// 
+pub fn report_warning(warning: &Warning) {
+    eprintln!("{}", render::warning_line(warning, ColorChoice::Auto.enabled(io::stderr().is_terminal())));
+}
+
+
+///
+/// Convert a raw expression to an abstract syntax tree.
+/// 
+pub fn parse_expression(raw_expression: &str) -> Result<Box<AstNode>, Error> {
+    parse_expression_with_format(raw_expression, NumberFormat::default())
+}
+
+
+///
+/// Convert a raw expression whose numbers are written in the given format,
+/// such as with a decimal comma, to an abstract syntax tree.
+/// 
+pub fn parse_expression_with_format(raw_expression: &str, number_format: NumberFormat) -> Result<Box<AstNode>, Error> {
+    parse_with(raw_expression, number_format, Precedence::default())
+}
+
+
+///
+/// Convert a raw expression to an abstract syntax tree, reading it with the
+/// number format of a context and the operator precedence of its compatibility
+/// version.
+/// 
+/// The source is also held to the length and depth limits of the context.
+/// 
+pub fn parse_expression_for(raw_expression: &str, context: &Context) -> Result<Box<AstNode>, Error> {
+    let (arena, root) = parse_arena_for(raw_expression, context)?;
+    Ok(Box::new(arena.to_tree(root)))
+}
+
+
+///
+/// Convert a raw expression to the nodes of an arena like
+/// `parse_expression_for`, as the evaluator works on.
+/// 
+/// # Returns
+/// The arena, and the id of the root of the expression in it.
+/// 
+pub(crate) fn parse_arena_for(raw_expression: &str, context: &Context) -> Result<(Arena, NodeId), Error> {
+    context.limits.check_length(raw_expression)?;
+    let tokens = tokenize(raw_expression, context.number_format)?;
+    let parser = Parser::from_tokens(tokens.into_iter().map(Ok))
+        .with_precedence(context.compat.precedence())
+        .with_max_depth(context.limits.max_depth);
+
+    stats::measure(Stage::Parser, || parser.parse_to_arena())
+}
+
+
+///
+/// Convert a raw tuple of expressions to abstract syntax trees like
+/// `parse_expression_for` does for one expression.
+/// 
+pub fn parse_tuple_for(raw_expression: &str, context: &Context) -> Result<Vec<Box<AstNode>>, Error> {
+    let (arena, roots) = parse_tuple_arena_for(raw_expression, context)?;
+    Ok(roots.into_iter().map(|root| Box::new(arena.to_tree(root))).collect())
+}
+
+
+///
+/// Convert a raw tuple of expressions to the nodes of an arena like
+/// `parse_tuple_for`, as the evaluator works on.
+/// 
+/// # Returns
+/// The arena, and the id of the root of each element in it.
+/// 
+pub(crate) fn parse_tuple_arena_for(raw_expression: &str, context: &Context) -> Result<(Arena, Vec<NodeId>), Error> {
+    context.limits.check_length(raw_expression)?;
+    let tokens = tokenize(raw_expression, context.number_format)?;
+    let parser = Parser::from_tokens(tokens.into_iter().map(Ok))
+        .with_precedence(context.compat.precedence())
+        .with_max_depth(context.limits.max_depth);
+
+    stats::measure(Stage::Parser, || parser.parse_tuple_to_arena())
+}
+
+
+fn parse_with(raw_expression: &str, number_format: NumberFormat, precedence: Precedence) -> Result<Box<AstNode>, Error> {
+    let tokens = tokenize(raw_expression, number_format)?;
+    let mut parser = Parser::from_tokens(tokens.into_iter().map(Ok)).with_precedence(precedence);
+
+    // Convert the token stream to an abstract syntax tree.
+    stats::measure(Stage::Parser, || parser.parse())
+}
+
+
+///
+/// Convert a raw tuple of expressions separated by commas, such as
+/// `17 / 5, 17 % 5`, to an abstract syntax tree for each element.
+/// 
+pub fn parse_tuple_with_format(raw_expression: &str, number_format: NumberFormat) -> Result<Vec<Box<AstNode>>, Error> {
+    parse_tuple_with_precedence(raw_expression, number_format, Precedence::default())
+}
+
+
+///
+/// Convert a raw tuple of expressions to abstract syntax trees like
+/// `parse_tuple_with_format`, reading binary operators with the given
+/// precedence, such as the flat precedence of version 0.x.
+/// 
+pub fn parse_tuple_with_precedence(raw_expression: &str, number_format: NumberFormat, precedence: Precedence) -> Result<Vec<Box<AstNode>>, Error> {
+    let tokens = tokenize(raw_expression, number_format)?;
+    let mut parser = Parser::from_tokens(tokens.into_iter().map(Ok)).with_precedence(precedence);
+
+    stats::measure(Stage::Parser, || parser.parse_tuple())
+}
+
+
+///
+/// Convert a raw tuple of expressions to abstract syntax trees like
+/// `parse_tuple_with_format`, but carry on past errors to report every
+/// lexical and syntax error at once, such as both bad characters in
+/// `1 + $ + @ + 2`.
+/// 
+/// # Returns
+/// The trees, or every error ordered by column.
+/// 
+pub fn parse_tuple_recovering(raw_expression: &str, number_format: NumberFormat) -> Result<Vec<Box<AstNode>>, Vec<Error>> {
+    let mut context = Context::default();
+    context.number_format = number_format;
+    parse_tuple_recovering_for(raw_expression, &context)
+}
+
+
+///
+/// Convert a raw tuple of expressions to abstract syntax trees like
+/// `parse_tuple_recovering`, with the number format, precedence and limits
+/// of a context, so that the errors found are those `parse_tuple_for` would
+/// find first.
+/// 
+/// # Returns
+/// The trees, or every error ordered by column.
+/// 
+pub fn parse_tuple_recovering_for(raw_expression: &str, context: &Context) -> Result<Vec<Box<AstNode>>, Vec<Error>> {
+    context.limits.check_length(raw_expression).map_err(|error| vec![error])?;
+    let mut tokenizer = Lexer::new(raw_expression).with_number_format(context.number_format);
+    let (tokens, mut errors) = tokenizer.tokenize_recovering();
+    if tokens.len() == 1 && errors.is_empty() {
+        return Err(vec![Error::parse(0, String::from("Empty expression"))]);
+    }
+    let tokens = tokenizer.into_tokens();
+
+    let mut parser = Parser::from_tokens(tokens.into_iter().map(Ok))
+        .with_precedence(context.compat.precedence())
+        .with_max_depth(context.limits.max_depth);
+    match parser.parse_tuple_recovering() {
+        Ok(elements) if errors.is_empty() => Ok(elements),
+        Ok(_) => Err(errors),
+        Err(parse_errors) => {
+            errors.extend(parse_errors);
+            errors.sort_by_key(Error::column);
+            Err(errors)
+        },
+    }
+}
+
+
+///
+/// Convert a raw expression to a stream of tokens.
+/// 
+fn tokenize(raw_expression: &str, number_format: NumberFormat) -> Result<Vec<Token<'_>>, Error> {
+    let mut tokenizer = Lexer::new(raw_expression).with_number_format(number_format);
+
+    let tokens = stats::measure(Stage::Lexer, || -> Result<Vec<Token<'_>>, Error> {
+        tokenizer.tokenize()?;
+        Ok(tokenizer.into_tokens())
+    })?;
+
+    // Only the end of the input is left when there is nothing but
+    // whitespace and comments.
+    match tokens.len() {
+        1 => Err(Error::parse(0, String::from("Empty expression"))),
+        _ => Ok(tokens),
+    }
+}
+
+
+///
+/// Check whether a raw expression has nothing to compute, being empty or
+/// holding only whitespace and comments, such as a comment on its own line
+/// of a file of expressions.
+/// 
+pub fn is_blank(raw_expression: &str) -> bool {
+    Lexer::new(raw_expression).tokenize().is_ok_and(|tokens| tokens.len() == 1)
+}
+
+
+///
+/// Compute a raw expression and get the result of the computation
+/// 
+/// # Arguments
+/// * `raw_expression` An immutable reference to the raw expression as a string.
+/// 
+/// # Return
+/// A `Result<Value, Error>` in which the `Ok()` value is the result of the
+/// computation and the error represents any error that happened during 
+/// computation of the expression.
+/// 
+pub fn compute_expression(raw_expression: &str, context: &mut Context) -> Result<Value, Error> {
+    if let Some(value) = compute_fast(raw_expression, context) {
+        return Ok(value);
+    }
+
+    CompiledExpression::new(raw_expression, context)?.evaluate(context)
+}
+
+
+///
+/// Compute the tree of a node of an arena with the backend of the context.
+/// The bytecode backend compiles the tree after optimizing it for the context.
+/// 
+fn evaluate(arena: &Arena, root: NodeId, context: &mut Context) -> Result<Value, Error> {
+    match context.backend {
+        Backend::Tree => execute_in(arena, root, context),
+        Backend::Vm => {
+            let (arena, root) = optimize_in(arena, root, context);
+            vm::compile_in(arena, root).run(context)
+        },
+    }
+}
+
+
+///
+/// Compute a tiny expression on the fast path, which reads and computes it
+/// at once, so the time it takes counts towards the evaluator.
+/// 
+fn compute_fast(raw_expression: &str, context: &Context) -> Option<Value> {
+    stats::measure(Stage::Evaluator, || fast::compute(raw_expression, context))
+}
+
+
+///
+/// Get the part of a source covered by a span.
+/// 
+fn source_text(source: &str, span: Span) -> String {
+    source.chars().skip(span.start as usize).take((span.end - span.start) as usize).collect()
+}
+
+
+///
+/// When `Context::pedantic_compat` is set, warn about each element of a
+/// tuple parsed into an arena whose meaning differs under the operator
+/// precedence of the other compatibility version, so that scripts can be
+/// made to mean the same under both before upgrading.
+/// 
+pub(crate) fn check_compat(source: &str, arena: &Arena, roots: &[NodeId], context: &mut Context) {
+    if !context.pedantic_compat {
+        return;
+    }
+
+    let other = match context.compat {
+        Compat::Current => Compat::Version0,
+        Compat::Version0 => Compat::Current,
+    };
+
+    let Ok(others) = parse_tuple_with_precedence(source, context.number_format, other.precedence()) else {
+        return;
+    };
+
+    for (root, other_element) in roots.iter().zip(&others) {
+        let element = arena.to_tree(*root);
+        if element != **other_element {
+            context.note(Warning::deprecation(element.span.start, format!(
+                "'{}' reads as '{}', but as '{}' with compatibility version {}; add parentheses to keep one meaning",
+                source_text(source, element.span), element.to_parenthesized_source(), other_element.to_parenthesized_source(), other)));
+        }
+    }
+}
+
+
+///
+/// Compute a raw tuple of expressions separated by commas, such as
+/// `17 / 5, 17 % 5`, so that several values can be computed at once.
+/// 
+/// # Returns
+/// The source and value of each element of the tuple, or the first error.
+/// 
+pub fn compute_tuple(raw_expression: &str, context: &mut Context) -> Result<Vec<(String, Value)>, Error> {
+    if let Some(value) = compute_fast(raw_expression, context) {
+        return Ok(vec![(raw_expression.trim().to_string(), value)]);
+    }
+
+    compute_with(&Infix, raw_expression, context).map_err(|mut errors| errors.remove(0))
+}
+
+
+///
+/// Compute a raw expression written in the notation of a front-end, such as
+/// one added by a library user, like `compute_tuple` does for the usual notation.
+/// 
+/// # Returns
+/// The source and value of each value the expression holds, or every error
+/// the front-end found, or else the first error computing a value.
+/// 
+pub fn compute_with(frontend: &dyn Frontend, raw_expression: &str, context: &mut Context) -> Result<Vec<(String, Value)>, Vec<Error>> {
+    let source = sanitize::sanitize(raw_expression, context);
+    context.limits.check_length(&source).map_err(|error| vec![error])?;
+    let (arena, roots) = frontend.parse_to_arena(&source, context)?;
+
+    stats::measure(Stage::Evaluator, || {
+        roots.iter()
+            .map(|root| {
+                let text = source_text(&source, arena[*root].span);
+                let value = evaluate(&arena, *root, context).map_err(|error| vec![frontend.explain(error, &text, context)])?;
+                Ok((text, value))
+            })
+            .collect()
+    })
+}
+
+
+///
+/// The most tokens an expression may have for `suggest_parentheses` to try
+/// placing parentheses in it, since the number of placements grows with the
+/// square of its length.
+/// 
+const MAX_SUGGESTION_TOKENS: usize = 32;
+
+
+///
+/// Find where parentheses would make an expression which fails with a type
+/// error compute, such as `!(1 == 2)` for `!1 == 2`, when the error comes from
+/// how its operators are grouped. Parentheses are placed around each run of
+/// tokens in turn, shortest first, and the expression parsed again and
+/// computed in a copy of the context.
+/// 
+/// # Returns
+/// The first expression with parentheses which computes, if any.
+/// 
+pub fn suggest_parentheses(source: &str, context: &Context) -> Option<String> {
+    let mut tokenizer = Lexer::new(source).with_number_format(context.number_format);
+    let tokens = tokenizer.tokenize().ok()?;
+    let tokens = &tokens[..tokens.len() - 1];
+    if tokens.len() > MAX_SUGGESTION_TOKENS {
+        return None;
+    }
+
+    let characters: Vec<char> = source.chars().collect();
+    for length in 3..=tokens.len() {
+        for run in tokens.windows(length) {
+            let (start, end) = (run[0].span.start as usize, run[length - 1].span.end as usize);
+            let candidate: String = characters[..start].iter()
+                .chain(['('].iter())
+                .chain(characters[start..end].iter())
+                .chain([')'].iter())
+                .chain(characters[end..].iter())
+                .collect();
+
+            let Ok(ast) = parse_expression_for(&candidate, context) else {
+                continue;
+            };
+
+            if execute(&ast, &mut context.fork()).is_ok() {
+                return Some(candidate);
+            }
+        }
+    }
+
+    None
+}
+
+
+///
+/// Add a suggestion of where to place parentheses to a type error, if some
+/// placement makes the expression compute.
+/// 
+pub(crate) fn suggest_on_type_error(error: Error, source: &str, context: &Context) -> Error {
+    if error.kind != ErrorKind::Type {
+        return error;
+    }
+
+    match suggest_parentheses(source.trim(), context) {
+        Some(suggestion) => Error { message: format!("{} (did you mean '{}'?)", error.message, suggestion), ..error },
+        None => error,
+    }
+}
+
+
+///
+/// Write a value as a literal which can be placed anywhere in the source of
+/// an expression, such as `"say \"hi\""` for a string containing quotes.
+/// Negative numbers are wrapped in parentheses so that they cannot combine
+/// with the operator before them, and the smallest integer, which has no
+/// literal, is written as `(-9223372036854775807 - 1)`.
+/// 
+/// # Returns
+/// The literal, or an error for infinite and NaN floats, which have no literal.
+/// 
+pub fn escape_literal(value: &Value) -> Result<String, Error> {
+    if let Value::Float(x) = value && !x.is_finite() {
+        return Err(Error::domain(0, format!("No literal can be written for {}", x)));
+    }
+
+    let literal = AstNode::new(value.to_literal(), Span::new(0, 0)).to_string();
+    match literal.starts_with('-') {
+        true => Ok(format!("({})", literal)),
+        false => Ok(literal),
+    }
+}
+
+
+///
+/// Lex and parse a raw expression once, so that it can be computed many
+/// times with `CompiledExpression::evaluate` without reading it again, such
+/// as `compile("a * b + c")?` computed for each row of a table.
+/// 
+pub fn compile(raw_expression: &str) -> Result<CompiledExpression, Error> {
+    CompiledExpression::new(raw_expression, &mut Context::new())
+}
+
+
+///
+/// Lex and parse a raw expression once like `compile`, reading it with the
+/// number format and operator precedence of a context, which records any
+/// notes about its source, such as replaced typographic symbols.
+/// 
+pub fn compile_for(raw_expression: &str, context: &mut Context) -> Result<CompiledExpression, Error> {
+    CompiledExpression::new(raw_expression, context)
+}
+
+
+///
+/// Compute a raw expression with the variables and settings of a context,
+/// without changing it, such as `eval_with("x * 10", &context)` in a program
+/// which binds `x` with `Context::set`. Not even the random number generator
+/// moves on, so `rand()` gives the same number each time. Warnings are not
+/// kept, so compute with `compute_expression` to read them from the context.
+/// 
+pub fn eval_with(raw_expression: &str, context: &Context) -> Result<Value, Error> {
+    compute_expression(raw_expression, &mut context.fork())
+}
+
+
+///
+/// Compute a raw expression in which each `?` stands for the next of the
+/// given parameters, such as `eval_with_params("price * ?", &[Value::Float(2.5)], context)`.
+/// The parameters are placed into the parsed expression rather than its
+/// source, so that, like the parameters of an SQL query, they are only ever
+/// values and cannot add operators to the expression.
+/// 
+pub fn eval_with_params(raw_expression: &str, parameters: &[Value], context: &mut Context) -> Result<Value, Error> {
+    let source = sanitize::sanitize(raw_expression, context);
+    let (mut arena, root) = parse_arena_for(&source, context)?;
+
+    // Every node the parser added belongs to the expression.
+    let mut count = 0;
+    for node in arena.nodes_mut() {
+        if let NodeKind::Parameter(index) = node.kind {
+            count += 1;
+            if let Some(parameter) = parameters.get(index) {
+                node.kind = NodeKind::literal(parameter);
+            }
+        }
+    }
+
+    if count != parameters.len() {
+        return Err(Error::usage(0, 
+            format!("The expression has {} parameter(s), but {} value(s) were given", count, parameters.len())));
+    }
+
+    stats::measure(Stage::Evaluator, || execute_in(&arena, root, context))
+}
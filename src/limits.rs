@@ -0,0 +1,109 @@
+use std::time::{Duration, Instant};
+
+use crate as pxpr;
+
+
+///
+/// How often, in steps, the time spent computing a tree is checked against
+/// the timeout, since reading the clock costs more than a step.
+///
+const STEPS_PER_CLOCK_CHECK: u64 = 1024;
+
+
+///
+/// Limits on the resources an expression may use, for computing expressions
+/// from untrusted users, such as the requests to a server. Each limit is
+/// unset by default, and exceeding one fails with an error of its own kind.
+///
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct Limits {
+    /// The most characters the source of an expression may have.
+    pub max_length: Option<usize>,
+
+    /// How deeply operands may be nested inside other operations, such as
+    /// the `1` in `-(2 * max(1))`, which is 3 deep. Operands chained along the
+    /// left side, as in `1 + 2 + 3`, do not count.
+    pub max_depth: Option<usize>,
+
+    /// The most steps computing a tree may take, each step computing a
+    /// node or running an instruction.
+    pub max_steps: Option<u64>,
+
+    /// The longest computing a tree may take.
+    pub timeout: Option<Duration>
+}
+
+
+impl Limits {
+    ///
+    /// Limits suited to expressions typed by users of a server: 10000
+    /// characters, 256 levels of nesting, a million steps and 100 milliseconds.
+    ///
+    pub fn untrusted() -> Self {
+        Limits {
+            max_length: Some(10_000),
+            max_depth: Some(256),
+            max_steps: Some(1_000_000),
+            timeout: Some(Duration::from_millis(100))
+        }
+    }
+
+
+    ///
+    /// Check that a source is no longer than the most characters allowed,
+    /// reading no further into it than that.
+    ///
+    pub fn check_length(&self, source: &str) -> Result<(), pxpr::Error> {
+        match self.max_length {
+            Some(max_length) if source.chars().nth(max_length).is_some() => Err(pxpr::Error::too_long(max_length as u32,
+                format!("Expression longer than the limit of {} characters", max_length))),
+            _ => Ok(()),
+        }
+    }
+}
+
+
+///
+/// The steps taken and time spent so far computing a tree, counted against
+/// the limits of a context.
+///
+#[derive(Debug, Default, Clone)]
+pub(crate) struct Budget {
+    steps: u64,
+    started: Option<Instant>
+}
+
+
+impl Budget {
+    ///
+    /// Start counting the computation of a tree, reading the clock only
+    /// when there is a timeout.
+    ///
+    pub(crate) fn start(limits: &Limits) -> Self {
+        Budget { steps: 0, started: limits.timeout.map(|_| Instant::now()) }
+    }
+
+
+    ///
+    /// Count a step, failing once there have been more than the limits allow.
+    ///
+    pub(crate) fn spend(&mut self, limits: &Limits) -> Result<(), pxpr::Error> {
+        if limits.max_steps.is_none() && limits.timeout.is_none() {
+            return Ok(());
+        }
+
+        self.steps += 1;
+        if let Some(max_steps) = limits.max_steps && self.steps > max_steps {
+            return Err(pxpr::Error::too_many_steps(0,
+                format!("Computation took more than the limit of {} steps", max_steps)));
+        }
+
+        if let (Some(timeout), Some(started)) = (limits.timeout, self.started)
+            && self.steps.is_multiple_of(STEPS_PER_CLOCK_CHECK) && started.elapsed() > timeout {
+            return Err(pxpr::Error::timeout(0,
+                format!("Computation took longer than the limit of {:?}", timeout)));
+        }
+
+        Ok(())
+    }
+}
@@ -1,116 +1,2115 @@
-mod lexer;
-mod parser;
-mod expression;
+use std::{borrow::Cow, collections::HashMap, fs::{self, File}, io::{self, IsTerminal, Write}, path::PathBuf, time::{Duration, Instant}};
+use rayon::prelude::*;
+use rustyline::{completion::Completer, error::ReadlineError, highlight::{CmdKind, Highlighter}, hint::Hinter, history::DefaultHistory, validate::Validator, Config, Editor, Helper};
+use pxpr::{
+    lexer::{Lexer, TokenType}, color::{highlight, red, ColorChoice}, builtins, compat::Compat, completions, import, latex, notation, compute_expression, compute_tuple, context::{Context, Roll}, expression::{execute, Value}, format::{DisplayMode, FloatNotation, ValueFormatter}, lexer::NumberFormat, limits::Limits, optimize::optimize, overflow::OverflowPolicy, parser::AstNode, parse_expression_for, parse_tuple_for, parse_tuple_recovering_for, 
+    random::{time_seed, Rng}, render::{error_line, warning_line, OutputFormat, Rendered, Renderer, ResultPrefix}, sanitize::sanitize, selftest::{check_deep_nesting, check_random_trees, run_differential}, span::{self, Span}, typecheck::{typecheck_for, Type}, 
+    stats::{self, Profile, SessionSummary, Stage, Stats}, vm::Backend, is_blank, ErrorKind, Warning
+};
 
-use std::{io::{self, Write}, rc::Rc};
-use expression::{execute, Value};
-use lexer::Lexer;
-use parser::Parser;
-use pxpr::report_error;
+
+///
+/// Compute only the subexpression of a raw expression which covers a span
+/// of its source, such as the selection in an editor. The span is given in
+/// display columns, as errors are shown, and converted to the characters
+/// spans count.
+/// 
+fn compute_range(raw_expression: &str, range: Span, context: &mut Context) -> Result<Value, pxpr::Error> {
+    let source = sanitize(raw_expression, context);
+    let ast = parse_expression_for(&source, context)?;
+
+    let characters = Span::new(span::character_column(&source, range.start), span::character_column(&source, range.end));
+    match ast.node_covering(characters) {
+        Some(node) => stats::measure(Stage::Evaluator, || execute(node, context)),
+        None => Err(pxpr::Error::usage(characters.start, format!("No subexpression covers columns {}", range))),
+    }
+}
+
+
+///
+/// Compute a raw tuple of expressions. If it cannot be parsed, every
+/// lexical and syntax error in it is found rather than only the first.
+/// 
+fn compute(raw_expression: &str, context: &mut Context) -> Result<Vec<(String, Value)>, Vec<pxpr::Error>> {
+    compute_tuple(raw_expression, context).map_err(|error| match error.kind() {
+        // The expression has already been sanitized once, recording its notes.
+        ErrorKind::Lex | ErrorKind::Parse => {
+            let source = sanitize(raw_expression, &mut context.fork());
+            parse_tuple_recovering_for(&source, context)
+                .err()
+                .filter(|errors| !errors.is_empty())
+                .unwrap_or_else(|| vec![error])
+        },
+        _ => vec![error],
+    })
+}
+
+
+///
+/// The elements of a computed tuple, or the errors which stopped it.
+/// 
+type Computation = Result<Vec<(String, Value)>, Vec<pxpr::Error>>;
+
+
+///
+/// The results of the expressions computed in a batch or a file, so that an
+/// expression repeated on several lines is computed once. An expression
+/// which draws random numbers, such as `rand()` or `2d6`, is never cached,
+/// since each line draws its own.
+/// 
+struct Cache {
+    enabled: bool,
+    results: HashMap<String, (Computation, Vec<Warning>)>
+}
+
+
+impl Cache {
+    fn new(enabled: bool) -> Self {
+        Cache { enabled, results: HashMap::new() }
+    }
+
+
+    ///
+    /// Compute an expression, or reuse its result if it has been computed
+    /// before. The warnings of a reused result are recorded again.
+    /// 
+    fn compute(&mut self, raw_expression: &str, context: &mut Context) -> Computation {
+        if let Some((result, warnings)) = self.results.get(raw_expression) {
+            for warning in warnings {
+                context.note(warning.clone());
+            }
+            return result.clone();
+        }
+
+        let rng = context.rng.clone();
+        let result = compute(raw_expression, context);
+        if self.enabled && context.rng == rng {
+            let warnings = context.take_warnings();
+            for warning in &warnings {
+                context.note(warning.clone());
+            }
+            self.results.insert(raw_expression.to_string(), (result.clone(), warnings));
+        }
+
+        result
+    }
+}
+
+
+///
+/// Render the result of a computation, along with the warnings and dice
+/// rolls recorded while computing it. Results are written to standard output
+/// and, in the text formats, errors and warnings to standard error.
+/// 
+fn emit(source: &str, result: &Result<Vec<(String, Value)>, Vec<pxpr::Error>>, renderer: &Renderer, context: &mut Context) {
+    print_vm_trace(&context.take_vm_trace());
+    let rendered = renderer.render_tuple(source, result, &context.take_warnings(), &context.take_rolls());
+    print!("{}", rendered.output);
+    eprint!("{}", rendered.diagnostics);
+}
+
+
+///
+/// Print the instructions the bytecode backend ran for an expression, with
+/// the stack each left, as recorded with `--trace-vm`. The trace goes to
+/// standard error, so that results stay readable by other programs.
+/// 
+fn print_vm_trace(trace: &[String]) {
+    for line in trace {
+        eprintln!("\t{}", line);
+    }
+}
+
+
+///
+/// Print the allocations made by each stage since they were last printed.
+/// 
+fn print_alloc_stats(stats: &Stats) {
+    if !stats::enabled() {
+        println!("\tallocation statistics require building pxpr with `--features profiling`");
+        return;
+    }
+
+    for (stage, counters) in [("lexer", stats.lexer), ("parser", stats.parser), ("evaluator", stats.evaluator)] {
+        println!("\t{:<9} {} allocation(s), {} byte(s)", stage, counters.allocations, counters.bytes);
+    }
+}
+
+
+///
+/// Print the time spent by each stage of computing expressions, in the
+/// unit which suits it, such as `12.500µs` or `3.200ms`.
+/// 
+fn print_times(stats: &Stats) {
+    for (stage, counters) in [("lexer", stats.lexer), ("parser", stats.parser), ("evaluator", stats.evaluator)] {
+        println!("\t{:<9} {:.3?}", stage, counters.time);
+    }
+}
+
+
+///
+/// Print the statistics requested on the command line, or with `.timer` in
+/// the REPL, for the expressions computed since they were last printed.
+/// 
+fn print_statistics(options: &Options, show_time: bool, context: &mut Context) {
+    if options.profile {
+        let profile = context.profile.replace(Profile::default()).unwrap_or_default();
+        for (operator, count) in profile.operators {
+            println!("	{:<9} {} time(s)", operator, count);
+        }
+        for (function, count) in profile.functions {
+            println!("	{:<9} {} call(s)", format!("{}()", function), count);
+        }
+    }
+
+    let show_allocations = options.alloc_stats || options.profile;
+    if !show_allocations && !show_time {
+        return;
+    }
+
+    let stats = stats::take();
+    if show_time {
+        print_times(&stats);
+    }
+    if show_allocations {
+        print_alloc_stats(&stats);
+    }
+}
+
+
+///
+/// Handle a `.timer` REPL command, which switches showing the time each
+/// stage of computing an expression took on or off: `.timer on|off`.
+/// 
+fn set_timer(argument: &str, show_time: &mut bool) -> Result<(), String> {
+    *show_time = match argument {
+        "on" => true,
+        "off" => false,
+        _ => return Err(String::from("Expected 'on' or 'off' after '.timer'")),
+    };
+
+    // The time spent before the timer was switched on is not shown.
+    stats::take();
+    Ok(())
+}
+
+
+///
+/// Handle a `.mode` REPL command, which switches the display mode.
+/// 
+fn set_mode(argument: &str, mode: &mut DisplayMode) -> Result<(), String> {
+    match argument {
+        "normal" => *mode = DisplayMode::Normal,
+        "prog" => *mode = DisplayMode::Programmer,
+        _ => return Err(format!("Unknown mode: '{}' (expected 'normal' or 'prog')", argument)),
+    }
+
+    Ok(())
+}
+
+
+///
+/// Handle a `.set` REPL command, which changes a setting: `.set group on|off`,
+/// `.set types on|off` or `.set bits on|off`.
+/// 
+fn set_option(argument: &str, renderer: &mut Renderer) -> Result<(), String> {
+    let (name, value) = argument.split_once(char::is_whitespace).unwrap_or((argument, ""));
+    let enabled = match value.trim() {
+        "on" => true,
+        "off" => false,
+        _ => return Err(format!("Expected 'on' or 'off' after '.set {}'", name)),
+    };
+
+    match name {
+        "group" => renderer.formatter.group_digits = enabled,
+        "types" => renderer.formatter.show_types = enabled,
+        "bits" => renderer.show_bits = enabled,
+        _ => return Err(format!("Unknown setting: '{}' (expected 'group', 'types' or 'bits')", name)),
+    }
+
+    Ok(())
+}
+
+
+///
+/// Handle a `.prefix` REPL command, which chooses what comes before each
+/// result: `.prefix equals|expression|none`.
+/// 
+fn set_prefix(argument: &str, prefix: &mut ResultPrefix) -> Result<(), String> {
+    *prefix = argument.parse()?;
+    Ok(())
+}
+
+
+///
+/// Handle a `.compat` REPL command, which switches the numeric semantics.
+/// 
+fn set_compat(argument: &str, context: &mut Context) -> Result<(), String> {
+    context.compat = argument.parse()?;
+    Ok(())
+}
+
+
+///
+/// The commands of the REPL, with the arguments they take and what they do,
+/// as `.help` lists them.
+/// 
+const REPL_COMMANDS: &[(&str, &str, &str)] = &[
+    (".help", "", "list the commands"),
+    (".vars", "", "list the constants and variables with their values"),
+    (".ast", "EXPR", "show the syntax tree of an expression"),
+    (".parens", "EXPR", "show an expression with every operation in parentheses"),
+    (".tokens", "EXPR", "show the tokens of an expression"),
+    (".type", "EXPR", "show the type of the value of an expression, such as '.type ans'"),
+    (".mode", "normal|prog", "switch between normal and programmer mode"),
+    (".set", "group|types|bits on|off", "change a display setting"),
+    (".bits", "", "switch the bit ruler of integer results on or off"),
+    (".prefix", "equals|expression|none", "choose what comes before each result"),
+    (".compat", "current|0.x", "switch the numeric semantics"),
+    (".timer", "on|off", "show the time each stage of computing an expression takes"),
+    (".clear", "", "restore the variables and settings the REPL started with"),
+    (".record", "[FILE]", "record the following lines to a file, or stop recording"),
+    (".replay", "FILE", "enter the lines of a recording again and report changed results"),
+    (".save", "FILE", "write the lines of the session which succeeded to a file"),
+    (".load", "FILE", "enter the lines of a file saved by '.save' into the session"),
+    (".quit", "", "leave the REPL"),
+];
+
+
+///
+/// Split a line entered in the REPL into the name of a command and its
+/// argument, or get `None` if the line is an expression.
+/// 
+fn parse_command(input: &str) -> Option<(&str, &str)> {
+    let is_command = input.strip_prefix('.').is_some_and(|rest| rest.starts_with(|ch: char| ch.is_ascii_alphabetic()));
+    match is_command {
+        true => {
+            let (name, argument) = input.split_once(char::is_whitespace).unwrap_or((input, ""));
+            Some((name, argument.trim()))
+        },
+        false => None,
+    }
+}
+
+
+///
+/// Handle a `.help` REPL command, which lists the commands.
+/// 
+fn list_commands() -> String {
+    REPL_COMMANDS.iter()
+        .map(|(name, arguments, description)| format!("{:<32}{}\n", format!("{} {}", name, arguments), description))
+        .collect()
+}
+
+
+///
+/// Handle a `.vars` REPL command, which lists the constants and then the
+/// variables, each with its value.
+/// 
+fn list_variables(renderer: &Renderer, context: &Context) -> String {
+    let constants = builtins::constant_names().filter_map(|name| builtins::constant(name).map(|value| (name, value)));
+    let variables = context.variable_names().filter_map(|name| context.variable(name).map(|value| (name, value.clone())));
+
+    constants.chain(variables)
+        .map(|(name, value)| format!("{} = {}\n", name, renderer.formatter.format(&value)))
+        .collect()
+}
+
+
+///
+/// Handle a `.ast` REPL command, which shows the syntax tree an expression
+/// is parsed into.
+/// 
+fn show_ast(argument: &str, context: &Context) -> Result<String, String> {
+    if argument.is_empty() {
+        return Err(String::from("Expected an expression after '.ast'"));
+    }
+
+    parse_expression_for(argument, context)
+        .map(|tree| tree.to_outline())
+        .map_err(|error| error_line(&error.located_in(argument), false))
+}
+
+
+///
+/// Handle a `.parens` REPL command, which shows an expression with every
+/// operation in parentheses, to see which operator binds tighter.
+/// 
+fn show_parentheses(argument: &str, context: &Context) -> Result<String, String> {
+    if argument.is_empty() {
+        return Err(String::from("Expected an expression after '.parens'"));
+    }
+
+    parse_expression_for(argument, context)
+        .map(|tree| format!("{}\n", tree.to_parenthesized_source()))
+        .map_err(|error| error_line(&error.located_in(argument), false))
+}
+
+
+///
+/// Handle a `.type` REPL command, which shows the type of the value an
+/// expression computes, such as `.type ans`, or of each value of a tuple.
+/// The expression is computed in a copy of the session, so that it changes
+/// nothing, not even the next random number.
+/// 
+fn show_type(argument: &str, context: &Context) -> Result<String, String> {
+    if argument.is_empty() {
+        return Err(String::from("Expected an expression after '.type'"));
+    }
+
+    let values = compute_tuple(argument, &mut context.fork()).map_err(|error| error_line(&error.located_in(argument), false))?;
+    match values.as_slice() {
+        [(_, value)] => Ok(format!("{}\n", value.type_name())),
+        _ => Ok(values.iter().map(|(source, value)| format!("{} : {}\n", source, value.type_name())).collect()),
+    }
+}
+
+
+///
+/// Handle a `.tokens` REPL command, which shows the tokens an expression
+/// is split into, each with its span.
+/// 
+fn show_tokens(argument: &str, context: &Context) -> Result<String, String> {
+    if argument.is_empty() {
+        return Err(String::from("Expected an expression after '.tokens'"));
+    }
+
+    let mut lexer = Lexer::new(argument).with_number_format(context.number_format);
+    let tokens = lexer.tokenize().map_err(|error| error_line(&error.clone().located_in(argument), false))?;
+    Ok(tokens.iter().map(|token| format!("{:<8}{}\n", token.span.to_string(), token)).collect())
+}
+
+
+///
+/// The outcome of a line entered in the REPL.
+/// 
+enum Entry {
+    /// A command, with the text it shows, or the message to show if it failed.
+    Command(Result<String, String>),
+
+    /// An expression, with its result and the warnings and dice rolls recorded
+    /// while computing it.
+    Computation(String, Result<Vec<(String, Value)>, Vec<pxpr::Error>>, Vec<Warning>, Vec<Roll>)
+}
+
+
+impl Entry {
+    fn render(&self, renderer: &Renderer) -> Rendered {
+        match self {
+            Entry::Command(Ok(text)) => Rendered { output: text.clone(), diagnostics: String::new() },
+            // Programs reading the output expect every line in the same format.
+            Entry::Command(Err(message)) if renderer.format.is_machine_readable() => {
+                renderer.render(&Err(pxpr::Error::usage(0, message.clone())), &[], &[])
+            },
+            Entry::Command(Err(message)) => Rendered { output: format!("{}\n", message), diagnostics: String::new() },
+            Entry::Computation(source, result, warnings, rolls) => renderer.render_tuple(source, result, warnings, rolls),
+        }
+    }
+
+
+    ///
+    /// Get the text the entry is recorded with by `.record`, which is its
+    /// output followed by its diagnostics, as plain text.
+    /// 
+    fn recorded_text(&self, renderer: &Renderer) -> String {
+        let plain = Renderer { format: OutputFormat::Plain, ..renderer.clone() };
+        let rendered = self.render(&plain);
+        rendered.output + &rendered.diagnostics
+    }
+
+
+    ///
+    /// Check whether `.save` keeps the line of the entry: an expression which
+    /// computed, or a command which changed a setting, which are the commands
+    /// showing nothing when they succeed.
+    /// 
+    fn is_saved(&self) -> bool {
+        match self {
+            Entry::Command(result) => result.as_ref().is_ok_and(String::is_empty),
+            Entry::Computation(_, result, _, _) => result.is_ok(),
+        }
+    }
+}
+
+
+///
+/// The variables holding the last value computed in the REPL, so that the
+/// next expression can build on it, as in `ans * 1.08`.
+/// 
+const PREVIOUS_RESULT: [&str; 2] = ["ans", "_"];
+
+
+///
+/// Handle a line entered in the REPL, which is either a command or an
+/// expression. The last value of an expression which computes is bound
+/// to `ans` and `_`.
+/// 
+fn enter_line(input: &str, renderer: &mut Renderer, context: &mut Context) -> Entry {
+    let Some((name, argument)) = parse_command(input) else {
+        let result = compute(input, context);
+        print_vm_trace(&context.take_vm_trace());
+        if let Some((_, value)) = result.as_ref().ok().and_then(|values| values.last()) {
+            for name in PREVIOUS_RESULT {
+                context.define(name, value.clone());
+            }
+        }
+        return Entry::Computation(input.to_string(), result, context.take_warnings(), context.take_rolls());
+    };
+
+    // Commands changing a setting show nothing when they succeed.
+    let quiet = |result: Result<(), String>| result.map(|()| String::new());
+
+    Entry::Command(match name {
+        ".help" => Ok(list_commands()),
+        ".vars" => Ok(list_variables(renderer, context)),
+        ".ast" => show_ast(argument, context),
+        ".parens" => show_parentheses(argument, context),
+        ".tokens" => show_tokens(argument, context),
+        ".type" => show_type(argument, context),
+        ".mode" => quiet(set_mode(argument, &mut renderer.mode)),
+        ".set" => quiet(set_option(argument, renderer)),
+        ".bits" if argument.is_empty() => {
+            renderer.show_bits = !renderer.show_bits;
+            Ok(String::new())
+        },
+        ".bits" => Err(String::from("Expected nothing after '.bits' (use '.set bits on|off' to choose)")),
+        ".prefix" => quiet(set_prefix(argument, &mut renderer.prefix)),
+        ".compat" => quiet(set_compat(argument, context)),
+        _ => Err(format!("Unknown command: '{}' (enter '.help' to list the commands)", name)),
+    })
+}
+
+
+///
+/// Handle a `.record` REPL command. With a file name, every following line
+/// and its output are written to the file, after the commands restoring the
+/// current settings so that the recording replays the same in any session.
+/// Without one, recording stops.
+/// 
+fn record(argument: &str, recording: &mut Option<File>, renderer: &Renderer, context: &Context) -> Result<(), String> {
+    if argument.is_empty() {
+        *recording = None;
+        return Ok(());
+    }
+
+    let mode = match renderer.mode {
+        DisplayMode::Normal => "normal",
+        DisplayMode::Programmer => "prog",
+    };
+
+    let switch = |enabled| match enabled {
+        true => "on",
+        false => "off",
+    };
+
+    let settings = [
+        format!(".mode {}", mode),
+        format!(".prefix {}", renderer.prefix.name()),
+        format!(".set group {}", switch(renderer.formatter.group_digits)),
+        format!(".set types {}", switch(renderer.formatter.show_types)),
+        format!(".set bits {}", switch(renderer.show_bits)),
+        format!(".compat {}", context.compat),
+    ];
+
+    let mut file = File::create(argument).map_err(|error| format!("Cannot record to '{}': {}", argument, error))?;
+    for setting in settings {
+        writeln!(file, "{}{}", RECORDED_INPUT, setting).map_err(|error| format!("Cannot record to '{}': {}", argument, error))?;
+    }
+
+    *recording = Some(file);
+    Ok(())
+}
+
+
+///
+/// Split a recording into its entries: the line number of each input, the
+/// input, and the text which was recorded for it.
+/// 
+fn parse_recording(recording: &str) -> Vec<(usize, &str, String)> {
+    let mut entries: Vec<(usize, &str, String)> = vec![];
+
+    for (index, line) in recording.lines().enumerate() {
+        match (line.strip_prefix(RECORDED_INPUT), entries.last_mut()) {
+            (Some(input), _) => entries.push((index + 1, input, String::new())),
+            (None, Some((_, _, text))) => {
+                text.push_str(line);
+                text.push('\n');
+            },
+            (None, None) => {},
+        }
+    }
+
+    entries
+}
+
+
+///
+/// Handle a `.replay` REPL command, which enters every line of a recording
+/// again and checks that it produces the same output as when it was recorded.
+/// The lines are entered with the settings of the session, but do not change them.
+/// 
+fn replay(path: &str, renderer: &Renderer, context: &Context) -> Result<(), String> {
+    let recording = fs::read_to_string(path).map_err(|error| format!("Cannot replay '{}': {}", path, error))?;
+
+    let mut renderer = renderer.clone();
+    let session = context;
+    let mut context = Context::new();
+    context.bit_width = session.bit_width;
+    context.strict = session.strict;
+    context.compat = session.compat;
+    context.pedantic_compat = session.pedantic_compat;
+    context.rng = session.rng.clone();
+    context.number_format = session.number_format;
+    context.sanitize = session.sanitize;
+    context.backend = session.backend;
+
+    let entries = parse_recording(&recording);
+    let mut mismatches = 0;
+
+    for (line_number, input, expected) in &entries {
+        let actual = enter_line(input, &mut renderer, &mut context).recorded_text(&renderer);
+        if actual != *expected {
+            mismatches += 1;
+            println!("{}:{}: {}", path, line_number, input);
+            println!("  expected:\n{}  actual:\n{}", expected, actual);
+        }
+    }
+
+    println!("Replayed {} line(s) from '{}': {} mismatch(es)", entries.len(), path, mismatches);
+    Ok(())
+}
+
+
+///
+/// Handle a `.save` REPL command, which writes the lines of the session
+/// which succeeded to a file, one per line, so that `.load` can enter them
+/// again in another session.
+/// 
+fn save(path: &str, lines: &[String]) -> Result<(), String> {
+    if path.is_empty() {
+        return Err(String::from("Expected a file name after '.save'"));
+    }
+
+    let contents: String = lines.iter().map(|line| format!("{}\n", line)).collect();
+    fs::write(path, contents).map_err(|error| format!("Cannot save to '{}': {}", path, error))
+}
+
+
+///
+/// Handle a `.load` REPL command, which enters every line of a file into
+/// the session as if it were typed, showing the result of each, and keeps
+/// the lines which succeed for the next `.save`. Blank lines, and lines
+/// holding only comments, are skipped.
+/// 
+fn load(path: &str, lines: &mut Vec<String>, renderer: &mut Renderer, context: &mut Context) -> Result<(), String> {
+    if path.is_empty() {
+        return Err(String::from("Expected a file name after '.load'"));
+    }
+
+    let contents = fs::read_to_string(path).map_err(|error| format!("Cannot load '{}': {}", path, error))?;
+    for line in contents.lines().map(str::trim) {
+        if is_blank(line) {
+            continue;
+        }
+
+        let entry = enter_line(line, renderer, context);
+        if entry.is_saved() {
+            lines.push(line.to_string());
+        }
+
+        let rendered = entry.render(renderer);
+        print!("{}", rendered.output);
+        eprint!("{}", rendered.diagnostics);
+    }
+
+    Ok(())
+}
+
+
+///
+/// Check whether an expression entered in the REPL is unfinished, having
+/// more `(` than `)` or ending with a binary operator, so that it carries
+/// on over the next line.
+/// 
+fn is_unfinished(input: &str, number_format: NumberFormat) -> bool {
+    let mut lexer = Lexer::new(input).with_number_format(number_format);
+    let (tokens, _) = lexer.tokenize_recovering();
+
+    let depth: i32 = tokens.iter()
+        .map(|token| match token.type_ {
+            TokenType::LeftParen => 1,
+            TokenType::RightParen => -1,
+            _ => 0,
+        })
+        .sum();
+
+    let last = tokens.iter().rev().find(|token| !matches!(token.type_, TokenType::Eof));
+    let ends_with_operator = last.is_some_and(|token| matches!(token.type_,
+        TokenType::Plus | TokenType::Minus | TokenType::Asterisk | TokenType::Slash | TokenType::Modulus |
+        TokenType::And | TokenType::Or | TokenType::If |
+        TokenType::Equal | TokenType::NotEqual | TokenType::Less | TokenType::LessEqual | TokenType::Greater | TokenType::GreaterEqual |
+        TokenType::BitwiseAnd | TokenType::BitwiseOr | TokenType::BitwiseXor | TokenType::BitwiseLeftShift | TokenType::BitwiseRightShift |
+        TokenType::Min | TokenType::Max | TokenType::Comma | TokenType::When));
+
+    depth > 0 || ends_with_operator
+}
+
+
+///
+/// Highlights the expression being typed at the REPL prompt.
+/// 
+struct InputHighlighter {
+    number_format: NumberFormat,
+    colored: bool
+}
+
+
+impl Highlighter for InputHighlighter {
+    fn highlight<'l>(&self, line: &'l str, _position: usize) -> Cow<'l, str> {
+        match self.colored && parse_command(line).is_none() {
+            true => Cow::Owned(highlight(line, self.number_format)),
+            false => Cow::Borrowed(line),
+        }
+    }
+
+
+    // Any change, even moving the cursor, can change how a line is highlighted.
+    fn highlight_char(&self, _line: &str, _position: usize, _kind: CmdKind) -> bool {
+        self.colored
+    }
+}
+
+
+impl Completer for InputHighlighter {
+    type Candidate = String;
+}
+
+
+impl Hinter for InputHighlighter {
+    type Hint = String;
+}
+
+
+impl Validator for InputHighlighter {}
+
+
+impl Helper for InputHighlighter {}
+
+
+///
+/// The environment variable naming the file the REPL history is kept in.
+/// 
+const HISTORY_VARIABLE: &str = "PXPR_HISTORY";
+
+
+///
+/// Get the file the REPL history is kept in across sessions, which is
+/// `PXPR_HISTORY`, or else `.pxpr_history` in the home directory. An empty
+/// `PXPR_HISTORY` keeps the history of each session to itself.
+/// 
+fn history_path() -> Option<PathBuf> {
+    match std::env::var_os(HISTORY_VARIABLE) {
+        Some(path) => (!path.is_empty()).then(|| PathBuf::from(path)),
+        None => std::env::var_os("HOME").map(|home| PathBuf::from(home).join(".pxpr_history")),
+    }
+}
+
+
+///
+/// Create the line editor of the REPL, with the history of earlier sessions.
+/// 
+fn create_editor(options: &Options, colored: bool, history: Option<&PathBuf>) -> Result<Editor<InputHighlighter, DefaultHistory>, ReadlineError> {
+    let config = Config::builder()
+        .max_history_size(options.history_size)?
+        .history_ignore_dups(!options.keep_history_dups)?
+        .build();
+
+    let mut editor = Editor::with_config(config)?;
+    editor.set_helper(Some(InputHighlighter { number_format: options.number_format, colored }));
+
+    // There is no history yet the first time the REPL is started.
+    match history.map(|path| editor.load_history(path)) {
+        Some(Err(ReadlineError::Io(error))) if error.kind() == io::ErrorKind::NotFound => Ok(editor),
+        Some(Err(error)) => Err(error),
+        _ => Ok(editor),
+    }
+}
+
+
+///
+/// Continouously reads lines from the user until the specified exit command
+/// is entered. Then for every line entered, considers that line to be an expression,
+/// and then computes the result_value of that expression.
+/// 
+fn run_repl(options: &Options, context: &mut Context) {
+    let initial = context.fork();
+    let mut renderer = options.renderer.clone();
+    let mut summary = SessionSummary::default();
+    let mut recording: Option<File> = None;
+    let mut saved_lines: Vec<String> = vec![];
+    let mut show_time = options.time;
+
+    let history = history_path();
+    let colored = options.color.enabled(io::stdout().is_terminal()) && !renderer.format.is_machine_readable();
+    let mut editor = match create_editor(options, colored, history.as_ref()) {
+        Ok(editor) => editor,
+        Err(error) => {
+            print_error(&format!("Cannot read from the terminal: {}", error), ColorChoice::Auto);
+            return;
+        }
+    };
+
+    if let Some(header) = renderer.header() {
+        print!("{}", header);
+    }
+
+    'repl: loop {
+        // The prompt would corrupt output meant for other programs.
+        let (prompt, continuation) = match renderer.format.is_machine_readable() {
+            true => ("", ""),
+            false => ("expr > ", "... > "),
+        };
+
+        // Ctrl-C abandons the line being entered, while Ctrl-D on an empty
+        // line, or the end of the input, leaves the REPL.
+        let mut input = match editor.readline(prompt) {
+            Ok(line) => line.trim().to_string(),
+            Err(ReadlineError::Interrupted) => continue 'repl,
+            Err(ReadlineError::Eof) => break 'repl,
+            Err(error) => {
+                print_error(&format!("Cannot read from the terminal: {}", error), ColorChoice::Auto);
+                break 'repl;
+            }
+        };
+
+        // An unfinished expression carries on over the following lines,
+        // until it is finished or an empty line is entered.
+        while parse_command(&input).is_none() && is_unfinished(&input, context.number_format) {
+            match editor.readline(continuation) {
+                Ok(line) if line.trim().is_empty() => break,
+                Ok(line) => {
+                    input.push(' ');
+                    input.push_str(line.trim());
+                },
+                Err(ReadlineError::Interrupted) => continue 'repl,
+                Err(_) => break,
+            }
+        }
+        let input = input.as_str();
+
+        // A blank line is not an expression, so there is nothing to print.
+        if is_blank(input) {
+            continue;
+        }
+
+        // Up and down, or Ctrl-R to search, recall the lines entered earlier.
+        let _ = editor.add_history_entry(input);
+
+        // The commands acting on the session itself are not recorded.
+        let command = match parse_command(input) {
+            Some((".quit", _)) => break 'repl,
+            Some((".record", argument)) => Some(record(argument, &mut recording, &renderer, context)),
+            Some((".replay", argument)) => Some(replay(argument, &renderer, context)),
+            Some((".save", argument)) => Some(save(argument, &saved_lines)),
+            Some((".load", argument)) => Some(load(argument, &mut saved_lines, &mut renderer, context)),
+            Some((".timer", argument)) => Some(set_timer(argument, &mut show_time)),
+            Some((".clear", _)) => {
+                let profile = context.profile.take();
+                *context = initial.fork();
+                context.profile = profile;
+                renderer = options.renderer.clone();
+                saved_lines.clear();
+                show_time = options.time;
+                Some(Ok(()))
+            },
+            _ => None,
+        };
+
+        if let Some(result) = command {
+            print!("{}", Entry::Command(result.map(|()| String::new())).render(&renderer).output);
+            continue;
+        }
+
+        let start = Instant::now();
+        let entry = enter_line(input, &mut renderer, context);
+        let time = start.elapsed();
+
+        if let Entry::Computation(_, result, _, _) = &entry {
+            summary.record(result.is_err(), time, context.profile.as_ref());
+            if !options.profile {
+                context.profile = context.profile.as_ref().map(|_| Profile::default());
+            }
+        }
+
+        if entry.is_saved() {
+            saved_lines.push(input.to_string());
+        }
+
+        let rendered = entry.render(&renderer);
+        print!("{}", rendered.output);
+        eprint!("{}", rendered.diagnostics);
+
+        if let Some(file) = &mut recording {
+            let written = write!(file, "{}{}\n{}", RECORDED_INPUT, input, entry.recorded_text(&renderer));
+            if let Err(error) = written {
+                println!("Stopped recording: {}", error);
+                recording = None;
+            }
+        }
+
+        if let Entry::Computation(..) = entry {
+            print_statistics(options, show_time, context);
+        }
+    }
+
+    if let Some((path, Err(error))) = history.as_ref().map(|path| (path, editor.append_history(path))) {
+        print_error(&format!("Cannot save the history to '{}': {}", path.display(), error), ColorChoice::Auto);
+    }
+
+    if options.summary {
+        print!("{}", summary);
+    }
+}
+
+
+///
+/// Compute every line of standard input as a separate expression, such as
+/// when expressions are piped into pxpr. Blank lines, and lines holding only comments, are skipped.
+/// 
+fn run_batch(options: &Options, context: &mut Context) -> Result<i32, String> {
+    if let Some(header) = options.renderer.header() {
+        print!("{}", header);
+    }
+
+    let mut cache = Cache::new(options.cache && !options.trace_vm);
+    let mut status = EXIT_SUCCESS;
+    for line in io::stdin().lines() {
+        let line = line.map_err(|error| format!("Cannot read standard input: {}", error))?;
+        if is_blank(&line) {
+            continue;
+        }
+
+        let computation_result = cache.compute(line.trim(), context);
+        status = first_failure(status, &computation_result);
+        emit(line.trim(), &computation_result, &options.renderer, context);
+    }
+
+    print_statistics(options, options.time, context);
+    Ok(status)
+}
+
+
+///
+/// How many lines of standard input `--batch` reads before computing them,
+/// so that a long input is not held in memory all at once.
+/// 
+const BATCH_CHUNK: usize = 8192;
+
+
+///
+/// A line computed by `--batch` on one of its threads, along with the
+/// statistics and profile the thread recorded while computing it.
+/// 
+struct BatchLine {
+    result: Computation,
+    rendered: Rendered,
+    stats: Stats,
+    profile: Option<Profile>,
+    vm_trace: Vec<String>
+}
+
+
+///
+/// Compute every line of standard input as a separate expression, as
+/// `run_batch` does, but across a pool of threads, printing the results in
+/// the order of the lines. Each thread computes in a context of its own with
+/// the settings and variables of `context`. The random numbers of a line are
+/// drawn from a generator split from that of `context` by the line number,
+/// so that with `--seed` a line gets the same numbers whichever thread
+/// computes it.
+/// 
+fn run_parallel_batch(options: &Options, context: &mut Context) -> Result<i32, String> {
+    if let Some(header) = options.renderer.header() {
+        print!("{}", header);
+    }
+
+    let compat = context.compat;
+    let variables: Vec<(String, Value)> = context.variable_names()
+        .filter_map(|name| context.variable(name).map(|value| (name.to_string(), value.clone())))
+        .collect();
+    let rng = context.rng.clone();
+    let worker_context = || {
+        let mut worker = new_context(options, compat);
+        for (name, value) in &variables {
+            worker.define(name.clone(), value.clone());
+        }
+        worker
+    };
+
+    let mut lines = io::stdin().lines().enumerate();
+    let mut status = EXIT_SUCCESS;
+    loop {
+        let chunk = lines.by_ref()
+            .take(BATCH_CHUNK)
+            .map(|(number, line)| line.map(|line| (number, line)))
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(|error| format!("Cannot read standard input: {}", error))?;
+        if chunk.is_empty() {
+            break;
+        }
+
+        let computed: Vec<BatchLine> = chunk.par_iter()
+            .filter(|(_, line)| !is_blank(line))
+            .map_init(worker_context, |worker, (number, line)| {
+                worker.rng = rng.split(*number as u64);
+                let result = compute(line.trim(), worker);
+                let rendered = options.renderer.render_tuple(line.trim(), &result, &worker.take_warnings(), &worker.take_rolls());
+                let profile = worker.profile.replace(Profile::default());
+                BatchLine { result, rendered, stats: stats::take(), profile, vm_trace: worker.take_vm_trace() }
+            })
+            .collect();
+
+        for line in computed {
+            status = first_failure(status, &line.result);
+            print_vm_trace(&line.vm_trace);
+            print!("{}", line.rendered.output);
+            eprint!("{}", line.rendered.diagnostics);
+            stats::add(&line.stats);
+            if let (Some(profile), Some(line_profile)) = (&mut context.profile, &line.profile) {
+                profile.merge(line_profile);
+            }
+        }
+    }
+
+    print_statistics(options, options.time, context);
+    Ok(status)
+}
 
 
-pub mod pxpr {
-    pub struct Error {
-        column: u32,
-        message: String
+///
+/// Split a line of an expression file into its statements, which are
+/// separated by `;`. A `;` inside parentheses or between the cases of a
+/// conditional chain does not separate statements. Each statement is the
+/// whole line with the other statements blanked out, so that its columns
+/// count from the start of the line.
+/// 
+fn split_statements(line: &str, number_format: NumberFormat) -> Vec<String> {
+    let mut lexer = Lexer::new(line).with_number_format(number_format);
+    let (tokens, _) = lexer.tokenize_recovering();
+
+    let mut separators = vec![];
+    let mut depth = 0;
+    let mut open_guards = 0;
+    for token in tokens {
+        match token.type_ {
+            TokenType::LeftParen => depth += 1,
+            TokenType::RightParen => depth -= 1,
+            TokenType::When if depth == 0 => open_guards += 1,
+            TokenType::Semicolon if depth == 0 && open_guards > 0 => open_guards -= 1,
+            TokenType::Semicolon if depth == 0 => separators.push(token.column as usize),
+            _ => {},
+        }
+    }
+
+    let characters: Vec<char> = line.chars().collect();
+    let mut bounds = vec![0];
+    bounds.extend(separators.iter().flat_map(|&separator| [separator, separator + 1]));
+    bounds.push(characters.len());
+
+    bounds.chunks(2)
+        .map(|statement| characters.iter()
+            .enumerate()
+            .map(|(column, &ch)| match (statement[0]..statement[1]).contains(&column) {
+                true => ch,
+                false => ' ',
+            })
+            .collect())
+        .collect()
+}
+
+
+///
+/// Compute every statement of a file of expressions in order, sharing one
+/// context, and print each result labeled with its statement. Diagnostics
+/// in the text formats start with the file name and line number. Unless
+/// `--keep-going` is given, the first statement which fails stops the file.
+/// 
+/// # Returns
+/// The exit status for the first statement which failed, if any.
+/// 
+fn run_file(path: &str, options: &Options, context: &mut Context) -> Result<i32, String> {
+    let contents = fs::read_to_string(path).map_err(|error| format!("Cannot read '{}': {}", path, error))?;
+    let renderer = Renderer { label: true, ..options.renderer.clone() };
+    let mut cache = Cache::new(options.cache && !options.trace_vm);
+    let mut status = EXIT_SUCCESS;
+
+    if let Some(header) = renderer.header() {
+        print!("{}", header);
+    }
+
+    for (index, line) in contents.lines().enumerate() {
+        for statement in split_statements(line, context.number_format) {
+            if is_blank(&statement) {
+                continue;
+            }
+
+            let result = cache.compute(&statement, context);
+            print_vm_trace(&context.take_vm_trace());
+            // Diagnostics are located in the line, as the statement has the
+            // characters of the others blanked out, whatever their width.
+            let rendered = renderer.render_tuple(line, &result, &context.take_warnings(), &context.take_rolls());
+            print!("{}", rendered.output);
+            for diagnostic in rendered.diagnostics.lines() {
+                eprintln!("{}:{}: {}", path, index + 1, diagnostic);
+            }
+
+            status = first_failure(status, &result);
+            if status != EXIT_SUCCESS && !options.keep_going {
+                return Ok(status);
+            }
+        }
     }
 
-    impl Error {
-        pub fn new(column: u32, message: String) -> Self {
-            Error { column, message }
+    print_statistics(options, options.time, context);
+    Ok(status)
+}
+
+
+///
+/// Parse a raw tuple of expressions and check the types of its elements
+/// without computing them. If it cannot be parsed, every lexical and syntax
+/// error in it is found, as by `compute`.
+/// 
+/// # Returns
+/// Each element written as source with its type, or every error found.
+/// 
+fn check_types(raw_expression: &str, context: &mut Context) -> Result<Vec<(String, Type)>, Vec<pxpr::Error>> {
+    let source = sanitize(raw_expression, context);
+    context.take_warnings();
+
+    let elements = parse_tuple_for(&source, context).map_err(|error| match error.kind() {
+        ErrorKind::Lex | ErrorKind::Parse => parse_tuple_recovering_for(&source, context)
+            .err()
+            .filter(|errors| !errors.is_empty())
+            .unwrap_or_else(|| vec![error]),
+        _ => vec![error],
+    })?;
+
+    let mut types = vec![];
+    let mut errors = vec![];
+    for element in &elements {
+        match typecheck_for(element, context) {
+            Ok(element_type) => types.push((element.to_source(), element_type)),
+            Err(type_errors) => errors.extend(type_errors.into_iter().map(pxpr::Error::from)),
         }
     }
 
-    pub fn report_error(error: &Error) {
-        println!("Column {}: [ \x1b[31merror:\x1b[39m {}", &error.column, &error.message);
+    match errors.is_empty() {
+        true => Ok(types),
+        false => Err(errors),
     }
 }
 
 
 ///
-/// Compute a raw expression and get the result of the computation
+/// Check the types of expressions with `--check`, without computing them:
+/// the expression given, each statement of the file given with `--file`,
+/// or else each line of standard input. The type of each expression is
+/// printed as `.type` shows it, and the errors found to standard error,
+/// starting with the file name and line for a file.
 /// 
-/// # Arguments
-/// * `raw_expression` An immutable reference to the raw expression as a string.
+/// # Returns
+/// The exit status for the first expression with an error, if any.
 /// 
-/// # Return
-/// A `Result<f64, ApplicationError>` in which the `Ok()` value (`f64`) is the
-/// result of the computation and the error represents any error that happened during 
-/// computation of the expression.
+fn run_check(options: &Options, context: &mut Context) -> Result<i32, String> {
+    // Each input is kept with the line it is on, to locate its errors in.
+    let inputs: Vec<(usize, String, String)> = match (&options.file, options.expression.is_empty()) {
+        (Some(path), _) => {
+            let contents = fs::read_to_string(path).map_err(|error| format!("Cannot read '{}': {}", path, error))?;
+            contents.lines()
+                .enumerate()
+                .flat_map(|(index, line)| split_statements(line, context.number_format)
+                    .into_iter()
+                    .map(move |statement| (index + 1, statement, line.to_string())))
+                .collect()
+        },
+        (None, false) if options.each => options.expression.iter().map(|input| (0, input.clone(), input.clone())).collect(),
+        (None, false) => {
+            let input = options.expression.join(" ");
+            vec![(0, input.clone(), input)]
+        },
+        (None, true) => io::stdin().lines()
+            .map(|line| line.map(|line| (0, line.clone(), line)))
+            .collect::<Result<_, _>>()
+            .map_err(|error| format!("Cannot read standard input: {}", error))?,
+    };
+
+    let colored = options.color.enabled(io::stderr().is_terminal());
+    let mut status = EXIT_SUCCESS;
+    for (line, input, source) in inputs {
+        if is_blank(&input) {
+            continue;
+        }
+
+        let result = check_types(&input, context);
+        match &result {
+            Ok(types) => match types.as_slice() {
+                [(_, element_type)] => println!("{}", element_type),
+                _ => types.iter().for_each(|(source, element_type)| println!("{} : {}", source, element_type)),
+            },
+            Err(errors) => for error in errors {
+                let diagnostic = error_line(&error.clone().located_in(&source), colored);
+                match &options.file {
+                    Some(path) => eprintln!("{}:{}: {}", path, line, diagnostic),
+                    None => eprintln!("{}", diagnostic),
+                }
+            },
+        }
+        status = first_failure(status, &result.map(|_| vec![]));
+    }
+
+    Ok(status)
+}
+
+
+///
+/// Find the end of the `${...}` placeholder whose expression starts at
+/// `start`: the first `}` which is not inside a string.
 /// 
-fn compute_expression(raw_expression: &str) -> Result<Value, pxpr::Error> {
-    let mut tokenizer = Lexer::new(raw_expression);
+fn placeholder_end(characters: &[char], start: usize) -> Option<usize> {
+    let mut in_string = false;
+    let mut escaped = false;
+    for (index, &ch) in characters.iter().enumerate().skip(start) {
+        match (ch, in_string, escaped) {
+            (_, true, true) => escaped = false,
+            ('\\', true, false) => escaped = true,
+            ('"', _, _) => in_string = !in_string,
+            ('}', false, _) => return Some(index),
+            _ => {},
+        }
+    }
 
-    // Convert the expression to a stream of tokens.
-    let tokens = tokenizer.tokenize()?
-                    .into_iter()
-                    .map(|b| Rc::new(b.as_ref().clone()))
-                    .collect();
+    None
+}
+
+
+///
+/// Read text from standard input and write it to standard output with each
+/// `${...}` placeholder replaced by the value of the expression inside it.
+/// `$${` is written as a literal `${`. Nothing is written unless every
+/// placeholder is computed, so that a failure never leaves a partly filled
+/// in file; instead every error is written to standard error.
+/// 
+/// # Returns
+/// The exit status for the first placeholder which failed, if any.
+/// 
+fn run_template(options: &Options, context: &mut Context) -> Result<i32, String> {
+    let input = io::read_to_string(io::stdin()).map_err(|error| format!("Cannot read standard input: {}", error))?;
+    let characters: Vec<char> = input.chars().collect();
+    let formatter = &options.renderer.formatter;
+    let colored = options.renderer.format == OutputFormat::Colored;
 
-    let mut parser = Parser::new(&tokens);
+    let mut output = String::new();
+    let mut status = EXIT_SUCCESS;
+    let mut index = 0;
+    while index < characters.len() {
+        match &characters[index..] {
+            ['$', '$', '{', ..] => {
+                output.push_str("${");
+                index += 3;
+            },
+            ['$', '{', ..] => {
+                let start = index + 2;
+                let Some(end) = placeholder_end(&characters, start) else {
+                    let error = pxpr::Error::parse(index as u32, String::from("Unterminated placeholder")).located_in(&input);
+                    eprintln!("{}", error_line(&error, colored));
+                    return Ok(EXIT_SYNTAX);
+                };
 
-    // Convert the token stream to an abstract syntax tree.
-    let ast = parser.parse()?;
+                // Errors and warnings are located in the whole text rather
+                // than in the expression alone.
+                let expression: String = characters[start..end].iter().collect();
+                let result = compute_expression(&expression, context);
+                for warning in context.take_warnings() {
+                    let warning = Warning::new(start as u32 + warning.column(), warning.message().to_string()).located_in(&input);
+                    eprintln!("{}", warning_line(&warning, colored));
+                }
 
-    // Walk through the AST and compute the result.
-    let result_value = execute(&ast)?;
+                match result {
+                    Ok(value) => output.push_str(&formatter.format(&value)),
+                    Err(error) => {
+                        status = first_failure(status, &Err(vec![error.clone()]));
+                        let error = pxpr::Error::new(error.kind(), start as u32 + error.column(), error.message().to_string());
+                        eprintln!("{}", error_line(&error.located_in(&input), colored));
+                    },
+                }
+                index = end + 1;
+            },
+            [ch, ..] => {
+                output.push(*ch);
+                index += 1;
+            },
+            [] => break,
+        }
+    }
 
-    Ok(result_value)
+    if status == EXIT_SUCCESS {
+        print!("{}", output);
+    }
+    Ok(status)
 }
 
 
 ///
-/// Continouously reads lines from the user until the specified exit command
-/// is entered. Then for every line entered, considers that line to be an expression,
-/// and then computes the result_value of that expression.
+/// A variable given on the command line.
 /// 
-fn run_repl() {
-    let mut line = String::new();
-    'repl: loop {
-        print!("expr > ");
-        io::stdout().flush().unwrap();
+enum Definition {
+    /// `-D name=value`, whose value is an expression.
+    Expression(String, String),
+
+    /// `--env NAME`, whose value is read from the environment variable of the same name.
+    Environment(String)
+}
+
+
+///
+/// Read the `name=value` of a `-D` option.
+/// 
+fn parse_definition(definition: &str) -> Result<Definition, String> {
+    match definition.split_once('=') {
+        Some((name, value)) => Ok(Definition::Expression(name.trim().to_string(), value.to_string())),
+        None => Err(format!("Expected 'name=value' after '-D', found '{}'", definition)),
+    }
+}
+
+
+///
+/// Get the value of an environment variable as the integer, float or
+/// boolean it spells, or as a string otherwise.
+/// 
+fn environment_value(text: &str) -> Value {
+    match (text.parse::<i64>(), text.parse::<f64>(), text) {
+        (Ok(n), _, _) => Value::Integer(n),
+        (_, Ok(x), _) if x.is_finite() => Value::Float(x),
+        (_, _, "true") => Value::Boolean(true),
+        (_, _, "false") => Value::Boolean(false),
+        _ => Value::String(text.to_string()),
+    }
+}
+
+
+///
+/// Bind a variable given on the command line in the context. The value of
+/// `-D` is an expression, which can use the variables defined before it.
+/// 
+fn define(definition: &Definition, context: &mut Context) -> Result<(), String> {
+    let (name, value) = match definition {
+        Definition::Expression(name, source) => {
+            let value = compute_expression(source, context)
+                .map_err(|error| format!("Invalid value for '{}': {}", name, error.message()))?;
+            (name, value)
+        },
+        Definition::Environment(name) => {
+            let text = std::env::var(name).map_err(|_| format!("Environment variable '{}' is not set", name))?;
+            (name, environment_value(&text))
+        },
+    };
+
+    // A variable is named the same way as a constant, which is read as a
+    // single identifier, such as `r` or `π`.
+    let mut lexer = Lexer::new(name);
+    match lexer.tokenize().map(|tokens| tokens.as_slice()) {
+        Ok([token, _]) if matches!(token.type_, TokenType::Identifier) => context.define(token.lexeme.as_ref(), value),
+        _ => return Err(format!("Invalid variable name: '{}'", name)),
+    }
+
+    Ok(())
+}
+
+
+///
+/// Options given on the command line.
+/// 
+struct Options {
+    renderer: Renderer,
+    eval_range: Option<Span>,
+    bit_width: Option<u32>,
+    strict: bool,
+    strict_types: bool,
+    compat: Option<Compat>,
+    pedantic_compat: bool,
+    number_format: NumberFormat,
+    sanitize: bool,
+    backend: Backend,
+    trace_vm: bool,
+    limits: Limits,
+    seed: Option<u64>,
+    alloc_stats: bool,
+    time: bool,
+    profile: bool,
+    summary: bool,
+    history_size: usize,
+    keep_history_dups: bool,
+    file: Option<String>,
+    template: bool,
+    keep_going: bool,
+    cache: bool,
+    each: bool,
+    batch: bool,
+    check: bool,
+    help: bool,
+    color: ColorChoice,
+    overflow: OverflowPolicy,
+    definitions: Vec<Definition>,
+    expression: Vec<String>
+}
+
 
-        // Read an expression from the user
-        io::stdin()
-            .read_line(&mut line)
-            .unwrap();
+///
+/// Separate the command line flags from the words of the expression.
+/// 
+fn parse_options(arguments: &[String]) -> Result<Options, String> {
+    let mut options = Options { 
+        renderer: Renderer::default(), 
+        eval_range: None, 
+        bit_width: None,
+        strict: false,
+        strict_types: false,
+        compat: None,
+        pedantic_compat: false,
+        number_format: NumberFormat::default(),
+        sanitize: true,
+        backend: Backend::default(),
+        trace_vm: false,
+        limits: Limits::default(),
+        seed: None,
+        alloc_stats: false,
+        time: false,
+        profile: false,
+        summary: false,
+        history_size: 1000,
+        keep_history_dups: false,
+        file: None,
+        template: false,
+        keep_going: false,
+        cache: true,
+        each: false,
+        batch: false,
+        check: false,
+        help: false,
+        color: ColorChoice::default(),
+        overflow: OverflowPolicy::default(),
+        definitions: vec![],
+        expression: vec![] 
+    };
+
+    let mut arguments = arguments.iter();
+    while let Some(argument) = arguments.next() {
+        match argument.as_str() {
+            "--prog" => options.renderer.mode = DisplayMode::Programmer,
+            "--strict" => options.strict = true,
+            "--strict-types" => options.strict_types = true,
+            "--pedantic-compat" => options.pedantic_compat = true,
+            "--seed" => {
+                let seed = arguments.next().ok_or("Expected a number after '--seed'")?;
+                options.seed = Some(seed.parse().map_err(|_| format!("Invalid seed: '{}'", seed))?);
+            }
+            "--compat" => {
+                let version = arguments.next().ok_or("Expected a version after '--compat'")?;
+                options.compat = Some(version.parse()?);
+            }
+            "--no-sanitize" => options.sanitize = false,
+            "--backend" => {
+                let backend = arguments.next().ok_or("Expected a backend after '--backend'")?;
+                options.backend = backend.parse()?;
+            }
+            "--trace-vm" => {
+                options.backend = Backend::Vm;
+                options.trace_vm = true;
+            }
+            "--max-length" => {
+                let length = arguments.next().ok_or("Expected a number of characters after '--max-length'")?;
+                options.limits.max_length = Some(length.parse().map_err(|_| format!("Invalid length: '{}'", length))?);
+            }
+            "--max-depth" => {
+                let depth = arguments.next().ok_or("Expected a number of levels after '--max-depth'")?;
+                options.limits.max_depth = Some(depth.parse().map_err(|_| format!("Invalid depth: '{}'", depth))?);
+            }
+            "--max-steps" => {
+                let steps = arguments.next().ok_or("Expected a number of steps after '--max-steps'")?;
+                options.limits.max_steps = Some(steps.parse().map_err(|_| format!("Invalid number of steps: '{}'", steps))?);
+            }
+            "--timeout" => {
+                let timeout = arguments.next().ok_or("Expected a number of milliseconds after '--timeout'")?;
+                let milliseconds = timeout.parse().map_err(|_| format!("Invalid timeout: '{}'", timeout))?;
+                options.limits.timeout = Some(Duration::from_millis(milliseconds));
+            }
+            "--group" => options.renderer.formatter.group_digits = true,
+            "--show-types" => options.renderer.formatter.show_types = true,
+            "--decimal-comma" => {
+                options.number_format = NumberFormat::decimal_comma();
+                options.renderer.formatter.number_format = options.number_format;
+            }
+            "--locale" => {
+                let locale = arguments.next().ok_or("Expected a locale after '--locale'")?;
+                options.number_format = NumberFormat::from_locale(locale)?;
+                options.renderer.formatter.number_format = options.number_format;
+            }
+            "--precision" => {
+                let precision = arguments.next().ok_or("Expected a number of digits after '--precision'")?;
+                let precision = precision.parse().map_err(|_| format!("Invalid precision: '{}'", precision))?;
+                options.renderer.formatter.precision = Some(precision);
+            }
+            "--scientific" => options.renderer.formatter.notation = FloatNotation::Scientific,
+            "--engineering" => options.renderer.formatter.notation = FloatNotation::Engineering,
+            "--alloc-stats" => options.alloc_stats = true,
+            "--time" => options.time = true,
+            "--profile-expr" => options.profile = true,
+            "--summary" => options.summary = true,
+            "--history-size" => {
+                let size = arguments.next().ok_or("Expected a number of lines after '--history-size'")?;
+                options.history_size = size.parse().map_err(|_| format!("Invalid history size: '{}'", size))?;
+            }
+            "--history-dups" => {
+                let dups = arguments.next().ok_or("Expected 'keep' or 'ignore' after '--history-dups'")?;
+                options.keep_history_dups = match dups.as_str() {
+                    "keep" => true,
+                    "ignore" => false,
+                    _ => return Err(format!("Unknown history duplicates: '{}' (expected 'keep' or 'ignore')", dups)),
+                };
+            }
+            "--show-rolls" => options.renderer.show_rolls = true,
+            "--show-bits" => options.renderer.show_bits = true,
+            "--file" => {
+                let path = arguments.next().ok_or("Expected a file after '--file'")?;
+                options.file = Some(path.clone());
+            }
+            "--keep-going" => options.keep_going = true,
+            "--template" => options.template = true,
+            "--no-cache" => options.cache = false,
+            "--each" => options.each = true,
+            "--batch" => options.batch = true,
+            "--check" => options.check = true,
+            "--help" => options.help = true,
+            "--overflow" => {
+                let specification = arguments.next().ok_or("Expected a policy after '--overflow'")?;
+                options.overflow.configure(specification)?;
+            }
+            "--prefix" => {
+                let prefix = arguments.next().ok_or("Expected a prefix after '--prefix'")?;
+                options.renderer.prefix = prefix.parse()?;
+            }
+            "--output" => {
+                let format = arguments.next().ok_or("Expected a format after '--output'")?;
+                options.renderer.format = format.parse()?;
+
+                // `plain` and `color` are the same text with and without highlighting.
+                options.color = match options.renderer.format {
+                    OutputFormat::Plain => ColorChoice::Never,
+                    OutputFormat::Colored => ColorChoice::Always,
+                    OutputFormat::Json | OutputFormat::Csv => options.color,
+                };
+            }
+            "--color" => {
+                let choice = arguments.next().ok_or("Expected 'auto', 'always' or 'never' after '--color'")?;
+                options.color = choice.parse()?;
+            }
+            "--eval-range" => {
+                let range = arguments.next().ok_or("Expected a range after '--eval-range'")?;
+                options.eval_range = Some(range.parse()?);
+            }
+            "--bits" => {
+                let bits = arguments.next().ok_or("Expected a bit width after '--bits'")?;
+                match bits.as_str() {
+                    "8" | "16" | "32" | "64" => {
+                        options.bit_width = bits.parse().ok();
+                        options.renderer.formatter.bit_width = options.bit_width;
+                    }
+                    _ => return Err(format!("Invalid bit width: '{}' (expected 8, 16, 32 or 64)", bits)),
+                }
+            }
 
-        // If the user entered the quit command, break out of the REPL.
-        if line.trim() == String::from(".quit") {
-            break 'repl;
+            // Everything after `--` is part of the expression.
+            "--" => options.expression.extend(arguments.by_ref().cloned()),
+
+            "-D" => {
+                let definition = arguments.next().ok_or("Expected 'name=value' after '-D'")?;
+                options.definitions.push(parse_definition(definition)?);
+            }
+            flag if flag.starts_with("-D") => options.definitions.push(parse_definition(&flag[2..])?),
+            "--env" => {
+                let names = arguments.next().ok_or("Expected the names of environment variables after '--env'")?;
+                options.definitions.extend(names.split(',').map(|name| Definition::Environment(name.to_string())));
+            }
+            flag if flag.starts_with("--color=") => options.color = flag["--color=".len()..].parse()?,
+            flag if flag.starts_with("--") => return Err(format!("Unknown option: '{}'", flag)),
+            _ => options.expression.push(argument.clone()),
         }
+    }
 
-        // Tokenize the input string.
-        let computation_result = compute_expression(line.trim());
+    if options.batch && (options.file.is_some() || options.template || !options.expression.is_empty()) {
+        return Err(String::from("'--batch' computes the lines of standard input, and takes no expression, '--file' or '--template'"));
+    }
 
-        match computation_result {
-            Ok(result_value) => {
-                println!("\t= {}", result_value)
-            },
-            Err(e) => {
-                report_error(&e);
+    if options.trace_vm && options.backend != Backend::Vm {
+        return Err(String::from("'--trace-vm' traces the bytecode backend, and cannot be used with '--backend tree'"));
+    }
+
+    if options.check && (options.batch || options.template) {
+        return Err(String::from("'--check' checks expressions without computing them, and cannot be used with '--batch' or '--template'"));
+    }
+
+    // Results go to standard output, but only the errors and warnings on
+    // standard error are highlighted.
+    if !options.renderer.format.is_machine_readable() {
+        options.renderer.format = match options.color.enabled(io::stderr().is_terminal()) {
+            true => OutputFormat::Colored,
+            false => OutputFormat::Plain,
+        };
+    }
+
+    Ok(options)
+}
+
+
+///
+/// Write a failure of the CLI itself, such as an unknown option, to standard error.
+/// 
+fn print_error(message: &str, color: ColorChoice) {
+    eprintln!("{} {}", red("error:", color.enabled(io::stderr().is_terminal())), message);
+}
+
+
+///
+/// Run the differential self-test: `pxpr selftest --random N [--seed S]`,
+/// along with the check of the columns errors are reported at.
+/// 
+fn run_selftest(arguments: &[String]) -> Result<bool, String> {
+    let mut cases = 1000;
+    let mut seed = time_seed();
+
+    let mut arguments = arguments.iter();
+    while let Some(argument) = arguments.next() {
+        let value = arguments.next().ok_or(format!("Expected a number after '{}'", argument))?;
+        let invalid = || format!("Invalid number for '{}': '{}'", argument, value);
+        match argument.as_str() {
+            "--random" => cases = value.parse().map_err(|_| invalid())?,
+            "--seed" => seed = value.parse().map_err(|_| invalid())?,
+            _ => return Err(format!("Unknown option: '{}'", argument)),
+        }
+    }
+
+    let mut rng = Rng::new(seed);
+    let mut divergences = run_differential(cases, &mut rng);
+    divergences.extend(check_random_trees(cases, &mut rng));
+    #[cfg(feature = "arbitrary")]
+    divergences.extend(pxpr::testing::check_round_trips(cases, &mut rng));
+    divergences.extend(check_deep_nesting(2, &mut rng));
+    for divergence in &divergences {
+        println!("{}", divergence.expression);
+        println!("\texpected: {}", divergence.expected);
+        println!("\tactual:   {}", divergence.actual);
+    }
+
+    println!("Checked {} expressions (seed {}): {} divergence(s)", cases, seed, divergences.len());
+    Ok(divergences.is_empty())
+}
+
+
+///
+/// Convert the saved session of another calculator into a file for `--file`:
+/// `pxpr import --from CALCULATOR PATH`. The file starts with a comment
+/// giving the options which hold the settings and variables of the session.
+/// 
+fn run_import(arguments: &[String]) -> Result<i32, String> {
+    let (calculator, path) = match arguments {
+        [from, calculator, path] if from == "--from" => (calculator.parse()?, path),
+        _ => return Err(String::from("Expected 'pxpr import --from bc|qalc|speedcrunch PATH'")),
+    };
+
+    let history = match fs::read_to_string(path) {
+        Ok(history) => history,
+        Err(error) => {
+            print_error(&format!("Cannot read '{}': {}", path, error), ColorChoice::Auto);
+            return Ok(EXIT_IO);
+        }
+    };
+
+    let conversion = import::convert(calculator, &history);
+    for (line, reason) in &conversion.skipped {
+        eprintln!("{}:{}: Skipped: {}", path, line, reason);
+    }
+
+    let mut command = vec![String::from("pxpr")];
+    command.extend(conversion.options);
+    command.push(String::from("--file FILE"));
+    println!("# Converted from {} by `pxpr import`. Compute it with:", calculator.name());
+    println!("#     {}", command.join(" "));
+    for expression in &conversion.expressions {
+        println!("{}", expression);
+    }
+
+    Ok(EXIT_SUCCESS)
+}
+
+
+///
+/// Print an expression as the optimizer rewrites it before compiling it to
+/// bytecode: `pxpr optimize [-D name=value]... "<expression>"`. Variables
+/// defined with `-D` or `--env` give the optimizer the types of their
+/// values, without which no identity such as `x + 0` is removed, since `x`
+/// might not hold a number.
+/// 
+fn run_optimize(arguments: &[String]) -> Result<i32, String> {
+    let usage = || String::from("Expected 'pxpr optimize [-D name=value]... \"<expression>\"'");
+    let mut definitions = vec![];
+    let mut source = None;
+    let mut arguments = arguments.iter();
+    while let Some(argument) = arguments.next() {
+        match argument.as_str() {
+            "-D" => definitions.push(parse_definition(arguments.next().ok_or("Expected 'name=value' after '-D'")?)?),
+            flag if flag.starts_with("-D") => definitions.push(parse_definition(&flag[2..])?),
+            "--env" => {
+                let names = arguments.next().ok_or("Expected the names of environment variables after '--env'")?;
+                definitions.extend(names.split(',').map(|name| Definition::Environment(name.to_string())));
             },
+            "--" if source.is_none() => source = arguments.next(),
+            flag if flag.starts_with("--") => return Err(format!("Unknown option: '{}'", flag)),
+            _ if source.is_some() => return Err(usage()),
+            _ => source = Some(argument),
+        }
+    }
+    let source = source.ok_or_else(usage)?;
+
+    let mut context = Context::new();
+    for definition in &definitions {
+        define(definition, &mut context)?;
+    }
+
+    match parse_expression_for(source, &context) {
+        Ok(tree) => {
+            println!("{}", optimize(&tree, &context).to_source());
+            Ok(EXIT_SUCCESS)
+        },
+        Err(error) => {
+            eprintln!("{}", error_line(&error.located_in(source), io::stderr().is_terminal()));
+            Ok(EXIT_SYNTAX)
+        }
+    }
+}
+
+
+///
+/// A function writing a tree in another notation.
+/// 
+type Conversion = fn(&AstNode) -> String;
+
+
+///
+/// The subcommands which print an expression in another notation, such as
+/// `pxpr latex "<expression>"`, with the function writing it.
+/// 
+const CONVERSIONS: [(&str, Conversion); 3] = [
+    ("latex", latex::to_latex),
+    ("to-rpn", notation::to_rpn),
+    ("to-prefix", notation::to_prefix),
+];
+
+
+///
+/// Print an expression in another notation: `pxpr COMMAND "<expression>"`,
+/// where the command is one of `CONVERSIONS`.
+/// 
+fn run_conversion(command: &str, write: Conversion, arguments: &[String]) -> Result<i32, String> {
+    let source = match arguments {
+        [source] => source,
+        _ => return Err(format!("Expected 'pxpr {} \"<expression>\"'", command)),
+    };
+
+    match parse_expression_for(source, &Context::new()) {
+        Ok(tree) => {
+            println!("{}", write(&tree));
+            Ok(EXIT_SUCCESS)
+        },
+        Err(error) => {
+            eprintln!("{}", error_line(&error.located_in(source), io::stderr().is_terminal()));
+            Ok(EXIT_SYNTAX)
+        }
+    }
+}
+
+
+///
+/// Print the syntax tree of an expression: `pxpr ast [--format FORMAT]
+/// "<expression>"`, where the format is `outline`, as `.ast` shows, `sexpr`
+/// or `json`.
+/// 
+fn run_ast(arguments: &[String]) -> Result<i32, String> {
+    let (format, source) = match arguments {
+        [source] => ("outline", source),
+        [flag, format, source] if flag == "--format" => (format.as_str(), source),
+        _ => return Err(String::from("Expected 'pxpr ast [--format outline|sexpr|json] \"<expression>\"'")),
+    };
+
+    let write: fn(&AstNode) -> String = match format {
+        "outline" => AstNode::to_outline,
+        "sexpr" => |tree| format!("{}\n", tree.to_sexpr()),
+        "json" => |tree| format!("{}\n", tree.to_json()),
+        _ => return Err(format!("Unknown tree format: '{}' (expected 'outline', 'sexpr' or 'json')", format)),
+    };
+
+    match parse_expression_for(source, &Context::new()) {
+        Ok(tree) => {
+            print!("{}", write(&tree));
+            Ok(EXIT_SUCCESS)
+        },
+        Err(error) => {
+            eprintln!("{}", error_line(&error.located_in(source), io::stderr().is_terminal()));
+            Ok(EXIT_SYNTAX)
         }
+    }
+}
+
+
+///
+/// The prefix of the inputs in a file written by `.record`.
+/// 
+const RECORDED_INPUT: &str = "> ";
+
+
+///
+/// The exit code when every expression was computed.
+/// 
+const EXIT_SUCCESS: i32 = 0;
+
+
+///
+/// The exit code when `pxpr selftest` finds a divergence.
+/// 
+const EXIT_FAILURE: i32 = 1;
+
+
+///
+/// The exit code for an invalid option, or when the expression given on the
+/// command line is empty.
+/// 
+const EXIT_USAGE: i32 = 2;
+
+
+///
+/// The exit code when an expression cannot be lexed or parsed.
+/// 
+const EXIT_SYNTAX: i32 = 3;
+
+
+///
+/// The exit code when an expression is parsed but cannot be computed, such
+/// as for a type error or a division by zero.
+/// 
+const EXIT_EVALUATION: i32 = 4;
+
 
-        line.clear();
+///
+/// The exit code when a file or standard input cannot be read.
+/// 
+const EXIT_IO: i32 = 5;
+
+
+///
+/// The text printed by `--help`.
+/// 
+const HELP: &str = "\
+Usage: pxpr [options] \"<expression>\"   compute an expression
+       pxpr [options]                  start the REPL, or compute each line piped in
+       pxpr selftest [--random N] [--seed N]
+       pxpr import --from NAME PATH    convert a session saved by bc, qalc or speedcrunch
+       pxpr optimize [-D name=value]... \"<expression>\"
+                                       print an expression with its constants folded
+       pxpr latex \"<expression>\"       print an expression as LaTeX math
+       pxpr to-rpn \"<expression>\"      print an expression in reverse Polish notation
+       pxpr to-prefix \"<expression>\"   print an expression in Polish (prefix) notation
+       pxpr ast [--format outline|sexpr|json] \"<expression>\"
+                                       print the syntax tree of an expression
+       pxpr completions bash|zsh|fish  write a script completing the options in a shell
+
+Input:
+  --file <path>          compute a file of expressions, separated by newlines or ';'
+  --keep-going           keep computing a file after an expression fails
+  --template             copy standard input to standard output, replacing each ${...}
+                         with the value of the expression inside it
+  --each                 compute each argument as a separate expression
+  --no-cache             compute repeated expressions in a batch or file again
+  --batch                compute the lines of standard input across all processor cores,
+                         printing the results in the order of the lines
+  --check                check the types of the expression, file or piped lines
+                         without computing them, printing the type of each
+  --eval-range <a..b>    compute only the subexpression covering columns a..b
+  -D <name>=<value>      define a variable as the value of an expression
+  --env <NAME,...>       define variables from environment variables of the same names
+  --no-sanitize          do not replace pasted symbols such as '×' and '−'
+
+Numbers:
+  --bits 8|16|32|64      wrap integers to a fixed bit width
+  --overflow <policy>    what integer overflow does: error, wrap, saturate or promote,
+                         for every operation or per class, such as add=wrap,mul=promote
+  --strict               turn warnings into errors
+  --strict-types         fail on arithmetic mixing integers and floats, rather than
+                         converting the integers, until converted with float() or int()
+  --compat <version>     use the numeric semantics of an earlier version
+  --pedantic-compat      warn where the compatibility version changes the meaning
+  --decimal-comma        read and write ',' as the decimal separator
+  --locale <locale>      read and write numbers with the separators of a locale
+  --seed <n>             seed the random number generator
+  --backend tree|vm      walk the syntax tree, or compile it to bytecode for a stack machine
+  --trace-vm             compute with the bytecode backend, unoptimized, printing each
+                         instruction it runs and the stack it leaves to standard error
+
+Limits:
+  --max-length <n>       fail on expressions longer than n characters
+  --max-depth <n>        fail on operands nested more than n levels deep
+  --max-steps <n>        fail on computations taking more than n steps
+  --timeout <ms>         fail on computations taking longer than ms milliseconds
+
+Output:
+  --output plain|color|json|csv
+  --color auto|always|never
+                         highlight errors and warnings; 'auto' only does on a terminal
+                         and without NO_COLOR
+  --prefix equals|expression|none
+  --prog                 show integers in several bases
+  --group                group digits in thousands
+  --show-types           show the type of each result
+  --precision <n>        round floats to at most n digits after the point
+  --scientific           write floats in scientific notation
+  --engineering          write floats in engineering notation
+  --show-rolls           show the individual dice rolled
+  --show-bits            show the bits of integer results below them
+  --profile-expr         count the operators applied and functions called
+  --alloc-stats          show the allocations made by each stage
+  --time                 show the time each stage took
+  --summary              show what the REPL session computed when it ends
+
+REPL:
+  --history-size <n>     keep at most n lines of history (default 1000)
+  --history-dups keep|ignore
+                         keep a line in the history again when it repeats the one
+                         before it, or leave it out (default)
+
+Exit codes:
+  0  every expression was computed
+  1  selftest found a divergence
+  2  invalid option, or no expression given
+  3  an expression could not be lexed or parsed
+  4  an expression could not be computed, such as a type error or division by 0,
+     or '--check' found a type error
+  5  a file or standard input could not be read
+";
+
+
+///
+/// Get the exit status for a computation: its status if it is the first
+/// which failed, or `status` otherwise.
+/// 
+fn first_failure(status: i32, result: &Computation) -> i32 {
+    match (status, result) {
+        (EXIT_SUCCESS, Err(errors)) => match errors.first().map(pxpr::Error::kind) {
+            Some(ErrorKind::Lex | ErrorKind::Parse) => EXIT_SYNTAX,
+            Some(ErrorKind::Usage) => EXIT_USAGE,
+            _ => EXIT_EVALUATION,
+        },
+        _ => status,
     }
 }
 
 
+///
+/// Create a context with the settings given on the command line, but
+/// without the variables they define.
+/// 
+fn new_context(options: &Options, compat: Compat) -> Context {
+    let mut context = Context::new();
+    context.bit_width = options.bit_width;
+    context.strict = options.strict;
+    context.strict_types = options.strict_types;
+    context.overflow = options.overflow;
+    context.compat = compat;
+    context.pedantic_compat = options.pedantic_compat;
+    context.number_format = options.number_format;
+    context.sanitize = options.sanitize;
+    context.backend = options.backend;
+    context.limits = options.limits;
+    if options.trace_vm {
+        context.vm_trace = Some(vec![]);
+    }
+    // The summary counts the operators of the session from the profile.
+    if options.profile || options.summary {
+        context.profile = Some(Profile::default());
+    }
+    context
+}
+
+
 fn main() -> io::Result<()> {   
     let arguments: Vec<String> = std::env::args().collect();
-    if arguments.len() < 2 {
-        run_repl();
+
+    if arguments.get(1).is_some_and(|command| command == "selftest") {
+        match run_selftest(&arguments[2..]) {
+            Ok(true) => return Ok(()),
+            Ok(false) => std::process::exit(EXIT_FAILURE),
+            Err(message) => {
+                print_error(&message, ColorChoice::Auto);
+                std::process::exit(EXIT_USAGE);
+            }
+        }
+    }
+
+    if arguments.get(1).is_some_and(|command| command == "import") {
+        match run_import(&arguments[2..]) {
+            Ok(status) => std::process::exit(status),
+            Err(message) => {
+                print_error(&message, ColorChoice::Auto);
+                std::process::exit(EXIT_USAGE);
+            }
+        }
+    }
+
+    if arguments.get(1).is_some_and(|command| command == "optimize") {
+        match run_optimize(&arguments[2..]) {
+            Ok(status) => std::process::exit(status),
+            Err(message) => {
+                print_error(&message, ColorChoice::Auto);
+                std::process::exit(EXIT_USAGE);
+            }
+        }
+    }
+
+    if let Some((command, write)) = CONVERSIONS.iter().find(|(command, _)| arguments.get(1).is_some_and(|argument| argument == command)) {
+        match run_conversion(command, *write, &arguments[2..]) {
+            Ok(status) => std::process::exit(status),
+            Err(message) => {
+                print_error(&message, ColorChoice::Auto);
+                std::process::exit(EXIT_USAGE);
+            }
+        }
+    }
+
+    if arguments.get(1).is_some_and(|command| command == "ast") {
+        match run_ast(&arguments[2..]) {
+            Ok(status) => std::process::exit(status),
+            Err(message) => {
+                print_error(&message, ColorChoice::Auto);
+                std::process::exit(EXIT_USAGE);
+            }
+        }
+    }
+
+    if arguments.get(1).is_some_and(|command| command == "completions") {
+        match arguments.get(2).ok_or(String::from("Expected a shell after 'completions'")).and_then(|shell| shell.parse()) {
+            Ok(shell) => {
+                print!("{}", completions::script(shell));
+                return Ok(());
+            },
+            Err(message) => {
+                print_error(&message, ColorChoice::Auto);
+                std::process::exit(EXIT_USAGE);
+            }
+        }
+    }
+
+    let options = match parse_options(&arguments[1..]) {
+        Ok(options) => options,
+        Err(message) => {
+            print_error(&message, ColorChoice::Auto);
+            std::process::exit(EXIT_USAGE);
+        }
+    };
+
+    if options.help {
+        print!("{}", HELP);
         return Ok(());
     }
 
-    let input = arguments[1..].join(" ");
+    // The `--compat` option takes priority over the `PXPR_COMPAT` variable.
+    let compat = match options.compat {
+        Some(compat) => Ok(compat),
+        None => Compat::from_env().map(Option::unwrap_or_default),
+    };
+
+    let compat = match compat {
+        Ok(compat) => compat,
+        Err(message) => {
+            print_error(&message, options.color);
+            std::process::exit(EXIT_USAGE);
+        }
+    };
 
-    let computation_result = compute_expression(&input);
-    match computation_result {
-        Ok(result) => println!("\t= {}", result),
-        Err(e) => report_error(&e),
+    let mut context = new_context(&options, compat);
+    if let Some(seed) = options.seed {
+        context.rng = Rng::new(seed);
     }
 
-    Ok(())
+    for definition in &options.definitions {
+        if let Err(message) = define(definition, &mut context) {
+            print_error(&message, options.color);
+            std::process::exit(EXIT_USAGE);
+        }
+    }
+    // Notes about the definitions, such as replaced characters, and the
+    // instructions computing them, are not about the expressions being computed.
+    context.take_warnings();
+    context.take_vm_trace();
+
+    let status = match (&options.file, options.expression.is_empty()) {
+        _ if options.check => Some(run_check(&options, &mut context)),
+        _ if options.template => Some(run_template(&options, &mut context)),
+        _ if options.batch => Some(run_parallel_batch(&options, &mut context)),
+        (Some(path), _) => Some(run_file(path, &options, &mut context)),
+        (None, true) => match io::stdin().is_terminal() {
+            true => {
+                run_repl(&options, &mut context);
+                Some(Ok(EXIT_SUCCESS))
+            },
+            false => Some(run_batch(&options, &mut context)),
+        },
+        (None, false) => None,
+    };
+
+    match status {
+        Some(Ok(status)) => std::process::exit(status),
+        Some(Err(message)) => {
+            print_error(&message, options.color);
+            std::process::exit(EXIT_IO);
+        },
+        None => {},
+    }
+
+    // With `--each`, every argument is a separate expression.
+    let inputs = match options.each {
+        true => options.expression.clone(),
+        false => vec![options.expression.join(" ")],
+    };
+
+    if inputs.iter().all(|input| input.trim().is_empty()) {
+        eprintln!("No expression given. Usage: pxpr [options] \"<expression>\", or pxpr [options] to start the REPL");
+        std::process::exit(EXIT_USAGE);
+    }
+
+    if let Some(header) = options.renderer.header() {
+        print!("{}", header);
+    }
+
+    let mut status = EXIT_SUCCESS;
+    for input in inputs {
+        let computation_result = match options.eval_range {
+            Some(range) => compute_range(&input, range, &mut context).map(|value| vec![(input.clone(), value)]).map_err(|error| vec![error]),
+            None => compute(&input, &mut context),
+        };
+
+        status = first_failure(status, &computation_result);
+        emit(&input, &computation_result, &options.renderer, &mut context);
+    }
+
+    print_statistics(&options, options.time, &mut context);
+
+    std::process::exit(status)
 }
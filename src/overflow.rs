@@ -0,0 +1,166 @@
+use std::{fmt, str::FromStr};
+
+use crate::expression::Value;
+
+
+///
+/// What an integer operation does when its result does not fit in 64 bits.
+///
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Overflow {
+    /// Fail with an overflow error.
+    Error,
+
+    /// Keep the lowest 64 bits, as two's complement hardware does.
+    Wrap,
+
+    /// Clamp to the largest or smallest 64-bit integer.
+    Saturate,
+
+    /// Compute the result as a float instead, which may round it.
+    Promote
+}
+
+
+impl Overflow {
+    ///
+    /// Get the value of an operation whose exact result is `exact`, or `None`
+    /// if it overflows and the policy is to fail.
+    ///
+    pub fn apply(self, exact: i128) -> Option<Value> {
+        match (self, i64::try_from(exact)) {
+            (_, Ok(n)) => Some(Value::Integer(n)),
+            (Overflow::Error, Err(_)) => None,
+            (Overflow::Wrap, Err(_)) => Some(Value::Integer(exact as i64)),
+            (Overflow::Saturate, Err(_)) => Some(Value::Integer(match exact > 0 {
+                true => i64::MAX,
+                false => i64::MIN,
+            })),
+            (Overflow::Promote, Err(_)) => Some(Value::Float(exact as f64)),
+        }
+    }
+}
+
+
+impl fmt::Display for Overflow {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Overflow::Error => write!(f, "error"),
+            Overflow::Wrap => write!(f, "wrap"),
+            Overflow::Saturate => write!(f, "saturate"),
+            Overflow::Promote => write!(f, "promote"),
+        }
+    }
+}
+
+
+impl FromStr for Overflow {
+    type Err = String;
+
+    fn from_str(overflow: &str) -> Result<Self, Self::Err> {
+        match overflow.trim() {
+            "error" => Ok(Overflow::Error),
+            "wrap" => Ok(Overflow::Wrap),
+            "saturate" => Ok(Overflow::Saturate),
+            "promote" => Ok(Overflow::Promote),
+            _ => Err(format!("Unknown overflow policy: '{}' (expected 'error', 'wrap', 'saturate' or 'promote')", overflow)),
+        }
+    }
+}
+
+
+///
+/// A class of integer operations which share an overflow policy.
+///
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OperationClass {
+    /// `+` and `-`.
+    Add,
+
+    /// `*`, `/` and `%`.
+    Multiply,
+
+    /// `<<`.
+    Shift
+}
+
+
+impl FromStr for OperationClass {
+    type Err = String;
+
+    fn from_str(class: &str) -> Result<Self, Self::Err> {
+        match class.trim() {
+            "add" => Ok(OperationClass::Add),
+            "mul" => Ok(OperationClass::Multiply),
+            "shift" => Ok(OperationClass::Shift),
+            _ => Err(format!("Unknown operation class: '{}' (expected 'add', 'mul' or 'shift')", class)),
+        }
+    }
+}
+
+
+///
+/// The overflow policy of each class of integer operations. A class without
+/// a policy of its own keeps the default: `+`, `-`, `*`, `/` and `%` fail,
+/// or wrap when integers have a fixed bit width, and `<<` discards the bits
+/// shifted out.
+///
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct OverflowPolicy {
+    pub add: Option<Overflow>,
+    pub multiply: Option<Overflow>,
+    pub shift: Option<Overflow>
+}
+
+
+impl OverflowPolicy {
+    ///
+    /// Get the policy of a class of operations on integers of the given bit width.
+    ///
+    pub fn get(&self, class: OperationClass, bit_width: Option<u32>) -> Overflow {
+        let policy = match class {
+            OperationClass::Add => self.add,
+            OperationClass::Multiply => self.multiply,
+            OperationClass::Shift => self.shift,
+        };
+
+        match (policy, class, bit_width) {
+            (Some(overflow), _, _) => overflow,
+            (None, OperationClass::Shift, _) | (None, _, Some(_)) => Overflow::Wrap,
+            (None, _, None) => Overflow::Error,
+        }
+    }
+
+
+    ///
+    /// Set the policy of a class of operations.
+    ///
+    pub fn set(&mut self, class: OperationClass, overflow: Overflow) {
+        match class {
+            OperationClass::Add => self.add = Some(overflow),
+            OperationClass::Multiply => self.multiply = Some(overflow),
+            OperationClass::Shift => self.shift = Some(overflow),
+        }
+    }
+
+
+    ///
+    /// Set policies from a specification such as `saturate`, which sets the
+    /// policy of every class, or `add=wrap,mul=promote`.
+    ///
+    pub fn configure(&mut self, specification: &str) -> Result<(), String> {
+        for setting in specification.split(',') {
+            match setting.split_once('=') {
+                Some((class, overflow)) => self.set(class.parse()?, overflow.parse()?),
+                None => {
+                    let overflow = setting.parse()?;
+                    for class in [OperationClass::Add, OperationClass::Multiply, OperationClass::Shift] {
+                        self.set(class, overflow);
+                    }
+                },
+            }
+        }
+
+        Ok(())
+    }
+}
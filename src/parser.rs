@@ -1,51 +1,885 @@
-use std::rc::Rc;
+use std::fmt;
 
-use crate::{lexer::{Token, TokenType}, pxpr};
+use crate::{self as pxpr, arena::{Arena, NodeId, NodeKind}, lexer::{Token, TokenType}, render::json_string, span::Span, visit::{self, AstTransformer}};
 
-#[allow(dead_code)]
-#[derive(Debug)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum BinaryOperationType {
     Add, Subtract, Multiply, Divide, Modulus,
 
     And, Or, If, Equal, NotEqual,
+
+    Less, LessEqual, Greater, GreaterEqual,
     
     BitwiseAnd, BitwiseOr, BitwiseXor, BitwiseLeftShift, BitwiseRightShift,
+
+    Minimum, Maximum,
 }
 
-#[derive(Debug)]
+impl BinaryOperationType {
+    ///
+    /// Get the symbol the operation is written with.
+    /// 
+    pub fn symbol(&self) -> &'static str {
+        match self {
+            BinaryOperationType::Add => "+",
+            BinaryOperationType::Subtract => "-",
+            BinaryOperationType::Multiply => "*",
+            BinaryOperationType::Divide => "/",
+            BinaryOperationType::Modulus => "%",
+            BinaryOperationType::And => "&&",
+            BinaryOperationType::Or => "||",
+            BinaryOperationType::If => "=>",
+            BinaryOperationType::Equal => "==",
+            BinaryOperationType::NotEqual => "!=",
+            BinaryOperationType::Less => "<",
+            BinaryOperationType::LessEqual => "<=",
+            BinaryOperationType::Greater => ">",
+            BinaryOperationType::GreaterEqual => ">=",
+            BinaryOperationType::BitwiseAnd => "&",
+            BinaryOperationType::BitwiseOr => "|",
+            BinaryOperationType::BitwiseXor => "^",
+            BinaryOperationType::BitwiseLeftShift => "<<",
+            BinaryOperationType::BitwiseRightShift => ">>",
+            BinaryOperationType::Minimum => "min",
+            BinaryOperationType::Maximum => "max",
+        }
+    }
+
+
+    ///
+    /// Check whether the operation is arithmetic, and so converts
+    /// its operands to floats.
+    /// 
+    pub fn is_arithmetic(&self) -> bool {
+        matches!(self, 
+            BinaryOperationType::Add | BinaryOperationType::Subtract | BinaryOperationType::Multiply |
+            BinaryOperationType::Divide | BinaryOperationType::Modulus)
+    }
+
+
+    ///
+    /// Get how tightly the operation binds its operands under
+    /// `Precedence::Table`, from 1 for the loosest to 11 for the tightest.
+    /// `min` and `max` bind looser than arithmetic, so that `x - 5 max 0`
+    /// caps the difference, but tighter than comparisons.
+    /// 
+    fn precedence(&self) -> u8 {
+        match self {
+            BinaryOperationType::If => 1,
+            BinaryOperationType::Or => 2,
+            BinaryOperationType::And => 3,
+            BinaryOperationType::Equal | BinaryOperationType::NotEqual | BinaryOperationType::Less |
+            BinaryOperationType::LessEqual | BinaryOperationType::Greater | BinaryOperationType::GreaterEqual => 4,
+            BinaryOperationType::Minimum | BinaryOperationType::Maximum => 5,
+            BinaryOperationType::BitwiseOr => 6,
+            BinaryOperationType::BitwiseXor => 7,
+            BinaryOperationType::BitwiseAnd => 8,
+            BinaryOperationType::BitwiseLeftShift | BinaryOperationType::BitwiseRightShift => 9,
+            BinaryOperationType::Add | BinaryOperationType::Subtract => 10,
+            BinaryOperationType::Multiply | BinaryOperationType::Divide | BinaryOperationType::Modulus => 11,
+        }
+    }
+}
+
+
+// How tightly prefix operators and primaries bind, tighter than any binary operation.
+const PREFIX: u8 = 12;
+pub(crate) const PRIMARY: u8 = 13;
+
+
+///
+/// The rules deciding which operators bind tighter than others.
+/// 
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum Precedence {
+    /// Operators bind as in Rust, so that `1 + 2 == 3` compares `3` with `3`
+    /// and `a || b && c` means `a || (b && c)`. Implication binds loosest.
+    #[default]
+    Table,
+
+    /// Every binary operator other than `*`, `/` and `%` binds equally from
+    /// left to right, as in pxpr 0.x, so that `a || b && c` means `(a || b) && c`.
+    Flat
+}
+
+
+///
+/// Get the binary operation joined at expression level which a token
+/// stands for, which is any but `*`, `/` and `%`.
+/// 
+fn expression_operator(token_type: &TokenType) -> Option<BinaryOperationType> {
+    match token_type {
+        TokenType::Plus => Some(BinaryOperationType::Add),
+        TokenType::Minus => Some(BinaryOperationType::Subtract),
+        TokenType::And => Some(BinaryOperationType::And),
+        TokenType::Or => Some(BinaryOperationType::Or),
+        TokenType::If => Some(BinaryOperationType::If),
+        TokenType::BitwiseAnd => Some(BinaryOperationType::BitwiseAnd),
+        TokenType::BitwiseOr => Some(BinaryOperationType::BitwiseOr),
+        TokenType::BitwiseXor => Some(BinaryOperationType::BitwiseXor),
+        TokenType::BitwiseLeftShift => Some(BinaryOperationType::BitwiseLeftShift),
+        TokenType::BitwiseRightShift => Some(BinaryOperationType::BitwiseRightShift),
+        TokenType::Equal => Some(BinaryOperationType::Equal),
+        TokenType::NotEqual => Some(BinaryOperationType::NotEqual),
+        TokenType::Less => Some(BinaryOperationType::Less),
+        TokenType::LessEqual => Some(BinaryOperationType::LessEqual),
+        TokenType::Greater => Some(BinaryOperationType::Greater),
+        TokenType::GreaterEqual => Some(BinaryOperationType::GreaterEqual),
+        TokenType::Min => Some(BinaryOperationType::Minimum),
+        TokenType::Max => Some(BinaryOperationType::Maximum),
+        _ => None,
+    }
+}
+
+///
+/// Check whether a token separates the cases of a conditional chain, which
+/// is a `;` whether or not `;` also separates function arguments.
+/// 
+fn is_clause_separator(token: &Token) -> bool {
+    match token.type_ {
+        TokenType::Semicolon => true,
+        TokenType::Comma => token.lexeme == ";",
+        _ => false,
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum UnaryOperationType {
     ArithmeticNegate,
     LogicalNot,
-    BitwiseNot
+    BitwiseNot,
+    Factorial
 }
 
-#[derive(Debug)]
-pub enum AstNode {
+impl UnaryOperationType {
+    ///
+    /// Get the symbol the operation is written with, placed around an
+    /// `x` standing for its operand.
+    /// 
+    pub fn symbol(&self) -> &'static str {
+        match self {
+            UnaryOperationType::ArithmeticNegate => "-x",
+            UnaryOperationType::LogicalNot => "!x",
+            UnaryOperationType::BitwiseNot => "~x",
+            UnaryOperationType::Factorial => "x!",
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum AstNodeKind {
     BinaryOperation(BinaryOperationType, Box<AstNode>, Box<AstNode>),
     UnaryOperation(UnaryOperationType, Box<AstNode>),
+    FunctionCall(String, Vec<AstNode>),
     Integer(i64),
     Float(f64),
-    Boolean(bool)
+    Boolean(bool),
+    String(String),
+    Dice(i64, i64),
+    Identifier(String),
+
+    /// The `?` standing for the parameter with this index, counted from 0
+    /// in the order the parameters appear in the source.
+    Parameter(usize),
+
+    /// A chain of cases such as `x when x >= 0; -x otherwise`, holding each
+    /// value followed by the guard choosing it, and last the value chosen
+    /// when no guard holds.
+    Conditional(Vec<AstNode>)
+}
+
+
+///
+/// A node in the abstract syntax tree, along with the span of the
+/// source it was parsed from.
+/// 
+/// With the `serde` feature, trees can be serialized and deserialized, in
+/// the shape `to_json` writes. Unlike the other operations on trees, serde
+/// recurses once per level of nesting.
+/// 
+#[derive(Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct AstNode {
+    pub kind: AstNodeKind,
+    pub span: Span
+}
+
+
+impl AstNode {
+    pub fn new(kind: AstNodeKind, span: Span) -> Self {
+        AstNode { kind, span }
+    }
+
+
+    ///
+    /// Get the direct children of this node, from left to right.
+    /// 
+    pub fn children(&self) -> Vec<&AstNode> {
+        match &self.kind {
+            AstNodeKind::BinaryOperation(_, left, right) => vec![left, right],
+            AstNodeKind::UnaryOperation(_, operand) => vec![operand],
+            AstNodeKind::FunctionCall(_, arguments) => arguments.iter().collect(),
+            AstNodeKind::Conditional(parts) => parts.iter().collect(),
+            _ => vec![],
+        }
+    }
+
+
+    ///
+    /// Find the innermost node covering a column of the source.
+    /// 
+    /// # Returns
+    /// The deepest node whose span contains `column`, or `None` if the
+    /// column lies outside of this node.
+    /// 
+    pub fn node_at(&self, column: u32) -> Option<&AstNode> {
+        self.node_covering(Span::new(column, column + 1))
+    }
+
+
+    ///
+    /// Find the innermost node covering an entire span of the source.
+    /// 
+    /// # Returns
+    /// The deepest node whose span covers `span`, or `None` if `span` is
+    /// not entirely within this node.
+    /// 
+    pub fn node_covering(&self, span: Span) -> Option<&AstNode> {
+        if !self.span.covers(span) {
+            return None;
+        }
+
+        let mut innermost = self;
+        while let Some(child) = innermost.children().into_iter().find(|child| child.span.covers(span)) {
+            innermost = child;
+        }
+
+        Some(innermost)
+    }
+
+
+    ///
+    /// Get a copy of the tree with every use of a variable replaced by
+    /// another tree, such as `(a + b) * 2` for `x * 2` with `x` replaced by
+    /// `a + b`, to plug a formula into a template before computing or
+    /// optimizing it. The replacement is not searched for the variable
+    /// again, so `x` can be replaced by `x + 1`. Each copy of the
+    /// replacement takes the span of the variable it replaces, so errors
+    /// computing it point at where the variable was.
+    /// 
+    pub fn substitute(&self, name: &str, replacement: &AstNode) -> AstNode {
+        visit::transform(self.clone(), &mut Substitution { name, replacement })
+    }
+
+
+    ///
+    /// Write the node as source, with only the parentheses it needs.
+    /// 
+    /// Parsing the source gives back a tree equal to this one, so tools
+    /// can rewrite an expression and parse the result again. This holds
+    /// for every tree the parser produces. A negative literal, which only
+    /// code building a tree can create, is written as `-3` and so reads
+    /// back as the negation of `3`, which computes to the same value. The
+    /// smallest integer is written as `(-9223372036854775807 - 1)`.
+    /// 
+    pub fn to_source(&self) -> String {
+        self.to_string()
+    }
+
+
+    ///
+    /// Write the node as source with every operation in parentheses, such
+    /// as `1 + (2 * (-x))`, to show how the parser grouped an expression
+    /// when looking into a precedence bug. The source parses back to the
+    /// same tree, as that of `to_source` does. This is the `{:#}` format.
+    /// 
+    pub fn to_parenthesized_source(&self) -> String {
+        format!("{:#}", self)
+    }
+
+
+    ///
+    /// Write the tree as an outline, one node per line followed by its span,
+    /// with the children of each node indented below it, such as for the
+    /// `.ast` command of the REPL.
+    /// 
+    pub fn to_outline(&self) -> String {
+        let mut outline = String::new();
+        let mut pending = vec![(self, 0)];
+
+        while let Some((node, depth)) = pending.pop() {
+            let label = match &node.kind {
+                AstNodeKind::BinaryOperation(operation_type, _, _) => operation_type.symbol().to_string(),
+                AstNodeKind::UnaryOperation(operation_type, _) => operation_type.symbol().to_string(),
+                AstNodeKind::FunctionCall(name, _) => format!("{}()", name),
+                AstNodeKind::Conditional(_) => String::from("when"),
+                _ => node.to_string(),
+            };
+
+            outline.push_str(&format!("{}{}  {}\n", "  ".repeat(depth), label, node.span));
+            pending.extend(node.children().into_iter().rev().map(|child| (child, depth + 1)));
+        }
+
+        outline
+    }
+
+
+    ///
+    /// Write the tree as an S-expression, such as `(+ 1 (* 2 x))`, where
+    /// each operation is labeled as in `to_outline` and each leaf is written
+    /// as source.
+    /// 
+    pub fn to_sexpr(&self) -> String {
+        let mut sexpr = String::new();
+        let mut pending = vec![Chunk::Node(self)];
+
+        while let Some(chunk) = pending.pop() {
+            let node = match chunk {
+                Chunk::Text(text) => {
+                    sexpr.push_str(&text);
+                    continue;
+                },
+                Chunk::Node(node) => node,
+            };
+
+            let label = match &node.kind {
+                AstNodeKind::BinaryOperation(operation_type, _, _) => operation_type.symbol().to_string(),
+                AstNodeKind::UnaryOperation(operation_type, _) => operation_type.symbol().to_string(),
+                AstNodeKind::FunctionCall(name, _) => format!("{}()", name),
+                AstNodeKind::Conditional(_) => String::from("when"),
+                _ => {
+                    sexpr.push_str(&node.to_string());
+                    continue;
+                },
+            };
+
+            sexpr.push('(');
+            sexpr.push_str(&label);
+            pending.push(Chunk::Text(String::from(")")));
+            for child in node.children().into_iter().rev() {
+                pending.extend([Chunk::Node(child), Chunk::Text(String::from(" "))]);
+            }
+        }
+
+        sexpr
+    }
+
+
+    ///
+    /// Write the tree as JSON on one line, with each node an object holding
+    /// its kind and its span. A kind is an object whose only key names the
+    /// variant of `AstNodeKind` and whose value holds its fields, such as
+    /// `{"BinaryOperation": ["Add", <left>, <right>]}` or `{"Integer": 3}`,
+    /// which is the shape serde gives the tree with the `serde` feature.
+    /// 
+    pub fn to_json(&self) -> String {
+        let mut json = String::new();
+        let mut pending = vec![Chunk::Node(self)];
+
+        while let Some(chunk) = pending.pop() {
+            let node = match chunk {
+                Chunk::Text(text) => {
+                    json.push_str(&text);
+                    continue;
+                },
+                Chunk::Node(node) => node,
+            };
+
+            let (variant, fields) = match &node.kind {
+                AstNodeKind::BinaryOperation(operation_type, _, _) => ("BinaryOperation", format!("[\"{:?}\", ", operation_type)),
+                AstNodeKind::UnaryOperation(operation_type, _) => ("UnaryOperation", format!("[\"{:?}\", ", operation_type)),
+                AstNodeKind::FunctionCall(name, _) => ("FunctionCall", format!("[{}, [", json_string(name))),
+                AstNodeKind::Conditional(_) => ("Conditional", String::from("[")),
+                AstNodeKind::Integer(x) => ("Integer", x.to_string()),
+                AstNodeKind::Float(x) => ("Float", match x.is_finite() {
+                    true => format!("{:?}", x),
+                    false => String::from("null"),
+                }),
+                AstNodeKind::Boolean(x) => ("Boolean", x.to_string()),
+                AstNodeKind::String(x) => ("String", json_string(x)),
+                AstNodeKind::Dice(count, sides) => ("Dice", format!("[{}, {}]", count, sides)),
+                AstNodeKind::Identifier(name) => ("Identifier", json_string(name)),
+                AstNodeKind::Parameter(index) => ("Parameter", index.to_string()),
+            };
+
+            json.push_str(&format!("{{\"kind\": {{\"{}\": {}", variant, fields));
+            let close = match &node.kind {
+                AstNodeKind::FunctionCall(_, _) => "]]",
+                AstNodeKind::BinaryOperation(..) | AstNodeKind::UnaryOperation(..) | AstNodeKind::Conditional(_) => "]",
+                _ => "",
+            };
+            pending.push(Chunk::Text(format!("{}}}, \"span\": {{\"start\": {}, \"end\": {}}}}}", close, node.span.start, node.span.end)));
+            for (index, child) in node.children().into_iter().enumerate().rev() {
+                pending.push(Chunk::Node(child));
+                if index > 0 {
+                    pending.push(Chunk::Text(String::from(", ")));
+                }
+            }
+        }
+
+        json
+    }
+
+
+    ///
+    /// Get how tightly the node binds when written as source, which decides
+    /// whether it needs parentheses as the operand of another node.
+    /// 
+    pub(crate) fn precedence(&self) -> u8 {
+        match &self.kind {
+            AstNodeKind::BinaryOperation(operation_type, _, _) => operation_type.precedence(),
+            AstNodeKind::UnaryOperation(UnaryOperationType::Factorial, _) => PRIMARY,
+            AstNodeKind::UnaryOperation(_, _) => PREFIX,
+            AstNodeKind::Integer(x) if *x < 0 && *x != i64::MIN => PREFIX,
+            AstNodeKind::Float(x) if x.is_sign_negative() => PREFIX,
+            AstNodeKind::Conditional(_) => 0,
+            _ => PRIMARY,
+        }
+    }
+
+
+    ///
+    /// Check whether the node applies an operator, which the alternate
+    /// format puts in parentheses, counting a negative literal as the
+    /// negation it is written as.
+    /// 
+    fn is_operation(&self) -> bool {
+        match &self.kind {
+            AstNodeKind::BinaryOperation(..) | AstNodeKind::UnaryOperation(..) | AstNodeKind::Conditional(_) => true,
+            _ => self.precedence() < PRIMARY,
+        }
+    }
+
+
+    ///
+    /// Write a leaf, or push the pieces of a node with children to be written
+    /// in order.
+    /// 
+    fn write_node<'a>(&'a self, f: &mut fmt::Formatter<'_>, pieces: &mut Vec<Piece<'a>>) -> fmt::Result {
+        let mut sequence = vec![];
+
+        match &self.kind {
+            AstNodeKind::BinaryOperation(operation_type, _, _) => {
+                // Walk down the left operands which need no parentheses with a
+                // loop, so that long chains such as `1 + 2 + ... + n` are
+                // written as one sequence.
+                let precedence = operation_type.precedence();
+                let mut chain = vec![];
+                let mut leftmost = self;
+
+                while let AstNodeKind::BinaryOperation(operation_type, left, right) = &leftmost.kind
+                    && operation_type.precedence() == precedence && (chain.is_empty() || !f.alternate()) {
+                    chain.push((operation_type, right));
+                    leftmost = left;
+                }
+
+                sequence.push(Piece::Operand(leftmost, precedence));
+                for (operation_type, right) in chain.into_iter().rev() {
+                    sequence.extend([Piece::Text(" "), Piece::Text(operation_type.symbol()), Piece::Text(" ")]);
+                    sequence.push(Piece::Operand(right, precedence + 1));
+                }
+            },
+            AstNodeKind::UnaryOperation(UnaryOperationType::Factorial, operand) => {
+                sequence.extend([Piece::Operand(operand, PRIMARY), Piece::Text("!")]);
+            },
+            AstNodeKind::UnaryOperation(operation_type, operand) => {
+                sequence.extend([Piece::Text(operation_type.symbol().trim_end_matches('x')), Piece::Operand(operand, PREFIX)]);
+            },
+            AstNodeKind::FunctionCall(name, arguments) => {
+                sequence.extend([Piece::Text(name), Piece::Text("(")]);
+                for (index, argument) in arguments.iter().enumerate() {
+                    if index > 0 {
+                        sequence.push(Piece::Text(", "));
+                    }
+                    sequence.push(Piece::Node(argument));
+                }
+                sequence.push(Piece::Text(")"));
+            },
+            AstNodeKind::Conditional(parts) => {
+                let cases = parts.chunks_exact(2);
+                let otherwise = cases.remainder();
+                for case in cases {
+                    sequence.extend([Piece::Operand(&case[0], 1), Piece::Text(" when "), Piece::Operand(&case[1], 1), Piece::Text("; ")]);
+                }
+                sequence.extend([Piece::Operand(&otherwise[0], 1), Piece::Text(" otherwise")]);
+            },
+            // The smallest integer has no literal, since its magnitude is
+            // larger than the largest integer.
+            AstNodeKind::Integer(i64::MIN) => write!(f, "({} - 1)", -i64::MAX)?,
+            AstNodeKind::Integer(x) => write!(f, "{}", x)?,
+
+            // Rust writes every digit of a float rather than using an exponent,
+            // which the lexer does not read, but may leave out the decimal point.
+            AstNodeKind::Float(x) => match x.fract() == 0.0 && x.is_finite() {
+                true => write!(f, "{}.0", x)?,
+                false => write!(f, "{}", x)?,
+            },
+            AstNodeKind::Boolean(x) => write!(f, "{}", x)?,
+            AstNodeKind::String(s) => write!(f, "\"{}\"", s.replace('\\', "\\\\").replace('"', "\\\""))?,
+            AstNodeKind::Dice(count, sides) => write!(f, "{}d{}", count, sides)?,
+            AstNodeKind::Identifier(name) => write!(f, "{}", name)?,
+            AstNodeKind::Parameter(_) => write!(f, "?")?,
+        }
+
+        pieces.extend(sequence.into_iter().rev());
+        Ok(())
+    }
+}
+
+
+impl PartialEq for AstNode {
+    ///
+    /// Compare the structure of two trees, ignoring their spans, so that
+    /// the same expression written with different spacing compares equal.
+    /// Floats are equal when their bits are, so that `0.0` and `-0.0` differ.
+    /// The trees are walked with a loop rather than recursion, as in `drop`.
+    /// 
+    fn eq(&self, other: &Self) -> bool {
+        let mut pairs = vec![(self, other)];
+
+        while let Some((a, b)) = pairs.pop() {
+            let same = match (&a.kind, &b.kind) {
+                (AstNodeKind::BinaryOperation(x, _, _), AstNodeKind::BinaryOperation(y, _, _)) => x == y,
+                (AstNodeKind::UnaryOperation(x, _), AstNodeKind::UnaryOperation(y, _)) => x == y,
+                (AstNodeKind::FunctionCall(x, xs), AstNodeKind::FunctionCall(y, ys)) => x == y && xs.len() == ys.len(),
+                (AstNodeKind::Integer(x), AstNodeKind::Integer(y)) => x == y,
+                (AstNodeKind::Float(x), AstNodeKind::Float(y)) => x.to_bits() == y.to_bits(),
+                (AstNodeKind::Boolean(x), AstNodeKind::Boolean(y)) => x == y,
+                (AstNodeKind::String(x), AstNodeKind::String(y)) => x == y,
+                (AstNodeKind::Dice(x, m), AstNodeKind::Dice(y, n)) => x == y && m == n,
+                (AstNodeKind::Identifier(x), AstNodeKind::Identifier(y)) => x == y,
+                (AstNodeKind::Parameter(x), AstNodeKind::Parameter(y)) => x == y,
+                (AstNodeKind::Conditional(xs), AstNodeKind::Conditional(ys)) => xs.len() == ys.len(),
+                _ => false,
+            };
+
+            if !same {
+                return false;
+            }
+
+            pairs.extend(a.children().into_iter().zip(b.children()));
+        }
+
+        true
+    }
+}
+
+
+impl fmt::Display for AstNode {
+    ///
+    /// Write the node as source which parses back to the same tree, with
+    /// only the parentheses it needs. Implicit multiplication is written
+    /// with an explicit `*`. See `to_source`. The alternate format, `{:#}`,
+    /// puts every operation which is an operand in parentheses instead. See
+    /// `to_parenthesized_source`.
+    /// 
+    /// The tree is written with a loop over a stack of the pieces left to
+    /// write, rather than recursion, as in `drop`.
+    /// 
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut pieces = vec![Piece::Node(self)];
+
+        while let Some(piece) = pieces.pop() {
+            match piece {
+                Piece::Text(text) => f.write_str(text)?,
+                Piece::Operand(node, precedence) => match node.precedence() < precedence || (f.alternate() && node.is_operation()) {
+                    true => pieces.extend([Piece::Text(")"), Piece::Node(node), Piece::Text("(")]),
+                    false => pieces.push(Piece::Node(node)),
+                },
+                Piece::Node(node) => node.write_node(f, &mut pieces)?,
+            }
+        }
+
+        Ok(())
+    }
+}
+
+
+///
+/// A piece of an S-expression or of JSON left to write.
+/// 
+enum Chunk<'a> {
+    Text(String),
+    Node(&'a AstNode)
+}
+
+
+///
+/// A piece of the source of a tree left to write.
+/// 
+enum Piece<'a> {
+    Text(&'a str),
+
+    /// A node, wrapped in parentheses if it binds looser than the precedence.
+    Operand(&'a AstNode, u8),
+
+    Node(&'a AstNode)
+}
+
+
+///
+/// Replaces a variable with a tree, for `AstNode::substitute`.
+/// 
+struct Substitution<'a> {
+    name: &'a str,
+    replacement: &'a AstNode
+}
+
+
+impl AstTransformer for Substitution<'_> {
+    fn transform_identifier(&mut self, node: AstNode) -> AstNode {
+        match &node.kind {
+            AstNodeKind::Identifier(name) if name == self.name => {
+                visit::transform(self.replacement.clone(), &mut Respan(node.span))
+            },
+            _ => node,
+        }
+    }
+}
+
+
+///
+/// Gives every node of a tree the same span.
+/// 
+struct Respan(Span);
+
+
+impl AstTransformer for Respan {
+    fn transform_node(&mut self, mut node: AstNode) -> AstNode {
+        node.span = self.0;
+        node
+    }
+}
+
+
+impl Clone for AstNode {
+    ///
+    /// Copy the tree with a loop rather than recursion, as in `drop`. The
+    /// nodes are copied children first, and each node is rebuilt from the
+    /// copies of its children on top of a stack.
+    /// 
+    fn clone(&self) -> Self {
+        let mut pending = vec![(self, false)];
+        let mut copies: Vec<AstNode> = vec![];
+
+        while let Some((node, children_copied)) = pending.pop() {
+            let children = node.children();
+            if !children_copied && !children.is_empty() {
+                pending.push((node, true));
+                pending.extend(children.into_iter().rev().map(|child| (child, false)));
+                continue;
+            }
+
+            let mut children = copies.split_off(copies.len() - children.len()).into_iter();
+            let mut child = || Box::new(children.next().expect("the children of a node are copied before it"));
+            let kind = match &node.kind {
+                AstNodeKind::BinaryOperation(operation_type, _, _) => {
+                    let left = child();
+                    AstNodeKind::BinaryOperation(*operation_type, left, child())
+                },
+                AstNodeKind::UnaryOperation(operation_type, _) => AstNodeKind::UnaryOperation(*operation_type, child()),
+                AstNodeKind::FunctionCall(name, _) => AstNodeKind::FunctionCall(name.clone(), children.collect()),
+                AstNodeKind::Conditional(_) => AstNodeKind::Conditional(children.collect()),
+                kind => kind.clone(),
+            };
+            copies.push(AstNode::new(kind, node.span));
+        }
+
+        copies.pop().expect("the root is copied last")
+    }
+}
+
+
+impl Drop for AstNode {
+    ///
+    /// Drop the children of a node with a loop instead of recursion, so that
+    /// very deep trees such as a chain of a million additions can be dropped
+    /// without overflowing the stack.
+    /// 
+    fn drop(&mut self) {
+        let mut stack: Vec<AstNode> = vec![];
+        let mut kind = std::mem::replace(&mut self.kind, AstNodeKind::Boolean(false));
+
+        loop {
+            match kind {
+                AstNodeKind::BinaryOperation(_, left, right) => {
+                    stack.push(*left);
+                    stack.push(*right);
+                },
+                AstNodeKind::UnaryOperation(_, operand) => stack.push(*operand),
+                AstNodeKind::FunctionCall(_, arguments) => stack.extend(arguments),
+                AstNodeKind::Conditional(parts) => stack.extend(parts),
+                _ => {},
+            }
+
+            match stack.pop() {
+                Some(mut node) => kind = std::mem::replace(&mut node.kind, AstNodeKind::Boolean(false)),
+                None => break,
+            }
+        }
+    }
+}
+
+
+///
+/// A rule of the grammar which the parser starts reading.
+/// 
+#[derive(Debug, Clone, Copy)]
+enum Goal {
+    /// Operands joined by binary operators, then any conditional chain.
+    Expression,
+
+    /// Terms joined by the binary operators binding at least as tightly as
+    /// this level. The right operand of each takes the operators which bind
+    /// more tightly than it, which is precedence climbing.
+    Binary(u8),
+
+    /// Factors joined by `*`, `/`, `%` or implicit multiplication.
+    Term,
+
+    /// A primary followed by any number of postfix operators.
+    Factor,
+
+    /// A terminal, an expression between parentheses, a prefix operation or a call.
+    Primary
+}
+
+
+///
+/// A rule the parser is reading, waiting for the node of a part of it.
+/// 
+enum Frame<'a> {
+    /// An expression, waiting for its binary operations.
+    Expression,
+
+    /// A conditional chain, waiting for the guard after the last value.
+    Guard(Vec<NodeId>),
+
+    /// A conditional chain, waiting for the value after the last guard.
+    Case(Vec<NodeId>),
+
+    /// Binary operations at a level, waiting for the first term, or for the
+    /// right operand of an operator given with its left operand.
+    Binary(u8, Option<(NodeId, BinaryOperationType)>),
+
+    /// A term, waiting for the first factor, or for the right operand of an
+    /// operator given with its left operand.
+    Term(Option<(NodeId, BinaryOperationType)>),
+
+    /// A factor, waiting for its primary.
+    Factor,
+
+    /// An expression between parentheses, waiting for the expression.
+    Parentheses(Token<'a>),
+
+    /// A prefix operation, waiting for its operand.
+    Unary(UnaryOperationType, Token<'a>),
+
+    /// A call to a function, waiting for the argument after the ones given.
+    Argument(Token<'a>, Vec<NodeId>)
+}
+
+
+impl Frame<'_> {
+    ///
+    /// Check whether the node the frame waits for is an operand of the node
+    /// it builds, one level deeper than it. An expression between parentheses
+    /// is no deeper than the parentheses, and the first operand of a chain
+    /// such as `1 + 2 + 3` does not count, so that long chains can be read.
+    /// 
+    fn nests(&self) -> bool {
+        matches!(self, Frame::Guard(_) | Frame::Case(_) | Frame::Binary(_, Some(_)) | Frame::Term(Some(_))
+            | Frame::Unary(..) | Frame::Argument(..))
+    }
+}
+
+
+///
+/// What the parser does next: start reading a rule, or give the node just
+/// read to the frame on top of the stack.
+/// 
+enum Action {
+    Read(Goal),
+    Return(NodeId)
 }
 
 
+///
+/// Reads tokens into the nodes of an `Arena`, from which the trees of the
+/// expressions read are taken. The tokens are taken from an iterator one at
+/// a time, looking one token ahead, so a `Lexer` can scan them as they are read.
+/// 
 pub struct Parser <'a> {
-    token_stream: &'a Vec<Rc<Token>>,
-    current_position: usize,
+    tokens: Box<dyn Iterator<Item = Result<Token<'a>, pxpr::Error>> + 'a>,
+    /// The next token, which has been taken from the iterator but not read.
+    next: Option<Token<'a>>,
+    /// The type and span of the most recently read token.
+    previous: Option<(TokenType, Span)>,
+    /// The first error the iterator gave, after which it is not read further.
+    lex_error: Option<pxpr::Error>,
+    /// The columns of the `Invalid` tokens read, whose errors the lexer has
+    /// already reported.
+    invalid_columns: Vec<u32>,
+    arena: Arena,
+    parameter_count: usize,
+    precedence: Precedence,
+    max_depth: Option<usize>,
+    depth: usize,
 }
 
 
 impl <'a> Parser<'a> {
-    pub fn new(token_stream: &'a Vec<Rc<Token>>) -> Self {
-        Parser { 
-            token_stream, 
-            current_position: 0, 
-        }
+    pub fn new(token_stream: &'a [Token<'a>]) -> Self {
+        Parser::from_tokens(token_stream.iter().cloned().map(Ok))
+    }
+
+
+    ///
+    /// Read the tokens given by an iterator, such as a `Lexer`, which are
+    /// scanned as the parser reads them rather than all up front. An error
+    /// from the iterator ends the tokens, and is what parsing fails with.
+    /// 
+    pub fn from_tokens<I>(tokens: I) -> Self
+    where
+        I: IntoIterator<Item = Result<Token<'a>, pxpr::Error>>,
+        I::IntoIter: 'a
+    {
+        let mut parser = Parser { 
+            tokens: Box::new(tokens.into_iter()),
+            next: None,
+            previous: None,
+            lex_error: None,
+            invalid_columns: vec![],
+            arena: Arena::new(),
+            parameter_count: 0,
+            precedence: Precedence::default(),
+            max_depth: None,
+            depth: 0,
+        };
+        parser.pull();
+        parser
+    }
+
+
+    ///
+    /// Use the given rules for which operators bind tighter than others.
+    /// 
+    pub fn with_precedence(mut self, precedence: Precedence) -> Self {
+        self.precedence = precedence;
+        self
     }
 
 
-    fn has_next(&self) -> bool {
-        self.current_position < self.token_stream.len()
+    ///
+    /// Fail with an error of kind `TooDeep` rather than read operands nested
+    /// deeper than the given number of levels, as counted by `Limits::max_depth`.
+    /// 
+    pub fn with_max_depth(mut self, max_depth: Option<usize>) -> Self {
+        self.max_depth = max_depth;
+        self
     }
 
 
@@ -53,17 +887,75 @@ impl <'a> Parser<'a> {
     /// Get the next token in the token stream, or `None` if no more tokens exist,
     /// without advancing in the token stream.
     /// 
-    fn peek(&self) -> Option<Rc<Token>> {
-        if !self.has_next() {
-            return None;
+    fn peek(&self) -> Option<&Token<'a>> {
+        self.next.as_ref()
+    }
+
+
+    ///
+    /// Take the next token from the iterator, unless it has failed.
+    /// 
+    fn pull(&mut self) {
+        if self.lex_error.is_some() {
+            return;
+        }
+
+        self.next = match self.tokens.next() {
+            Some(Ok(token)) => {
+                if matches!(token.type_, TokenType::Invalid) {
+                    self.invalid_columns.push(token.column);
+                }
+                Some(token)
+            },
+            Some(Err(error)) => {
+                self.lex_error = Some(error);
+                None
+            },
+            None => None,
+        };
+    }
+
+
+    ///
+    /// Fail with the error of the iterator if it gave one, since reading
+    /// stopped there, whatever came of parsing the tokens before it.
+    /// 
+    fn check_tokens<T>(&mut self, result: Result<T, pxpr::Error>) -> Result<T, pxpr::Error> {
+        match self.lex_error.take() {
+            Some(error) => Err(error),
+            None => result,
         }
-        let next_token = self.token_stream[self.current_position].clone();
-        Some(next_token)
     }
 
 
     fn error(&self, message: String, column: u32) -> pxpr::Error {
-        pxpr::Error::new(column, message)
+        pxpr::Error::parse(column, message)
+    }
+
+
+    ///
+    /// Check that nothing but the end of the input is left after what was
+    /// parsed, so that a token such as the `2` of `1 2` is not ignored.
+    /// 
+    fn expect_end<T>(&self, parsed: T) -> Result<T, pxpr::Error> {
+        match self.peek() {
+            Some(tok) if !matches!(tok.type_, TokenType::Eof) => Err(self.error(
+                format!("Unexpected token: '{}'", tok.lexeme), tok.column)),
+            _ => Ok(parsed),
+        }
+    }
+
+
+    ///
+    /// Get the column just past the last token, where a token missing from
+    /// the end of the expression is reported.
+    /// 
+    fn end_column(&self) -> u32 {
+        match (&self.next, &self.previous) {
+            (Some(token), _) => token.span.end,
+            (None, Some((_, span))) => span.end,
+            (None, None) => 0,
+        }
     }
 
 
@@ -71,73 +963,262 @@ impl <'a> Parser<'a> {
     /// Get the next token in the token stream and advance in the stream,
     /// or an error if no more tokens exist in the token stream.
     /// 
-    fn advance(&mut self) -> Option<Rc<Token>> {
-        let next_token = self.peek();
-        self.current_position += 1;
+    fn advance(&mut self) -> Option<Token<'a>> {
+        let next_token = self.next.take();
+        if let Some(token) = &next_token {
+            self.previous = Some((token.type_.clone(), token.span));
+            self.pull();
+        }
         next_token
     }
 
 
     ///
-    /// Parse an factor between parentheses.
+    /// Check whether the previous token ends an operand which can be multiplied
+    /// implicitly, being a number or a closing parenthesis.
+    /// 
+    fn follows_operand(&self) -> bool {
+        matches!(self.previous, Some((TokenType::Integer | TokenType::Float | TokenType::RightParen, _)))
+    }
+
+
+    ///
+    /// Parse an expression made of terms joined by binary operators, such as
+    /// `1 + 2 == 3`, optionally followed by a conditional chain.
     /// 
-    fn parse_parentheses(&mut self) -> Result<Box<AstNode>, pxpr::Error> {
-        let factor = self.parse_expression();
-        
-        if let None = self.peek() {
-            return Err(self.error(
-                String::from("Expected: ')'"), 0));
+    /// The rules of the grammar are read in a loop over a stack of frames
+    /// rather than by recursing into each nested operand, so that an input
+    /// nested a hundred thousand parentheses or prefix operators deep is
+    /// bounded only by memory and cannot overflow the stack.
+    /// 
+    fn parse_expression(&mut self) -> Result<NodeId, pxpr::Error> {
+        // Only the end of the input is left when there is nothing but
+        // whitespace and comments.
+        if self.previous.is_none() && self.peek().is_some_and(|token| matches!(token.type_, TokenType::Eof)) {
+            return Err(self.error(String::from("Empty expression"), 0));
         }
 
-        let tok = self.peek().unwrap();
-        match tok.type_ {
-            TokenType::RightParen => {
-                self.advance();
-                return factor;
+        let mut frames = vec![];
+        let mut action = Action::Read(Goal::Expression);
+        self.depth = 0;
+
+        loop {
+            action = match action {
+                Action::Read(goal) => self.read(goal, &mut frames)?,
+                Action::Return(node) => match frames.pop() {
+                    Some(frame) => {
+                        if frame.nests() {
+                            self.depth -= 1;
+                        }
+                        self.resume(frame, node, &mut frames)?
+                    },
+                    None => return Ok(node),
+                },
+            };
+        }
+    }
+
+
+    ///
+    /// Push a frame, counting how deeply the node it waits for is nested
+    /// against the most levels allowed.
+    /// 
+    fn push(&mut self, frames: &mut Vec<Frame<'a>>, frame: Frame<'a>) -> Result<(), pxpr::Error> {
+        if frame.nests() {
+            self.depth += 1;
+            if let Some(max_depth) = self.max_depth && self.depth > max_depth {
+                let column = self.peek().map_or(self.end_column(), |token| token.column);
+                return Err(pxpr::Error::too_deep(column,
+                    format!("Expression nested deeper than the limit of {} levels", max_depth)));
             }
-            _ => Err(self.error(
-                String::from("Expected: ')', found"), 0))
+        }
+
+        frames.push(frame);
+        Ok(())
+    }
+
+
+    ///
+    /// Start reading a rule, pushing the frame which waits for its first part.
+    /// 
+    fn read(&mut self, goal: Goal, frames: &mut Vec<Frame<'a>>) -> Result<Action, pxpr::Error> {
+        match goal {
+            Goal::Expression => {
+                self.push(frames, Frame::Expression)?;
+                Ok(Action::Read(Goal::Binary(0)))
+            },
+            Goal::Binary(min_level) => {
+                self.push(frames, Frame::Binary(min_level, None))?;
+                Ok(Action::Read(Goal::Term))
+            },
+            Goal::Term => {
+                self.push(frames, Frame::Term(None))?;
+                Ok(Action::Read(Goal::Factor))
+            },
+            Goal::Factor => {
+                self.push(frames, Frame::Factor)?;
+                Ok(Action::Read(Goal::Primary))
+            },
+            Goal::Primary => self.read_primary(frames),
+        }
+    }
+
+
+    ///
+    /// Carry on reading the rule of a frame, given the node of the part it
+    /// was waiting for.
+    /// 
+    fn resume(&mut self, frame: Frame<'a>, node: NodeId, frames: &mut Vec<Frame<'a>>) -> Result<Action, pxpr::Error> {
+        match frame {
+            Frame::Expression => match self.peek() {
+                Some(token) if matches!(token.type_, TokenType::When) => {
+                    self.advance();
+                    self.push(frames, Frame::Guard(vec![node]))?;
+                    Ok(Action::Read(Goal::Binary(0)))
+                },
+                _ => Ok(Action::Return(node)),
+            },
+            Frame::Guard(parts) => self.resume_guard(parts, node, frames),
+            Frame::Case(parts) => self.resume_case(parts, node, frames),
+            Frame::Binary(min_level, pending) => {
+                let left_hand = self.join(pending, node);
+                match self.peek().and_then(|token| expression_operator(&token.type_)) {
+                    Some(operation_type) if self.level(operation_type) >= min_level => {
+                        self.advance();
+                        self.push(frames, Frame::Binary(min_level, Some((left_hand, operation_type))))?;
+                        Ok(Action::Read(Goal::Binary(self.level(operation_type) + 1)))
+                    },
+                    _ => Ok(Action::Return(left_hand)),
+                }
+            },
+            Frame::Term(pending) => {
+                let left_hand = self.join(pending, node);
+                self.resume_term(left_hand, frames)
+            },
+            Frame::Factor => Ok(Action::Return(self.parse_postfix(node))),
+            Frame::Parentheses(left_paren) => self.close_parentheses(&left_paren, node).map(Action::Return),
+            Frame::Unary(operation_type, operator_token) => {
+                let span = operator_token.span.to(self.arena[node].span);
+                Ok(Action::Return(self.arena.add(NodeKind::UnaryOperation(operation_type, node), span)))
+            },
+            Frame::Argument(name, arguments) => self.resume_arguments(name, arguments, node, frames),
         }
     }
 
 
-    fn parse_unary_operation(&mut self, operator: UnaryOperationType) -> Result<Box<AstNode>, pxpr::Error> {
-        let operand = self.parse_factor()?;
-        Ok(Box::new(
-            AstNode::UnaryOperation(
-                operator, 
-                operand
-            )
-        ))
+    ///
+    /// Join the right operand of a pending binary operation to its left
+    /// operand, in an operation spanning both.
+    /// 
+    fn join(&mut self, pending: Option<(NodeId, BinaryOperationType)>, right_hand: NodeId) -> NodeId {
+        match pending {
+            Some((left_hand, operation_type)) => {
+                let span = self.arena[left_hand].span.to(self.arena[right_hand].span);
+                self.arena.add(NodeKind::BinaryOperation(operation_type, left_hand, right_hand), span)
+            },
+            None => right_hand,
+        }
     }
 
 
     ///
-    /// Parse a factor, which is either a terminal such as a number,
-    /// or in the case that the next token is a '(', a nested factor.
+    /// Carry on reading a term after one of its factors: another factor
+    /// follows `*`, `/` or `%`, or directly follows a number or closing
+    /// parenthesis, which multiplies it implicitly as in `2(3 + 4)` or `2pi`.
     /// 
-    fn parse_factor(&mut self) -> Result<Box<AstNode>, pxpr::Error> {
+    fn resume_term(&mut self, left_hand: NodeId, frames: &mut Vec<Frame<'a>>) -> Result<Action, pxpr::Error> {
+        let operation_type = self.peek().and_then(|token| match token.type_ {
+            TokenType::Asterisk => Some(BinaryOperationType::Multiply),
+            TokenType::Slash => Some(BinaryOperationType::Divide),
+            TokenType::Modulus => Some(BinaryOperationType::Modulus),
+            _ => None,
+        });
+
+        match operation_type {
+            Some(operation_type) => {
+                self.advance();
+                self.push(frames, Frame::Term(Some((left_hand, operation_type))))?;
+                Ok(Action::Read(Goal::Factor))
+            },
+            None if self.peek().is_some_and(|token| matches!(token.type_, TokenType::LeftParen | TokenType::Identifier))
+                && self.follows_operand() => {
+                self.push(frames, Frame::Term(Some((left_hand, BinaryOperationType::Multiply))))?;
+                Ok(Action::Read(Goal::Factor))
+            },
+            None => Ok(Action::Return(left_hand)),
+        }
+    }
+
+
+    ///
+    /// Apply any number of postfix operators to a primary, such as the
+    /// factorial in `5!`.
+    /// 
+    fn parse_postfix(&mut self, mut operand: NodeId) -> NodeId {
+        while let Some(token) = self.peek() {
+            match token.type_ {
+                TokenType::Not => {
+                    let span = self.arena[operand].span.to(token.span);
+                    self.advance();
+                    operand = self.arena.add(NodeKind::UnaryOperation(UnaryOperationType::Factorial, operand), span);
+                },
+
+                _ => break
+            }
+        }
+
+        operand
+    }
+
+
+    ///
+    /// Start reading a primary, which is either a terminal such as a number,
+    /// or an expression between parentheses, a prefix operation or a call,
+    /// which wait in a frame for what they contain.
+    /// 
+    fn read_primary(&mut self, frames: &mut Vec<Frame<'a>>) -> Result<Action, pxpr::Error> {
         let next_token = self.advance();
 
-        if let None = next_token {
-            return Err(self.error(String::from("Expected an operand"), 0))
+        if next_token.is_none() {
+            return Err(self.error(String::from("Expected an operand"), self.end_column()))
         }
 
         let tok = next_token.unwrap();
+        let operation_type = match tok.type_ {
+            TokenType::Minus => Some(UnaryOperationType::ArithmeticNegate),
+            TokenType::Not => Some(UnaryOperationType::LogicalNot),
+            TokenType::BitwiseNot => Some(UnaryOperationType::BitwiseNot),
+            _ => None,
+        };
 
         match tok.type_ {
-            TokenType::LeftParen 
-                => self.parse_parentheses(),
+            TokenType::LeftParen => {
+                self.push(frames, Frame::Parentheses(tok))?;
+                Ok(Action::Read(Goal::Expression))
+            },
+
+            // The operand of a prefix operator is a factor, so `-5!` negates `5!`.
+            _ if operation_type.is_some() => {
+                self.push(frames, Frame::Unary(operation_type.unwrap(), tok))?;
+                Ok(Action::Read(Goal::Factor))
+            },
 
-            TokenType::Minus
-                => self.parse_unary_operation(UnaryOperationType::ArithmeticNegate),
+            // `min` and `max` are operators between operands, as in `x max 0`,
+            // but name functions or constants where an operand is expected.
+            TokenType::Identifier | TokenType::Min | TokenType::Max 
+                if self.peek().is_some_and(|next| matches!(next.type_, TokenType::LeftParen))
+                => self.read_function_call(tok, frames),
 
-            TokenType::Not 
-                => self.parse_unary_operation(UnaryOperationType::LogicalNot),
+            _ => self.parse_terminal(&tok).map(Action::Return),
+        }
+    }
 
-            TokenType::BitwiseNot 
-                => self.parse_unary_operation(UnaryOperationType::BitwiseNot),
 
+    ///
+    /// Parse a terminal, such as a number, a string or a name.
+    /// 
+    fn parse_terminal(&mut self, tok: &Token) -> Result<NodeId, pxpr::Error> {
+        match tok.type_ {
             TokenType::Boolean => {
                 if tok.value.is_none() {
                     return Err(self.error("Expected a boolean value".to_string(), tok.column))
@@ -152,9 +1233,7 @@ impl <'a> Parser<'a> {
                     return Err(self.error("Expected a boolean value".to_string(), tok.column))
                 }
 
-                Ok(Box::new(
-                    AstNode::Boolean(bool_value.unwrap())
-                ))
+                Ok(self.arena.add(NodeKind::Boolean(bool_value.unwrap()), tok.span))
             },
 
             TokenType::Integer => {
@@ -171,9 +1250,7 @@ impl <'a> Parser<'a> {
                     return Err(self.error("Expected an integer value".to_string(), tok.column))
                 }
 
-                Ok(Box::new(
-                    AstNode::Integer(integer_value.unwrap())
-                ))
+                Ok(self.arena.add(NodeKind::Integer(integer_value.unwrap()), tok.span))
             },
 
             TokenType::Float => {
@@ -190,9 +1267,44 @@ impl <'a> Parser<'a> {
                     return Err(self.error("Expected a float value".to_string(), tok.column))
                 }
 
-                Ok(Box::new(
-                    AstNode::Float(float_value.unwrap())
-                ))
+                Ok(self.arena.add(NodeKind::Float(float_value.unwrap()), tok.span))
+            }
+
+            TokenType::String => {
+                let string_value = tok.value
+                            .as_ref()
+                            .and_then(|value| value.as_string());
+
+                if string_value.is_none() {
+                    return Err(self.error("Expected a string value".to_string(), tok.column))
+                }
+
+                Ok(self.arena.add(NodeKind::String(string_value.unwrap().to_string()), tok.span))
+            }
+
+            TokenType::Dice => {
+                let dice = tok.value
+                            .as_ref()
+                            .and_then(|value| value.as_dice());
+
+                match dice {
+                    Some((count, sides)) => Ok(self.arena.add(NodeKind::Dice(count, sides), tok.span)),
+                    None => Err(self.error("Expected dice".to_string(), tok.column)),
+                }
+            }
+
+            TokenType::Identifier | TokenType::Min | TokenType::Max
+                => Ok(self.arena.add(NodeKind::Identifier(tok.lexeme.to_string()), tok.span)),
+
+            // The lexer has already reported the error, so the token stands
+            // in for an operand to let parsing carry on. A tree containing it
+            // is never returned.
+            TokenType::Invalid
+                => Ok(self.arena.add(NodeKind::Identifier(tok.lexeme.to_string()), tok.span)),
+
+            TokenType::Parameter => {
+                self.parameter_count += 1;
+                Ok(self.arena.add(NodeKind::Parameter(self.parameter_count - 1), tok.span))
             }
 
             _ => Err(self.error(String::from("Expected an factor."), tok.column))
@@ -201,156 +1313,287 @@ impl <'a> Parser<'a> {
 
 
     ///
-    /// Parse a term by splitting it into factors.
+    /// Start reading a call to the function named by `name`, whose arguments
+    /// each wait in a frame.
     /// 
-    fn parse_term(&mut self) -> Result<Box<AstNode>, pxpr::Error> {
-        let mut left_hand = self.parse_factor()?;
+    fn read_function_call(&mut self, name: Token<'a>, frames: &mut Vec<Frame<'a>>) -> Result<Action, pxpr::Error> {
+        match self.advance() {
+            Some(tok) if matches!(tok.type_, TokenType::LeftParen) => {},
+            _ => return Err(self.error(
+                format!("Expected: '(' after '{}'", name.lexeme), name.column))
+        }
 
-        while let Some(token) = self.peek() {
-            match token.as_ref().type_ {
-                TokenType::Asterisk => {
-                    self.advance().unwrap();
-                    let right_hand = self.parse_factor()?;
-                    left_hand = Box::new(
-                        AstNode::BinaryOperation(BinaryOperationType::Multiply, left_hand, right_hand));
-                },
+        match self.peek() {
+            Some(tok) if matches!(tok.type_, TokenType::RightParen) => {
+                self.advance();
+                Ok(Action::Return(self.function_call(&name, vec![])))
+            },
+            _ => {
+                self.push(frames, Frame::Argument(name, vec![]))?;
+                Ok(Action::Read(Goal::Expression))
+            },
+        }
+    }
 
-                TokenType::Slash => {
-                    self.advance().unwrap();
-                    let right_hand = self.parse_factor()?;
-                    left_hand = Box::new(
-                        AstNode::BinaryOperation(BinaryOperationType::Divide, left_hand, right_hand));
-                },
 
-                TokenType::Modulus => {
-                    self.advance().unwrap();
-                    let right_hand = self.parse_factor()?;
-                    left_hand = Box::new(AstNode::BinaryOperation(BinaryOperationType::Modulus, left_hand, right_hand));
-                },
+    ///
+    /// Carry on reading the comma separated arguments of a call after one
+    /// of them.
+    /// 
+    fn resume_arguments(&mut self, name: Token<'a>, mut arguments: Vec<NodeId>, argument: NodeId, frames: &mut Vec<Frame<'a>>) -> Result<Action, pxpr::Error> {
+        arguments.push(argument);
 
-                _ => {
-                    break;
-                }
+        let next_token = self.advance();
+        if next_token.is_none() {
+            return Err(self.error(String::from("Expected: ')'"), self.end_column()));
+        }
+
+        let tok = next_token.unwrap();
+        match tok.type_ {
+            TokenType::Comma => {
+                self.push(frames, Frame::Argument(name, arguments))?;
+                Ok(Action::Read(Goal::Expression))
+            },
+            TokenType::RightParen => Ok(Action::Return(self.function_call(&name, arguments))),
+            _ => Err(self.error(String::from("Expected: ',' or ')'"), tok.column))
+        }
+    }
+
+
+    ///
+    /// Create a call to the function named by `name`, spanning up to the
+    /// closing parenthesis which was just consumed.
+    /// 
+    fn function_call(&mut self, name: &Token, arguments: Vec<NodeId>) -> NodeId {
+        let span = name.span.to(self.previous.as_ref().map_or(name.span, |(_, span)| *span));
+        let arguments = self.arena.add_list(&arguments);
+        self.arena.add(NodeKind::FunctionCall(name.lexeme.to_string(), arguments), span)
+    }
+
+
+    ///
+    /// Finish an expression between parentheses. The span of the expression
+    /// is widened to include the parentheses.
+    /// 
+    fn close_parentheses(&mut self, left_paren: &Token, factor: NodeId) -> Result<NodeId, pxpr::Error> {
+        // An error inside the parentheses comes first, rather than being
+        // hidden by the missing ')' which follows from it.
+        match self.peek() {
+            Some(tok) if matches!(tok.type_, TokenType::RightParen) => {
+                self.arena[factor].span = left_paren.span.to(tok.span);
+                self.advance();
+                Ok(factor)
             }
+            Some(tok) if !matches!(tok.type_, TokenType::Eof) => Err(self.error(
+                format!("Expected: ')', found '{}'", tok.lexeme), tok.column)),
+            _ => Err(self.error(
+                String::from("Expected: ')'"), self.end_column())),
+        }
+    }
+
+
+    ///
+    /// Carry on reading a conditional chain such as `x when x >= 0; -x otherwise`
+    /// after the guard of a case, which must be followed by `;` and its value.
+    /// 
+    fn resume_guard(&mut self, mut parts: Vec<NodeId>, guard: NodeId, frames: &mut Vec<Frame<'a>>) -> Result<Action, pxpr::Error> {
+        parts.push(guard);
+
+        match self.advance() {
+            Some(tok) if is_clause_separator(&tok) => {},
+            Some(tok) if !matches!(tok.type_, TokenType::Eof) => return Err(self.error(
+                format!("Expected: ';' after the guard, found '{}'", tok.lexeme), tok.column)),
+            _ => return Err(self.error(String::from("Expected: ';' after the guard"), self.end_column())),
         }
 
-        Ok(left_hand)
+        self.push(frames, Frame::Case(parts))?;
+        Ok(Action::Read(Goal::Binary(0)))
     }
 
 
     ///
-    /// Parse an factor by splitting it into terms.
+    /// Carry on reading a conditional chain after the value of a case, which
+    /// is followed by the next case or by the value chosen when no guard
+    /// holds. Every chain ends with that value.
+    /// 
+    fn resume_case(&mut self, mut parts: Vec<NodeId>, value: NodeId, frames: &mut Vec<Frame<'a>>) -> Result<Action, pxpr::Error> {
+        parts.push(value);
+
+        match self.peek() {
+            Some(tok) if matches!(tok.type_, TokenType::When) => {
+                self.advance();
+                self.push(frames, Frame::Guard(parts))?;
+                Ok(Action::Read(Goal::Binary(0)))
+            },
+            Some(tok) if matches!(tok.type_, TokenType::Otherwise) => {
+                let span = self.arena[parts[0]].span.to(tok.span);
+                self.advance();
+                let parts = self.arena.add_list(&parts);
+                Ok(Action::Return(self.arena.add(NodeKind::Conditional(parts), span)))
+            },
+            Some(tok) if !matches!(tok.type_, TokenType::Eof) => Err(self.error(
+                format!("Expected: 'when' or 'otherwise', found '{}'", tok.lexeme), tok.column)),
+            _ => Err(self.error(String::from("Expected: 'when' or 'otherwise'"), self.end_column())),
+        }
+    }
+
+
+    ///
+    /// Get how tightly a binary operator joined in an expression binds under the
+    /// precedence rules of the parser.
+    /// 
+    fn level(&self, operation_type: BinaryOperationType) -> u8 {
+        match self.precedence {
+            Precedence::Table => operation_type.precedence(),
+            Precedence::Flat => 0,
+        }
+    }
+
+
+    ///
+    /// Parse an abstract syntax tree from a stream of tokens.
     /// 
     /// # Returns
-    /// A `Result<Box<AstNode>, ParserError>` in which, on success,
-    /// holds an abstract syntax tree representing the factor.
+    /// A `Result` encapsulating either a `Box<AstNode>` or a `ParserError`.
+    pub fn parse(&mut self) -> Result<Box<AstNode>, pxpr::Error> {
+        let result = self.parse_expression().and_then(|root| self.expect_end(root));
+        let root = self.check_tokens(result)?;
+        Ok(Box::new(self.arena.to_tree(root)))
+    }
+
+
     ///
-    /// TODO: Refactor the match conditions into a separate function.
+    /// Parse an expression like `parse`, but get the arena its nodes were
+    /// read into instead of a tree, as the evaluator works on.
     /// 
-    fn parse_expression(&mut self) -> Result<Box<AstNode>, pxpr::Error> {
-        let mut left_hand = self.parse_term()?;
+    /// # Returns
+    /// The arena, and the id of the root of the expression in it.
+    /// 
+    pub fn parse_to_arena(mut self) -> Result<(Arena, NodeId), pxpr::Error> {
+        let result = self.parse_expression().and_then(|root| self.expect_end(root));
+        let root = self.check_tokens(result)?;
+        Ok((self.arena, root))
+    }
 
-        while let Some(token) = self.peek() {
-            match token.as_ref().type_ {
-                TokenType::Plus => {
-                    self.advance().unwrap();
-                    let right_hand = self.parse_term()?;
-                    left_hand = Box::new(
-                        AstNode::BinaryOperation(BinaryOperationType::Add, left_hand, right_hand));
-                },
 
-                TokenType::Minus => {
-                    self.advance().unwrap();
-                    let right_hand = self.parse_term()?;
-                    left_hand = Box::new(
-                        AstNode::BinaryOperation(BinaryOperationType::Subtract, left_hand, right_hand));
-                },
+    ///
+    /// Parse a tuple of expressions separated by commas, such as
+    /// `17 / 5, 17 % 5`. A single expression is a tuple of one element.
+    /// 
+    pub fn parse_tuple(&mut self) -> Result<Vec<Box<AstNode>>, pxpr::Error> {
+        let result = self.parse_tuple_elements();
+        let elements = self.check_tokens(result)?;
+        Ok(elements.into_iter().map(|element| Box::new(self.arena.to_tree(element))).collect())
+    }
 
-                TokenType::And => {
-                    self.advance().unwrap();
-                    let right_hand = self.parse_term()?;
-                    left_hand = Box::new(
-                        AstNode::BinaryOperation(BinaryOperationType::And, left_hand, right_hand));
-                },
 
-                TokenType::Or => {
-                    self.advance().unwrap();
-                    let right_hand = self.parse_term()?;
-                    left_hand = Box::new(
-                        AstNode::BinaryOperation(BinaryOperationType::Or, left_hand, right_hand));
-                },
+    ///
+    /// Parse a tuple like `parse_tuple`, but get the arena its nodes were
+    /// read into instead of trees, like `parse_to_arena`.
+    /// 
+    /// # Returns
+    /// The arena, and the id of the root of each element in it.
+    /// 
+    pub fn parse_tuple_to_arena(mut self) -> Result<(Arena, Vec<NodeId>), pxpr::Error> {
+        let result = self.parse_tuple_elements();
+        let elements = self.check_tokens(result)?;
+        Ok((self.arena, elements))
+    }
 
-                TokenType::If => {
-                    self.advance().unwrap();
-                    let right_hand = self.parse_term()?;
-                    left_hand = Box::new(
-                        AstNode::BinaryOperation(BinaryOperationType::If, left_hand, right_hand));
-                }
 
-                TokenType::BitwiseAnd => {
-                    self.advance().unwrap();
-                    let right_hand = self.parse_term()?;
-                    left_hand = Box::new(
-                        AstNode::BinaryOperation(BinaryOperationType::BitwiseAnd, left_hand, right_hand)
-                    )
-                }
+    fn parse_tuple_elements(&mut self) -> Result<Vec<NodeId>, pxpr::Error> {
+        let mut elements = vec![self.parse_expression()?];
 
-                TokenType::BitwiseOr => {
-                    self.advance().unwrap();
-                    let right_hand = self.parse_term()?;
-                    left_hand = Box::new(
-                        AstNode::BinaryOperation(BinaryOperationType::BitwiseOr, left_hand, right_hand)
-                    )
-                }
+        while self.peek().is_some_and(|token| matches!(token.type_, TokenType::Comma)) {
+            self.advance();
+            elements.push(self.parse_expression()?);
+        }
 
-                TokenType::BitwiseXor => {
-                    self.advance().unwrap();
-                    let right_hand = self.parse_term()?;
-                    left_hand = Box::new(
-                        AstNode::BinaryOperation(BinaryOperationType::BitwiseXor, left_hand, right_hand)
-                    )
-                }
+        self.expect_end(elements)
+    }
 
-                TokenType::BitwiseLeftShift => {
-                    self.advance().unwrap();
-                    let right_hand = self.parse_term()?;
-                    left_hand = Box::new(
-                        AstNode::BinaryOperation(BinaryOperationType::BitwiseLeftShift, left_hand, right_hand)
-                    )
-                }
 
-                TokenType::BitwiseRightShift => {
-                    self.advance().unwrap();
-                    let right_hand = self.parse_term()?;
-                    left_hand = Box::new(
-                        AstNode::BinaryOperation(BinaryOperationType::BitwiseRightShift, left_hand, right_hand)
-                    )
-                }
+    ///
+    /// Parse a tuple like `parse_tuple`, but carry on past errors to find
+    /// every one of them. After an error, the parser skips to the next binary
+    /// operator and parses what follows it, or to the next element of the tuple.
+    /// 
+    /// The tokens are best taken from `Lexer::tokenize_recovering`, which
+    /// stands in a token for each error, since an error from the iterator
+    /// ends the tokens. That error is reported after the others. An error at
+    /// a token standing in for a lexer error is not reported again.
+    /// 
+    pub fn parse_tuple_recovering(&mut self) -> Result<Vec<Box<AstNode>>, Vec<pxpr::Error>> {
+        let mut elements = vec![];
+        let mut errors = vec![];
+
+        loop {
+            match self.parse_expression() {
+                Ok(element) => elements.push(element),
+                Err(error) => {
+                    self.report(&mut errors, error);
+                    if self.synchronize() {
+                        continue;
+                    }
+                },
+            }
 
-                _ => {
-                    break;
+            // An element must be followed by a comma or the end of the input.
+            loop {
+                match self.advance() {
+                    Some(token) if matches!(token.type_, TokenType::Comma) => break,
+                    Some(token) if !matches!(token.type_, TokenType::Eof) => {
+                        let error = self.error(format!("Unexpected token: '{}'", token.lexeme), token.column);
+                        self.report(&mut errors, error);
+                        if self.synchronize() {
+                            break;
+                        }
+                    },
+                    _ => return match errors.is_empty() && self.lex_error.is_none() {
+                        true => Ok(elements.into_iter().map(|element| Box::new(self.arena.to_tree(element))).collect()),
+                        false => {
+                            errors.extend(self.lex_error.take());
+                            Err(errors)
+                        },
+                    },
                 }
             }
         }
+    }
+
 
-        Ok(left_hand)
+    ///
+    /// Add an error found while recovering, unless it is at a token whose
+    /// error the lexer has already reported.
+    /// 
+    fn report(&self, errors: &mut Vec<pxpr::Error>, error: pxpr::Error) {
+        if !self.invalid_columns.contains(&error.column()) {
+            errors.push(error);
+        }
     }
 
 
     ///
-    /// Parse an abstract syntax tree from a stream of tokens.
+    /// Skip tokens after an error up to the next binary operator, which is
+    /// consumed, or up to the next comma or the end of the input.
     /// 
     /// # Returns
-    /// A `Result` encapsulating either a `Box<AstNode>` or a `ParserError`.
-    pub fn parse(&mut self) -> Result<Box<AstNode>, pxpr::Error> {
-        let root = self.parse_expression();
-        match root {
-            Ok(node) => {
-                return Ok(node);
-            },
-            Err(e) => {
-                return Err(e);
+    /// Whether an operator was found, so that an operand follows.
+    /// 
+    fn synchronize(&mut self) -> bool {
+        while let Some(token) = self.peek() {
+            if matches!(token.type_, TokenType::Comma | TokenType::Eof) {
+                return false;
+            }
+
+            let is_operator = matches!(token.type_, TokenType::Asterisk | TokenType::Slash | TokenType::Modulus)
+                || expression_operator(&token.type_).is_some();
+            self.advance();
+            if is_operator {
+                return true;
             }
         }
+
+        false
     }
 }
\ No newline at end of file
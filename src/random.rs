@@ -0,0 +1,96 @@
+use std::time::{SystemTime, UNIX_EPOCH};
+
+
+///
+/// A small, fast pseudo-random number generator (xorshift64*). It is not
+/// suitable for cryptography, but is reproducible from its seed.
+///
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Rng {
+    state: u64
+}
+
+
+impl Rng {
+    pub fn new(seed: u64) -> Self {
+        // The state of xorshift must never be zero.
+        Rng { state: seed ^ 0x9E37_79B9_7F4A_7C15 | 1 }
+    }
+
+
+    ///
+    /// Create a generator seeded from the current time.
+    ///
+    pub fn from_time() -> Self {
+        Rng::new(time_seed())
+    }
+
+
+    pub fn next_u64(&mut self) -> u64 {
+        self.state ^= self.state >> 12;
+        self.state ^= self.state << 25;
+        self.state ^= self.state >> 27;
+        self.state.wrapping_mul(0x2545_F491_4F6C_DD1D)
+    }
+
+
+    ///
+    /// Get a float in the range [0, 1).
+    ///
+    pub fn next_f64(&mut self) -> f64 {
+        (self.next_u64() >> 11) as f64 / (1u64 << 53) as f64
+    }
+
+
+    ///
+    /// Get an integer in the inclusive range [low, high].
+    ///
+    pub fn range(&mut self, low: i64, high: i64) -> i64 {
+        let size = high.wrapping_sub(low) as u64;
+        match size.checked_add(1) {
+            Some(count) => low.wrapping_add((self.next_u64() % count) as i64),
+            None => self.next_u64() as i64,
+        }
+    }
+
+
+    ///
+    /// Get a generator for the `index`th of many independent streams drawn
+    /// from this one, such as one for each line of a batch computed across
+    /// threads. The stream depends only on the state of this generator and
+    /// on `index`, which is mixed in with splitmix64, and this generator is
+    /// left as it is.
+    ///
+    pub fn split(&self, index: u64) -> Self {
+        let mut mixed = self.state.wrapping_add(index.wrapping_add(1).wrapping_mul(0x9E37_79B9_7F4A_7C15));
+        mixed = (mixed ^ (mixed >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+        mixed = (mixed ^ (mixed >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+        Rng { state: mixed ^ (mixed >> 31) | 1 }
+    }
+
+
+    ///
+    /// Get `true` with a probability of one in `n`.
+    ///
+    pub fn one_in(&mut self, n: u64) -> bool {
+        self.next_u64().is_multiple_of(n)
+    }
+}
+
+
+impl Default for Rng {
+    fn default() -> Self {
+        Rng::from_time()
+    }
+}
+
+
+///
+/// Get a seed from the current time.
+///
+pub fn time_seed() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|duration| duration.as_nanos() as u64)
+        .unwrap_or(0)
+}
@@ -0,0 +1,450 @@
+use std::{fmt::Write, str::FromStr};
+
+use crate::{
+    context::{bit_mask, Roll}, expression::Value, format::{bit_ruler, format_value, shortest, DefaultFormatter, DisplayMode, ValueFormatter},
+    Error, Severity, Warning
+};
+
+
+///
+/// The format results and errors are written in.
+///
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum OutputFormat {
+    /// Text without escape codes, for screen readers and logs.
+    Plain,
+
+    /// Text with errors and warnings highlighted by ANSI escape codes.
+    #[default]
+    Colored,
+
+    /// One JSON object per expression.
+    Json,
+
+    /// One CSV record per expression, after a header.
+    Csv
+}
+
+
+impl OutputFormat {
+    ///
+    /// Whether the format is meant to be read by programs rather than people.
+    ///
+    pub fn is_machine_readable(self) -> bool {
+        matches!(self, OutputFormat::Json | OutputFormat::Csv)
+    }
+}
+
+
+impl FromStr for OutputFormat {
+    type Err = String;
+
+    fn from_str(format: &str) -> Result<Self, Self::Err> {
+        match format {
+            "plain" => Ok(OutputFormat::Plain),
+            "color" => Ok(OutputFormat::Colored),
+            "json" => Ok(OutputFormat::Json),
+            "csv" => Ok(OutputFormat::Csv),
+            _ => Err(format!("Unknown output format: '{}' (expected 'plain', 'color', 'json' or 'csv')", format)),
+        }
+    }
+}
+
+
+///
+/// What comes before each result in the text formats.
+///
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum ResultPrefix {
+    /// An indented `= `, such as `    = 3`.
+    #[default]
+    Equals,
+
+    /// The expression itself, such as `1 + 2 = 3`.
+    Expression,
+
+    /// Nothing, such as `3`, for scripts which read the value alone.
+    None
+}
+
+
+impl ResultPrefix {
+    pub fn name(self) -> &'static str {
+        match self {
+            ResultPrefix::Equals => "equals",
+            ResultPrefix::Expression => "expression",
+            ResultPrefix::None => "none",
+        }
+    }
+}
+
+
+impl FromStr for ResultPrefix {
+    type Err = String;
+
+    fn from_str(prefix: &str) -> Result<Self, Self::Err> {
+        match prefix {
+            "equals" => Ok(ResultPrefix::Equals),
+            "expression" => Ok(ResultPrefix::Expression),
+            "none" => Ok(ResultPrefix::None),
+            _ => Err(format!("Unknown result prefix: '{}' (expected 'equals', 'expression' or 'none')", prefix)),
+        }
+    }
+}
+
+
+///
+/// The text rendered for one expression. `output` belongs on standard output,
+/// while `diagnostics` holds the errors and warnings which belong on standard
+/// error in the text formats. Every line ends with a newline.
+///
+#[derive(Debug, Default)]
+pub struct Rendered {
+    pub output: String,
+    pub diagnostics: String
+}
+
+
+///
+/// Renders the outcome of computing an expression, so that the REPL, single
+/// expressions and batches of expressions all produce the same output.
+///
+#[derive(Debug, Default, Clone)]
+pub struct Renderer {
+    pub format: OutputFormat,
+    pub mode: DisplayMode,
+    pub formatter: DefaultFormatter,
+    pub prefix: ResultPrefix,
+
+    /// Whether the individual dice rolled are shown.
+    pub show_rolls: bool,
+
+    /// Whether integer results are followed by a ruler of their bits.
+    pub show_bits: bool,
+
+    /// Whether a single result is labeled with its source, the same as each
+    /// element of a tuple, such as when computing a file of expressions.
+    pub label: bool
+}
+
+
+impl Renderer {
+    ///
+    /// Get the line written once before any results, if the format has one.
+    ///
+    pub fn header(&self) -> Option<String> {
+        match self.format {
+            OutputFormat::Csv => Some(String::from("ok,value,type,column,message\n")),
+            _ => None,
+        }
+    }
+
+
+    ///
+    /// Render the result of computing an expression along with the warnings
+    /// and dice rolls recorded while computing it.
+    ///
+    pub fn render(&self, result: &Result<Value, Error>, warnings: &[Warning], rolls: &[Roll]) -> Rendered {
+        self.render_labeled(None, result, warnings, rolls)
+    }
+
+
+    ///
+    /// Render the result of computing a tuple of expressions, or the errors
+    /// which stopped it from being computed. Each element of a tuple with
+    /// several elements is labeled with its source, while a single expression
+    /// or error is rendered the same as by `render` unless `label` is set or
+    /// results are prefixed with their expression.
+    ///
+    /// Errors and warnings are located in `source`, the expression as it was
+    /// entered, so that they line up with it in a terminal. See
+    /// `Error::located_in`.
+    ///
+    pub fn render_tuple(&self, source: &str, result: &Result<Vec<(String, Value)>, Vec<Error>>, warnings: &[Warning], rolls: &[Roll]) -> Rendered {
+        let warnings: Vec<Warning> = warnings.iter().map(|warning| warning.clone().located_in(source)).collect();
+
+        let elements = match result {
+            Ok(elements) => elements,
+            Err(errors) => {
+                let errors: Vec<Error> = errors.iter().map(|error| error.clone().located_in(source)).collect();
+                return self.render_errors(&errors, &warnings, rolls);
+            },
+        };
+        let warnings = warnings.as_slice();
+
+        let labeled = self.label || self.prefix == ResultPrefix::Expression;
+        if let [(_, value)] = elements.as_slice() && !labeled {
+            return self.render(&Ok(value.clone()), warnings, rolls);
+        }
+
+        // The warnings and rolls belong to the whole tuple, so they are
+        // rendered once, with its first element.
+        let mut rendered = Rendered::default();
+        for (index, (label, value)) in elements.iter().enumerate() {
+            let element = match index {
+                0 => self.render_labeled(Some(label), &Ok(value.clone()), warnings, rolls),
+                _ => self.render_labeled(Some(label), &Ok(value.clone()), &[], &[]),
+            };
+            rendered.output.push_str(&element.output);
+            rendered.diagnostics.push_str(&element.diagnostics);
+        }
+
+        rendered
+    }
+
+
+    ///
+    /// Render several errors found in the same expression. Text formats list
+    /// every error, JSON adds them all as `errors` after the first `error`,
+    /// and CSV, which has room for one, keeps the first.
+    ///
+    fn render_errors(&self, errors: &[Error], warnings: &[Warning], rolls: &[Roll]) -> Rendered {
+        let first = self.render(&Err(errors[0].clone()), warnings, rolls);
+
+        match self.format {
+            OutputFormat::Plain | OutputFormat::Colored => {
+                let colored = self.format == OutputFormat::Colored;
+                let diagnostics = errors[1..].iter()
+                    .map(|error| format!("{}\n", error_line(error, colored)))
+                    .collect::<String>();
+                Rendered { output: first.output, diagnostics: first.diagnostics + &diagnostics }
+            },
+            OutputFormat::Json if errors.len() > 1 => {
+                let errors: Vec<String> = errors.iter().map(json_error).collect();
+                let object = first.output.trim_end().strip_suffix('}').unwrap();
+                Rendered { output: format!("{}, \"errors\": [{}]}}\n", object, errors.join(", ")), diagnostics: first.diagnostics }
+            },
+            OutputFormat::Json | OutputFormat::Csv => first,
+        }
+    }
+
+
+    fn render_labeled(&self, label: Option<&str>, result: &Result<Value, Error>, warnings: &[Warning], rolls: &[Roll]) -> Rendered {
+        match self.format {
+            OutputFormat::Plain => self.render_text(label, result, warnings, rolls, false),
+            OutputFormat::Colored => self.render_text(label, result, warnings, rolls, true),
+            OutputFormat::Json => self.render_json(label, result, warnings, rolls),
+            OutputFormat::Csv => self.render_csv(result, warnings),
+        }
+    }
+
+
+    fn render_text(&self, label: Option<&str>, result: &Result<Value, Error>, warnings: &[Warning], rolls: &[Roll], colored: bool) -> Rendered {
+        let mut rendered = Rendered::default();
+
+        for warning in warnings {
+            writeln!(rendered.diagnostics, "{}", warning_line(warning, colored)).unwrap();
+        }
+
+        if self.show_rolls {
+            for roll in rolls {
+                let results: Vec<String> = roll.results.iter().map(i64::to_string).collect();
+                writeln!(rendered.output, "\t{}d{}: {}", roll.count, roll.sides, results.join(", ")).unwrap();
+            }
+        }
+
+        match result {
+            Ok(value) => {
+                let mut rows = format_value(value, self.mode, &self.formatter, self.formatter.bit_width);
+                if self.formatter.show_types {
+                    rows[0] = format!("{} : {}", rows[0], value.type_name());
+                }
+
+                let indent = match self.prefix {
+                    ResultPrefix::Equals => "\t",
+                    ResultPrefix::Expression | ResultPrefix::None => "",
+                };
+
+                // Without a prefix, a single row is the value alone, while the
+                // rows of programmer mode keep their names to tell them apart.
+                if self.prefix == ResultPrefix::None && rows.len() == 1 {
+                    rows[0] = rows[0].trim_start_matches("= ").to_string();
+                }
+
+                // A single row such as `= 3` reads well after its label, while
+                // several rows go below it.
+                match (label, rows.len(), self.prefix) {
+                    (_, _, ResultPrefix::None) | (None, _, _) => {},
+                    (Some(label), 1, _) => rows[0] = format!("{} {}", label, rows[0]),
+                    (Some(label), _, _) => writeln!(rendered.output, "{}{}", indent, label).unwrap(),
+                }
+
+                if let (true, Value::Integer(n)) = (self.show_bits, value) {
+                    rows.extend(bit_ruler(*n, self.formatter.bit_width));
+                }
+
+                for row in rows {
+                    writeln!(rendered.output, "{}{}", indent, row).unwrap();
+                }
+            },
+            Err(error) => writeln!(rendered.diagnostics, "{}", error_line(error, colored)).unwrap(),
+        }
+
+        rendered
+    }
+
+
+    fn render_json(&self, label: Option<&str>, result: &Result<Value, Error>, warnings: &[Warning], rolls: &[Roll]) -> Rendered {
+        let mut fields = match result {
+            Ok(value) => vec![
+                String::from("\"ok\": true"),
+                format!("\"value\": {}", self.json_value(value)),
+                format!("\"type\": \"{}\"", value.type_name()),
+            ],
+            Err(error) => vec![
+                String::from("\"ok\": false"),
+                format!("\"error\": {}", json_error(error)),
+            ],
+        };
+
+        if let Some(label) = label {
+            fields.insert(1, format!("\"label\": {}", json_string(label)));
+        }
+
+        if !warnings.is_empty() {
+            let warnings: Vec<String> = warnings.iter()
+                .map(|warning| format!("{{\"severity\": \"{}\", \"message\": {}, {}}}", 
+                    warning.severity().name(), json_string(warning.message()), json_position(warning.line(), warning.column())))
+                .collect();
+            fields.push(format!("\"warnings\": [{}]", warnings.join(", ")));
+        }
+
+        if self.show_rolls && !rolls.is_empty() {
+            let rolls: Vec<String> = rolls.iter()
+                .map(|roll| {
+                    let results: Vec<String> = roll.results.iter().map(i64::to_string).collect();
+                    format!("{{\"dice\": \"{}d{}\", \"results\": [{}]}}", roll.count, roll.sides, results.join(", "))
+                })
+                .collect();
+            fields.push(format!("\"rolls\": [{}]", rolls.join(", ")));
+        }
+
+        Rendered { output: format!("{{{}}}\n", fields.join(", ")), diagnostics: String::new() }
+    }
+
+
+    fn render_csv(&self, result: &Result<Value, Error>, warnings: &[Warning]) -> Rendered {
+        let diagnostics = warnings.iter()
+            .map(|warning| format!("{}\n", warning_line(warning, false)))
+            .collect();
+
+        let record = match result {
+            Ok(value) => format!("true,{},{},,", csv_field(&self.formatter.format(value)), value.type_name()),
+            Err(error) => format!("false,,,{},{}", error.column(), csv_field(error.message())),
+        };
+
+        Rendered { output: format!("{}\n", record), diagnostics }
+    }
+
+
+    ///
+    /// Convert a value to JSON, ignoring digit grouping and decimal commas.
+    /// Floats which JSON cannot represent, such as infinity, become strings.
+    ///
+    fn json_value(&self, value: &Value) -> String {
+        match value {
+            Value::Integer(n) => match self.formatter.bit_width {
+                Some(bits) => (*n as u64 & bit_mask(bits)).to_string(),
+                None => n.to_string(),
+            },
+            Value::Float(x) if x.is_finite() => shortest(*x),
+            Value::Float(x) => json_string(&x.to_string()),
+            Value::Boolean(b) => b.to_string(),
+            Value::String(s) => json_string(s),
+        }
+    }
+}
+
+
+///
+/// Get where an error or warning is, such as `Column 4` or, in a source with
+/// several lines, `Line 2, column 4`.
+///
+fn position(line: Option<u32>, column: u32) -> String {
+    match line {
+        Some(line) => format!("Line {}, column {}", line, column),
+        None => format!("Column {}", column),
+    }
+}
+
+
+///
+/// Get the line describing an error along with its code, optionally highlighted.
+///
+pub fn error_line(error: &Error, colored: bool) -> String {
+    let code = error.kind().code();
+    let position = position(error.line(), error.column());
+    match colored {
+        true => format!("{}: \x1b[31merror[{}]:\x1b[39m {}", position, code, error.message()),
+        false => format!("{}: error[{}]: {}", position, code, error.message()),
+    }
+}
+
+
+///
+/// Get the line describing a warning, optionally highlighted.
+///
+pub fn warning_line(warning: &Warning, colored: bool) -> String {
+    let color = match warning.severity() {
+        Severity::Info => 36,
+        Severity::Warning => 33,
+        Severity::Deprecation => 35,
+    };
+
+    let label = warning.severity().name();
+    let position = position(warning.line(), warning.column());
+    match colored {
+        true => format!("{}: \x1b[{}m{}:\x1b[39m {}", position, color, label, warning.message()),
+        false => format!("{}: {}: {}", position, label, warning.message()),
+    }
+}
+
+
+fn json_error(error: &Error) -> String {
+    format!("{{\"code\": \"{}\", \"kind\": \"{}\", \"message\": {}, {}}}", 
+        error.kind().code(), error.kind().name(), json_string(error.message()), json_position(error.line(), error.column()))
+}
+
+
+///
+/// Get the JSON fields locating an error or warning, which include its line
+/// only in a source with several lines.
+///
+fn json_position(line: Option<u32>, column: u32) -> String {
+    match line {
+        Some(line) => format!("\"line\": {}, \"column\": {}", line, column),
+        None => format!("\"column\": {}", column),
+    }
+}
+
+
+///
+/// Quote a string for JSON, escaping the characters JSON requires.
+///
+pub(crate) fn json_string(text: &str) -> String {
+    let mut quoted = String::from("\"");
+    for ch in text.chars() {
+        match ch {
+            '"' => quoted.push_str("\\\""),
+            '\\' => quoted.push_str("\\\\"),
+            '\n' => quoted.push_str("\\n"),
+            '\r' => quoted.push_str("\\r"),
+            '\t' => quoted.push_str("\\t"),
+            c if (c as u32) < 0x20 => write!(quoted, "\\u{:04x}", c as u32).unwrap(),
+            c => quoted.push(c),
+        }
+    }
+    quoted.push('"');
+    quoted
+}
+
+
+///
+/// Quote a CSV field if it contains a separator, a quote or a line break.
+///
+fn csv_field(text: &str) -> String {
+    match text.contains([',', '"', '\n', '\r']) {
+        true => format!("\"{}\"", text.replace('"', "\"\"")),
+        false => text.to_string(),
+    }
+}
@@ -0,0 +1,183 @@
+use crate::{builtins, context::Context, lexer, Warning};
+
+
+///
+/// Typographic characters which word processors substitute for ASCII
+/// operators, the operator each stands for, and the name of the character.
+///
+const SUBSTITUTIONS: &[(char, char, &str)] = &[
+    ('–', '-', "en dash"),
+    ('—', '-', "em dash"),
+    ('‒', '-', "figure dash"),
+    ('‐', '-', "hyphen"),
+    ('‑', '-', "non-breaking hyphen"),
+    ('“', '"', "left double quotation mark"),
+    ('”', '"', "right double quotation mark"),
+    ('„', '"', "double low-9 quotation mark"),
+    ('″', '"', "double prime"),
+    ('∗', '*', "asterisk operator"),
+    ('⁄', '/', "fraction slash"),
+    ('∕', '/', "division slash"),
+    ('＋', '+', "fullwidth plus sign"),
+    ('－', '-', "fullwidth hyphen-minus"),
+    ('（', '(', "fullwidth left parenthesis"),
+    ('）', ')', "fullwidth right parenthesis"),
+];
+
+
+///
+/// Replace the typographic characters of formulas pasted from documents,
+/// such as en dashes and curly quotes, with the ASCII operators they stand
+/// for, noting each replacement in the context. Every replacement is a
+/// single character, so columns are unchanged. Characters are only
+/// replaced when `context.sanitize` is set, while names which look like
+/// other names are always warned about by `warn_confusables`.
+///
+pub fn sanitize(source: &str, context: &mut Context) -> String {
+    warn_confusables(source, context);
+    if !context.sanitize {
+        return source.to_string();
+    }
+
+    source.chars()
+        .enumerate()
+        .map(|(column, ch)| match SUBSTITUTIONS.iter().find(|(typographic, _, _)| *typographic == ch) {
+            Some((typographic, replacement, name)) => {
+                context.note(Warning::info(column as u32, 
+                    format!("Replaced '{}' ({}) with '{}'", typographic, name, replacement)));
+                *replacement
+            },
+            None => ch,
+        })
+        .collect()
+}
+
+
+///
+/// Letters of other scripts which look the same as a Latin letter, the
+/// letter each looks like, and the name of the character.
+///
+const CONFUSABLES: &[(char, char, &str)] = &[
+    ('а', 'a', "Cyrillic small letter a"),
+    ('с', 'c', "Cyrillic small letter es"),
+    ('е', 'e', "Cyrillic small letter ie"),
+    ('һ', 'h', "Cyrillic small letter shha"),
+    ('і', 'i', "Cyrillic small letter Byelorussian-Ukrainian i"),
+    ('ј', 'j', "Cyrillic small letter je"),
+    ('о', 'o', "Cyrillic small letter o"),
+    ('р', 'p', "Cyrillic small letter er"),
+    ('ѕ', 's', "Cyrillic small letter dze"),
+    ('х', 'x', "Cyrillic small letter ha"),
+    ('у', 'y', "Cyrillic small letter u"),
+    ('А', 'A', "Cyrillic capital letter a"),
+    ('В', 'B', "Cyrillic capital letter ve"),
+    ('С', 'C', "Cyrillic capital letter es"),
+    ('Е', 'E', "Cyrillic capital letter ie"),
+    ('Н', 'H', "Cyrillic capital letter en"),
+    ('І', 'I', "Cyrillic capital letter Byelorussian-Ukrainian i"),
+    ('К', 'K', "Cyrillic capital letter ka"),
+    ('М', 'M', "Cyrillic capital letter em"),
+    ('О', 'O', "Cyrillic capital letter o"),
+    ('Р', 'P', "Cyrillic capital letter er"),
+    ('Т', 'T', "Cyrillic capital letter te"),
+    ('Х', 'X', "Cyrillic capital letter ha"),
+    ('ο', 'o', "Greek small letter omicron"),
+    ('ν', 'v', "Greek small letter nu"),
+    ('Α', 'A', "Greek capital letter alpha"),
+    ('Β', 'B', "Greek capital letter beta"),
+    ('Ε', 'E', "Greek capital letter epsilon"),
+    ('Η', 'H', "Greek capital letter eta"),
+    ('Ι', 'I', "Greek capital letter iota"),
+    ('Κ', 'K', "Greek capital letter kappa"),
+    ('Μ', 'M', "Greek capital letter mu"),
+    ('Ν', 'N', "Greek capital letter nu"),
+    ('Ο', 'O', "Greek capital letter omicron"),
+    ('Ρ', 'P', "Greek capital letter rho"),
+    ('Τ', 'T', "Greek capital letter tau"),
+    ('Χ', 'X', "Greek capital letter chi"),
+    ('Υ', 'Y', "Greek capital letter upsilon"),
+    ('Ζ', 'Z', "Greek capital letter zeta"),
+];
+
+
+///
+/// Warn about each name in a source which contains a letter that looks the
+/// same as a Latin letter, such as a Cyrillic `р` in `рi`, when the name
+/// also contains Latin letters or reads as a built-in name. Names written
+/// entirely in another script, such as `Δt`, are left alone. Names inside
+/// string literals and comments are skipped.
+///
+fn warn_confusables(source: &str, context: &mut Context) {
+    let chars: Vec<char> = source.chars().collect();
+    let mut position = 0;
+    let mut in_string = false;
+
+    while position < chars.len() {
+        let ch = chars[position];
+        match (in_string, ch) {
+            (true, '\\') => position += 2,
+            (false, '#') => {
+                while position < chars.len() && chars[position] != '\n' {
+                    position += 1;
+                }
+            },
+            (false, '/') if chars.get(position + 1) == Some(&'*') => {
+                position += 2;
+                while position < chars.len() && !(chars[position] == '*' && chars.get(position + 1) == Some(&'/')) {
+                    position += 1;
+                }
+                position += 2;
+            },
+            (_, '"') => {
+                in_string = !in_string;
+                position += 1;
+            },
+            (false, c) if c.is_alphabetic() || c == '_' => {
+                let start = position;
+                while position < chars.len() && (chars[position].is_alphanumeric() || chars[position] == '_') {
+                    position += 1;
+                }
+                warn_confusable_name(&chars[start..position], start as u32, context);
+            },
+            (_, c) if c.is_ascii_digit() && !in_string => {
+                // Skip numbers with letters in them, such as `0xff` and `1e5`.
+                while position < chars.len() && chars[position].is_ascii_alphanumeric() {
+                    position += 1;
+                }
+            },
+            _ => position += 1,
+        }
+    }
+}
+
+
+fn warn_confusable_name(name: &[char], column: u32, context: &mut Context) {
+    let confusables: Vec<&(char, char, &str)> = name.iter()
+        .filter_map(|ch| CONFUSABLES.iter().find(|(confusable, _, _)| confusable == ch))
+        .collect();
+
+    if confusables.is_empty() {
+        return;
+    }
+
+    let latin: String = name.iter()
+        .map(|ch| match CONFUSABLES.iter().find(|(confusable, _, _)| confusable == ch) {
+            Some((_, latin, _)) => *latin,
+            None => *ch,
+        })
+        .collect();
+
+    let mixed = name.iter().any(|ch| ch.is_ascii_alphabetic());
+    let builtin = builtins::constant(&latin).is_some() || builtins::lookup(&latin).is_some() || lexer::keyword(&latin).is_some();
+    if !mixed && !builtin {
+        return;
+    }
+
+    let characters: Vec<String> = confusables.iter()
+        .map(|(confusable, latin, character)| format!("'{}' ({}, not '{}')", confusable, character, latin))
+        .collect();
+
+    let name: String = name.iter().collect();
+    context.note(Warning::new(column, 
+        format!("'{}' looks like '{}' but contains {}", name, latin, characters.join(", "))));
+}
@@ -0,0 +1,869 @@
+use std::fmt;
+
+use crate::{
+    builtins, compat::Compat, compute_expression, context::Context, expression::{execute, Value}, fast, lexer::Lexer,
+    parse_expression, parser::{AstNode, AstNodeKind, BinaryOperationType, Parser, UnaryOperationType}, random::Rng, optimize::optimize,
+    span::Span, typecheck::{typecheck_for, Type}, vm::{self, Backend}
+};
+
+
+///
+/// An operator of the mirrored AST. These are kept separate from the
+/// parser's operation types so that the reference evaluator shares no
+/// code with the evaluator it is checking.
+///
+#[derive(Debug, Clone, Copy)]
+enum Operator {
+    Add, Subtract, Multiply, Divide, Modulus,
+    And, Or, If,
+    BitwiseAnd, BitwiseOr, BitwiseXor, LeftShift, RightShift,
+    Negate, Not, BitwiseNot, Factorial
+}
+
+
+impl Operator {
+    fn is_arithmetic(&self) -> bool {
+        matches!(self, Operator::Add | Operator::Subtract | Operator::Multiply | Operator::Divide | Operator::Modulus)
+    }
+
+
+    fn symbol(&self) -> &'static str {
+        match self {
+            Operator::Add => "+",
+            Operator::Subtract | Operator::Negate => "-",
+            Operator::Not | Operator::Factorial => "!",
+            Operator::Multiply => "*",
+            Operator::Divide => "/",
+            Operator::Modulus => "%",
+            Operator::And => "&&",
+            Operator::Or => "||",
+            Operator::If => "=>",
+            Operator::BitwiseAnd => "&",
+            Operator::BitwiseOr => "|",
+            Operator::BitwiseXor => "^",
+            Operator::LeftShift => "<<",
+            Operator::RightShift => ">>",
+            Operator::BitwiseNot => "~",
+        }
+    }
+
+
+    ///
+    /// Get the precedence of a binary operator, which follows Rust's, with
+    /// the implication binding loosest.
+    ///
+    fn precedence(&self) -> u8 {
+        match self {
+            Operator::If => 1,
+            Operator::Or => 2,
+            Operator::And => 3,
+            Operator::BitwiseOr => 4,
+            Operator::BitwiseXor => 5,
+            Operator::BitwiseAnd => 6,
+            Operator::LeftShift | Operator::RightShift => 7,
+            Operator::Add | Operator::Subtract => 8,
+            Operator::Multiply | Operator::Divide | Operator::Modulus => 9,
+            _ => 10,
+        }
+    }
+}
+
+
+///
+/// A mirror of the AST which the reference evaluator works on.
+///
+#[derive(Debug)]
+enum Mirror {
+    Integer(i64),
+    Float(f64),
+    Boolean(bool),
+    Unary(Operator, Box<Mirror>),
+    Binary(Operator, Box<Mirror>, Box<Mirror>)
+}
+
+
+///
+/// The result of evaluating an expression, where every error is
+/// considered equal.
+///
+#[derive(Debug, Clone, Copy)]
+enum Outcome {
+    Integer(i64),
+    Float(f64),
+    Boolean(bool),
+    Error
+}
+
+
+impl PartialEq for Outcome {
+    fn eq(&self, other: &Self) -> bool {
+        match (self, other) {
+            (Outcome::Integer(a), Outcome::Integer(b)) => a == b,
+            (Outcome::Float(a), Outcome::Float(b)) => a == b || (a.is_nan() && b.is_nan()),
+            (Outcome::Boolean(a), Outcome::Boolean(b)) => a == b,
+            (Outcome::Error, Outcome::Error) => true,
+            _ => false,
+        }
+    }
+}
+
+
+impl fmt::Display for Outcome {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Outcome::Integer(n) => write!(f, "{} (integer)", n),
+            Outcome::Float(x) => write!(f, "{} (float)", x),
+            Outcome::Boolean(b) => write!(f, "{} (boolean)", b),
+            Outcome::Error => write!(f, "an error"),
+        }
+    }
+}
+
+
+///
+/// An expression for which pxpr and the reference evaluator disagree.
+///
+#[derive(Debug)]
+pub struct Divergence {
+    pub expression: String,
+    pub expected: String,
+    pub actual: String
+}
+
+
+///
+/// The type of value a generated expression is meant to produce.
+///
+#[derive(Debug, Clone, Copy)]
+enum Kind {
+    Integer, Number, Boolean
+}
+
+
+const MAX_DEPTH: u32 = 5;
+
+
+fn generate(rng: &mut Rng, kind: Kind, depth: u32) -> Mirror {
+    // Occasionally generate an operand of the wrong type, so that type
+    // errors and coercions are checked too.
+    let kind = match rng.one_in(12) {
+        true => [Kind::Integer, Kind::Number, Kind::Boolean][rng.range(0, 2) as usize],
+        false => kind,
+    };
+
+    let is_leaf = depth >= MAX_DEPTH || rng.one_in(3);
+
+    match (kind, is_leaf) {
+        (Kind::Integer, true) => Mirror::Integer(rng.range(0, 100)),
+        (Kind::Number, true) if rng.one_in(2) => Mirror::Integer(rng.range(0, 100)),
+        (Kind::Number, true) => {
+            // Render the literal first, so that the mirror holds exactly the
+            // value pxpr will scan.
+            let literal = format!("{:.2}", rng.next_f64() * 100.0);
+            Mirror::Float(literal.parse().unwrap())
+        },
+        (Kind::Boolean, true) => Mirror::Boolean(rng.one_in(2)),
+
+        (Kind::Integer, false) => {
+            let operator = [
+                Operator::BitwiseAnd, Operator::BitwiseOr, Operator::BitwiseXor,
+                Operator::LeftShift, Operator::RightShift, Operator::Negate, Operator::BitwiseNot,
+                Operator::Factorial
+            ][rng.range(0, 7) as usize];
+
+            match operator {
+                Operator::Factorial if rng.one_in(2)
+                    => Mirror::Unary(operator, Box::new(Mirror::Integer(rng.range(0, 25)))),
+                Operator::Negate | Operator::BitwiseNot | Operator::Factorial
+                    => Mirror::Unary(operator, Box::new(generate(rng, Kind::Integer, depth + 1))),
+                Operator::LeftShift | Operator::RightShift => Mirror::Binary(
+                    operator,
+                    Box::new(generate(rng, Kind::Integer, depth + 1)),
+                    Box::new(Mirror::Integer(rng.range(0, 70)))
+                ),
+                _ => Mirror::Binary(
+                    operator,
+                    Box::new(generate(rng, Kind::Integer, depth + 1)),
+                    Box::new(generate(rng, Kind::Integer, depth + 1))
+                ),
+            }
+        },
+
+        (Kind::Number, false) => {
+            let operator = [
+                Operator::Add, Operator::Subtract, Operator::Multiply,
+                Operator::Divide, Operator::Modulus, Operator::Negate
+            ][rng.range(0, 5) as usize];
+
+            match operator {
+                Operator::Negate
+                    => Mirror::Unary(operator, Box::new(generate(rng, Kind::Number, depth + 1))),
+                _ => Mirror::Binary(
+                    operator,
+                    Box::new(generate(rng, Kind::Number, depth + 1)),
+                    Box::new(generate(rng, Kind::Number, depth + 1))
+                ),
+            }
+        },
+
+        (Kind::Boolean, false) => {
+            let operator = [
+                Operator::And, Operator::Or, Operator::If, Operator::Not
+            ][rng.range(0, 3) as usize];
+
+            match operator {
+                Operator::Not
+                    => Mirror::Unary(operator, Box::new(generate(rng, Kind::Boolean, depth + 1))),
+                _ => Mirror::Binary(
+                    operator,
+                    Box::new(generate(rng, Kind::Boolean, depth + 1)),
+                    Box::new(generate(rng, Kind::Boolean, depth + 1))
+                ),
+            }
+        },
+    }
+}
+
+
+///
+/// Render a mirrored AST as source, inserting parentheses only where the
+/// grammar requires them. Binary operators are left associative, so a right
+/// operand of equal precedence is parenthesized.
+///
+fn render(node: &Mirror, parent_precedence: u8, is_right_operand: bool) -> String {
+    match node {
+        Mirror::Integer(n) => n.to_string(),
+        Mirror::Float(x) => format!("{:.2}", x),
+        Mirror::Boolean(b) => b.to_string(),
+
+        Mirror::Unary(Operator::Factorial, operand) => {
+            match operand.as_ref() {
+                Mirror::Integer(_) | Mirror::Float(_) => format!("{}!", render(operand, 0, false)),
+                _ => format!("({})!", render(operand, 0, false)),
+            }
+        },
+
+        Mirror::Unary(operator, operand) => {
+            match operand.as_ref() {
+                Mirror::Binary(..) => format!("{}({})", operator.symbol(), render(operand, 0, false)),
+                _ => format!("{}{}", operator.symbol(), render(operand, 0, false)),
+            }
+        },
+
+        Mirror::Binary(operator, left, right) => {
+            let precedence = operator.precedence();
+            let source = format!("{} {} {}",
+                render(left, precedence, false),
+                operator.symbol(),
+                render(right, precedence, true)
+            );
+
+            match precedence < parent_precedence || (precedence == parent_precedence && is_right_operand) {
+                true => format!("({})", source),
+                false => source,
+            }
+        },
+    }
+}
+
+
+///
+/// Evaluate a mirrored AST with Rust's own arithmetic, following the
+/// documented semantics of pxpr: arithmetic on integers produces integers
+/// unless it overflows or divides inexactly, other arithmetic produces floats, bitwise
+/// operators require integers, and logical operators require booleans.
+///
+fn evaluate(node: &Mirror) -> Outcome {
+    match node {
+        Mirror::Integer(n) => Outcome::Integer(*n),
+        Mirror::Float(x) => Outcome::Float(*x),
+        Mirror::Boolean(b) => Outcome::Boolean(*b),
+
+        Mirror::Unary(operator, operand) => {
+            match (operator, evaluate(operand)) {
+                (Operator::Negate, Outcome::Integer(n)) => n.checked_neg().map_or(Outcome::Error, Outcome::Integer),
+                (Operator::Negate, Outcome::Float(x)) => Outcome::Float(-x),
+                (Operator::Not, Outcome::Boolean(b)) => Outcome::Boolean(!b),
+                (Operator::BitwiseNot, Outcome::Integer(n)) => Outcome::Integer(!n),
+                (Operator::Factorial, operand) => evaluate_factorial(operand),
+                _ => Outcome::Error,
+            }
+        },
+
+        Mirror::Binary(operator, left, right) => {
+            let (left, right) = (evaluate(left), evaluate(right));
+            match (operator, left, right) {
+                (_, Outcome::Error, _) | (_, _, Outcome::Error) => Outcome::Error,
+
+                (Operator::And, Outcome::Boolean(a), Outcome::Boolean(b)) => Outcome::Boolean(a && b),
+                (Operator::Or, Outcome::Boolean(a), Outcome::Boolean(b)) => Outcome::Boolean(a || b),
+                (Operator::If, Outcome::Boolean(a), Outcome::Boolean(b)) => Outcome::Boolean(!a || b),
+
+                (Operator::BitwiseAnd, Outcome::Integer(a), Outcome::Integer(b)) => Outcome::Integer(a & b),
+                (Operator::BitwiseOr, Outcome::Integer(a), Outcome::Integer(b)) => Outcome::Integer(a | b),
+                (Operator::BitwiseXor, Outcome::Integer(a), Outcome::Integer(b)) => Outcome::Integer(a ^ b),
+                (Operator::LeftShift, Outcome::Integer(a), Outcome::Integer(b)) => match b {
+                    ..0 => Outcome::Error,
+                    0..64 => Outcome::Integer(((a as u64) << b) as i64),
+                    _ => Outcome::Integer(0),
+                },
+                (Operator::RightShift, Outcome::Integer(a), Outcome::Integer(b)) => match b {
+                    ..0 => Outcome::Error,
+                    _ => Outcome::Integer(a >> b.min(63)),
+                },
+
+                (_, Outcome::Integer(a), Outcome::Integer(b)) if operator.is_arithmetic() 
+                    => evaluate_integer_arithmetic(*operator, a, b),
+
+                (_, left, right) => match (as_number(left), as_number(right)) {
+                    (Some(a), Some(b)) => evaluate_arithmetic(*operator, a, b),
+                    _ => Outcome::Error,
+                },
+            }
+        },
+    }
+}
+
+
+///
+/// Compute the factorial of an integer, or of a float without a
+/// fractional part, failing on overflow.
+///
+fn evaluate_factorial(operand: Outcome) -> Outcome {
+    let n = match operand {
+        Outcome::Integer(n) => n,
+        Outcome::Float(x) if x.fract() == 0.0 && x.abs() < i64::MAX as f64 => x as i64,
+        _ => return Outcome::Error,
+    };
+
+    let mut product: i64 = 1;
+    for factor in 1..=n {
+        match product.checked_mul(factor) {
+            Some(next) => product = next,
+            None => return Outcome::Error,
+        }
+    }
+
+    match n < 0 {
+        true => Outcome::Error,
+        false => Outcome::Integer(product),
+    }
+}
+
+
+fn as_number(outcome: Outcome) -> Option<f64> {
+    match outcome {
+        Outcome::Integer(n) => Some(n as f64),
+        Outcome::Float(x) => Some(x),
+        _ => None,
+    }
+}
+
+
+fn evaluate_arithmetic(operator: Operator, a: f64, b: f64) -> Outcome {
+    match operator {
+        Operator::Add => Outcome::Float(a + b),
+        Operator::Subtract => Outcome::Float(a - b),
+        Operator::Multiply => Outcome::Float(a * b),
+        Operator::Divide | Operator::Modulus if b == 0.0 => Outcome::Error,
+        Operator::Divide => Outcome::Float(a / b),
+        Operator::Modulus => Outcome::Float(a % b),
+        _ => Outcome::Error,
+    }
+}
+
+
+///
+/// Compute an arithmetic operation on two integers, falling back to floats
+/// when dividing inexactly.
+///
+fn evaluate_integer_arithmetic(operator: Operator, a: i64, b: i64) -> Outcome {
+    let result = match operator {
+        Operator::Add => a.checked_add(b),
+        Operator::Subtract => a.checked_sub(b),
+        Operator::Multiply => a.checked_mul(b),
+        Operator::Divide | Operator::Modulus if b == 0 => return Outcome::Error,
+        Operator::Divide if a.wrapping_rem(b) != 0 => return evaluate_arithmetic(operator, a as f64, b as f64),
+        Operator::Divide => a.checked_div(b),
+        Operator::Modulus => a.checked_rem(b),
+        _ => None,
+    };
+
+    match result {
+        Some(n) => Outcome::Integer(n),
+        None => Outcome::Error,
+    }
+}
+
+
+fn outcome_of(result: Result<Value, crate::Error>) -> Outcome {
+    match result {
+        Ok(Value::Integer(n)) => Outcome::Integer(n),
+        Ok(Value::Float(x)) => Outcome::Float(x),
+        Ok(Value::Boolean(b)) => Outcome::Boolean(b),
+        _ => Outcome::Error,
+    }
+}
+
+
+///
+/// Generate random arithmetic, bitwise and boolean expressions and check
+/// that pxpr computes the same result as the reference evaluator for each,
+/// that each parses back to the same tree after being printed, and that
+/// the fast path for tiny expressions and the bytecode backend agree with
+/// walking the tree.
+///
+/// # Returns
+/// Every expression for which the results differ or the tree changes.
+///
+pub fn run_differential(cases: u32, rng: &mut Rng) -> Vec<Divergence> {
+    let mut divergences = vec![];
+
+    for _ in 0..cases {
+        let kind = [Kind::Integer, Kind::Number, Kind::Boolean][rng.range(0, 2) as usize];
+        let mirror = generate(rng, kind, 0);
+        let source = render(&mirror, 0, false);
+
+        let expected = evaluate(&mirror);
+        let actual = outcome_of(compute_expression(&source, &mut Context::new()));
+
+        if expected != actual {
+            divergences.push(Divergence {
+                expression: source.clone(),
+                expected: expected.to_string(),
+                actual: actual.to_string(),
+            });
+        }
+
+        if let Some(divergence) = check_round_trip(&source) {
+            divergences.push(divergence);
+        }
+
+        if let Some(divergence) = check_streaming(&source) {
+            divergences.push(divergence);
+        }
+
+        if let Some(divergence) = check_vm(&source) {
+            divergences.push(divergence);
+        }
+
+        if let Some(divergence) = check_optimizer(&source) {
+            divergences.push(divergence);
+        }
+
+        // Most generated expressions are too long for the fast path, so a
+        // shallow one is generated for it too.
+        let tiny = render(&generate(rng, Kind::Number, MAX_DEPTH - 2), 0, false);
+        divergences.extend([&source, &tiny].into_iter().filter_map(|source| check_fast_path(source)));
+    }
+
+    divergences
+}
+
+
+///
+/// How many levels deep the expressions generated by `check_deep_nesting` are.
+///
+const NESTING_DEPTH: usize = 100_000;
+
+
+///
+/// Generate an expression nested `NESTING_DEPTH` levels deep, by wrapping
+/// `1` in random layers such as parentheses, a negation, an operand of an
+/// addition, an argument of a call or the value of a conditional chain.
+///
+/// # Returns
+/// The source of the expression and the value it computes.
+///
+fn generate_nested(rng: &mut Rng) -> (String, i64) {
+    let mut prefixes = vec![];
+    let mut suffixes = vec![];
+    let mut value: i64 = 1;
+
+    for _ in 0..NESTING_DEPTH {
+        let (prefix, suffix) = match rng.range(0, 6) {
+            0 => ("(", ")"),
+            1 => {
+                value = -value;
+                ("-", "")
+            },
+            2 => {
+                value += 1;
+                ("(1 + ", ")")
+            },
+            3 => {
+                value -= 1;
+                ("(", " - 1)")
+            },
+            4 => ("max(", ", -1000000)"),
+            5 => ("(", " when true; 0 otherwise)"),
+            _ => ("~~", ""),
+        };
+        prefixes.push(prefix);
+        suffixes.push(suffix);
+    }
+
+    let source = prefixes.iter().rev().copied().chain(["1"]).chain(suffixes).collect();
+    (source, value)
+}
+
+
+///
+/// Check that expressions nested far deeper than recursion would allow are
+/// parsed, computed with both backends, optimized, printed and copied
+/// without overflowing the stack, and compute the values they were built to.
+///
+pub fn check_deep_nesting(cases: u32, rng: &mut Rng) -> Vec<Divergence> {
+    let mut divergences = vec![];
+
+    for _ in 0..cases {
+        let (source, value) = generate_nested(rng);
+        let expression = format!("{}...{} ({} levels deep)", &source[..40], &source[source.len() - 40..], NESTING_DEPTH);
+        let mut diverge = |expected: String, actual: String| divergences.push(Divergence { expression: expression.clone(), expected, actual });
+
+        for backend in [Backend::Tree, Backend::Vm] {
+            let mut context = Context::new();
+            context.backend = backend;
+            match compute_expression(&source, &mut context) {
+                Ok(Value::Integer(n)) if n == value => {},
+                result => diverge(format!("the value {}", value), format!("{:?} from the {:?} backend", result, backend)),
+            }
+        }
+
+        let tree = match parse_expression(&source) {
+            Ok(tree) => tree,
+            Err(error) => {
+                diverge(String::from("a tree"), format!("the error '{}'", error));
+                continue;
+            },
+        };
+
+        match execute(&optimize(&tree, &Context::new()), &mut Context::new()) {
+            Ok(Value::Integer(n)) if n == value => {},
+            result => diverge(format!("the value {}", value), format!("{:?} from the optimized tree", result)),
+        }
+
+        let copy = tree.clone();
+        match parse_expression(&tree.to_source()) {
+            Ok(reparsed) if reparsed == tree && copy == tree => {},
+            _ => diverge(String::from("the same tree when printed, parsed and copied"), String::from("a different tree")),
+        }
+    }
+
+    divergences
+}
+
+
+///
+/// Check that the fast path for tiny expressions, when it computes an
+/// expression at all, computes the same value as the general path.
+///
+fn check_fast_path(source: &str) -> Option<Divergence> {
+    let mut context = Context::new();
+    let fast = outcome_of(Ok(fast::compute(source, &context)?));
+    let general = outcome_of(parse_expression(source).and_then(|tree| execute(&tree, &mut context)));
+
+    match fast == general {
+        true => None,
+        false => Some(Divergence {
+            expression: source.to_string(),
+            expected: format!("{}, as the general path computes", general),
+            actual: format!("{} from the fast path", fast),
+        }),
+    }
+}
+
+
+///
+/// Check that running the program compiled from the tree of a source gives
+/// the same value or error, at the same column, and the same warnings as
+/// walking the tree.
+///
+fn check_vm(source: &str) -> Option<Divergence> {
+    let tree = parse_expression(source).ok()?;
+    compare_backends(source, &tree, &Context::new())
+}
+
+
+///
+/// Compute a tree by walking it and by running the program compiled from
+/// it, each in a copy of `context`, and check that both give the same value
+/// or error, at the same column, the same warnings and the same dice rolls.
+///
+fn compare_backends(expression: &str, tree: &AstNode, context: &Context) -> Option<Divergence> {
+    let describe = |result: Result<Value, crate::Error>, context: &mut Context| {
+        let warnings: Vec<String> = context.take_warnings().iter().map(|warning| warning.message().to_string()).collect();
+        let rolls: Vec<Vec<i64>> = context.take_rolls().into_iter().map(|roll| roll.results).collect();
+        match result {
+            Ok(value) => format!("the value {:?} with warnings {:?} and rolls {:?}", value, warnings, rolls),
+            Err(error) => format!("the error '{}' ({}) with warnings {:?} and rolls {:?}", error, error.kind().name(), warnings, rolls),
+        }
+    };
+
+    let mut walked = context.fork();
+    let expected = describe(execute(tree, &mut walked), &mut walked);
+    // Tracing the instructions run must not change what they compute.
+    let mut run = context.fork();
+    run.vm_trace = Some(vec![]);
+    let actual = describe(vm::compile(tree).run(&mut run), &mut run);
+
+    match expected == actual {
+        true => None,
+        false => Some(Divergence {
+            expression: expression.to_string(),
+            expected: format!("{}, as walking the tree computes", expected),
+            actual: format!("{} from the bytecode backend", actual),
+        }),
+    }
+}
+
+
+///
+/// Every binary operation, from which `generate_tree` and the `Arbitrary`
+/// trees of the `testing` module pick. An operation added to the parser
+/// must be added here, so that the self-test checks that both backends
+/// compute it alike and that it round trips through its source.
+///
+pub(crate) const BINARY_OPERATIONS: [BinaryOperationType; 21] = [
+    BinaryOperationType::Add, BinaryOperationType::Subtract, BinaryOperationType::Multiply,
+    BinaryOperationType::Divide, BinaryOperationType::Modulus,
+    BinaryOperationType::And, BinaryOperationType::Or, BinaryOperationType::If,
+    BinaryOperationType::Equal, BinaryOperationType::NotEqual,
+    BinaryOperationType::Less, BinaryOperationType::LessEqual, BinaryOperationType::Greater, BinaryOperationType::GreaterEqual,
+    BinaryOperationType::BitwiseAnd, BinaryOperationType::BitwiseOr, BinaryOperationType::BitwiseXor,
+    BinaryOperationType::BitwiseLeftShift, BinaryOperationType::BitwiseRightShift,
+    BinaryOperationType::Minimum, BinaryOperationType::Maximum,
+];
+
+
+///
+/// Every unary operation, from which `generate_tree` and the `Arbitrary`
+/// trees of the `testing` module pick.
+///
+pub(crate) const UNARY_OPERATIONS: [UnaryOperationType; 4] = [
+    UnaryOperationType::ArithmeticNegate, UnaryOperationType::LogicalNot,
+    UnaryOperationType::BitwiseNot, UnaryOperationType::Factorial,
+];
+
+
+///
+/// Integers at the edges of arithmetic, which the leaves of generated trees
+/// favor, since the backends are most likely to differ on overflow.
+///
+const EDGE_INTEGERS: [i64; 8] = [0, 1, -1, 2, 63, 64, i64::MAX, i64::MIN];
+
+
+///
+/// Floats at the edges of arithmetic, which the leaves of generated trees
+/// favor.
+///
+const EDGE_FLOATS: [f64; 6] = [0.0, -0.0, 0.5, f64::INFINITY, f64::NAN, 1e300];
+
+
+///
+/// Names the leaves of generated trees refer to: a variable, which
+/// `check_random_trees` defines, a constant and a name bound to nothing.
+///
+const NAMES: [&str; 3] = ["x", "pi", "nothing"];
+
+
+///
+/// Generate a random tree directly, rather than from source, mixing every
+/// kind of node, every operation and every built-in function with operands
+/// of any type, so that the trees reach the errors and corners of the
+/// backends which the expressions of `generate` avoid. Each node is given
+/// a span of its own, so that an error reported at the wrong node is seen.
+///
+fn generate_tree(rng: &mut Rng, depth: u32) -> AstNode {
+    let span = Span::new(depth * 100 + rng.range(0, 99) as u32, depth * 100 + 100);
+    let pick = |rng: &mut Rng, count: usize| rng.range(0, count as i64 - 1) as usize;
+
+    let kind = match depth >= MAX_DEPTH || rng.one_in(3) {
+        true => match rng.range(0, 7) {
+            0 => AstNodeKind::Integer(EDGE_INTEGERS[pick(rng, EDGE_INTEGERS.len())]),
+            1 => AstNodeKind::Integer(rng.range(-100, 100)),
+            2 => AstNodeKind::Float(EDGE_FLOATS[pick(rng, EDGE_FLOATS.len())]),
+            3 => AstNodeKind::Float(rng.range(-1000, 1000) as f64 / 8.0),
+            4 => AstNodeKind::Boolean(rng.one_in(2)),
+            5 => AstNodeKind::String(String::from(["", "a", "pxpr"][pick(rng, 3)])),
+            6 => AstNodeKind::Dice(rng.range(0, 3), rng.range(0, 6)),
+            _ => AstNodeKind::Identifier(NAMES[pick(rng, NAMES.len())].to_string()),
+        },
+        false => match rng.range(0, 5) {
+            0 | 1 => AstNodeKind::BinaryOperation(
+                BINARY_OPERATIONS[pick(rng, BINARY_OPERATIONS.len())],
+                Box::new(generate_tree(rng, depth + 1)),
+                Box::new(generate_tree(rng, depth + 1))
+            ),
+            2 => AstNodeKind::UnaryOperation(
+                UNARY_OPERATIONS[pick(rng, UNARY_OPERATIONS.len())],
+                Box::new(generate_tree(rng, depth + 1))
+            ),
+            3 | 4 => {
+                let names: Vec<&str> = builtins::function_names().chain(["nothing"]).collect();
+                let name = names[pick(rng, names.len())].to_string();
+                let arguments = (0..rng.range(0, 3)).map(|_| generate_tree(rng, depth + 1)).collect();
+                AstNodeKind::FunctionCall(name, arguments)
+            },
+            _ => {
+                // A chain holds the value and guard of each case, then the
+                // value chosen when no guard holds.
+                let cases = rng.range(1, 2) as usize;
+                AstNodeKind::Conditional((0..cases * 2 + 1).map(|_| generate_tree(rng, depth + 1)).collect())
+            },
+        },
+    };
+
+    AstNode::new(kind, span)
+}
+
+
+///
+/// Generate random trees with `generate_tree` and check that both backends
+/// compute each alike, in a context with a variable, a random bit width and
+/// a generator seeded the same for both, so that dice and `rand()` draw the
+/// same numbers. A tree both compute alike is then checked with
+/// `check_inferred_type`.
+///
+/// # Returns
+/// Every tree, printed as source, on which the backends differ or whose
+/// value is not of its inferred type.
+///
+pub fn check_random_trees(cases: u32, rng: &mut Rng) -> Vec<Divergence> {
+    (0..cases)
+        .filter_map(|_| {
+            let tree = generate_tree(rng, 0);
+            let mut context = Context::new();
+            context.define("x", Value::Integer(rng.range(-10, 10)));
+            context.bit_width = [None, Some(8), Some(32)][rng.range(0, 2) as usize];
+            context.strict = rng.one_in(4);
+            context.strict_types = rng.one_in(4);
+            context.rng = Rng::new(rng.next_u64());
+            compare_backends(&tree.to_source(), &tree, &context).or_else(|| {
+                // Overflows promoted to floats and the float arithmetic of
+                // version 0.x give integer operations float results.
+                if rng.one_in(4) {
+                    context.overflow.configure("promote").expect("'promote' is an overflow policy");
+                }
+                if rng.one_in(4) {
+                    context.compat = Compat::Version0;
+                }
+                check_inferred_type(&tree, &context)
+            })
+        })
+        .collect()
+}
+
+
+///
+/// Check that a tree which computes a value computes one of the type
+/// `typecheck_for` finds for it, unless it finds a type error.
+///
+fn check_inferred_type(tree: &AstNode, context: &Context) -> Option<Divergence> {
+    let inferred = typecheck_for(tree, context).ok()?;
+    let value = execute(tree, &mut context.fork()).ok()?;
+
+    match inferred.contains(Type::of(&value)) {
+        true => None,
+        false => Some(Divergence {
+            expression: tree.to_source(),
+            expected: format!("a value of the type {}, as typecheck_for finds", inferred),
+            actual: format!("the {} {:?}", Type::of(&value), value),
+        }),
+    }
+}
+
+
+///
+/// Check that the optimized tree of a source computes the same value or
+/// error, at the same column, and the same warnings as the tree. Generated
+/// expressions have no names, so the optimizer folds every subtree which
+/// computes without warnings.
+///
+fn check_optimizer(source: &str) -> Option<Divergence> {
+    let tree = parse_expression(source).ok()?;
+    let optimized = optimize(&tree, &Context::new());
+    let describe = |tree: &AstNode| {
+        let mut context = Context::new();
+        let result = execute(tree, &mut context);
+        let warnings: Vec<String> = context.take_warnings().iter().map(|warning| warning.message().to_string()).collect();
+        match result {
+            Ok(value) => format!("the value {} with warnings {:?}", value, warnings),
+            Err(error) => format!("the error '{}' with warnings {:?}", error, warnings),
+        }
+    };
+
+    let (expected, actual) = (describe(&tree), describe(&optimized));
+    match expected == actual {
+        true => None,
+        false => Some(Divergence {
+            expression: source.to_string(),
+            expected: format!("{}, as the tree computes", expected),
+            actual: format!("{} from the optimized tree {}", actual, optimized.to_source()),
+        }),
+    }
+}
+
+
+///
+/// Check that writing the tree parsed from a source as source again, and
+/// parsing that, gives back the same tree.
+///
+fn check_round_trip(source: &str) -> Option<Divergence> {
+    check_tree_round_trip(&*parse_expression(source).ok()?)
+}
+
+
+///
+/// Check that writing a tree as source, with the fewest parentheses and
+/// with every operation in parentheses, and parsing that, gives back an
+/// equal tree. This holds for every tree the parser could produce, such as
+/// those generated by the `Arbitrary` implementation of the `testing`
+/// module, so that tools can rewrite an expression and parse it again.
+///
+/// # Returns
+/// The source the tree was written as and the tree it parsed to, when
+/// they differ.
+///
+pub fn check_tree_round_trip(ast: &AstNode) -> Option<Divergence> {
+    [ast.to_source(), ast.to_parenthesized_source()].into_iter().find_map(|printed| check_printed(ast, printed))
+}
+
+
+///
+/// Check that a tree written as source parses back to the same tree.
+///
+fn check_printed(ast: &AstNode, printed: String) -> Option<Divergence> {
+    match parse_expression(&printed) {
+        Ok(reparsed) if *reparsed == *ast => None,
+        Ok(reparsed) => Some(Divergence {
+            expression: printed.clone(),
+            expected: format!("the same tree when printed as {}", printed),
+            actual: format!("a different tree, printed as {}", reparsed.to_source()),
+        }),
+        Err(error) => Some(Divergence {
+            expression: printed.clone(),
+            expected: format!("the same tree when printed as {}", printed),
+            actual: format!("the error '{}'", error.message()),
+        }),
+    }
+}
+
+
+///
+/// Check that parsing the tokens of a source as the lexer scans them gives
+/// the same tree as lexing the whole source first.
+///
+fn check_streaming(source: &str) -> Option<Divergence> {
+    let expected = format!("{:?}", parse_expression(source).map(|ast| ast.to_outline()));
+    let actual = format!("{:?}", Parser::from_tokens(Lexer::new(source)).parse().map(|ast| ast.to_outline()));
+
+    match expected == actual {
+        true => None,
+        false => Some(Divergence {
+            expression: source.to_string(),
+            expected: format!("{} when lexed first", expected),
+            actual: format!("{} when streamed", actual),
+        }),
+    }
+}
@@ -0,0 +1,166 @@
+use std::{fmt, str::FromStr};
+
+use unicode_segmentation::UnicodeSegmentation;
+use unicode_width::UnicodeWidthStr;
+
+
+///
+/// A range of columns in the source of an expression, from `start`
+/// (inclusive) to `end` (exclusive).
+///
+/// Columns count characters from the start of the source, across lines, as
+/// do those of errors and warnings until they are located in their source.
+/// Users are shown display columns instead, which count the width of each
+/// grapheme within its line; `locate` converts to them and
+/// `character_column` back.
+///
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Span {
+    pub start: u32,
+    pub end: u32
+}
+
+
+impl Span {
+    pub fn new(start: u32, end: u32) -> Self {
+        Span { start, end }
+    }
+
+
+    ///
+    /// Get the smallest span covering both `self` and `other`.
+    ///
+    pub fn to(&self, other: Span) -> Span {
+        Span::new(self.start.min(other.start), self.end.max(other.end))
+    }
+
+
+    ///
+    /// Check whether a column lies within the span.
+    ///
+    pub fn contains(&self, column: u32) -> bool {
+        self.start <= column && column < self.end
+    }
+
+
+    ///
+    /// Check whether another span lies entirely within this span.
+    ///
+    pub fn covers(&self, other: Span) -> bool {
+        self.start <= other.start && other.end <= self.end
+    }
+}
+
+
+///
+/// Get the line a column of a source is on, counted from 1, and the column
+/// within that line, for a source with several lines. Columns count from the
+/// start of the whole source, so a source with a single line has no need
+/// for lines and gets `None`.
+///
+pub fn line_column(source: &str, column: u32) -> Option<(u32, u32)> {
+    if !source.contains('\n') {
+        return None;
+    }
+
+    let mut line = 1;
+    let mut line_start = 0;
+    for (index, ch) in source.chars().take(column as usize).enumerate() {
+        if ch == '\n' {
+            line += 1;
+            line_start = index as u32 + 1;
+        }
+    }
+
+    Some((line, column - line_start))
+}
+
+
+///
+/// Get the column at which the character at `column` of a source is shown in
+/// a terminal. Each grapheme before it counts for its width, so that `日本`
+/// takes four columns, while an `e` followed by a combining accent takes one.
+///
+pub fn display_column(source: &str, column: u32) -> u32 {
+    let mut characters = 0;
+    let mut width = 0;
+
+    for grapheme in source.graphemes(true) {
+        if characters >= column as usize {
+            break;
+        }
+        characters += grapheme.chars().count();
+        width += grapheme.width() as u32;
+    }
+
+    // Columns past the end of the source, such as that of a missing ')',
+    // keep their distance from it.
+    width + (column as usize).saturating_sub(characters) as u32
+}
+
+
+///
+/// Get the column of the character shown at a display column of a source,
+/// the reverse of `display_column`. A display column within a wide grapheme
+/// is that of its first character.
+///
+pub fn character_column(source: &str, display_column: u32) -> u32 {
+    let mut characters = 0;
+    let mut width = 0;
+
+    for grapheme in source.graphemes(true) {
+        let grapheme_width = grapheme.width() as u32;
+        if width + grapheme_width > display_column {
+            return characters;
+        }
+        characters += grapheme.chars().count() as u32;
+        width += grapheme_width;
+    }
+
+    characters + display_column.saturating_sub(width)
+}
+
+
+///
+/// Get where the character at `column` of a source is shown: its line, for a
+/// source with several lines, and its display column within that line.
+///
+pub fn locate(source: &str, column: u32) -> (Option<u32>, u32) {
+    match line_column(source, column) {
+        Some((line, column)) => {
+            let text = source.split('\n').nth(line as usize - 1).unwrap_or_default();
+            (Some(line), display_column(text, column))
+        },
+        None => (None, display_column(source, column)),
+    }
+}
+
+
+impl fmt::Display for Span {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}..{}", self.start, self.end)
+    }
+}
+
+
+impl FromStr for Span {
+    type Err = String;
+
+    ///
+    /// Parse a span written as `start..end`, the same way it is displayed.
+    ///
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || format!("Invalid range: '{}' (expected start..end)", s);
+
+        let (start, end) = s.split_once("..").ok_or_else(invalid)?;
+        let start: u32 = start.trim().parse().map_err(|_| invalid())?;
+        let end: u32 = end.trim().parse().map_err(|_| invalid())?;
+
+        if start >= end {
+            return Err(invalid());
+        }
+
+        Ok(Span::new(start, end))
+    }
+}
@@ -0,0 +1,296 @@
+use std::{cell::Cell, collections::BTreeMap, fmt, time::{Duration, Instant}};
+
+
+///
+/// The allocations made, and the time spent, during one stage of computing
+/// an expression.
+///
+#[derive(Debug, Default, Clone, Copy)]
+pub struct AllocationStats {
+    pub allocations: u64,
+    pub bytes: u64,
+    pub time: Duration
+}
+
+
+///
+/// The allocations made by each stage of computing expressions, and the time
+/// each spent, since the statistics were last taken. Allocations are only
+/// counted when pxpr is built with the `profiling` feature, while the time is
+/// always measured.
+///
+#[derive(Debug, Default, Clone, Copy)]
+pub struct Stats {
+    pub lexer: AllocationStats,
+    pub parser: AllocationStats,
+    pub evaluator: AllocationStats
+}
+
+
+impl AllocationStats {
+    pub fn merge(&mut self, other: &AllocationStats) {
+        self.allocations += other.allocations;
+        self.bytes += other.bytes;
+        self.time += other.time;
+    }
+}
+
+
+impl Stats {
+    ///
+    /// Add the allocations and time of other statistics to these.
+    ///
+    pub fn merge(&mut self, other: &Stats) {
+        self.lexer.merge(&other.lexer);
+        self.parser.merge(&other.parser);
+        self.evaluator.merge(&other.evaluator);
+    }
+}
+
+
+///
+/// How many times each operator was applied and each function called while
+/// computing an expression, to show which parts of a formula dominate its cost.
+///
+#[derive(Debug, Default, Clone)]
+pub struct Profile {
+    /// Counts by operator, such as `+` or `x!`.
+    pub operators: BTreeMap<&'static str, u64>,
+
+    /// Counts by function name.
+    pub functions: BTreeMap<String, u64>
+}
+
+
+impl Profile {
+    pub fn count_operator(&mut self, operator: &'static str) {
+        *self.operators.entry(operator).or_default() += 1;
+    }
+
+
+    pub fn count_call(&mut self, function: &str) {
+        match self.functions.get_mut(function) {
+            Some(count) => *count += 1,
+            None => {
+                self.functions.insert(function.to_string(), 1);
+            }
+        }
+    }
+
+
+    ///
+    /// Add the counts of another profile to this one.
+    ///
+    pub fn merge(&mut self, other: &Profile) {
+        for (operator, count) in &other.operators {
+            *self.operators.entry(operator).or_default() += count;
+        }
+        for (function, count) in &other.functions {
+            *self.functions.entry(function.clone()).or_default() += count;
+        }
+    }
+}
+
+
+///
+/// What was computed over a REPL session, which `--summary` shows on exit.
+///
+#[derive(Debug, Default, Clone)]
+pub struct SessionSummary {
+    pub expressions: u64,
+    pub errors: u64,
+
+    /// The operators applied and functions called by every expression.
+    pub profile: Profile,
+
+    /// The time spent computing, leaving out the time waiting for input.
+    pub time: Duration
+}
+
+
+impl SessionSummary {
+    ///
+    /// Count an expression which was computed, along with what it applied.
+    ///
+    pub fn record(&mut self, failed: bool, time: Duration, profile: Option<&Profile>) {
+        self.expressions += 1;
+        self.errors += failed as u64;
+        self.time += time;
+        if let Some(profile) = profile {
+            self.profile.merge(profile);
+        }
+    }
+
+
+    ///
+    /// Get the operators and functions applied most often, with functions
+    /// written as `name()`, from the most to the least used.
+    ///
+    pub fn most_used(&self, limit: usize) -> Vec<(String, u64)> {
+        let operators = self.profile.operators.iter().map(|(operator, count)| (operator.to_string(), *count));
+        let functions = self.profile.functions.iter().map(|(function, count)| (format!("{}()", function), *count));
+
+        let mut used: Vec<(String, u64)> = operators.chain(functions).collect();
+        used.sort_by_key(|(_, count)| std::cmp::Reverse(*count));
+        used.truncate(limit);
+        used
+    }
+}
+
+
+impl fmt::Display for SessionSummary {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "Computed {} expression(s) with {} error(s) in {:.3?}", self.expressions, self.errors, self.time)?;
+
+        let used: Vec<String> = self.most_used(5)
+            .into_iter()
+            .map(|(name, count)| format!("{} ({})", name, count))
+            .collect();
+
+        match used.is_empty() {
+            true => Ok(()),
+            false => writeln!(f, "Most used: {}", used.join(", ")),
+        }
+    }
+}
+
+
+#[derive(Debug, Clone, Copy)]
+pub enum Stage {
+    Lexer, Parser, Evaluator
+}
+
+
+thread_local! {
+    static STATS: Cell<Stats> = const { Cell::new(Stats {
+        lexer: AllocationStats { allocations: 0, bytes: 0, time: Duration::ZERO },
+        parser: AllocationStats { allocations: 0, bytes: 0, time: Duration::ZERO },
+        evaluator: AllocationStats { allocations: 0, bytes: 0, time: Duration::ZERO },
+    }) };
+}
+
+
+///
+/// Check whether allocations are being counted.
+///
+pub fn enabled() -> bool {
+    cfg!(feature = "profiling")
+}
+
+
+///
+/// Run `f`, counting the allocations it makes and the time it takes towards
+/// `stage`.
+///
+pub fn measure<T>(stage: Stage, f: impl FnOnce() -> T) -> T {
+    let before = counting::current();
+    let start = Instant::now();
+    let result = f();
+    let time = start.elapsed();
+    let after = counting::current();
+
+    STATS.with(|stats| {
+        let mut current = stats.get();
+        let counters = match stage {
+            Stage::Lexer => &mut current.lexer,
+            Stage::Parser => &mut current.parser,
+            Stage::Evaluator => &mut current.evaluator,
+        };
+
+        counters.allocations += after.allocations - before.allocations;
+        counters.bytes += after.bytes - before.bytes;
+        counters.time += time;
+        stats.set(current);
+    });
+
+    result
+}
+
+
+///
+/// Get the statistics gathered on this thread, and reset them.
+///
+pub fn take() -> Stats {
+    STATS.with(|stats| stats.take())
+}
+
+
+///
+/// Add statistics gathered on another thread, such as one computing part of
+/// a batch, to those of this thread.
+///
+pub fn add(other: &Stats) {
+    STATS.with(|stats| {
+        let mut current = stats.get();
+        current.merge(other);
+        stats.set(current);
+    });
+}
+
+
+#[cfg(feature = "profiling")]
+mod counting {
+    use std::{alloc::{GlobalAlloc, Layout, System}, cell::Cell, time::Duration};
+
+    use super::AllocationStats;
+
+
+    thread_local! {
+        static ALLOCATIONS: Cell<u64> = const { Cell::new(0) };
+        static BYTES: Cell<u64> = const { Cell::new(0) };
+    }
+
+
+    ///
+    /// An allocator which counts the allocations made by each thread
+    /// before deferring to the system allocator.
+    ///
+    struct CountingAllocator;
+
+
+    unsafe impl GlobalAlloc for CountingAllocator {
+        unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+            record(layout.size());
+            unsafe { System.alloc(layout) }
+        }
+
+        unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+            unsafe { System.dealloc(ptr, layout) }
+        }
+
+        unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+            record(new_size);
+            unsafe { System.realloc(ptr, layout, new_size) }
+        }
+    }
+
+
+    #[global_allocator]
+    static ALLOCATOR: CountingAllocator = CountingAllocator;
+
+
+    fn record(size: usize) {
+        // The counters may already be destroyed while a thread exits.
+        let _ = ALLOCATIONS.try_with(|count| count.set(count.get() + 1));
+        let _ = BYTES.try_with(|bytes| bytes.set(bytes.get() + size as u64));
+    }
+
+
+    pub fn current() -> AllocationStats {
+        AllocationStats {
+            allocations: ALLOCATIONS.try_with(Cell::get).unwrap_or(0),
+            bytes: BYTES.try_with(Cell::get).unwrap_or(0),
+            time: Duration::ZERO,
+        }
+    }
+}
+
+
+#[cfg(not(feature = "profiling"))]
+mod counting {
+    use super::AllocationStats;
+
+    pub fn current() -> AllocationStats {
+        AllocationStats::default()
+    }
+}
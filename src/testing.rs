@@ -0,0 +1,128 @@
+use arbitrary::{Arbitrary, Result, Unstructured};
+
+use crate::{
+    builtins, parser::{AstNode, AstNodeKind}, random::Rng,
+    selftest::{check_tree_round_trip, Divergence, BINARY_OPERATIONS, UNARY_OPERATIONS}, span::Span
+};
+
+
+///
+/// How many levels deep a generated tree may be, so that trees stay small
+/// enough to read when a property fails on one.
+///
+const MAX_DEPTH: u32 = 6;
+
+
+///
+/// The names a generated tree refers to, which are valid identifiers and
+/// no keyword, including names outside of ASCII and a constant.
+///
+const NAMES: [&str; 6] = ["x", "y", "rate", "Δt", "naïve", "pi"];
+
+
+///
+/// Builds a tree from unstructured bytes, numbering the `?` parameters in
+/// the order they are generated, which is the order they are written in.
+///
+struct Generator<'u, 'a> {
+    u: &'u mut Unstructured<'a>,
+    parameters: usize
+}
+
+
+impl Generator<'_, '_> {
+    fn node(&mut self, depth: u32) -> Result<AstNode> {
+        let is_leaf = depth >= MAX_DEPTH || self.u.ratio(1, 3)?;
+        let kind = match is_leaf {
+            true => self.leaf()?,
+            false => self.branch(depth)?,
+        };
+
+        // The spans of a generated tree are not those of any source, and
+        // trees are compared without them.
+        Ok(AstNode::new(kind, Span::new(0, 0)))
+    }
+
+
+    ///
+    /// Generate a leaf as the parser would read it: numbers are never
+    /// negative, since `-3` is read as the negation of `3`, and floats are
+    /// finite.
+    ///
+    fn leaf(&mut self) -> Result<AstNodeKind> {
+        Ok(match self.u.int_in_range(0..=7)? {
+            0 => AstNodeKind::Integer(self.u.int_in_range(0..=i64::MAX)?),
+            1 => AstNodeKind::Integer(self.u.int_in_range(0..=100)?),
+            2 => {
+                let x = f64::arbitrary(self.u)?.abs();
+                AstNodeKind::Float(match x.is_finite() {
+                    true => x,
+                    false => 0.5,
+                })
+            },
+            3 => AstNodeKind::Boolean(self.u.arbitrary()?),
+            4 => AstNodeKind::String(self.u.arbitrary()?),
+            5 => AstNodeKind::Dice(self.u.int_in_range(0..=i64::MAX)?, self.u.int_in_range(0..=i64::MAX)?),
+            6 => AstNodeKind::Identifier(self.u.choose(&NAMES)?.to_string()),
+            _ => {
+                self.parameters += 1;
+                AstNodeKind::Parameter(self.parameters - 1)
+            },
+        })
+    }
+
+
+    fn branch(&mut self, depth: u32) -> Result<AstNodeKind> {
+        Ok(match self.u.int_in_range(0..=3)? {
+            0 => {
+                let operation_type = *self.u.choose(&BINARY_OPERATIONS)?;
+                let left = self.node(depth + 1)?;
+                AstNodeKind::BinaryOperation(operation_type, Box::new(left), Box::new(self.node(depth + 1)?))
+            },
+            1 => AstNodeKind::UnaryOperation(*self.u.choose(&UNARY_OPERATIONS)?, Box::new(self.node(depth + 1)?)),
+            2 => {
+                let names: Vec<&str> = builtins::function_names().chain(["f"]).collect();
+                let name = self.u.choose(&names)?.to_string();
+                let arguments = (0..self.u.int_in_range(0..=3)?).map(|_| self.node(depth + 1)).collect::<Result<_>>()?;
+                AstNodeKind::FunctionCall(name, arguments)
+            },
+            _ => {
+                // A chain holds the value and guard of each case, then the
+                // value chosen when no guard holds.
+                let cases = self.u.int_in_range(1..=3)?;
+                AstNodeKind::Conditional((0..cases * 2 + 1).map(|_| self.node(depth + 1)).collect::<Result<_>>()?)
+            },
+        })
+    }
+}
+
+
+impl<'a> Arbitrary<'a> for AstNode {
+    ///
+    /// Generate a tree the parser could have read from some source, mixing
+    /// every kind of node and every operation, for property tests and fuzz
+    /// targets. Writing such a tree with `to_source` and parsing the result
+    /// gives back an equal tree, which `check_tree_round_trip` checks.
+    ///
+    fn arbitrary(u: &mut Unstructured<'a>) -> Result<Self> {
+        Generator { u, parameters: 0 }.node(0)
+    }
+}
+
+
+///
+/// Generate trees with `AstNode::arbitrary` from random bytes and check that
+/// each round trips through its source.
+///
+/// # Returns
+/// Every tree, printed as source, which does not parse back to itself.
+///
+pub fn check_round_trips(cases: u32, rng: &mut Rng) -> Vec<Divergence> {
+    (0..cases)
+        .filter_map(|_| {
+            let bytes: Vec<u8> = (0..512).map(|_| rng.next_u64() as u8).collect();
+            let tree = AstNode::arbitrary(&mut Unstructured::new(&bytes)).ok()?;
+            check_tree_round_trip(&tree)
+        })
+        .collect()
+}
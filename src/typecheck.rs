@@ -0,0 +1,528 @@
+use std::fmt;
+
+use crate::{
+    arena::{Arena, NodeId, NodeKind}, builtins, context::Context, expression::Value, parser::{AstNode, AstNodeKind, BinaryOperationType, UnaryOperationType},
+    span::Span, visit::{walk, AstVisitor}, Error
+};
+
+
+///
+/// The type of the values an expression may compute, as far as can be told
+/// without computing it. Integer arithmetic gives a `Number`, since a
+/// division which is not exact, an overflow promoted to a float or an
+/// earlier compatibility version can all make it a float.
+///
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Type {
+    Integer,
+    Float,
+
+    /// Either an integer or a float.
+    Number,
+
+    Boolean,
+    String,
+
+    /// A value of any type, such as that of a variable whose value is not known.
+    Any
+}
+
+
+impl Type {
+    ///
+    /// Get the type of a value.
+    ///
+    pub fn of(value: &Value) -> Type {
+        match value {
+            Value::Integer(_) => Type::Integer,
+            Value::Float(_) => Type::Float,
+            Value::Boolean(_) => Type::Boolean,
+            Value::String(_) => Type::String,
+        }
+    }
+
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Type::Integer => "integer",
+            Type::Float => "float",
+            Type::Number => "number",
+            Type::Boolean => "boolean",
+            Type::String => "string",
+            Type::Any => "any",
+        }
+    }
+
+
+    ///
+    /// Check whether every value of another type is also of this type, such
+    /// as an integer for `Number`.
+    ///
+    pub fn contains(self, other: Type) -> bool {
+        self == other || self == Type::Any || (self == Type::Number && matches!(other, Type::Integer | Type::Float))
+    }
+
+
+    ///
+    /// Check whether some value is of both types, so that an operand of this
+    /// type may be of the type an operation expects.
+    ///
+    fn overlaps(self, other: Type) -> bool {
+        self.contains(other) || other.contains(self)
+    }
+
+
+    ///
+    /// Get the smallest type holding the values of both types, such as for
+    /// the values of a conditional chain.
+    ///
+    fn join(self, other: Type) -> Type {
+        match (self, other) {
+            _ if self.contains(other) => self,
+            _ if other.contains(self) => other,
+            (Type::Integer | Type::Float | Type::Number, Type::Integer | Type::Float | Type::Number) => Type::Number,
+            _ => Type::Any,
+        }
+    }
+
+
+    ///
+    /// Get the type of the numbers among the values of this type, for the
+    /// result of an operation which only succeeds on numbers.
+    ///
+    fn numeric(self) -> Type {
+        match self {
+            Type::Integer | Type::Float => self,
+            _ => Type::Number,
+        }
+    }
+
+
+    ///
+    /// Describe a value of the type, such as `an integer`, for messages.
+    ///
+    fn described(self) -> &'static str {
+        match self {
+            Type::Integer => "an integer",
+            Type::Float => "a float",
+            Type::Number => "a number",
+            Type::Boolean => "a boolean",
+            Type::String => "a string",
+            Type::Any => "any value",
+        }
+    }
+}
+
+
+impl fmt::Display for Type {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.name())
+    }
+}
+
+
+///
+/// An operand or argument which can never be of the type its operation
+/// expects, such as the `true` of `true + 1`, with the span of its source.
+///
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TypeError {
+    pub span: Span,
+    pub message: String
+}
+
+
+impl fmt::Display for TypeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} at columns {}", self.message, self.span)
+    }
+}
+
+
+impl From<TypeError> for Error {
+    fn from(error: TypeError) -> Self {
+        Error::type_mismatch(error.span.start, error.message)
+    }
+}
+
+
+///
+/// The types a built-in function expects its arguments to be, as it reads
+/// them.
+///
+#[derive(Clone, Copy)]
+enum Parameter {
+    /// An integer, or a float without a fractional part.
+    Integral,
+
+    /// An integer, and never a float.
+    Integer,
+
+    Number,
+    Boolean,
+    String,
+
+    /// Any value, such as for the conversions.
+    Any
+}
+
+
+impl Parameter {
+    fn accepts(self, argument: Type) -> bool {
+        match self {
+            Parameter::Integral | Parameter::Number => argument.overlaps(Type::Number),
+            Parameter::Integer => argument.overlaps(Type::Integer),
+            Parameter::Boolean => argument.overlaps(Type::Boolean),
+            Parameter::String => argument.overlaps(Type::String),
+            Parameter::Any => true,
+        }
+    }
+
+
+    fn described(self) -> &'static str {
+        match self {
+            Parameter::Integral | Parameter::Integer => "an integer",
+            Parameter::Number => "a number",
+            Parameter::Boolean => "a boolean",
+            Parameter::String => "a string",
+            Parameter::Any => "a value",
+        }
+    }
+}
+
+
+///
+/// The type of the result of a built-in function.
+///
+#[derive(Clone, Copy)]
+enum Returns {
+    Type(Type),
+
+    /// The type of one of its arguments, such as for `max`, which returns the
+    /// largest unchanged.
+    Argument,
+
+    /// The type of its first argument, such as for `round`.
+    First
+}
+
+
+///
+/// The parameters and result of each built-in function. A function taking
+/// any number of arguments, such as `max`, expects each argument past the
+/// last parameter to be of the type of the last parameter.
+///
+const SIGNATURES: &[(&str, &[Parameter], Returns)] = &[
+    ("hex", &[Parameter::Integral], Returns::Type(Type::String)),
+    ("bin", &[Parameter::Integral], Returns::Type(Type::String)),
+    ("oct", &[Parameter::Integral], Returns::Type(Type::String)),
+    ("to_base", &[Parameter::Integral, Parameter::Integral], Returns::Type(Type::String)),
+    ("from_base", &[Parameter::String, Parameter::Integral], Returns::Type(Type::Integer)),
+    ("rotl", &[Parameter::Integral, Parameter::Integral], Returns::Type(Type::Integer)),
+    ("rotr", &[Parameter::Integral, Parameter::Integral], Returns::Type(Type::Integer)),
+    ("popcount", &[Parameter::Integral], Returns::Type(Type::Integer)),
+    ("clz", &[Parameter::Integral], Returns::Type(Type::Integer)),
+    ("ctz", &[Parameter::Integral], Returns::Type(Type::Integer)),
+    ("bit", &[Parameter::Integral, Parameter::Integral], Returns::Type(Type::Integer)),
+    ("ncr", &[Parameter::Integral, Parameter::Integral], Returns::Type(Type::Integer)),
+    ("npr", &[Parameter::Integral, Parameter::Integral], Returns::Type(Type::Integer)),
+    ("gcd", &[Parameter::Integer, Parameter::Integer], Returns::Type(Type::Integer)),
+    ("lcm", &[Parameter::Integer, Parameter::Integer], Returns::Type(Type::Integer)),
+    ("modpow", &[Parameter::Integer, Parameter::Integer, Parameter::Integer], Returns::Type(Type::Integer)),
+    ("is_prime", &[Parameter::Integer], Returns::Type(Type::Boolean)),
+    ("rand", &[], Returns::Type(Type::Float)),
+    ("rand_int", &[Parameter::Integral, Parameter::Integral], Returns::Type(Type::Integer)),
+    ("min", &[Parameter::Number], Returns::Argument),
+    ("max", &[Parameter::Number], Returns::Argument),
+    ("all", &[Parameter::Boolean], Returns::Type(Type::Boolean)),
+    ("any", &[Parameter::Boolean], Returns::Type(Type::Boolean)),
+    ("clamp", &[Parameter::Number, Parameter::Number, Parameter::Number], Returns::Argument),
+    ("sign", &[Parameter::Number], Returns::First),
+    ("round", &[Parameter::Number, Parameter::Integer], Returns::First),
+    ("trunc", &[Parameter::Number], Returns::First),
+    ("floor", &[Parameter::Number], Returns::First),
+    ("ceil", &[Parameter::Number], Returns::First),
+    ("int", &[Parameter::Any], Returns::Type(Type::Integer)),
+    ("float", &[Parameter::Any], Returns::Type(Type::Float)),
+    ("bool", &[Parameter::Any], Returns::Type(Type::Boolean)),
+    ("vars", &[], Returns::Type(Type::String)),
+    ("functions", &[], Returns::Type(Type::String)),
+];
+
+
+///
+/// Find the types of the values an expression computes without computing
+/// it, and every operand which can never be of the type its operation
+/// expects, such as the `true` of `true + 1` or the `3.5` of `~3.5`, so
+/// that stored expressions can be checked before they are needed.
+///
+/// An operand is only flagged when no value it could compute is accepted,
+/// so that an expression which computes is never flagged, but one which
+/// is not flagged may still fail. Names may hold values of any type. Every
+/// case of a conditional chain is checked, even those which are never
+/// chosen.
+///
+/// # Returns
+/// The type of the expression, or every type error ordered by column.
+///
+pub fn typecheck(tree: &AstNode) -> Result<Type, Vec<TypeError>> {
+    check(tree, None)
+}
+
+
+///
+/// Check the types of an expression like `typecheck`, for computing it with
+/// a context: a variable of the context has the type of its value, and a
+/// builtin constant it does not hide is a float. A function registered in
+/// the context, which hides a builtin function of the same name, may take
+/// and return values of any type. With the strict types of the context,
+/// arithmetic mixing an integer and a float is a type error.
+///
+pub fn typecheck_for(tree: &AstNode, context: &Context) -> Result<Type, Vec<TypeError>> {
+    check(tree, Some(context))
+}
+
+
+///
+/// Find the type of a node of an arena for computing it with a context,
+/// like `typecheck_for`, from the types the children of the node were found
+/// to have. Only the operation of the node itself is checked, so that the
+/// optimizer can type its nodes as it builds them, one at a time.
+///
+/// # Returns
+/// The type of the node, or the type errors of its operation.
+///
+pub(crate) fn typecheck_node(arena: &Arena, id: NodeId, types: &[Type], context: &Context) -> Result<Type, Vec<TypeError>> {
+    let mut checker = Checker { context: Some(context), types: vec![], errors: vec![] };
+    let spans: Vec<Span> = arena.children(id).into_iter().map(|child| arena[child].span).collect();
+    let result = match &arena[id].kind {
+        NodeKind::BinaryOperation(operation_type, _, _) => checker.binary(*operation_type, &spans, types),
+        NodeKind::UnaryOperation(operation_type, _) => checker.unary(*operation_type, spans[0], types[0]),
+        NodeKind::FunctionCall(name, _) => checker.call(name, &spans, types),
+        NodeKind::Conditional(_) => checker.conditional(&spans, types),
+        NodeKind::Integer(_) | NodeKind::Dice(..) => Type::Integer,
+        NodeKind::Float(_) => Type::Float,
+        NodeKind::Boolean(_) => Type::Boolean,
+        NodeKind::String(_) => Type::String,
+        NodeKind::Identifier(name) => checker.identifier(name),
+        NodeKind::Parameter(_) => Type::Any,
+    };
+
+    match checker.errors.is_empty() {
+        true => Ok(result),
+        false => Err(checker.errors),
+    }
+}
+
+
+fn check(tree: &AstNode, context: Option<&Context>) -> Result<Type, Vec<TypeError>> {
+    let mut checker = Checker { context, types: vec![], errors: vec![] };
+    walk(tree, &mut checker);
+
+    match checker.errors.is_empty() {
+        true => Ok(checker.types.pop().expect("the root is checked last")),
+        false => {
+            checker.errors.sort_by_key(|error| error.span.start);
+            Err(checker.errors)
+        },
+    }
+}
+
+
+///
+/// Finds the type of each node after those of its children, which are kept
+/// on a stack.
+///
+struct Checker<'a> {
+    context: Option<&'a Context>,
+    types: Vec<Type>,
+    errors: Vec<TypeError>
+}
+
+
+impl AstVisitor for Checker<'_> {
+    fn leave(&mut self, node: &AstNode) {
+        let spans: Vec<Span> = node.children().iter().map(|child| child.span).collect();
+        let types = self.types.split_off(self.types.len() - spans.len());
+        let result = match &node.kind {
+            AstNodeKind::BinaryOperation(operation_type, _, _) => self.binary(*operation_type, &spans, &types),
+            AstNodeKind::UnaryOperation(operation_type, _) => self.unary(*operation_type, spans[0], types[0]),
+            AstNodeKind::FunctionCall(name, _) => self.call(name, &spans, &types),
+            AstNodeKind::Conditional(_) => self.conditional(&spans, &types),
+            AstNodeKind::Integer(_) | AstNodeKind::Dice(..) => Type::Integer,
+            AstNodeKind::Float(_) => Type::Float,
+            AstNodeKind::Boolean(_) => Type::Boolean,
+            AstNodeKind::String(_) => Type::String,
+            AstNodeKind::Identifier(name) => self.identifier(name),
+            AstNodeKind::Parameter(_) => Type::Any,
+        };
+        self.types.push(result);
+    }
+}
+
+
+impl Checker<'_> {
+    ///
+    /// Flag an operand which can never be of the expected type.
+    ///
+    fn expect(&mut self, operand: Span, actual: Type, expected: Type, what: String) {
+        if !actual.overlaps(expected) {
+            self.errors.push(TypeError {
+                span: operand,
+                message: format!("{}: expected {}, found {}", what, expected.described(), actual.described()),
+            });
+        }
+    }
+
+
+    fn unary(&mut self, operation_type: UnaryOperationType, operand: Span, actual: Type) -> Type {
+        // The symbols are written with a placeholder operand, such as `~x`.
+        let what = format!("Invalid operand for '{}'", operation_type.symbol().trim_matches('x'));
+        match operation_type {
+            UnaryOperationType::ArithmeticNegate => {
+                // Negating the smallest integer may be promoted to a float,
+                // as an overflow in `+` and `-` may.
+                self.expect(operand, actual, Type::Number, what);
+                match actual {
+                    Type::Float => Type::Float,
+                    _ => Type::Number,
+                }
+            },
+            UnaryOperationType::LogicalNot => {
+                self.expect(operand, actual, Type::Boolean, what);
+                Type::Boolean
+            },
+            UnaryOperationType::BitwiseNot => {
+                self.expect(operand, actual, Type::Integer, what);
+                Type::Integer
+            },
+            UnaryOperationType::Factorial => {
+                // A float without a fractional part is accepted.
+                self.expect(operand, actual, Type::Number, what);
+                Type::Integer
+            },
+        }
+    }
+
+
+    fn binary(&mut self, operation_type: BinaryOperationType, operands: &[Span], types: &[Type]) -> Type {
+        let symbol = operation_type.symbol();
+        let (left, right) = (types[0], types[1]);
+        let expect_both = |checker: &mut Self, expected: Type| {
+            checker.expect(operands[0], left, expected, format!("Invalid left operand for '{}'", symbol));
+            checker.expect(operands[1], right, expected, format!("Invalid right operand for '{}'", symbol));
+        };
+
+        match operation_type {
+            BinaryOperationType::Add | BinaryOperationType::Subtract | BinaryOperationType::Multiply |
+            BinaryOperationType::Divide | BinaryOperationType::Modulus => {
+                expect_both(self, Type::Number);
+                let mixed = matches!((left, right), (Type::Integer, Type::Float) | (Type::Float, Type::Integer));
+                if mixed && self.context.is_some_and(|context| context.strict_types) {
+                    self.errors.push(TypeError {
+                        span: operands[0].to(operands[1]),
+                        message: format!("Cannot mix an integer and a float in '{}' with strict types (convert one with float() or int())", symbol),
+                    });
+                }
+                match left == Type::Float || right == Type::Float {
+                    true => Type::Float,
+                    false => Type::Number,
+                }
+            },
+            BinaryOperationType::And | BinaryOperationType::Or | BinaryOperationType::If => {
+                expect_both(self, Type::Boolean);
+                Type::Boolean
+            },
+            BinaryOperationType::Equal | BinaryOperationType::NotEqual => {
+                // Numbers compare with numbers, and other values only with
+                // values of the same type.
+                let family = |t: Type| match t {
+                    Type::Integer | Type::Float => Type::Number,
+                    t => t,
+                };
+                if !family(left).overlaps(family(right)) {
+                    self.errors.push(TypeError {
+                        span: operands[0].to(operands[1]),
+                        message: format!("Cannot compare {} with {} using '{}'", left.described(), right.described(), symbol),
+                    });
+                }
+                Type::Boolean
+            },
+            BinaryOperationType::Less | BinaryOperationType::LessEqual |
+            BinaryOperationType::Greater | BinaryOperationType::GreaterEqual => {
+                expect_both(self, Type::Number);
+                Type::Boolean
+            },
+            BinaryOperationType::BitwiseAnd | BinaryOperationType::BitwiseOr | BinaryOperationType::BitwiseXor |
+            BinaryOperationType::BitwiseRightShift => {
+                expect_both(self, Type::Integer);
+                Type::Integer
+            },
+            BinaryOperationType::BitwiseLeftShift => {
+                // An overflow promoted to a float makes a float.
+                expect_both(self, Type::Integer);
+                Type::Number
+            },
+            BinaryOperationType::Minimum | BinaryOperationType::Maximum => {
+                expect_both(self, Type::Number);
+                left.numeric().join(right.numeric())
+            },
+        }
+    }
+
+
+    fn call(&mut self, name: &str, arguments: &[Span], types: &[Type]) -> Type {
+        if self.context.is_some_and(|context| context.function(name).is_some()) {
+            return Type::Any;
+        }
+
+        let Some((_, parameters, returns)) = SIGNATURES.iter().find(|(function, _, _)| *function == name) else {
+            return Type::Any;
+        };
+
+        for (index, (argument, actual)) in arguments.iter().zip(types).enumerate() {
+            let Some(parameter) = parameters.get(index).or(parameters.last()) else {
+                break;
+            };
+            if !parameter.accepts(*actual) {
+                self.errors.push(TypeError {
+                    span: *argument,
+                    message: format!("Invalid argument for '{}': expected {}, found {}", name, parameter.described(), actual.described()),
+                });
+            }
+        }
+
+        match returns {
+            Returns::Type(result) => *result,
+            Returns::Argument => types.iter().map(|t| t.numeric()).reduce(Type::join).unwrap_or(Type::Number),
+            Returns::First => types.first().map_or(Type::Number, |t| t.numeric()),
+        }
+    }
+
+
+    fn conditional(&mut self, parts: &[Span], types: &[Type]) -> Type {
+        for (guard, actual) in parts.iter().zip(types).skip(1).step_by(2) {
+            if !actual.overlaps(Type::Boolean) {
+                self.errors.push(TypeError {
+                    span: *guard,
+                    message: format!("Expected a boolean guard after 'when', found {}", actual.described()),
+                });
+            }
+        }
+
+        types.iter().step_by(2).copied().reduce(Type::join).expect("a conditional chain has a value")
+    }
+
+
+    fn identifier(&self, name: &str) -> Type {
+        let Some(context) = self.context else {
+            return Type::Any;
+        };
+
+        match (context.variable(name), builtins::constant(name)) {
+            (Some(value), _) => Type::of(value),
+            (None, Some(value)) => Type::of(&value),
+            (None, None) => Type::Any,
+        }
+    }
+}
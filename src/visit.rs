@@ -0,0 +1,205 @@
+use crate::parser::{AstNode, AstNodeKind, BinaryOperationType, UnaryOperationType};
+
+
+///
+/// Reads a tree node by node, such as to collect the names it refers to,
+/// without matching on every kind of node. Each method does nothing by
+/// default, so a visitor only overrides those for the nodes it cares about,
+/// and `walk` takes it through the whole tree.
+///
+pub trait AstVisitor {
+    ///
+    /// Visit a node before its children. By default, this calls the method
+    /// for the kind of the node, such as `visit_identifier`, and goes on into
+    /// the children.
+    ///
+    /// # Returns
+    /// Whether to visit the children of the node, so that overriding this
+    /// can skip parts of the tree, such as the arguments of some function.
+    ///
+    fn enter(&mut self, node: &AstNode) -> bool {
+        match &node.kind {
+            AstNodeKind::BinaryOperation(operation_type, _, _) => self.visit_binary_operation(*operation_type, node),
+            AstNodeKind::UnaryOperation(operation_type, _) => self.visit_unary_operation(*operation_type, node),
+            AstNodeKind::FunctionCall(name, arguments) => self.visit_function_call(name, arguments, node),
+            AstNodeKind::Conditional(parts) => self.visit_conditional(parts, node),
+            AstNodeKind::Identifier(name) => self.visit_identifier(name, node),
+            AstNodeKind::Parameter(index) => self.visit_parameter(*index, node),
+            _ => self.visit_literal(node),
+        }
+        true
+    }
+
+
+    ///
+    /// Visit a node after its children, such as to keep track of how deep
+    /// the visit is.
+    ///
+    fn leave(&mut self, _node: &AstNode) {}
+
+    fn visit_binary_operation(&mut self, _operation_type: BinaryOperationType, _node: &AstNode) {}
+
+    fn visit_unary_operation(&mut self, _operation_type: UnaryOperationType, _node: &AstNode) {}
+
+    fn visit_function_call(&mut self, _name: &str, _arguments: &[AstNode], _node: &AstNode) {}
+
+    ///
+    /// Visit a conditional chain, whose parts are each value and the guard
+    /// choosing it, and last the value chosen when no guard holds.
+    ///
+    fn visit_conditional(&mut self, _parts: &[AstNode], _node: &AstNode) {}
+
+    fn visit_identifier(&mut self, _name: &str, _node: &AstNode) {}
+
+    fn visit_parameter(&mut self, _index: usize, _node: &AstNode) {}
+
+    ///
+    /// Visit an integer, float, boolean, string or dice roll.
+    ///
+    fn visit_literal(&mut self, _node: &AstNode) {}
+}
+
+
+///
+/// Rewrites a tree from its leaves up, such as to replace constants with
+/// their values. Each node is given to the method for its kind once its
+/// children have been rewritten, and is replaced by what the method returns.
+/// Each method returns the node as it is by default.
+///
+pub trait AstTransformer {
+    ///
+    /// Rewrite a node whose children have already been rewritten. By
+    /// default, this calls the method for the kind of the node.
+    ///
+    fn transform_node(&mut self, node: AstNode) -> AstNode {
+        match &node.kind {
+            AstNodeKind::BinaryOperation(..) => self.transform_binary_operation(node),
+            AstNodeKind::UnaryOperation(..) => self.transform_unary_operation(node),
+            AstNodeKind::FunctionCall(..) => self.transform_function_call(node),
+            AstNodeKind::Conditional(_) => self.transform_conditional(node),
+            AstNodeKind::Identifier(_) => self.transform_identifier(node),
+            AstNodeKind::Parameter(_) => self.transform_parameter(node),
+            _ => self.transform_literal(node),
+        }
+    }
+
+    fn transform_binary_operation(&mut self, node: AstNode) -> AstNode {
+        node
+    }
+
+    fn transform_unary_operation(&mut self, node: AstNode) -> AstNode {
+        node
+    }
+
+    fn transform_function_call(&mut self, node: AstNode) -> AstNode {
+        node
+    }
+
+    fn transform_conditional(&mut self, node: AstNode) -> AstNode {
+        node
+    }
+
+    fn transform_identifier(&mut self, node: AstNode) -> AstNode {
+        node
+    }
+
+    fn transform_parameter(&mut self, node: AstNode) -> AstNode {
+        node
+    }
+
+    ///
+    /// Rewrite an integer, float, boolean, string or dice roll.
+    ///
+    fn transform_literal(&mut self, node: AstNode) -> AstNode {
+        node
+    }
+}
+
+
+///
+/// Take a visitor through a tree in the order it is written, calling
+/// `enter` on each node before its children and `leave` after them.
+///
+/// The tree is walked with a loop over a stack rather than recursion, as
+/// in `AstNode::to_source`, so that very deep trees can be visited.
+///
+pub fn walk<V: AstVisitor + ?Sized>(tree: &AstNode, visitor: &mut V) {
+    let mut pending = vec![(tree, false)];
+
+    while let Some((node, entered)) = pending.pop() {
+        if entered {
+            visitor.leave(node);
+            continue;
+        }
+
+        pending.push((node, true));
+        if visitor.enter(node) {
+            pending.extend(node.children().into_iter().rev().map(|child| (child, false)));
+        }
+    }
+}
+
+
+///
+/// Rewrite a tree with a transformer, children first, and get the tree
+/// rewritten at its root.
+///
+/// Like `walk`, this loops over a stack rather than recursing. Each node
+/// has its children taken out while they are rewritten, and put back
+/// before the node itself is rewritten.
+///
+pub fn transform<T: AstTransformer + ?Sized>(tree: AstNode, transformer: &mut T) -> AstNode {
+    let mut pending = vec![(tree, None)];
+    let mut rewritten: Vec<AstNode> = vec![];
+
+    while let Some((mut node, taken)) = pending.pop() {
+        match taken {
+            Some(count) => put_children(&mut node, rewritten.split_off(rewritten.len() - count)),
+            None => {
+                let children = take_children(&mut node);
+                if !children.is_empty() {
+                    pending.push((node, Some(children.len())));
+                    pending.extend(children.into_iter().rev().map(|child| (child, None)));
+                    continue;
+                }
+            },
+        }
+        rewritten.push(transformer.transform_node(node));
+    }
+
+    rewritten.pop().expect("the root is rewritten last")
+}
+
+
+///
+/// Take the children out of a node, from left to right, leaving a
+/// placeholder in place of each operand until `put_children` puts them back.
+///
+fn take_children(node: &mut AstNode) -> Vec<AstNode> {
+    let placeholder = || AstNode::new(AstNodeKind::Boolean(false), node.span);
+    match &mut node.kind {
+        AstNodeKind::BinaryOperation(_, left, right) => {
+            vec![std::mem::replace(&mut **left, placeholder()), std::mem::replace(&mut **right, placeholder())]
+        },
+        AstNodeKind::UnaryOperation(_, operand) => vec![std::mem::replace(&mut **operand, placeholder())],
+        AstNodeKind::FunctionCall(_, arguments) => std::mem::take(arguments),
+        AstNodeKind::Conditional(parts) => std::mem::take(parts),
+        _ => vec![],
+    }
+}
+
+
+fn put_children(node: &mut AstNode, children: Vec<AstNode>) {
+    let mut children = children.into_iter();
+    let mut child = || children.next().expect("a node gets back as many children as were taken");
+    match &mut node.kind {
+        AstNodeKind::BinaryOperation(_, left, right) => {
+            **left = child();
+            **right = child();
+        },
+        AstNodeKind::UnaryOperation(_, operand) => **operand = child(),
+        AstNodeKind::FunctionCall(_, arguments) => *arguments = children.collect(),
+        AstNodeKind::Conditional(parts) => *parts = children.collect(),
+        _ => {},
+    }
+}
@@ -0,0 +1,352 @@
+use std::{fmt, str::FromStr};
+
+use crate::{
+    self as pxpr, builtins, context::Context,
+    expression::{apply_binary, apply_unary, call_function, check_function, compute_dice, compute_identifier, compute_tree, Value},
+    arena::{Arena, NodeId, NodeKind}, parser::{AstNode, BinaryOperationType, UnaryOperationType}
+};
+
+
+///
+/// How the trees of expressions are computed.
+///
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum Backend {
+    /// Walk the tree, computing each node from the values of its children.
+    #[default]
+    Tree,
+
+    /// Compile the tree into a `Program` of instructions, and run it on a
+    /// stack. Once compiled, computing it again only runs the instructions.
+    Vm
+}
+
+
+impl FromStr for Backend {
+    type Err = String;
+
+    fn from_str(backend: &str) -> Result<Self, Self::Err> {
+        match backend {
+            "tree" => Ok(Backend::Tree),
+            "vm" => Ok(Backend::Vm),
+            _ => Err(format!("Unknown backend: '{}' (expected 'tree' or 'vm')", backend)),
+        }
+    }
+}
+
+
+///
+/// An instruction of a `Program`. Each instruction pops its operands from
+/// the stack and pushes its result, and carries the columns which locate
+/// its errors and warnings, as the nodes it was compiled from do.
+///
+#[derive(Debug, Clone)]
+enum Instruction {
+    /// Push a literal value.
+    Push(Value),
+
+    /// Push the value of a variable or constant.
+    Load(String, u32),
+
+    /// Push the total of rolling dice.
+    Roll(i64, i64, u32),
+
+    Unary(UnaryOperationType, u32),
+
+    /// Apply an operation to the two values on top of the stack, given the
+    /// columns of its left and right operands.
+    Binary(BinaryOperationType, u32, u32),
+
+    /// Check that a function exists before its arguments are computed.
+    Lookup(String, u32),
+
+    /// Call a function with the given number of arguments on top of the stack.
+    Call(String, usize, u32),
+
+    /// Pop a guard, and jump to an instruction unless it is true.
+    JumpUnless(usize, u32),
+
+    /// Jump to an instruction.
+    Jump(usize),
+
+    /// Compute the tree of a node of the arena of the program by walking
+    /// it, for a function which computes its own arguments, such as `any`.
+    Walk(NodeId),
+
+    /// Fail with an error, such as for a parameter without a value.
+    Fail(pxpr::Error)
+}
+
+
+///
+/// A part of compiling a tree, kept on a stack by `Program::emit`.
+///
+enum Task {
+    /// Compile a node.
+    Emit(NodeId),
+
+    /// Append an instruction.
+    Append(Instruction),
+
+    /// Start a conditional chain, collecting the jumps to its end.
+    Begin,
+
+    /// Append a jump past the value of a case, unless its guard holds.
+    JumpUnless(u32),
+
+    /// Append a jump to the end of the conditional chain.
+    Jump,
+
+    /// Point the last jump past a value of a case here, after the value.
+    PastValue,
+
+    /// Point the jumps to the end of the conditional chain here.
+    End
+}
+
+
+///
+/// An expression compiled into instructions for a stack machine, which
+/// computes the same values, warnings and errors as walking its tree. The
+/// program keeps the arena it was compiled from for the trees it walks.
+///
+#[derive(Debug, Clone)]
+pub struct Program {
+    instructions: Vec<Instruction>,
+    arena: Arena
+}
+
+
+///
+/// Compile the tree of an expression into a program.
+///
+pub fn compile(tree: &AstNode) -> Program {
+    let (arena, root) = Arena::from_tree(tree);
+    compile_in(arena, root)
+}
+
+
+///
+/// Compile the tree of a node of an arena into a program, which takes over
+/// the arena.
+///
+pub fn compile_in(arena: Arena, root: NodeId) -> Program {
+    let mut program = Program { instructions: vec![], arena: Arena::new() };
+    program.emit(&arena, root);
+    program.arena = arena;
+    program
+}
+
+
+impl Program {
+    ///
+    /// Append the instructions computing a tree. The parts of the tree left
+    /// to compile are kept on a stack of their own rather than recursing
+    /// once per node, so that deeply nested trees cannot overflow the stack.
+    /// Flat chains such as `1 + 2 + 3` are walked down their left side like
+    /// `expression::execute` computes them.
+    ///
+    fn emit(&mut self, arena: &Arena, root: NodeId) {
+        let mut tasks = vec![Task::Emit(root)];
+        let mut guards: Vec<(usize, u32)> = vec![];
+        let mut chains: Vec<Vec<usize>> = vec![];
+
+        while let Some(task) = tasks.pop() {
+            match task {
+                Task::Emit(id) => self.schedule(arena, id, &mut tasks),
+                Task::Append(instruction) => self.instructions.push(instruction),
+                Task::Begin => chains.push(vec![]),
+                Task::JumpUnless(column) => {
+                    guards.push((self.instructions.len(), column));
+                    self.instructions.push(Instruction::JumpUnless(0, column));
+                },
+                Task::Jump => {
+                    chains.last_mut().expect("a jump is inside a conditional chain").push(self.instructions.len());
+                    self.instructions.push(Instruction::Jump(0));
+                },
+                Task::PastValue => {
+                    let (jump, column) = guards.pop().expect("the jump past a value is appended before it");
+                    self.instructions[jump] = Instruction::JumpUnless(self.instructions.len(), column);
+                },
+                Task::End => {
+                    let end = self.instructions.len();
+                    for jump in chains.pop().expect("a conditional chain is begun before it ends") {
+                        self.instructions[jump] = Instruction::Jump(end);
+                    }
+                },
+            }
+        }
+    }
+
+
+    ///
+    /// Push the tasks compiling a node, in the reverse of the order they are
+    /// taken, or append its instruction if it has no children.
+    ///
+    fn schedule(&mut self, arena: &Arena, id: NodeId, tasks: &mut Vec<Task>) {
+        let node = &arena[id];
+        let column = node.span.start;
+        match &node.kind {
+            NodeKind::BinaryOperation(..) => {
+                let mut chain = vec![];
+                let mut first = id;
+                while let NodeKind::BinaryOperation(operation_type, left, right) = arena[first].kind {
+                    chain.push((operation_type, right));
+                    first = left;
+                }
+
+                for (operation_type, right) in chain {
+                    tasks.push(Task::Append(Instruction::Binary(operation_type, arena[first].span.start, arena[right].span.start)));
+                    tasks.push(Task::Emit(right));
+                }
+                tasks.push(Task::Emit(first));
+            },
+            NodeKind::UnaryOperation(operation_type, operand) => {
+                tasks.extend([Task::Append(Instruction::Unary(*operation_type, column)), Task::Emit(*operand)]);
+            },
+            NodeKind::FunctionCall(name, _) if builtins::lookup(name).is_some_and(|builtin| builtin.is_lazy()) => {
+                self.instructions.push(Instruction::Walk(id));
+            },
+            NodeKind::FunctionCall(name, arguments) => {
+                tasks.push(Task::Append(Instruction::Call(name.clone(), arguments.len(), column)));
+                tasks.extend(arena.list(*arguments).iter().rev().copied().map(Task::Emit));
+                tasks.push(Task::Append(Instruction::Lookup(name.clone(), column)));
+            },
+            NodeKind::Conditional(parts) => Self::schedule_conditional(arena, arena.list(*parts), tasks),
+            NodeKind::Integer(x) => self.instructions.push(Instruction::Push(Value::Integer(*x))),
+            NodeKind::Float(x) => self.instructions.push(Instruction::Push(Value::Float(*x))),
+            NodeKind::Boolean(x) => self.instructions.push(Instruction::Push(Value::Boolean(*x))),
+            NodeKind::String(x) => self.instructions.push(Instruction::Push(Value::String(x.clone()))),
+            NodeKind::Dice(count, sides) => self.instructions.push(Instruction::Roll(*count, *sides, column)),
+            NodeKind::Identifier(name) => self.instructions.push(Instruction::Load(name.clone(), column)),
+            NodeKind::Parameter(index) => self.instructions.push(Instruction::Fail(pxpr::Error::usage(column,
+                format!("No value given for parameter {}", index + 1)))),
+        }
+    }
+
+
+    ///
+    /// Push the tasks compiling a conditional chain: each guard is followed
+    /// by a jump past its value unless it holds, and each value by a jump to
+    /// the end of the chain.
+    ///
+    fn schedule_conditional(arena: &Arena, parts: &[NodeId], tasks: &mut Vec<Task>) {
+        let cases = parts.chunks_exact(2);
+        let otherwise = cases.remainder()[0];
+
+        tasks.extend([Task::End, Task::Emit(otherwise)]);
+        for case in cases.rev() {
+            let (value, guard) = (case[0], case[1]);
+            tasks.extend([Task::PastValue, Task::Jump, Task::Emit(value), Task::JumpUnless(arena[guard].span.start), Task::Emit(guard)]);
+        }
+        tasks.push(Task::Begin);
+    }
+
+
+    ///
+    /// Run the program with the variables, functions and settings of a
+    /// context, which records warnings and dice rolls as computing the tree
+    /// would. Each instruction counts as a step towards the limits of the
+    /// context. When the context keeps a trace, each instruction run is
+    /// recorded in it along with the stack it leaves, or the error it fails
+    /// with.
+    ///
+    pub fn run(&self, context: &mut Context) -> Result<Value, pxpr::Error> {
+        let mut stack: Vec<Value> = vec![];
+        let mut next = 0;
+        context.start_budget();
+
+        while let Some(instruction) = self.instructions.get(next) {
+            context.spend()?;
+            let index = next;
+            next += 1;
+            let result = self.execute(instruction, &mut stack, &mut next, context);
+
+            if let Some(trace) = &mut context.vm_trace {
+                let outcome = match &result {
+                    Ok(()) => format!("[{}]", stack.iter().map(Value::to_string).collect::<Vec<String>>().join(", ")),
+                    Err(error) => format!("failed: {}", error.message()),
+                };
+                trace.push(format!("{:>4}  {:<24}{}", index, self.describe(instruction), outcome));
+            }
+            result?;
+        }
+
+        Ok(pop(&mut stack))
+    }
+
+
+    ///
+    /// Run one instruction on the stack, setting `next` to the instruction
+    /// to run after it when it jumps.
+    ///
+    fn execute(&self, instruction: &Instruction, stack: &mut Vec<Value>, next: &mut usize, context: &mut Context) -> Result<(), pxpr::Error> {
+        match instruction {
+            Instruction::Push(value) => stack.push(value.clone()),
+            Instruction::Load(name, column) => stack.push(compute_identifier(name, context).map_err(|error| error.at(*column))?),
+            Instruction::Roll(count, sides, column) => stack.push(compute_dice(*count, *sides, context).map_err(|error| error.at(*column))?),
+            Instruction::Unary(operation_type, column) => {
+                let operand = pop(stack);
+                stack.push(apply_unary(operation_type, operand, *column, context)?);
+            },
+            Instruction::Binary(operation_type, left_column, right_column) => {
+                let right = pop(stack);
+                let left = pop(stack);
+                stack.push(apply_binary(operation_type, left, *left_column, right, *right_column, context)?);
+            },
+            Instruction::Lookup(name, column) => check_function(name, *column, context)?,
+            Instruction::Call(name, count, column) => {
+                let arguments = stack.split_off(stack.len() - count);
+                stack.push(call_function(name, &arguments, *column, context)?);
+            },
+            Instruction::JumpUnless(target, column) => match pop(stack) {
+                Value::Boolean(true) => {},
+                Value::Boolean(false) => *next = *target,
+                other => return Err(pxpr::Error::type_mismatch(*column,
+                    format!("Expected a boolean guard after 'when', found {}", other))),
+            },
+            Instruction::Jump(target) => *next = *target,
+            Instruction::Walk(id) => stack.push(compute_tree(&self.arena, *id, context)?),
+            Instruction::Fail(error) => return Err(error.clone()),
+        }
+        Ok(())
+    }
+
+
+    ///
+    /// Describe an instruction as it is listed, such as `binary +`.
+    ///
+    fn describe(&self, instruction: &Instruction) -> String {
+        match instruction {
+            Instruction::Push(value) => format!("push {}", value),
+            Instruction::Load(name, _) => format!("load {}", name),
+            Instruction::Roll(count, sides, _) => format!("roll {}d{}", count, sides),
+            Instruction::Unary(operation_type, _) => format!("unary {}", operation_type.symbol()),
+            Instruction::Binary(operation_type, _, _) => format!("binary {}", operation_type.symbol()),
+            Instruction::Lookup(name, _) => format!("lookup {}", name),
+            Instruction::Call(name, count, _) => format!("call {}/{}", name, count),
+            Instruction::JumpUnless(target, _) => format!("jump unless true {}", target),
+            Instruction::Jump(target) => format!("jump {}", target),
+            Instruction::Walk(id) => format!("walk {}", self.arena.to_tree(*id).to_source()),
+            Instruction::Fail(error) => format!("fail {}", error.message()),
+        }
+    }
+}
+
+
+///
+/// Pop the operand of an instruction, which the compiler always pushed before it.
+///
+fn pop(stack: &mut Vec<Value>) -> Value {
+    stack.pop().expect("the operands of an instruction are pushed before it")
+}
+
+
+impl fmt::Display for Program {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (index, instruction) in self.instructions.iter().enumerate() {
+            writeln!(f, "{:>4}  {}", index, self.describe(instruction))?;
+        }
+        Ok(())
+    }
+}
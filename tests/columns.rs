@@ -0,0 +1,113 @@
+//!
+//! Checks that errors are reported at the column of the token or operation
+//! they are about, counted in characters, and displayed at the column they
+//! take on screen once located in their source.
+//!
+//! Run with `cargo test --test columns`.
+//!
+
+use pxpr::{
+    builtins::Arity, compute_expression, context::Context, expression::Value, parse_tuple_recovering,
+    lexer::NumberFormat, span::{character_column, display_column}, vm::Backend, Error, ErrorKind
+};
+
+
+///
+/// Invalid expressions, the column their error is expected at, which is the
+/// start of the token or operation the error is about, and the column it is
+/// displayed at, which differs from it after wide or combining characters.
+///
+const ERROR_COLUMNS: &[(&str, u32, u32)] = &[
+    ("1 + $", 4, 4),
+    ("12 + 345 +", 10, 10),
+    ("(1 + 2", 6, 6),
+    ("max(1 2)", 6, 6),
+    ("1 + \"a\\q\"", 6, 6),
+    ("1 + 2 / 0", 4, 4),
+    ("3 + (-1)!", 4, 4),
+    ("10 + sinn(2)", 5, 5),
+    ("true && 12 + false", 8, 8),
+    ("2 * (1 + foo)", 9, 9),
+    ("π + $", 4, 4),
+    ("\"日本\" + $", 7, 9),
+    ("\"e\u{301}\" + $", 7, 6),
+    ("1 when 日本; 2 otherwise", 7, 7),
+    ("1 + /* note", 4, 4),
+    ("1 /* one */ + # two\n true", 0, 0),
+    ("1 + volume(2, 3)", 4, 4),
+    ("2 * volume(1, -2, 3)", 4, 4),
+    ("1 2", 2, 2),
+    ("1 + 2 )", 6, 6),
+    ("日本 + 1 1", 7, 9),
+];
+
+
+///
+/// Create the context the expressions of `ERROR_COLUMNS` are computed in,
+/// which registers `volume(l, w, h)` so that the errors of registered
+/// functions are checked to be reported at their call.
+///
+fn context_with_volume(backend: Backend) -> Context {
+    let mut context = Context::new();
+    context.backend = backend;
+    context.register_fn("volume", Arity::Exact(3), |arguments| {
+        let sides: Vec<f64> = arguments.iter().filter_map(Value::as_float).collect();
+        match sides.iter().any(|side| *side < 0.0) {
+            true => Err(Error::domain(0, String::from("A side of 'volume' is negative"))),
+            false => Ok(Value::Float(sides.iter().product())),
+        }
+    });
+    context
+}
+
+
+#[test]
+fn errors_are_reported_at_the_start_of_their_token_or_operation() {
+    for backend in [Backend::Tree, Backend::Vm] {
+        for (source, column, display) in ERROR_COLUMNS {
+            let error = compute_expression(source, &mut context_with_volume(backend))
+                .expect_err(&format!("{:?} computes", source));
+            assert_eq!(error.column(), *column, "the column of {:?} ({:?} backend): {}", source, backend, error.message());
+            assert_eq!(display_column(source, error.column()), *display, "the display column of {:?}", source);
+            assert_eq!(error.located_in(source).column(), *display, "the located column of {:?}", source);
+        }
+    }
+}
+
+
+#[test]
+fn display_columns_convert_back_to_columns() {
+    for (source, column, display) in ERROR_COLUMNS {
+        assert_eq!(character_column(source, *display), *column, "the column displayed at {} in {:?}", display, source);
+    }
+}
+
+
+#[test]
+fn errors_on_later_lines_are_located_on_their_line() {
+    let source = "1 +\n  日本 + $";
+    let error = compute_expression(source, &mut Context::new()).unwrap_err().located_in(source);
+    assert_eq!((error.line(), error.column()), (Some(2), 9));
+}
+
+
+#[test]
+fn every_bad_character_is_reported_once() {
+    let errors = parse_tuple_recovering("1 + $ + @ + 2", NumberFormat::default()).unwrap_err();
+    let found: Vec<(ErrorKind, u32)> = errors.iter().map(|error| (error.kind(), error.column())).collect();
+    assert_eq!(found, [(ErrorKind::Lex, 4), (ErrorKind::Lex, 8)]);
+
+    for source in ["3 $ 1", "(3 $ 1)", "3 – 1"] {
+        let errors = parse_tuple_recovering(source, NumberFormat::default()).unwrap_err();
+        assert_eq!(errors.len(), 1, "the errors of {:?}: {:?}", source, errors);
+        assert_eq!(errors[0].kind(), ErrorKind::Lex);
+    }
+}
+
+
+#[test]
+fn tokens_left_over_are_reported_while_recovering() {
+    let errors = parse_tuple_recovering("1 2, 3 + 4 )", NumberFormat::default()).unwrap_err();
+    let found: Vec<(ErrorKind, u32)> = errors.iter().map(|error| (error.kind(), error.column())).collect();
+    assert_eq!(found, [(ErrorKind::Parse, 2), (ErrorKind::Parse, 11)]);
+}
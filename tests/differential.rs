@@ -0,0 +1,41 @@
+//!
+//! Runs the differential checks of `pxpr selftest` with fixed seeds, so that
+//! they check the same expressions on every run. `pxpr selftest` runs them
+//! with a new seed each time to find more.
+//!
+//! Run with `cargo test --test differential`.
+//!
+
+use pxpr::{random::Rng, selftest::{check_deep_nesting, check_random_trees, run_differential}};
+
+
+///
+/// The seeds the checks run with, including those which found a divergence
+/// before.
+///
+const SEEDS: [u64; 4] = [1, 814, 20240229, 1792156367649529301];
+
+
+#[test]
+fn random_expressions_compute_as_the_reference_evaluator_does() {
+    for seed in SEEDS {
+        let divergences = run_differential(200, &mut Rng::new(seed));
+        assert!(divergences.is_empty(), "seed {}: {:#?}", seed, divergences);
+    }
+}
+
+
+#[test]
+fn random_trees_compute_alike_on_every_path() {
+    for seed in SEEDS {
+        let divergences = check_random_trees(200, &mut Rng::new(seed));
+        assert!(divergences.is_empty(), "seed {}: {:#?}", seed, divergences);
+    }
+}
+
+
+#[test]
+fn deeply_nested_expressions_compute_without_overflowing_the_stack() {
+    let divergences = check_deep_nesting(2, &mut Rng::new(814));
+    assert!(divergences.is_empty(), "{:#?}", divergences);
+}
@@ -0,0 +1,50 @@
+//!
+//! Checks that expressions exceeding one of their limits fail with its kind
+//! of error on both backends, and compute without them.
+//!
+//! Run with `cargo test --test limits`.
+//!
+
+use std::time::Duration;
+
+use pxpr::{compute_expression, context::Context, limits::Limits, vm::Backend, ErrorKind};
+
+
+///
+/// Expressions which exceed one of their limits, with the kind of error
+/// they fail with. The calls to `rand` cannot be folded, so that both
+/// backends take many steps.
+///
+const LIMITED: [(&str, Limits, ErrorKind); 4] = [
+    ("1 + 2 + 3", Limits { max_length: Some(5), max_depth: None, max_steps: None, timeout: None }, ErrorKind::TooLong),
+    ("-(2 * max(1))", Limits { max_length: None, max_depth: Some(2), max_steps: None, timeout: None }, ErrorKind::TooDeep),
+    ("rand() + rand() + rand()", Limits { max_length: None, max_depth: None, max_steps: Some(4), timeout: None }, ErrorKind::TooManySteps),
+    ("rand() + rand() + rand()", Limits { max_length: None, max_depth: None, max_steps: None, timeout: Some(Duration::ZERO) }, ErrorKind::Timeout),
+];
+
+
+#[test]
+fn expressions_fail_once_they_exceed_their_limits() {
+    for (source, limits, kind) in LIMITED {
+        // The timeout is only checked every so many steps, so its expression
+        // is repeated until it takes enough of them.
+        let source = match kind {
+            ErrorKind::Timeout => vec![source; 1000].join(" + "),
+            _ => source.to_string(),
+        };
+
+        for backend in [Backend::Tree, Backend::Vm] {
+            let mut context = Context::new();
+            context.backend = backend;
+            if let Err(error) = compute_expression(&source, &mut context) {
+                panic!("{:.40} fails without limits: {} ({:?} backend)", source, error.message(), backend);
+            }
+
+            context.limits = limits;
+            match compute_expression(&source, &mut context) {
+                Err(error) => assert_eq!(error.kind(), kind, "{:.40} ({:?} backend): {}", source, backend, error.message()),
+                Ok(value) => panic!("{:.40} computes {} despite its limits ({:?} backend)", source, value, backend),
+            }
+        }
+    }
+}
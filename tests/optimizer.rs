@@ -0,0 +1,95 @@
+//!
+//! Checks that optimized trees and compiled programs compute what the trees
+//! they come from compute, whatever type of value their variables hold.
+//!
+//! Run with `cargo test --test optimizer`.
+//!
+
+use pxpr::{
+    compile, context::Context, expression::{execute, Value}, optimize::optimize, overflow::Overflow, parse_expression,
+    parser::AstNode, vm::Backend
+};
+
+
+///
+/// Expressions of a variable `x` which the optimizer may remove an identity
+/// or a double negation from.
+///
+const IDENTITIES: [&str; 9] = ["x + 0", "0 + x", "x - 0", "x * 1", "1 * x", "x / 1", "-(-x)", "!(!x)", "1 * x * 1"];
+
+
+///
+/// The values `x` takes, none first, and the smallest integer, whose
+/// negation depends on the overflow policy.
+///
+fn values() -> [Option<Value>; 6] {
+    [
+        None, Some(Value::Integer(i64::MIN)), Some(Value::Integer(0)), Some(Value::Float(2.5)),
+        Some(Value::Boolean(true)), Some(Value::String("s".to_string())),
+    ]
+}
+
+
+fn describe(tree: &AstNode, context: &Context) -> String {
+    match execute(tree, &mut context.fork()) {
+        Ok(value) => format!("the value {}", value),
+        Err(error) => format!("the error '{}'", error),
+    }
+}
+
+
+#[test]
+fn optimized_identities_compute_as_written() {
+    for source in IDENTITIES {
+        let tree = parse_expression(source).expect("the identities parse");
+        for value in values() {
+            for policy in [Overflow::Error, Overflow::Saturate, Overflow::Promote] {
+                for strict_types in [false, true] {
+                    let mut context = Context::new();
+                    context.overflow.add = Some(policy);
+                    context.strict_types = strict_types;
+                    if let Some(value) = &value {
+                        context.define("x", value.clone());
+                    }
+
+                    let optimized = optimize(&tree, &context);
+                    assert_eq!(describe(&optimized, &context), describe(&tree, &context),
+                        "{} optimized to {} with x = {:?}, {:?} on overflow and strict types {}", source, optimized.to_source(), value, policy, strict_types);
+                }
+            }
+        }
+    }
+}
+
+
+#[test]
+fn compiled_identities_follow_the_types_of_their_variables() {
+    // A number comes first, so that identities are removed from the first
+    // program compiled.
+    let values = values();
+    for source in IDENTITIES {
+        let tree = parse_expression(source).expect("the identities parse");
+        let compiled = compile(source).expect("the identities parse");
+        for value in values.iter().cycle().skip(2).take(values.len()) {
+            let mut context = Context::new();
+            if let Some(value) = value {
+                context.define("x", value.clone());
+            }
+
+            let expected = format!("{:?}", execute(&tree, &mut context.fork()));
+            context.backend = Backend::Vm;
+            assert_eq!(format!("{:?}", compiled.evaluate(&mut context)), expected, "{} compiled, with x = {:?}", source, value);
+        }
+    }
+}
+
+
+#[test]
+fn identities_around_a_defined_variable_are_removed() {
+    let tree = parse_expression("1 * x * 1 + 0").unwrap();
+    assert_eq!(optimize(&tree, &Context::new()).to_source(), "1 * x");
+
+    let mut context = Context::new();
+    context.define("x", Value::Integer(4));
+    assert_eq!(optimize(&tree, &context).to_source(), "x");
+}
@@ -0,0 +1,76 @@
+//!
+//! Checks for bugs which were fixed, so that they stay fixed.
+//!
+//! Run with `cargo test --test regressions`.
+//!
+
+use pxpr::{
+    compute_expression, context::Context, expression::Value, import::{convert, Calculator}, overflow::Overflow,
+    parse_expression, typecheck::{typecheck_for, Type}, vm::Backend, ErrorKind
+};
+
+
+#[test]
+fn negating_an_integer_is_typed_as_any_number_it_computes() {
+    for policy in [Overflow::Error, Overflow::Wrap, Overflow::Saturate, Overflow::Promote] {
+        for backend in [Backend::Tree, Backend::Vm] {
+            let mut context = Context::new();
+            context.backend = backend;
+            context.overflow.add = Some(policy);
+            context.define("x", Value::Integer(i64::MIN));
+
+            let tree = parse_expression("-x").unwrap();
+            let expected = typecheck_for(&tree, &context).expect("negating an integer is typed");
+            assert_eq!(expected, Type::Number, "the type of -x ({:?} on overflow)", policy);
+            match compute_expression("-x", &mut context) {
+                Ok(value) => assert!(expected.contains(Type::of(&value)), "-x computes {} ({:?} on overflow, {:?} backend)", value, policy, backend),
+                Err(error) => assert_eq!((policy, error.kind()), (Overflow::Error, ErrorKind::Overflow), "{}", error.message()),
+            }
+        }
+    }
+}
+
+
+#[test]
+fn tokens_left_over_after_an_expression_are_errors() {
+    for (source, column) in [("1 2", 2), ("1 + 2 )", 6), ("true false", 5), ("1 when true; 2 otherwise 3", 25)] {
+        let error = parse_expression(source).expect_err(source);
+        assert_eq!((error.kind(), error.column()), (ErrorKind::Parse, column), "{:?}: {}", source, error.message());
+    }
+}
+
+
+#[test]
+fn integer_literals_beyond_the_largest_integer_are_overflow_errors() {
+    for source in ["99999999999999999999", "1 + 9223372036854775808"] {
+        let error = compute_expression(source, &mut Context::new()).expect_err(source);
+        assert_eq!(error.kind(), ErrorKind::Overflow, "{:?}: {}", source, error.message());
+    }
+    assert!(matches!(compute_expression("99999999999999999999.0", &mut Context::new()), Ok(Value::Float(_))));
+}
+
+
+#[test]
+fn compatibility_warnings_show_both_readings() {
+    let mut context = Context::new();
+    context.pedantic_compat = true;
+    compute_expression("1 << 2 + 1", &mut context).unwrap();
+    let messages: Vec<String> = context.take_warnings().iter().map(|warning| warning.message().to_string()).collect();
+    assert_eq!(messages, ["'1 << 2 + 1' reads as '1 << (2 + 1)', but as '(1 << 2) + 1' with compatibility version 0.x; add parentheses to keep one meaning"]);
+}
+
+
+#[test]
+fn bc_lines_using_a_variable_assigned_again_are_left_out() {
+    let conversion = convert(Calculator::Bc, "x = 2\nx + 1\nx = 3\nx * 2\n5 + 1");
+    assert_eq!(conversion.options, ["-D 'x=2'"]);
+    assert_eq!(conversion.expressions, ["x + 1", "5 + 1"]);
+    assert_eq!(conversion.skipped.iter().map(|(line, _)| *line).collect::<Vec<_>>(), [3, 4]);
+}
+
+
+#[test]
+fn bc_divisions_are_truncated_until_scale_is_set() {
+    let conversion = convert(Calculator::Bc, "7 / 2\n1 + 10 / 3\nscale = 4\n1 / 3");
+    assert_eq!(conversion.expressions, ["trunc(7 / 2)", "1 + trunc(10 / 3)", "1 / 3"]);
+}
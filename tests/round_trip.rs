@@ -0,0 +1,88 @@
+//!
+//! Checks that trees written as source, and values written as literals,
+//! read back to the same tree or value.
+//!
+//! Run with `cargo test --test round_trip`, adding `--features arbitrary` to
+//! check trees generated by the `testing` module too.
+//!
+
+use pxpr::{compute_expression, context::Context, escape_literal, expression::Value, parse_expression};
+
+
+///
+/// Expressions whose operators, precedences and associativities are the
+/// most likely to be written with too few parentheses.
+///
+const SOURCES: &[&str] = &[
+    "1 - (2 - 3)",
+    "(1 - 2) - 3",
+    "2 * (3 + 4) / 5",
+    "-(2 - 3)",
+    "-(-2)!",
+    "(-2)!",
+    "~(1 + 2) >> 1",
+    "!(true && false) || true",
+    "1 << 2 + 3 & 7 | 8 ^ 1",
+    "(3!)! + -(-4)",
+    "max(1, 2 * 3) % 4",
+    "1 when x > 2; 2 otherwise",
+    "\"a\\\"b\" + \"c\\\\\"",
+    "-9223372036854775807 - 1",
+];
+
+
+///
+/// Values whose literals are the most likely not to read back.
+///
+const ESCAPED: [Value; 6] = [
+    Value::Integer(i64::MIN), Value::Integer(i64::MAX), Value::Integer(-3), Value::Float(-0.5),
+    Value::Float(-1e300), Value::Boolean(false),
+];
+
+
+#[test]
+fn trees_written_as_source_parse_back_to_the_same_tree() {
+    for source in SOURCES {
+        let tree = parse_expression(source).unwrap_or_else(|error| panic!("{:?} does not parse: {}", source, error));
+        for printed in [tree.to_source(), tree.to_parenthesized_source()] {
+            let reparsed = parse_expression(&printed).unwrap_or_else(|error| panic!("{:?}, printed from {:?}, does not parse: {}", printed, source, error));
+            assert!(*reparsed == *tree, "{:?} parses to {:?} once printed as {:?}", source, reparsed.to_source(), printed);
+        }
+    }
+}
+
+
+#[test]
+fn escaped_literals_compute_back_to_their_value() {
+    let string = Value::String(String::from("say \\\"hi\\\""));
+    for value in ESCAPED.iter().chain([&string]) {
+        let literal = escape_literal(value).expect("the values are finite");
+        let computed = compute_expression(&literal, &mut Context::new()).unwrap_or_else(|error| panic!("{} fails: {}", literal, error));
+        assert_eq!(format!("{:?}", computed), format!("{:?}", value), "the value of {}", literal);
+        let compared = compute_expression(&format!("{0} == {0}", literal), &mut Context::new());
+        assert!(matches!(compared, Ok(Value::Boolean(true))), "{} == {} gives {:?}", literal, literal, compared);
+    }
+}
+
+
+#[test]
+fn the_smallest_integer_is_escaped_as_a_subtraction() {
+    assert_eq!(escape_literal(&Value::Integer(i64::MIN)).unwrap(), "(-9223372036854775807 - 1)");
+    assert_eq!(parse_expression("-9223372036854775807 - 1").unwrap().to_source(), "-9223372036854775807 - 1");
+}
+
+
+#[test]
+fn non_finite_floats_have_no_literal() {
+    for value in [f64::NAN, f64::INFINITY, f64::NEG_INFINITY] {
+        assert!(escape_literal(&Value::Float(value)).is_err(), "{} has a literal", value);
+    }
+}
+
+
+#[cfg(feature = "arbitrary")]
+#[test]
+fn arbitrary_trees_written_as_source_parse_back_to_the_same_tree() {
+    let divergences = pxpr::testing::check_round_trips(500, &mut pxpr::random::Rng::new(814));
+    assert!(divergences.is_empty(), "{:#?}", divergences);
+}